@@ -1,10 +1,45 @@
 use std::io::{Cursor, Result, Write};
 use std::path::Path;
 
-use zip::write::FileOptions;
-
 use crate::spec::TargetAmalgamation;
-use crate::{create_targz, GeneratedAsset, GeneratedAssetKind, PlatformFile, Project};
+use crate::{create_targz, zip_options, GeneratedAsset, GeneratedAssetKind, PlatformFile, Project};
+
+mod templates {
+    use crate::Project;
+
+    pub(crate) fn readme(project: &Project, include: &[String]) -> String {
+        let name = &project.spec.package.name;
+        let version = &project.version;
+        let license = &project.spec.package.license;
+        let sources = include
+            .iter()
+            .filter(|path| path.ends_with(".c"))
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            r#"# {name} {version} amalgamation
+
+The C source of {name}, for compiling it yourself.
+
+Compile it as a loadable extension:
+
+```sh
+# linux
+gcc -fPIC -shared {sources} -o {name}.so
+# macos
+gcc -fPIC -dynamiclib {sources} -o {name}.dylib
+# windows
+cl {sources} -link -dll -out:{name}.dll
+```
+
+Or add the files to your project and compile them alongside the SQLite amalgamation.
+
+License: {license}
+"#
+        )
+    }
+}
 
 pub(crate) fn write_amalgamation(
     project: &Project,
@@ -21,10 +56,25 @@ pub(crate) fn write_amalgamation(
                 name: relative_path.to_owned(),
                 data: data.into(),
                 metadata: Some(std::fs::metadata(&path)?),
+                unsigned_sha256: None,
             })
         })
         .collect();
-    let files = files?;
+    let mut files = files?;
+    let readme = match &amalgamation_config.readme {
+        Some(readme) => std::fs::read_to_string(project.spec_directory.join(readme))?,
+        None => templates::readme(project, &amalgamation_config.include),
+    };
+    let readme = project.render_template("amalgamation/README.md", None, readme)?;
+    files.push(PlatformFile::new("README.md", readme, None));
+    if let Some(license_file) = &amalgamation_config.license_file {
+        let path = project.spec_directory.join(license_file);
+        files.push(PlatformFile::new(
+            "LICENSE",
+            std::fs::read(&path)?,
+            Some(std::fs::metadata(&path)?),
+        ));
+    }
     let mut assets = vec![];
 
     let targz = create_targz(files.iter().collect::<Vec<&PlatformFile>>().as_ref())?;
@@ -40,7 +90,7 @@ pub(crate) fn write_amalgamation(
     let buffer = Cursor::new(Vec::new());
     let mut zipfile = zip::ZipWriter::new(buffer);
     for file in files {
-        let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        let options = zip_options(zip::CompressionMethod::Deflated, file.data.len());
         zipfile.start_file(file.name, options)?;
         zipfile.write_all(&file.data)?;
     }
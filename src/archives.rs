@@ -0,0 +1,75 @@
+use std::io::Result;
+use std::path::Path;
+
+use crate::layout::release_file_name;
+use crate::spec::{ArchiveFormat, TargetArchives};
+use crate::{create_targz, create_zip, GeneratedAsset, GeneratedAssetKind, PlatformFile, Project};
+
+/// The LICENSE and README from the spec, shared by every platform's archive
+fn documentation_files(project: &Project, config: &TargetArchives) -> Result<Vec<PlatformFile>> {
+    let mut files = vec![];
+    for (name, relative_path) in [
+        ("LICENSE", &config.license_file),
+        ("README.md", &config.readme),
+    ] {
+        if let Some(relative_path) = relative_path {
+            let path = project.spec_directory.join(relative_path);
+            files.push(PlatformFile::new(
+                name,
+                std::fs::read(&path)?,
+                Some(std::fs::metadata(&path)?),
+            ));
+        }
+    }
+    Ok(files)
+}
+
+/// One `$NAME-$VERSION-$OS-$CPU.tar.gz` and/or `.zip` per platform with nothing but the
+/// loadables, extra files, LICENSE, and README. Everything is under a `$NAME/` directory,
+/// so the paths inside don't change between versions or platforms.
+pub(crate) fn write_archives(
+    project: &Project,
+    archives_dir: &Path,
+    config: &TargetArchives,
+) -> Result<Vec<GeneratedAsset>> {
+    let name = &project.spec.package.name;
+    let documentation = documentation_files(project, config)?;
+    let mut assets = vec![];
+    for platform_dir in &project.platform_directories {
+        let _span = platform_dir.span().entered();
+        let files: Vec<PlatformFile> = platform_dir
+            .loadable_files
+            .iter()
+            .map(|loadable| &loadable.file)
+            .chain(&platform_dir.extra_files)
+            .chain(&documentation)
+            .map(|file| PlatformFile {
+                name: format!("{name}/{}", file.name),
+                ..file.clone()
+            })
+            .collect();
+        let files = files.iter().collect::<Vec<&PlatformFile>>();
+        for format in &config.formats {
+            let (ext, data) = match format {
+                ArchiveFormat::TarGz => ("tar.gz", create_targz(&files)?),
+                ArchiveFormat::Zip => ("zip", create_zip(&files)?),
+            };
+            let file_name =
+                release_file_name(project, "archive", &platform_dir.os, &platform_dir.cpu, ext)
+                    .unwrap_or_else(|| {
+                        format!(
+                            "{name}-{}-{}-{}.{ext}",
+                            project.version,
+                            platform_dir.os.to_string(),
+                            platform_dir.cpu.to_string()
+                        )
+                    });
+            assets.push(GeneratedAsset::from(
+                GeneratedAssetKind::Archive((platform_dir.os.clone(), platform_dir.cpu.clone())),
+                &archives_dir.join(file_name),
+                &data,
+            )?);
+        }
+    }
+    Ok(assets)
+}
@@ -0,0 +1,76 @@
+use std::io::Result;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::{create_output_dir, GeneratedAsset, GeneratedAssetKind, Project};
+
+/// https://shields.io/badges/endpoint-badge
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EndpointBadge {
+    schema_version: u8,
+    label: String,
+    message: String,
+    color: String,
+}
+
+fn human_size(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{bytes} B"),
+        1024..=1048575 => format!("{:.0} kB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+/// Writes shields.io endpoint badges for the version, the number of platforms, and the
+/// download size of the loadables. They're uploaded with the release under stable names,
+/// so `https://img.shields.io/endpoint?url=<repo>/releases/latest/download/badge-version.json`
+/// always shows the latest release.
+pub(crate) fn write_badges(
+    project: &Project,
+    color: &str,
+    assets: &[GeneratedAsset],
+    output_dir: &Path,
+) -> Result<Vec<GeneratedAsset>> {
+    let badges_dir = output_dir.join("badges");
+    create_output_dir(&badges_dir)?;
+
+    let loadable_sizes: Vec<usize> = assets
+        .iter()
+        .filter(|asset| matches!(asset.kind, GeneratedAssetKind::GithubReleaseLoadable(_)))
+        .map(|asset| asset.size)
+        .collect();
+    let size = match (loadable_sizes.iter().min(), loadable_sizes.iter().max()) {
+        (Some(min), Some(max)) if min != max => {
+            format!("{} – {}", human_size(*min), human_size(*max))
+        }
+        (Some(size), _) => human_size(*size),
+        _ => "unknown".to_owned(),
+    };
+    let badges = [
+        ("version", "version", format!("v{}", project.version)),
+        (
+            "platforms",
+            "platforms",
+            project.platform_directories.len().to_string(),
+        ),
+        ("size", "download size", size),
+    ];
+
+    let mut generated = vec![];
+    for (name, label, message) in badges {
+        let badge = EndpointBadge {
+            schema_version: 1,
+            label: label.to_owned(),
+            message,
+            color: color.to_owned(),
+        };
+        generated.push(GeneratedAsset::from(
+            GeneratedAssetKind::Badges,
+            &badges_dir.join(format!("badge-{name}.json")),
+            serde_json::to_string(&badge)?.as_bytes(),
+        )?);
+    }
+    Ok(generated)
+}
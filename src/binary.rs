@@ -0,0 +1,132 @@
+use crate::{Cpu, Os};
+
+pub(crate) fn u16_at(data: &[u8], offset: usize, little_endian: bool) -> Option<u16> {
+    let bytes: [u8; 2] = data.get(offset..offset + 2)?.try_into().ok()?;
+    Some(if little_endian {
+        u16::from_le_bytes(bytes)
+    } else {
+        u16::from_be_bytes(bytes)
+    })
+}
+
+pub(crate) fn u32_at(data: &[u8], offset: usize, little_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if little_endian {
+        u32::from_le_bytes(bytes)
+    } else {
+        u32::from_be_bytes(bytes)
+    })
+}
+
+pub(crate) fn u64_at(data: &[u8], offset: usize, little_endian: bool) -> Option<u64> {
+    let bytes: [u8; 8] = data.get(offset..offset + 8)?.try_into().ok()?;
+    Some(if little_endian {
+        u64::from_le_bytes(bytes)
+    } else {
+        u64::from_be_bytes(bytes)
+    })
+}
+
+fn elf_platform(data: &[u8]) -> Option<(Os, Cpu)> {
+    let little_endian = *data.get(5)? == 1;
+    let os = match data.get(7)? {
+        9 => Os::Freebsd,
+        12 => Os::Openbsd,
+        _ => Os::Linux,
+    };
+    let cpu = match u16_at(data, 18, little_endian)? {
+        0x3e => Cpu::X86_64,
+        0xb7 => Cpu::Aarch64,
+        0x03 => Cpu::I686,
+        0x28 => Cpu::Armv7a,
+        0xf3 => Cpu::Riscv64,
+        0x102 => Cpu::Loongarch64,
+        0x16 => Cpu::S390x,
+        _ => return None,
+    };
+    Some((os, cpu))
+}
+
+const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+const LC_VERSION_MIN_MACOSX: u32 = 0x24;
+const LC_VERSION_MIN_IPHONEOS: u32 = 0x25;
+const LC_BUILD_VERSION: u32 = 0x32;
+
+/// A thin 64-bit Mach-O. The OS comes from its build version load command,
+/// which is what tells iOS, the iOS simulator, and macOS apart.
+fn macho_platform(data: &[u8]) -> Option<(Os, Cpu)> {
+    let cpu = match u32_at(data, 4, true)? {
+        CPU_TYPE_X86_64 => Cpu::X86_64,
+        CPU_TYPE_ARM64 => Cpu::Aarch64,
+        _ => return None,
+    };
+    let ncmds = u32_at(data, 16, true)?;
+    let mut offset = 32;
+    let mut os = Os::Macos;
+    for _ in 0..ncmds {
+        let cmd = u32_at(data, offset, true)?;
+        let cmdsize = u32_at(data, offset + 4, true)? as usize;
+        match cmd {
+            LC_BUILD_VERSION => {
+                os = match u32_at(data, offset + 8, true)? {
+                    2 => Os::Ios,
+                    7 => Os::IosSimulator,
+                    _ => Os::Macos,
+                };
+                break;
+            }
+            LC_VERSION_MIN_IPHONEOS => {
+                os = Os::Ios;
+                break;
+            }
+            LC_VERSION_MIN_MACOSX => break,
+            _ => {}
+        }
+        if cmdsize == 0 {
+            break;
+        }
+        offset += cmdsize;
+    }
+    Some((os, cpu))
+}
+
+/// A fat Mach-O, which sqlite-dist only produces for macos-universal2
+fn fat_macho_platform(data: &[u8]) -> Option<(Os, Cpu)> {
+    let nfat_arch = u32_at(data, 4, false)?;
+    let cpus: Vec<u32> = (0..nfat_arch as usize)
+        .map(|i| u32_at(data, 8 + i * 20, false))
+        .collect::<Option<_>>()?;
+    if cpus.contains(&CPU_TYPE_X86_64) && cpus.contains(&CPU_TYPE_ARM64) {
+        Some((Os::Macos, Cpu::Universal2))
+    } else {
+        None
+    }
+}
+
+fn pe_platform(data: &[u8]) -> Option<(Os, Cpu)> {
+    let pe_offset = u32_at(data, 0x3c, true)? as usize;
+    if data.get(pe_offset..pe_offset + 4)? != b"PE\0\0" {
+        return None;
+    }
+    let cpu = match u16_at(data, pe_offset + 4, true)? {
+        0x8664 => Cpu::X86_64,
+        0xaa64 => Cpu::Aarch64,
+        0x14c => Cpu::I686,
+        _ => return None,
+    };
+    Some((Os::Windows, cpu))
+}
+
+/// The platform a loadable or static library was built for, None when it isn't a
+/// binary or is for an architecture sqlite-dist doesn't support. Android loadables
+/// are plain ELF files and are reported as linux.
+pub(crate) fn detect_platform(data: &[u8]) -> Option<(Os, Cpu)> {
+    match data.get(..4)? {
+        b"\x7fELF" => elf_platform(data),
+        [0xcf, 0xfa, 0xed, 0xfe] => macho_platform(data),
+        [0xca, 0xfe, 0xba, 0xbe] => fat_macho_platform(data),
+        [b'M', b'Z', _, _] => pe_platform(data),
+        _ => None,
+    }
+}
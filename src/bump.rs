@@ -0,0 +1,311 @@
+use std::{fs, io, path::Path, process::Command};
+
+use semver::{Prerelease, Version};
+use thiserror::Error;
+use toml_edit::{value, Document};
+
+use crate::{
+    pip,
+    spec::{Spec, SpecError},
+    template_overrides::TemplateOverrides,
+    Project,
+};
+
+#[derive(Error, Debug)]
+pub enum BumpError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] io::Error),
+    #[error("Invalid spec file: {0}")]
+    InvalidSpec(#[from] SpecError),
+    #[error("could not edit spec: {0}")]
+    TomlError(#[from] toml_edit::TomlError),
+    #[error("package.version must be set in the spec to bump it")]
+    MissingVersion,
+    #[error("{0} is not a valid {1} version: {2}")]
+    UnsupportedVersion(Version, &'static str, String),
+    #[error("could not derive a dev version: {0}")]
+    DevVersion(String),
+}
+
+#[derive(Clone, Copy)]
+pub enum BumpLevel {
+    Major,
+    Minor,
+    Patch,
+    Prerelease,
+}
+
+/// The next version at `level`. Bumping a pre-release releases it, like
+/// `npm version`: `1.2.0-alpha.1` bumped to minor is `1.2.0`.
+pub fn bump_version(version: &Version, level: BumpLevel) -> Version {
+    let mut next = Version::new(version.major, version.minor, version.patch);
+    let released = !version.pre.is_empty();
+    match level {
+        BumpLevel::Major if released && version.minor == 0 && version.patch == 0 => {}
+        BumpLevel::Major => next = Version::new(version.major + 1, 0, 0),
+        BumpLevel::Minor if released && version.patch == 0 => {}
+        BumpLevel::Minor => next = Version::new(version.major, version.minor + 1, 0),
+        BumpLevel::Patch if released => {}
+        BumpLevel::Patch => next.patch += 1,
+        BumpLevel::Prerelease if released => {
+            let pre = match version.pre.rsplit_once('.') {
+                Some((tag, number)) if number.parse::<u64>().is_ok() => {
+                    format!("{tag}.{}", number.parse::<u64>().unwrap() + 1)
+                }
+                _ => format!("{}.1", version.pre),
+            };
+            next.pre = Prerelease::new(&pre).unwrap_or_default();
+        }
+        BumpLevel::Prerelease => {
+            next.patch += 1;
+            next.pre = Prerelease::new("alpha.0").unwrap_or_default();
+        }
+    }
+    next
+}
+
+/// Where the number of a dev version comes from
+#[derive(Clone, Copy)]
+pub enum DevVersionSource {
+    /// commits since the last tag, from `git describe`
+    Git,
+    /// UTC date and time of the build, `YYYYMMDDHHMM`
+    Date,
+    /// `GITHUB_RUN_NUMBER`, or `CI_PIPELINE_IID` on GitLab
+    RunNumber,
+}
+
+fn dev_number(source: DevVersionSource, spec_directory: &Path) -> Result<u64, BumpError> {
+    let number = match source {
+        DevVersionSource::Git => {
+            let output = Command::new("git")
+                .args(["describe", "--tags", "--long"])
+                .current_dir(spec_directory)
+                .output()
+                .map_err(|err| BumpError::DevVersion(format!("could not run git: {err}")))?;
+            if !output.status.success() {
+                return Err(BumpError::DevVersion(format!(
+                    "git describe failed, is there a tag? {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )));
+            }
+            // <tag>-<commits since>-g<hash>
+            let describe = String::from_utf8_lossy(&output.stdout);
+            describe
+                .trim()
+                .rsplit_once('-')
+                .and_then(|(rest, _hash)| rest.rsplit_once('-'))
+                .map(|(_tag, commits)| commits.to_owned())
+                .unwrap_or_default()
+        }
+        DevVersionSource::Date => chrono::Utc::now().format("%Y%m%d%H%M").to_string(),
+        DevVersionSource::RunNumber => ["GITHUB_RUN_NUMBER", "CI_PIPELINE_IID"]
+            .iter()
+            .find_map(|name| std::env::var(name).ok())
+            .ok_or_else(|| {
+                BumpError::DevVersion(
+                    "set GITHUB_RUN_NUMBER or CI_PIPELINE_IID for run-number".to_owned(),
+                )
+            })?,
+    };
+    number
+        .parse()
+        .map_err(|_| BumpError::DevVersion(format!("'{number}' is not a number")))
+}
+
+/// The dev version of the release after `version`, ex `1.2.4-dev.17` for 1.2.3, which
+/// every target converts: `1.2.4.dev17` on PyPI, `1.2.4-dev.17` on npm, and the
+/// `1.2.4.dev.17` pre-release gem. Pre-releases keep their version, so 1.3.0-alpha.1
+/// gives `1.3.0-dev.17`.
+pub fn dev_version(
+    version: &Version,
+    source: DevVersionSource,
+    spec_directory: &Path,
+) -> Result<Version, BumpError> {
+    let mut dev = if version.pre.is_empty() {
+        bump_version(version, BumpLevel::Patch)
+    } else {
+        Version::new(version.major, version.minor, version.patch)
+    };
+    let number = dev_number(source, spec_directory)?;
+    dev.pre = Prerelease::new(&format!("dev.{number}")).unwrap_or_default();
+    Ok(dev)
+}
+
+/// Checks `version` converts to the version scheme of every package registry the spec targets
+pub fn check_version(spec: &Spec, version: &Version) -> Result<(), BumpError> {
+    if spec.targets.pip.is_some() {
+        pip::semver_to_pip_version(version).map_err(|err| {
+            BumpError::UnsupportedVersion(version.clone(), "PEP 440", err.to_string())
+        })?;
+    }
+    if spec.targets.gem.is_some() {
+        // gems use the version with - replaced by ., which only allows alphanumeric segments
+        let gem_version = version.to_string().replace('-', ".");
+        if !gem_version.split('.').all(|segment| {
+            !segment.is_empty() && segment.chars().all(|c| c.is_ascii_alphanumeric())
+        }) {
+            return Err(BumpError::UnsupportedVersion(
+                version.clone(),
+                "gem",
+                format!("'{gem_version}' may only contain letters and digits between dots"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+mod templates {
+    use semver::Version;
+
+    pub(crate) fn changelog_entry(version: &Version) -> String {
+        let date = chrono::offset::Local::now().format("%Y-%m-%d");
+        format!("## [{version}] - {date}\n\n")
+    }
+}
+
+/// Adds `entry` below `## [Unreleased]` so its notes become the new version's,
+/// otherwise above the latest version.
+fn insert_changelog_entry(changelog: &str, entry: &str) -> String {
+    let mut offset = 0;
+    for line in changelog.split_inclusive('\n') {
+        if line.trim().eq_ignore_ascii_case("## [unreleased]") {
+            let (before, after) = changelog.split_at(offset + line.len());
+            return format!("{before}\n{}{after}", entry.trim_end_matches('\n'));
+        }
+        if line.starts_with("## ") {
+            let (before, after) = changelog.split_at(offset);
+            return format!("{before}{entry}{after}");
+        }
+        offset += line.len();
+    }
+    format!("{changelog}\n{entry}")
+}
+
+/// Bumps package.version in the spec at `spec_path` and returns the new version,
+/// leaving the rest of the file untouched. The `CHANGELOG.md` template override
+/// replaces the default changelog entry.
+pub fn bump(
+    spec_path: &Path,
+    level: BumpLevel,
+    changelog: Option<&Path>,
+) -> Result<Version, BumpError> {
+    let spec = Spec::from_path(spec_path)?;
+    let version = spec
+        .package
+        .version
+        .as_ref()
+        .ok_or(BumpError::MissingVersion)?;
+    let next = bump_version(version, level);
+    check_version(&spec, &next)?;
+
+    if let Some(changelog_path) = changelog {
+        let spec_directory = spec_path.parent().unwrap_or(Path::new("."));
+        let template_overrides = spec
+            .templates
+            .as_ref()
+            .map(|templates| TemplateOverrides::load(&spec_directory.join(templates)))
+            .transpose()?;
+        let mut project = Project::new(spec.clone(), next.clone());
+        project.template_overrides = template_overrides;
+        let entry =
+            project.render_template("CHANGELOG.md", None, templates::changelog_entry(&next))?;
+        let contents = match fs::read_to_string(changelog_path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => "# Changelog\n\n".to_owned(),
+            Err(err) => return Err(err.into()),
+        };
+        fs::write(changelog_path, insert_changelog_entry(&contents, &entry))?;
+    }
+
+    let mut document: Document = fs::read_to_string(spec_path)?.parse()?;
+    document["package"]["version"] = value(next.to_string());
+    fs::write(spec_path, document.to_string())?;
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path, process::Command};
+
+    use semver::Version;
+
+    use super::{check_version, dev_version, DevVersionSource};
+    use crate::spec::Spec;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args([
+                "-c",
+                "user.name=Example",
+                "-c",
+                "user.email=example@example.com",
+            ])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?}");
+    }
+
+    #[test]
+    fn dev_version_from_git() {
+        let dir = env::temp_dir().join(format!("sqlite-dist-dev-git-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        git(&dir, &["init", "-q"]);
+        git(&dir, &["commit", "-q", "--allow-empty", "-m", "release"]);
+        // a tag with dashes, the commit count is read from the end
+        git(&dir, &["tag", "v1.2.3-beta"]);
+        for _ in 0..2 {
+            git(&dir, &["commit", "-q", "--allow-empty", "-m", "change"]);
+        }
+        let released = Version::new(1, 2, 3);
+        assert_eq!(
+            dev_version(&released, DevVersionSource::Git, &dir).unwrap(),
+            Version::parse("1.2.4-dev.2").unwrap()
+        );
+        // pre-releases keep their version
+        let pre = Version::parse("1.3.0-alpha.1").unwrap();
+        assert_eq!(
+            dev_version(&pre, DevVersionSource::Git, &dir).unwrap(),
+            Version::parse("1.3.0-dev.2").unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dev_version_from_date_and_run_number() {
+        let version = Version::new(0, 1, 0);
+        let dev = dev_version(&version, DevVersionSource::Date, Path::new(".")).unwrap();
+        let (tag, number) = dev.pre.split_once('.').unwrap();
+        assert_eq!((dev.major, dev.minor, dev.patch, tag), (0, 1, 1, "dev"));
+        assert_eq!(number.len(), "YYYYMMDDHHMM".len());
+
+        env::set_var("GITHUB_RUN_NUMBER", "42");
+        let dev = dev_version(&version, DevVersionSource::RunNumber, Path::new("."));
+        env::remove_var("GITHUB_RUN_NUMBER");
+        assert_eq!(dev.unwrap(), Version::parse("0.1.1-dev.42").unwrap());
+    }
+
+    #[test]
+    fn dev_versions_convert_for_every_registry() {
+        let spec: Spec = toml::from_str(
+            r#"
+[package]
+name = "sqlite-sample"
+license = "MIT"
+homepage = "https://example.com"
+repo = "https://github.com/example/sqlite-sample"
+description = "A sample SQLite extension"
+authors = ["Example"]
+
+[targets]
+pip = {}
+gem = { module_name = "SqliteSample" }
+"#,
+        )
+        .unwrap();
+        check_version(&spec, &Version::parse("1.2.4-dev.17").unwrap()).unwrap();
+        assert!(check_version(&spec, &Version::parse("1.2.4-dev.x-1").unwrap()).is_err());
+    }
+}
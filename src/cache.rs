@@ -0,0 +1,454 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    BuildError, Cpu, GeneratedAsset, GeneratedAssetKind, GithubRelease, Os, PlatformFile, Project,
+};
+
+pub(crate) const CACHE_FILE: &str = ".sqlite-dist-cache.json";
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "GeneratedAssetKind")]
+enum GeneratedAssetKindDef {
+    Npm(Option<(Os, Cpu)>),
+    Gem((Os, Cpu)),
+    Pip((Os, Cpu)),
+    PipRename,
+    Datasette,
+    SqliteUtils,
+    GithubReleaseLoadable(GithubRelease),
+    GithubReleaseStatic(GithubRelease),
+    GithubReleaseWasm(GithubRelease),
+    Sqlpkg,
+    Spm,
+    Amalgamation,
+    Static((Os, Cpu)),
+    Xcframework,
+    Cli((Os, Cpu)),
+    Archive((Os, Cpu)),
+    Homebrew(Option<(Os, Cpu)>),
+    Docker,
+    DebugSymbols((Os, Cpu)),
+    Docs,
+    Badges,
+    UpdateApi,
+    ReleaseNotes,
+    Plugin(String),
+    Manifest,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedAsset {
+    #[serde(with = "GeneratedAssetKindDef")]
+    kind: GeneratedAssetKind,
+    name: String,
+    path: String,
+    checksum_sha256: String,
+    size: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    assets: Vec<CachedAsset>,
+}
+
+/// Assets of previous builds in the same output directory, keyed by the hash of
+/// every input of the target that generated them.
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct BuildCache {
+    entries: HashMap<String, CacheEntry>,
+    /// the entries `insert` replaced, for `forget_under`
+    #[serde(skip)]
+    replaced: HashMap<String, CacheEntry>,
+}
+
+impl BuildCache {
+    /// A missing or unreadable cache is treated as empty, which regenerates everything.
+    pub(crate) fn load(output_dir: &Path) -> Self {
+        fs::read(output_dir.join(CACHE_FILE))
+            .ok()
+            .and_then(|contents| serde_json::from_slice(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn save(&self, output_dir: &Path) -> io::Result<()> {
+        fs::File::create(output_dir.join(CACHE_FILE))?
+            .write_all(serde_json::to_string(self)?.as_bytes())
+    }
+
+    /// Rewrites the recorded asset paths, for assets moved after they were generated
+    pub(crate) fn relocate(&mut self, final_path: impl Fn(&str) -> String) {
+        for entry in self.entries.values_mut() {
+            for asset in &mut entry.assets {
+                asset.path = final_path(&asset.path);
+            }
+        }
+    }
+
+    /// Goes back to the previous entry of every target with assets under `dir`, for
+    /// assets that were removed with it
+    pub(crate) fn forget_under(&mut self, dir: &Path) {
+        let forgotten: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| {
+                entry
+                    .assets
+                    .iter()
+                    .any(|asset| Path::new(&asset.path).starts_with(dir))
+            })
+            .map(|(target, _)| target.clone())
+            .collect();
+        for target in forgotten {
+            match self.replaced.remove(&target) {
+                Some(previous) => self.entries.insert(target, previous),
+                None => self.entries.remove(&target),
+            };
+        }
+    }
+
+    fn get(&self, target: &str, key: &str) -> Option<Vec<GeneratedAsset>> {
+        let entry = self.entries.get(target).filter(|entry| entry.key == key)?;
+        let unchanged = entry.assets.iter().all(|asset| {
+            fs::metadata(&asset.path).is_ok_and(|metadata| metadata.len() as usize == asset.size)
+        });
+        if !unchanged {
+            return None;
+        }
+        Some(
+            entry
+                .assets
+                .iter()
+                .map(|asset| GeneratedAsset {
+                    kind: asset.kind.clone(),
+                    name: asset.name.clone(),
+                    path: asset.path.clone(),
+                    checksum_sha256: asset.checksum_sha256.clone(),
+                    size: asset.size,
+                })
+                .collect(),
+        )
+    }
+
+    fn insert(&mut self, target: String, key: String, assets: &[GeneratedAsset]) {
+        let assets = assets
+            .iter()
+            .map(|asset| CachedAsset {
+                kind: asset.kind.clone(),
+                name: asset.name.clone(),
+                path: asset.path.clone(),
+                checksum_sha256: asset.checksum_sha256.clone(),
+                size: asset.size,
+            })
+            .collect();
+        if let Some(previous) = self
+            .entries
+            .insert(target.clone(), CacheEntry { key, assets })
+        {
+            self.replaced.entry(target).or_insert(previous);
+        }
+    }
+}
+
+fn hash_file(hasher: &mut Sha256, file: &PlatformFile) {
+    hasher.update(file.name.as_bytes());
+    match &file.unsigned_sha256 {
+        Some(unsigned_sha256) => hasher.update(unsigned_sha256),
+        None => {
+            hasher.update((file.data.len() as u64).to_le_bytes());
+            hasher.update(&file.data);
+        }
+    }
+}
+
+/// Hash of everything a target reads: every top-level section of the spec, the
+/// target's own config, every input file, template overrides, and files
+/// referenced by the spec.
+fn target_key<T: Serialize>(project: &Project, config: &T) -> Result<String, BuildError> {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update(project.version.to_string().as_bytes());
+    // sections like [sqlite], [verify], [[requires]] or yanked are read by
+    // several targets, only the other targets' own configs are left out
+    let mut shared = serde_json::to_value(&project.spec).map_err(io::Error::from)?;
+    if let Some(sections) = shared.as_object_mut() {
+        sections.remove("targets");
+    }
+    hasher.update(serde_json::to_vec(&shared).map_err(io::Error::from)?);
+    hasher.update(serde_json::to_vec(config).map_err(io::Error::from)?);
+    for platform_dir in &project.platform_directories {
+        hasher.update(platform_dir.os.to_string().as_bytes());
+        hasher.update(platform_dir.cpu.to_string().as_bytes());
+        for loadable in &platform_dir.loadable_files {
+            hash_file(&mut hasher, &loadable.file);
+        }
+        for file in platform_dir
+            .static_files
+            .iter()
+            .chain(&platform_dir.header_files)
+            .chain(&platform_dir.sqlite3)
+            .chain(&platform_dir.extra_files)
+            .chain(&platform_dir.debug_files)
+        {
+            hash_file(&mut hasher, file);
+        }
+    }
+    if let Some(wasm_directory) = &project.wasm_directory {
+        for file in &wasm_directory.files {
+            hash_file(&mut hasher, file);
+        }
+    }
+    if let Some(template_overrides) = &project.template_overrides {
+        for (name, data) in template_overrides.sources()? {
+            hasher.update(name.as_bytes());
+            hasher.update(data);
+        }
+    }
+    let targets = &project.spec.targets;
+    let referenced = targets
+        .pip
+        .iter()
+        .filter_map(|pip| pip.extra_init_py.as_ref())
+        .chain(
+            targets
+                .amalgamation
+                .iter()
+                .flat_map(|a| a.include.iter().chain(&a.readme).chain(&a.license_file)),
+        )
+        .chain(
+            targets
+                .archives
+                .iter()
+                .flat_map(|a| a.readme.iter().chain(&a.license_file)),
+        )
+        .chain(
+            targets
+                .github_releases
+                .iter()
+                .filter_map(|gh| gh.changelog.as_ref()),
+        );
+    for relative_path in referenced {
+        hasher.update(relative_path.as_bytes());
+        hasher.update(fs::read(project.spec_directory.join(relative_path))?);
+    }
+    Ok(base16ct::lower::encode_string(&hasher.finalize()))
+}
+
+/// Reuses the assets of `target` from a previous build when none of its inputs
+/// changed, otherwise runs `generate` and records its assets.
+pub(crate) fn cached<T: Serialize>(
+    cache: Option<&Mutex<BuildCache>>,
+    project: &Project,
+    target: &str,
+    config: &T,
+    generate: impl FnOnce() -> Result<Vec<GeneratedAsset>, BuildError>,
+) -> Result<Vec<GeneratedAsset>, BuildError> {
+    let Some(cache) = cache else {
+        return generate();
+    };
+    let name = format!("{}/{target}", project.spec.package.name);
+    let key = target_key(project, config)?;
+    if let Some(assets) = cache.lock().unwrap().get(&name, &key) {
+        tracing::info!("{name}: up to date");
+        return Ok(assets);
+    }
+    let assets = generate()?;
+    cache.lock().unwrap().insert(name, key, &assets);
+    Ok(assets)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        env, fs,
+        path::{Path, PathBuf},
+        sync::Mutex,
+    };
+
+    use semver::Version;
+
+    use super::{cached, target_key, BuildCache};
+    use crate::{spec::Spec, BuildError, GeneratedAsset, GeneratedAssetKind, Project};
+
+    const SPEC: &str = r#"[package]
+name = "sqlite-sample"
+license = "MIT"
+homepage = "https://example.com"
+repo = "https://github.com/example/sqlite-sample"
+description = "A sample SQLite extension"
+authors = ["Sample Author"]
+
+[targets]
+pip = { extra_init_py = "extra_init.py" }
+npm = {}
+"#;
+
+    /// A temporary spec directory with the spec's `extra_init.py`
+    struct Dir(PathBuf);
+
+    impl Dir {
+        fn new(name: &str) -> Self {
+            let dir =
+                env::temp_dir().join(format!("sqlite-dist-cache-{name}-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("extra_init.py"), "print()\n").unwrap();
+            Self(dir)
+        }
+
+        fn project(&self, spec: &str, version: &str) -> Project {
+            let spec: Spec = toml::from_str(spec).unwrap();
+            Project::new(spec, Version::parse(version).unwrap()).spec_directory(self.0.clone())
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn asset(path: &Path, contents: &str) -> GeneratedAsset {
+        fs::write(path, contents).unwrap();
+        GeneratedAsset {
+            kind: GeneratedAssetKind::Sqlpkg,
+            name: "sqlpkg.json".to_owned(),
+            path: path.to_string_lossy().into_owned(),
+            checksum_sha256: String::new(),
+            size: contents.len(),
+        }
+    }
+
+    fn pip_key(project: &Project) -> String {
+        target_key(project, &project.spec.targets.pip).unwrap()
+    }
+
+    #[test]
+    fn key_changes_with_the_target_inputs() {
+        let dir = Dir::new("key");
+        let project = dir.project(SPEC, "0.1.0");
+        let key = pip_key(&project);
+        assert_eq!(key, pip_key(&dir.project(SPEC, "0.1.0")));
+
+        assert_ne!(key, pip_key(&dir.project(SPEC, "0.1.1")), "version");
+        let shared = format!("{SPEC}\n[sqlite]\nmin_version = \"3.40.0\"\n");
+        assert_ne!(
+            key,
+            pip_key(&dir.project(&shared, "0.1.0")),
+            "shared section"
+        );
+        let config = SPEC.replace(
+            "extra_init_py = \"extra_init.py\"",
+            "extra_init_py = \"extra_init.py\", apsw = true",
+        );
+        assert_ne!(key, pip_key(&dir.project(&config, "0.1.0")), "own config");
+        fs::write(dir.0.join("extra_init.py"), "print(1)\n").unwrap();
+        assert_ne!(key, pip_key(&project), "referenced file");
+        fs::write(dir.0.join("extra_init.py"), "print()\n").unwrap();
+
+        // only the other targets' configs are left out
+        let other_target = SPEC.replace("npm = {}", "npm = { package_name = \"sample\" }");
+        assert_eq!(key, pip_key(&dir.project(&other_target, "0.1.0")));
+    }
+
+    #[test]
+    fn cached_reuses_assets_until_an_input_changes() {
+        let dir = Dir::new("cached");
+        let path = dir.0.join("sqlpkg.json");
+        let generated = Cell::new(0);
+        let build = |cache: Option<&Mutex<BuildCache>>, project: &Project, contents: &str| {
+            cached(cache, project, "sqlpkg", &(), || {
+                generated.set(generated.get() + 1);
+                Ok::<_, BuildError>(vec![asset(&path, contents)])
+            })
+            .unwrap()
+        };
+        let cache = Mutex::new(BuildCache::default());
+        let project = dir.project(SPEC, "0.1.0");
+
+        let first = build(Some(&cache), &project, "{}");
+        let second = build(Some(&cache), &project, "{}");
+        assert_eq!(generated.get(), 1);
+        assert_eq!(second[0].path, first[0].path);
+        assert_eq!(second[0].size, 2);
+
+        build(Some(&cache), &dir.project(SPEC, "0.2.0"), "{}");
+        assert_eq!(generated.get(), 2, "a new version regenerates");
+
+        // an asset changed or removed since it was cached isn't reused
+        fs::write(&path, "{\"changed\": true}").unwrap();
+        build(Some(&cache), &dir.project(SPEC, "0.2.0"), "{}");
+        assert_eq!(generated.get(), 3);
+        fs::remove_file(&path).unwrap();
+        build(Some(&cache), &dir.project(SPEC, "0.2.0"), "{}");
+        assert_eq!(generated.get(), 4);
+
+        build(None, &dir.project(SPEC, "0.2.0"), "{}");
+        assert_eq!(generated.get(), 5, "no cache always generates");
+
+        // the cache survives a save and load
+        cache.into_inner().unwrap().save(&dir.0).unwrap();
+        let cache = Mutex::new(BuildCache::load(&dir.0));
+        build(Some(&cache), &dir.project(SPEC, "0.2.0"), "{}");
+        assert_eq!(generated.get(), 5);
+    }
+
+    #[test]
+    fn relocate_points_at_the_moved_assets() {
+        let dir = Dir::new("relocate");
+        let staging = dir.0.join("staging");
+        fs::create_dir_all(&staging).unwrap();
+        let project = dir.project(SPEC, "0.1.0");
+        let mut cache = BuildCache::default();
+        let key = pip_key(&project);
+        cache.insert(
+            "sqlite-sample/pip".to_owned(),
+            key.clone(),
+            &[asset(&staging.join("sqlpkg.json"), "{}")],
+        );
+        fs::rename(staging.join("sqlpkg.json"), dir.0.join("sqlpkg.json")).unwrap();
+        assert!(cache.get("sqlite-sample/pip", &key).is_none());
+
+        let staging_prefix = staging.to_string_lossy().into_owned();
+        let output_prefix = dir.0.to_string_lossy().into_owned();
+        cache.relocate(|path| path.replacen(&staging_prefix, &output_prefix, 1));
+        let assets = cache.get("sqlite-sample/pip", &key).unwrap();
+        assert_eq!(assets[0].path, dir.0.join("sqlpkg.json").to_string_lossy());
+    }
+
+    #[test]
+    fn forget_under_restores_the_previous_entry() {
+        let dir = Dir::new("forget");
+        let staging = dir.0.join("staging");
+        fs::create_dir_all(&staging).unwrap();
+        let mut cache = BuildCache::default();
+        cache.insert(
+            "sqlite-sample/pip".to_owned(),
+            "old".to_owned(),
+            &[asset(&dir.0.join("old.json"), "{}")],
+        );
+        cache.insert(
+            "sqlite-sample/pip".to_owned(),
+            "new".to_owned(),
+            &[asset(&staging.join("new.json"), "{}")],
+        );
+        cache.insert(
+            "sqlite-sample/npm".to_owned(),
+            "new".to_owned(),
+            &[asset(&staging.join("npm.json"), "{}")],
+        );
+        cache.forget_under(&staging);
+        assert!(cache.get("sqlite-sample/pip", "old").is_some());
+        assert!(cache.get("sqlite-sample/pip", "new").is_none());
+        assert!(cache.get("sqlite-sample/npm", "new").is_none());
+    }
+}
@@ -0,0 +1,65 @@
+use serde::Serialize;
+
+use crate::spec::{SpecCollation, SpecFunction, SqlFunctionKind};
+use crate::Project;
+
+pub(crate) const CAPABILITIES_FILE: &str = "capabilities.json";
+
+/// What the extension registers once loaded, for tools to introspect without
+/// loading it. Embedded as `capabilities.json` next to the loadables of each package.
+#[derive(Serialize)]
+pub(crate) struct Capabilities<'a> {
+    name: &'a str,
+    version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_sqlite_version: Option<String>,
+    /// loadable file stems, ex `["vec0"]`
+    entrypoints: Vec<&'a str>,
+    functions: Vec<&'a SpecFunction>,
+    /// table-valued functions and virtual table modules
+    virtual_tables: Vec<&'a SpecFunction>,
+    collations: &'a [SpecCollation],
+}
+
+impl<'a> Capabilities<'a> {
+    /// None when the spec declares no functions or collations
+    pub(crate) fn new(project: &'a Project) -> Option<Self> {
+        let spec = &project.spec;
+        if spec.functions.is_empty() && spec.collations.is_empty() {
+            return None;
+        }
+        let (virtual_tables, functions) = spec.functions.iter().partition(|function| {
+            matches!(
+                function.kind,
+                SqlFunctionKind::Table | SqlFunctionKind::Module
+            )
+        });
+        Some(Self {
+            name: &spec.package.name,
+            version: project.version.to_string(),
+            min_sqlite_version: spec.min_sqlite_version().map(|v| v.to_string()),
+            entrypoints: project
+                .platform_directories
+                .first()
+                .map(|platform_dir| {
+                    platform_dir
+                        .loadable_files
+                        .iter()
+                        .map(|loadable| loadable.file_stem.as_str())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            functions,
+            virtual_tables,
+            collations: &spec.collations,
+        })
+    }
+}
+
+/// `capabilities.json` of the project, when it declares any capabilities
+pub(crate) fn capabilities_json(project: &Project) -> Option<String> {
+    Capabilities::new(project).map(|capabilities| {
+        serde_json::to_string_pretty(&capabilities)
+            .expect("capabilities should always serialize as JSON")
+    })
+}
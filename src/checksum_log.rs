@@ -0,0 +1,429 @@
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    path::Path,
+    process::{Command, ExitStatus, Stdio},
+};
+
+use semver::Version;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::http;
+use crate::spec::SpecChecksumLog;
+
+pub(crate) const LOG_FILE: &str = "checksums.log";
+
+/// Namespace of the `ssh-keygen -Y sign` signature, verifiers pass the same one
+const SIGNATURE_NAMESPACE: &str = "sqlite-dist-checksums";
+
+#[derive(Error, Debug)]
+pub enum ChecksumLogError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] io::Error),
+    #[error("could not fetch the previous checksum log from {0}: {1}")]
+    Fetch(String, String),
+    #[error("the previous checksum log {0} doesn't exist, pass --init-checksum-log for the first release")]
+    MissingPrevious(String),
+    #[error("the previous checksum log {0} has no {0}.sig signature")]
+    MissingSignature(String),
+    #[error("could not run ssh-keygen to verify the previous checksum log: {0}")]
+    VerifySpawn(io::Error),
+    #[error("the signature of the previous checksum log {0} doesn't verify with the signing key")]
+    BadSignature(String),
+    #[error("the previous checksum log was modified: {0}")]
+    Tampered(String),
+    #[error(
+        "the checksum log already has different checksums for {0}, versions can't be re-released"
+    )]
+    VersionExists(String),
+    #[error("could not run ssh-keygen to sign the checksum log: {0}")]
+    SignSpawn(io::Error),
+    #[error("signing the checksum log failed, ssh-keygen exited with {0}")]
+    SignFailed(ExitStatus),
+}
+
+/// The entries of one release and the hash chaining it to every release before it
+struct LogBlock {
+    version: String,
+    entries: Vec<String>,
+    hash: String,
+}
+
+fn block_hash(previous_hash: &str, entries: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(previous_hash.as_bytes());
+    for entry in entries {
+        hasher.update(entry.as_bytes());
+        hasher.update(b"\n");
+    }
+    base16ct::lower::encode_string(&hasher.finalize())
+}
+
+/// Parses a log, checking every block's hash against the blocks before it
+fn parse_log(contents: &str) -> Result<Vec<LogBlock>, ChecksumLogError> {
+    let mut blocks: Vec<LogBlock> = vec![];
+    let mut entries = vec![];
+    for line in contents.lines().filter(|line| !line.is_empty()) {
+        let Some(footer) = line.strip_prefix("# ") else {
+            entries.push(line.to_owned());
+            continue;
+        };
+        let Some((version, hash)) = footer.split_once(' ') else {
+            return Err(ChecksumLogError::Tampered(format!(
+                "malformed line '{line}'"
+            )));
+        };
+        let previous_hash = blocks.last().map_or("", |block| block.hash.as_str());
+        if block_hash(previous_hash, &entries) != hash {
+            return Err(ChecksumLogError::Tampered(format!(
+                "the hash of {version} doesn't match its entries"
+            )));
+        }
+        blocks.push(LogBlock {
+            version: version.to_owned(),
+            entries: std::mem::take(&mut entries),
+            hash: hash.to_owned(),
+        });
+    }
+    if !entries.is_empty() {
+        return Err(ChecksumLogError::Tampered(
+            "entries after the last release".to_owned(),
+        ));
+    }
+    Ok(blocks)
+}
+
+/// Contents of the previous log or its signature, `None` when it doesn't exist
+fn read_previous(
+    previous: &str,
+    spec_directory: &Path,
+) -> Result<Option<Vec<u8>>, ChecksumLogError> {
+    if previous.starts_with("https://") || previous.starts_with("http://") {
+        let agent = http::agent()
+            .map_err(|err| ChecksumLogError::Fetch(previous.to_owned(), err.to_string()))?;
+        return match http::send(previous, || agent.get(previous), None) {
+            Ok(response) => {
+                let mut contents = vec![];
+                response
+                    .into_reader()
+                    .read_to_end(&mut contents)
+                    .map_err(|err| ChecksumLogError::Fetch(previous.to_owned(), err.to_string()))?;
+                Ok(Some(contents))
+            }
+            Err(err) => match *err {
+                ureq::Error::Status(404, _) => Ok(None),
+                err => Err(ChecksumLogError::Fetch(
+                    previous.to_owned(),
+                    err.to_string(),
+                )),
+            },
+        };
+    }
+    match fs::read(spec_directory.join(previous)) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        contents => Ok(Some(contents?)),
+    }
+}
+
+/// Checks `signature` of the previous log against the public key of the key this
+/// release is signed with, so a replaced log can't be extended unnoticed
+fn verify_previous(
+    previous: &str,
+    log: &[u8],
+    signature: &[u8],
+    signing_key: &Path,
+) -> Result<(), ChecksumLogError> {
+    let output = Command::new("ssh-keygen")
+        .arg("-y")
+        .arg("-f")
+        .arg(signing_key)
+        .output()
+        .map_err(ChecksumLogError::VerifySpawn)?;
+    if !output.status.success() {
+        return Err(ChecksumLogError::VerifySpawn(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        )));
+    }
+    let dir = env::temp_dir().join(format!("sqlite-dist-checksum-log-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let allowed_signers = dir.join("allowed_signers");
+    let signature_path = dir.join(format!("{LOG_FILE}.sig"));
+    fs::write(
+        &allowed_signers,
+        format!(
+            "{SIGNATURE_NAMESPACE} {}",
+            String::from_utf8_lossy(&output.stdout).trim()
+        ),
+    )?;
+    fs::write(&signature_path, signature)?;
+    let verified = Command::new("ssh-keygen")
+        .args([
+            "-Y",
+            "verify",
+            "-I",
+            SIGNATURE_NAMESPACE,
+            "-n",
+            SIGNATURE_NAMESPACE,
+            "-f",
+        ])
+        .arg(&allowed_signers)
+        .arg("-s")
+        .arg(&signature_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .and_then(|mut child| {
+            child.stdin.take().expect("piped stdin").write_all(log)?;
+            child.wait()
+        });
+    let _ = fs::remove_dir_all(&dir);
+    if !verified.map_err(ChecksumLogError::VerifySpawn)?.success() {
+        return Err(ChecksumLogError::BadSignature(previous.to_owned()));
+    }
+    Ok(())
+}
+
+/// Appends the `name sha256` checksums of this release to the previous log, writing
+/// `checksums.log` (and `checksums.log.sig` with a signing key) to the output directory.
+/// Re-running a release with the same checksums leaves the log as it was.
+/// A missing previous log is an error unless `init` starts a new one, and with a
+/// signing key the previous log's signature must verify with it.
+pub(crate) fn write_checksum_log(
+    config: &SpecChecksumLog,
+    spec_directory: &Path,
+    version: &Version,
+    checksums: &[(&str, &str)],
+    output_dir: &Path,
+    init: bool,
+) -> Result<(), ChecksumLogError> {
+    let previous = match &config.previous {
+        Some(previous) => match read_previous(previous, spec_directory)? {
+            Some(contents) => {
+                if let Some(signing_key) = &config.signing_key {
+                    let signature_location = format!("{previous}.sig");
+                    let signature = read_previous(&signature_location, spec_directory)?
+                        .ok_or_else(|| ChecksumLogError::MissingSignature(previous.clone()))?;
+                    verify_previous(
+                        previous,
+                        &contents,
+                        &signature,
+                        &spec_directory.join(signing_key),
+                    )?;
+                }
+                String::from_utf8(contents).map_err(|_| {
+                    ChecksumLogError::Tampered("the log isn't valid UTF-8".to_owned())
+                })?
+            }
+            None if init => String::new(),
+            None => return Err(ChecksumLogError::MissingPrevious(previous.clone())),
+        },
+        None => String::new(),
+    };
+    let blocks = parse_log(&previous)?;
+    let version = version.to_string();
+    let mut entries: Vec<String> = checksums
+        .iter()
+        .map(|(name, checksum)| format!("{version} {name} sha256:{checksum}"))
+        .collect();
+    entries.sort();
+
+    let mut log = previous.trim_end().to_owned();
+    match blocks.iter().find(|block| block.version == version) {
+        Some(block) if block.entries == entries => {}
+        Some(_) => return Err(ChecksumLogError::VersionExists(version)),
+        None => {
+            let previous_hash = blocks.last().map_or("", |block| block.hash.as_str());
+            let hash = block_hash(previous_hash, &entries);
+            if !log.is_empty() {
+                log.push('\n');
+            }
+            log.push_str(&entries.join("\n"));
+            log.push_str(&format!("\n# {version} {hash}"));
+        }
+    }
+    log.push('\n');
+    let log_path = output_dir.join(LOG_FILE);
+    fs::write(&log_path, log)?;
+
+    if let Some(signing_key) = &config.signing_key {
+        let signature_path = output_dir.join(format!("{LOG_FILE}.sig"));
+        if signature_path.exists() {
+            fs::remove_file(&signature_path)?;
+        }
+        let status = Command::new("ssh-keygen")
+            .args(["-Y", "sign", "-n", SIGNATURE_NAMESPACE, "-f"])
+            .arg(spec_directory.join(signing_key))
+            .arg(&log_path)
+            .status()
+            .map_err(ChecksumLogError::SignSpawn)?;
+        if !status.success() {
+            return Err(ChecksumLogError::SignFailed(status));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process::Command};
+
+    use semver::Version;
+
+    use super::{write_checksum_log, ChecksumLogError, LOG_FILE};
+    use crate::spec::SpecChecksumLog;
+
+    /// A spec directory whose previous log is `previous/checksums.log`
+    struct Dir(PathBuf);
+
+    impl Dir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!(
+                "sqlite-dist-checksum-log-{name}-{}",
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("previous")).unwrap();
+            Self(dir)
+        }
+
+        /// Writes the log of `version` into `out`, then publishes it as the previous log
+        fn release(
+            &self,
+            config: &SpecChecksumLog,
+            version: &str,
+            checksums: &[(&str, &str)],
+            init: bool,
+        ) -> Result<String, ChecksumLogError> {
+            let out = self.0.join("out");
+            let _ = fs::remove_dir_all(&out);
+            fs::create_dir_all(&out).unwrap();
+            write_checksum_log(
+                config,
+                &self.0,
+                &Version::parse(version).unwrap(),
+                checksums,
+                &out,
+                init,
+            )?;
+            for file in [LOG_FILE.to_owned(), format!("{LOG_FILE}.sig")] {
+                if out.join(&file).exists() {
+                    fs::copy(out.join(&file), self.0.join("previous").join(&file)).unwrap();
+                }
+            }
+            Ok(fs::read_to_string(out.join(LOG_FILE)).unwrap())
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn config(signing_key: Option<&str>) -> SpecChecksumLog {
+        SpecChecksumLog {
+            previous: Some("previous/checksums.log".to_owned()),
+            signing_key: signing_key.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn releases_are_appended_and_chained() {
+        let dir = Dir::new("chain");
+        let config = config(None);
+        assert!(matches!(
+            dir.release(&config, "0.1.0", &[("a.tar.gz", "aa")], false),
+            Err(ChecksumLogError::MissingPrevious(_))
+        ));
+        let first = dir
+            .release(
+                &config,
+                "0.1.0",
+                &[("b.tar.gz", "bb"), ("a.tar.gz", "aa")],
+                true,
+            )
+            .unwrap();
+        let lines: Vec<&str> = first.lines().collect();
+        assert_eq!(
+            lines[..2],
+            ["0.1.0 a.tar.gz sha256:aa", "0.1.0 b.tar.gz sha256:bb"]
+        );
+        assert!(lines[2].starts_with("# 0.1.0 "));
+
+        let second = dir
+            .release(&config, "0.2.0", &[("a.tar.gz", "cc")], false)
+            .unwrap();
+        assert!(second.starts_with(&first), "{second}");
+        assert!(second.lines().last().unwrap().starts_with("# 0.2.0 "));
+
+        // re-running a release changes nothing, new checksums for it are refused
+        let rerun = dir
+            .release(&config, "0.2.0", &[("a.tar.gz", "cc")], false)
+            .unwrap();
+        assert_eq!(rerun, second);
+        assert!(matches!(
+            dir.release(&config, "0.2.0", &[("a.tar.gz", "dd")], false),
+            Err(ChecksumLogError::VersionExists(_))
+        ));
+    }
+
+    #[test]
+    fn modified_previous_log_is_refused() {
+        let dir = Dir::new("tampered");
+        let config = config(None);
+        let log = dir
+            .release(&config, "0.1.0", &[("a.tar.gz", "aa")], true)
+            .unwrap();
+        fs::write(
+            dir.0.join("previous").join(LOG_FILE),
+            log.replace("sha256:aa", "sha256:ee"),
+        )
+        .unwrap();
+        assert!(matches!(
+            dir.release(&config, "0.2.0", &[("a.tar.gz", "cc")], false),
+            Err(ChecksumLogError::Tampered(_))
+        ));
+    }
+
+    /// Signs with a throwaway ed25519 key. Skipped when ssh-keygen isn't installed.
+    #[test]
+    fn signed_log_must_verify() {
+        let dir = Dir::new("signed");
+        let Ok(status) = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", "", "-f", "key"])
+            .current_dir(&dir.0)
+            .status()
+        else {
+            return;
+        };
+        assert!(status.success());
+        let config = config(Some("key"));
+        dir.release(&config, "0.1.0", &[("a.tar.gz", "aa")], true)
+            .unwrap();
+        assert!(dir.0.join("previous/checksums.log.sig").exists());
+        dir.release(&config, "0.2.0", &[("a.tar.gz", "bb")], false)
+            .unwrap();
+
+        // a log signed by another key doesn't verify
+        fs::rename(dir.0.join("key"), dir.0.join("old-key")).unwrap();
+        fs::remove_file(dir.0.join("key.pub")).unwrap();
+        let status = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", "", "-f", "key"])
+            .current_dir(&dir.0)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(matches!(
+            dir.release(&config, "0.3.0", &[("a.tar.gz", "cc")], false),
+            Err(ChecksumLogError::BadSignature(_))
+        ));
+
+        fs::remove_file(dir.0.join("previous/checksums.log.sig")).unwrap();
+        assert!(matches!(
+            dir.release(&config, "0.3.0", &[("a.tar.gz", "cc")], false),
+            Err(ChecksumLogError::MissingSignature(_))
+        ));
+    }
+}
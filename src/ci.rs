@@ -0,0 +1,615 @@
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::spec::{Spec, SpecError};
+use crate::{Cpu, Os};
+
+#[derive(Error, Debug)]
+pub enum CiError {
+    #[error("{0}")]
+    InvalidSpec(#[from] SpecError),
+    #[error("unknown platform '{0}', must be one of {1}")]
+    UnknownPlatform(String, String),
+}
+
+/// CI services a release pipeline can be generated for
+#[derive(Debug, Clone, Copy)]
+pub enum CiProvider {
+    Github,
+    Gitlab,
+    Circleci,
+    Buildkite,
+}
+
+/// Platforms built by default, every one of them has a hosted runner
+const DEFAULT_PLATFORMS: [(Os, Cpu); 5] = [
+    (Os::Linux, Cpu::X86_64),
+    (Os::Linux, Cpu::Aarch64),
+    (Os::Macos, Cpu::X86_64),
+    (Os::Macos, Cpu::Aarch64),
+    (Os::Windows, Cpu::X86_64),
+];
+
+/// Platforms with a hosted runner that are only built when asked for
+const OTHER_PLATFORMS: [(Os, Cpu); 4] = [
+    (Os::Linux, Cpu::Armv7a),
+    (Os::Linux, Cpu::I686),
+    (Os::Windows, Cpu::Aarch64),
+    (Os::Windows, Cpu::I686),
+];
+
+fn platform_name((os, cpu): &(Os, Cpu)) -> String {
+    format!("{}-{}", os.to_string(), cpu.to_string())
+}
+
+pub struct CiOptions {
+    pub spec_path: PathBuf,
+    /// shell command compiling the loadables, ran on every platform
+    pub build_command: String,
+    /// where `build_command` leaves the loadables, a path or glob relative to the repo root
+    pub loadables: String,
+    /// `$OS-$CPU` names, defaults to linux, macos, and windows on x86_64 plus linux and macos on aarch64
+    pub platforms: Option<Vec<String>>,
+}
+
+/// A platform's build job
+pub(crate) struct BuildJob {
+    pub(crate) os: Os,
+    pub(crate) cpu: Cpu,
+}
+
+impl BuildJob {
+    /// Name of the platform directory and of the artifact carrying its loadables
+    pub(crate) fn name(&self) -> String {
+        format!("{}-{}", self.os.to_string(), self.cpu.to_string())
+    }
+}
+
+/// Commands of a release job publishing some of the generated assets
+pub(crate) struct PublishJob {
+    pub(crate) name: &'static str,
+    /// debian packages the commands need, preinstalled on GitHub's runners
+    pub(crate) tools: &'static [&'static str],
+    /// environment variable -> name of the CI secret it's read from
+    pub(crate) secrets: Vec<(&'static str, &'static str)>,
+    pub(crate) commands: Vec<String>,
+}
+
+/// Everything a release pipeline does, independent of the CI service it runs on.
+/// Builds the loadables on every platform, runs sqlite-dist on them, then publishes.
+pub(crate) struct Pipeline {
+    pub(crate) package: String,
+    pub(crate) spec_path: String,
+    pub(crate) build_command: String,
+    pub(crate) loadables: String,
+    /// glob of the git tags that trigger a release, ex `v*`
+    pub(crate) tag_pattern: String,
+    /// prefix stripped from the tag to get the version, ex `v`
+    pub(crate) tag_prefix: String,
+    pub(crate) builds: Vec<BuildJob>,
+    pub(crate) publishes: Vec<PublishJob>,
+}
+
+fn parse_platform(name: &str) -> Result<(Os, Cpu), CiError> {
+    let platforms = DEFAULT_PLATFORMS.iter().chain(&OTHER_PLATFORMS);
+    platforms
+        .clone()
+        .find(|platform| platform_name(platform) == name)
+        .cloned()
+        .ok_or_else(|| {
+            CiError::UnknownPlatform(
+                name.to_owned(),
+                platforms
+                    .map(platform_name)
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            )
+        })
+}
+
+/// Shell commands releasing whatever the spec's targets generate into `dist/`
+fn publish_jobs(spec: &Spec) -> Vec<PublishJob> {
+    let targets = &spec.targets;
+    let mut jobs = vec![];
+    if targets.github_releases.is_some() {
+        let dirs = [
+            ("github_releases", true),
+            ("sqlpkg", targets.sqlpkg.is_some()),
+            ("spm", targets.spm.is_some()),
+            ("amalgamation", targets.amalgamation.is_some()),
+            ("static", targets.static_.is_some()),
+            ("xcframework", targets.xcframework.is_some()),
+            ("cli", targets.cli.is_some()),
+            ("archives", targets.archives.is_some()),
+            ("homebrew", targets.homebrew.is_some()),
+            ("docker", targets.docker.is_some()),
+            ("debug-symbols", targets.debug_symbols.is_some()),
+            ("badges", targets.badges.is_some()),
+            ("update-api", targets.update_api.is_some()),
+        ]
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(dir, _)| format!("dist/{dir}/*"))
+        .collect::<Vec<String>>()
+        .join(" ");
+        jobs.push(PublishJob {
+            name: "github-release",
+            tools: &["gh"],
+            secrets: vec![("GH_TOKEN", "GITHUB_TOKEN")],
+            commands: vec![
+                r#"gh release view "$TAG" > /dev/null 2>&1 || gh release create "$TAG" --verify-tag --title "$TAG""#.to_owned(),
+                // hidden files are sqlite-dist's own state, ex the build cache
+                format!(r#"gh release upload "$TAG" $(find dist -maxdepth 1 -type f ! -name '.*') {dirs} --clobber"#),
+            ],
+        });
+    }
+    if targets.pip.is_some() {
+        jobs.push(PublishJob {
+            name: "pypi",
+            tools: &[],
+            secrets: vec![("PYPI_TOKEN", "PYPI_API_TOKEN")],
+            commands: vec!["sqlite-dist publish pypi dist".to_owned()],
+        });
+    }
+    if targets.npm.is_some() {
+        jobs.push(PublishJob {
+            name: "npm",
+            tools: &["npm"],
+            secrets: vec![("NPM_TOKEN", "NPM_TOKEN")],
+            commands: vec!["sqlite-dist publish npm dist".to_owned()],
+        });
+    }
+    if targets.gem.is_some() {
+        jobs.push(PublishJob {
+            name: "rubygems",
+            tools: &[],
+            secrets: vec![("GEM_HOST_API_KEY", "RUBYGEMS_API_KEY")],
+            commands: vec!["sqlite-dist publish rubygems dist".to_owned()],
+        });
+    }
+    jobs
+}
+
+impl Pipeline {
+    pub(crate) fn new(options: &CiOptions) -> Result<Self, CiError> {
+        let spec = Spec::from_path(&options.spec_path)?;
+        let builds = match &options.platforms {
+            Some(platforms) => platforms
+                .iter()
+                .map(|name| parse_platform(name))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => DEFAULT_PLATFORMS.to_vec(),
+        }
+        .into_iter()
+        .map(|(os, cpu)| BuildJob { os, cpu })
+        .collect();
+        let tag_prefix = spec
+            .package
+            .git_tag_format
+            .as_deref()
+            .and_then(|format| format.split_once("$VERSION"))
+            .map_or("", |(prefix, _)| prefix)
+            .to_owned();
+        Ok(Self {
+            package: spec.package.name.clone(),
+            spec_path: options.spec_path.to_string_lossy().into_owned(),
+            build_command: options.build_command.clone(),
+            loadables: options.loadables.clone(),
+            tag_pattern: format!("{tag_prefix}*"),
+            tag_prefix,
+            builds,
+            publishes: publish_jobs(&spec),
+        })
+    }
+
+    /// `sqlite-dist` invocation packaging the downloaded loadables in `input/` into `dist/`
+    pub(crate) fn dist_command(&self) -> String {
+        let spec_path = &self.spec_path;
+        let tag_prefix = &self.tag_prefix;
+        format!(
+            r#"sqlite-dist {spec_path} --input input --output dist --layout by-target --version "${{TAG#{tag_prefix}}}""#
+        )
+    }
+
+    pub(crate) fn install_command(&self) -> String {
+        format!(
+            "cargo install sqlite-dist --version {}",
+            env!("CARGO_PKG_VERSION")
+        )
+    }
+
+    /// Regex matching the release tags, for services filtering tags with one
+    pub(crate) fn tag_regex(&self) -> String {
+        let prefix: String = self
+            .tag_prefix
+            .chars()
+            .flat_map(|c| {
+                let escape = !c.is_alphanumeric() && !matches!(c, '-' | '_');
+                escape.then_some('\\').into_iter().chain([c])
+            })
+            .collect();
+        format!("/^{prefix}/")
+    }
+
+    /// Shell commands copying a build's loadables to its platform directory under `input/`,
+    /// for services whose artifacts are restored at the path they were saved from
+    pub(crate) fn collect_command(&self, build: &BuildJob) -> String {
+        let platform = build.name();
+        let loadables = &self.loadables;
+        format!("mkdir -p input/{platform} && cp {loadables} input/{platform}/")
+    }
+
+    /// Shell script of the release job on services without preinstalled tools,
+    /// starting from a rust image with the loadables in `input/`
+    pub(crate) fn dist_script(&self, tag_variable: &str) -> Vec<String> {
+        let mut tools: Vec<&str> = self
+            .publishes
+            .iter()
+            .flat_map(|publish| publish.tools)
+            .copied()
+            .collect();
+        tools.sort_unstable();
+        tools.dedup();
+        let mut script = vec![format!(r#"export TAG="${tag_variable}""#)];
+        if !tools.is_empty() {
+            script.push(format!(
+                "apt-get update && apt-get install -y --no-install-recommends {}",
+                tools.join(" ")
+            ));
+        }
+        script.push(self.install_command());
+        script.push(self.dist_command());
+        for publish in &self.publishes {
+            for (var, secret) in &publish.secrets {
+                script.push(format!(r#"export {var}="${secret}""#));
+            }
+            script.extend(publish.commands.iter().cloned());
+        }
+        script
+    }
+}
+
+/// Where a provider's pipeline conventionally lives in the repo
+pub fn default_path(provider: CiProvider) -> &'static Path {
+    match provider {
+        CiProvider::Github => Path::new(".github/workflows/release.yml"),
+        CiProvider::Gitlab => Path::new(".gitlab-ci.yml"),
+        CiProvider::Circleci => Path::new(".circleci/config.yml"),
+        CiProvider::Buildkite => Path::new(".buildkite/pipeline.yml"),
+    }
+}
+
+/// Renders the release pipeline of the spec for `provider`
+pub fn generate_ci(provider: CiProvider, options: &CiOptions) -> Result<String, CiError> {
+    let pipeline = Pipeline::new(options)?;
+    Ok(match provider {
+        CiProvider::Github => templates::github(&pipeline),
+        CiProvider::Gitlab => templates::gitlab(&pipeline),
+        CiProvider::Circleci => templates::circleci(&pipeline),
+        CiProvider::Buildkite => templates::buildkite(&pipeline),
+    })
+}
+
+mod templates {
+    use super::Pipeline;
+    use crate::{Cpu, Os};
+
+    fn github_runner(os: &Os, cpu: &Cpu) -> &'static str {
+        match (os, cpu) {
+            (Os::Linux, Cpu::Aarch64) | (Os::Linux, Cpu::Armv7a) => "ubuntu-22.04-arm",
+            (Os::Macos, Cpu::X86_64) => "macos-13",
+            (Os::Macos, _) => "macos-14",
+            (Os::Windows, Cpu::Aarch64) => "windows-11-arm",
+            (Os::Windows, _) => "windows-2022",
+            _ => "ubuntu-22.04",
+        }
+    }
+
+    /// Tags of GitLab's hosted runners
+    fn gitlab_runner(os: &Os, cpu: &Cpu) -> &'static str {
+        match (os, cpu) {
+            (Os::Linux, Cpu::Aarch64) | (Os::Linux, Cpu::Armv7a) => "saas-linux-small-arm64",
+            (Os::Macos, _) => "saas-macos-medium-m1",
+            (Os::Windows, _) => "saas-windows-medium-amd64",
+            _ => "saas-linux-small-amd64",
+        }
+    }
+
+    /// Executor and resource class of CircleCI's machine images
+    fn circleci_executor(os: &Os, cpu: &Cpu) -> &'static str {
+        match (os, cpu) {
+            (Os::Linux, Cpu::Aarch64) | (Os::Linux, Cpu::Armv7a) => {
+                "machine:\n      image: ubuntu-2204:current\n    resource_class: arm.medium"
+            }
+            (Os::Macos, _) => "macos:\n      xcode: 15.3.0\n    resource_class: macos.m1.medium.gen1",
+            (Os::Windows, _) => "machine:\n      image: windows-server-2022-gui:current\n      shell: bash.exe\n    resource_class: windows.medium",
+            _ => "machine:\n      image: ubuntu-2204:current\n    resource_class: medium",
+        }
+    }
+
+    /// `os` and `arch` agent tags, Buildkite agents are self-hosted
+    fn buildkite_agent(os: &Os, cpu: &Cpu) -> (&'static str, &'static str) {
+        let os = match os {
+            Os::Macos => "darwin",
+            Os::Windows => "windows",
+            _ => "linux",
+        };
+        let arch = match cpu {
+            Cpu::Aarch64 => "arm64",
+            Cpu::Armv7a => "arm",
+            Cpu::I686 => "386",
+            _ => "amd64",
+        };
+        (os, arch)
+    }
+
+    /// Indents every line of a (possibly multi-line) command for a `run: |` block
+    fn run_block(commands: &[String], indent: &str) -> String {
+        commands
+            .iter()
+            .map(|command| format!("{indent}{command}"))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub(crate) fn github(pipeline: &Pipeline) -> String {
+        let tag_pattern = &pipeline.tag_pattern;
+        let build_command = &pipeline.build_command;
+        let loadables = &pipeline.loadables;
+        let install_command = pipeline.install_command();
+        let dist_command = pipeline.dist_command();
+        let package = &pipeline.package;
+        let matrix = pipeline
+            .builds
+            .iter()
+            .map(|build| {
+                format!(
+                    "          - platform: {}\n            runner: {}",
+                    build.name(),
+                    github_runner(&build.os, &build.cpu)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        let publishes = pipeline
+            .publishes
+            .iter()
+            .map(|publish| {
+                let name = publish.name;
+                let env = publish
+                    .secrets
+                    .iter()
+                    .map(|(var, secret)| format!("          {var}: ${{{{ secrets.{secret} }}}}"))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                let run = run_block(&publish.commands, "          ");
+                format!(
+                    r#"      - name: Publish to {name}
+        env:
+{env}
+          TAG: ${{{{ github.ref_name }}}}
+        run: |
+{run}"#
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!(
+            r#"# Generated by `sqlite-dist generate-ci github` for {package}
+name: release
+on:
+  push:
+    tags:
+      - "{tag_pattern}"
+permissions:
+  contents: write
+jobs:
+  build:
+    strategy:
+      matrix:
+        include:
+{matrix}
+    runs-on: ${{{{ matrix.runner }}}}
+    defaults:
+      run:
+        shell: bash
+    steps:
+      - uses: actions/checkout@v4
+      - name: Build loadables
+        run: {build_command}
+      - uses: actions/upload-artifact@v4
+        with:
+          name: ${{{{ matrix.platform }}}}
+          path: {loadables}
+  dist:
+    needs: build
+    runs-on: ubuntu-22.04
+    steps:
+      - uses: actions/checkout@v4
+      - uses: actions/download-artifact@v4
+        with:
+          path: input
+      - name: Install sqlite-dist
+        run: {install_command}
+      - name: Package
+        env:
+          TAG: ${{{{ github.ref_name }}}}
+        run: {dist_command}
+{publishes}
+"#
+        )
+    }
+
+    pub(crate) fn gitlab(pipeline: &Pipeline) -> String {
+        let package = &pipeline.package;
+        let tag_regex = pipeline.tag_regex();
+        let build_command = &pipeline.build_command;
+        let builds = pipeline
+            .builds
+            .iter()
+            .map(|build| {
+                let platform = build.name();
+                let runner = gitlab_runner(&build.os, &build.cpu);
+                let collect = match build.os {
+                    Os::Windows => format!(
+                        "New-Item -ItemType Directory -Force input/{platform}; Copy-Item {} input/{platform}/",
+                        pipeline.loadables
+                    ),
+                    _ => pipeline.collect_command(build),
+                };
+                format!(
+                    r#"build-{platform}:
+  stage: build
+  tags: [{runner}]
+  rules: *release
+  script:
+    - {build_command}
+    - {collect}
+  artifacts:
+    paths:
+      - input/{platform}/
+"#
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        let script = run_block(&pipeline.dist_script("CI_COMMIT_TAG"), "    - ");
+        format!(
+            r#"# Generated by `sqlite-dist generate-ci gitlab` for {package}
+stages:
+  - build
+  - dist
+
+.release: &release
+  - if: $CI_COMMIT_TAG =~ {tag_regex}
+
+{builds}
+dist:
+  stage: dist
+  image: rust:1-bookworm
+  rules: *release
+  script:
+{script}
+"#
+        )
+    }
+
+    pub(crate) fn circleci(pipeline: &Pipeline) -> String {
+        let package = &pipeline.package;
+        let tag_regex = pipeline.tag_regex();
+        let build_command = &pipeline.build_command;
+        let jobs = pipeline
+            .builds
+            .iter()
+            .map(|build| {
+                let platform = build.name();
+                let executor = circleci_executor(&build.os, &build.cpu);
+                let collect = pipeline.collect_command(build);
+                format!(
+                    r#"  build-{platform}:
+    {executor}
+    steps:
+      - checkout
+      - run: {build_command}
+      - run: {collect}
+      - persist_to_workspace:
+          root: .
+          paths:
+            - input/{platform}
+"#
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        // jobs only run for tags when they filter on them, and every job has to
+        let filters = format!(
+            "          filters:\n            tags:\n              only: {tag_regex}\n            branches:\n              ignore: /.*/"
+        );
+        let workflow_jobs = pipeline
+            .builds
+            .iter()
+            .map(|build| format!("      - build-{}:\n{filters}", build.name()))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let requires = pipeline
+            .builds
+            .iter()
+            .map(|build| format!("            - build-{}", build.name()))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let script = run_block(&pipeline.dist_script("CIRCLE_TAG"), "            ");
+        format!(
+            r#"# Generated by `sqlite-dist generate-ci circleci` for {package}
+version: 2.1
+jobs:
+{jobs}
+  dist:
+    docker:
+      - image: rust:1-bookworm
+    steps:
+      - checkout
+      - attach_workspace:
+          at: .
+      - run:
+          name: Package and publish
+          command: |
+{script}
+workflows:
+  release:
+    jobs:
+{workflow_jobs}
+      - dist:
+{filters}
+          requires:
+{requires}
+"#
+        )
+    }
+
+    pub(crate) fn buildkite(pipeline: &Pipeline) -> String {
+        let package = &pipeline.package;
+        let tag_regex = pipeline.tag_regex();
+        let build_command = &pipeline.build_command;
+        let builds = pipeline
+            .builds
+            .iter()
+            .map(|build| {
+                let platform = build.name();
+                let (os, arch) = buildkite_agent(&build.os, &build.cpu);
+                let collect = pipeline.collect_command(build);
+                format!(
+                    r#"  - label: "build {platform}"
+    if: build.tag =~ {tag_regex}
+    agents:
+      os: {os}
+      arch: {arch}
+    commands:
+      - {build_command}
+      - {collect}
+    artifact_paths: "input/{platform}/*"
+"#
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        let script = run_block(&pipeline.dist_script("BUILDKITE_TAG"), "      - ");
+        format!(
+            r#"# Generated by `sqlite-dist generate-ci buildkite` for {package}
+steps:
+{builds}
+  - wait
+
+  - label: "dist"
+    if: build.tag =~ {tag_regex}
+    plugins:
+      - docker#v5.11.0:
+          image: rust:1-bookworm
+          propagate-environment: true
+    commands:
+      - buildkite-agent artifact download "input/*" .
+{script}
+"#
+        )
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::Result;
+use std::path::Path;
+
+use crate::spec::TargetCli;
+use crate::{
+    create_targz, create_zip, GeneratedAsset, GeneratedAssetKind, Os, PlatformDirectory,
+    PlatformFile, Project,
+};
+
+/// The loadables, the platform's `sqlite3` shell if there is one, and a wrapper
+/// script starting it with every loadable already loaded.
+fn cli_archive_files(
+    project: &Project,
+    platform_dir: &PlatformDirectory,
+    command_name: &str,
+) -> Result<Vec<PlatformFile>> {
+    let mut files: Vec<PlatformFile> = platform_dir
+        .loadable_files
+        .iter()
+        .map(|loadable| loadable.file.clone())
+        .collect();
+    files.extend(platform_dir.sqlite3.clone());
+    files.extend(platform_dir.extra_files.iter().cloned());
+    let file_stems: Vec<&str> = platform_dir
+        .loadable_files
+        .iter()
+        .map(|loadable| loadable.file_stem.as_str())
+        .collect();
+    let platform = Some((&platform_dir.os, &platform_dir.cpu));
+    let wrapper = match platform_dir.os {
+        Os::Windows => PlatformFile::new(
+            format!("{command_name}.cmd"),
+            project.render_template(
+                "cli/wrapper.cmd",
+                platform,
+                templates::wrapper_cmd(&file_stems, &project.spec.init_functions(&file_stems)),
+            )?,
+            None,
+        ),
+        _ => PlatformFile::new(
+            command_name,
+            project.render_template(
+                "cli/wrapper.sh",
+                platform,
+                templates::wrapper_sh(&file_stems, &project.spec.init_functions(&file_stems)),
+            )?,
+            None,
+        ),
+    };
+    files.push(wrapper);
+    Ok(files)
+}
+
+pub(crate) fn write_cli_bundles(
+    project: &Project,
+    cli_dir: &Path,
+    config: &TargetCli,
+) -> Result<Vec<GeneratedAsset>> {
+    let command_name = config
+        .command_name
+        .as_deref()
+        .unwrap_or(&project.spec.package.name);
+    let mut assets = vec![];
+    for platform_dir in &project.platform_directories {
+        let _span = platform_dir.span().entered();
+        let files = cli_archive_files(project, platform_dir, command_name)?;
+        let files = files.iter().collect::<Vec<&PlatformFile>>();
+        let base_name = format!(
+            "{}-{}-cli-{}-{}",
+            project.spec.package.name,
+            project.version,
+            platform_dir.os.to_string(),
+            platform_dir.cpu.to_string()
+        );
+        let (name, data) = match platform_dir.os {
+            Os::Windows => (format!("{base_name}.zip"), create_zip(&files)?),
+            _ => (format!("{base_name}.tar.gz"), create_targz(&files)?),
+        };
+        assets.push(GeneratedAsset::from(
+            GeneratedAssetKind::Cli((platform_dir.os.clone(), platform_dir.cpu.clone())),
+            &cli_dir.join(name),
+            &data,
+        )?);
+    }
+    Ok(assets)
+}
+
+mod templates {
+    /// ` init_function` after the `.load` of `stem`, when it declares one
+    fn init_function_arg(stem: &str, init_functions: &[(&str, &str)]) -> String {
+        init_functions
+            .iter()
+            .find(|(file_stem, _)| *file_stem == stem)
+            .map(|(_, init_function)| format!(" {init_function}"))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn wrapper_sh(file_stems: &[&str], init_functions: &[(&str, &str)]) -> String {
+        let loads = file_stems
+            .iter()
+            .map(|stem| {
+                let init_function = init_function_arg(stem, init_functions);
+                format!(r#"-cmd ".load '$dir/{stem}'{init_function}""#)
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+        format!(
+            r#"#!/bin/sh
+dir="$(cd "$(dirname "$0")" && pwd)"
+if [ -x "$dir/sqlite3" ]; then
+  sqlite3="$dir/sqlite3"
+else
+  sqlite3=sqlite3
+fi
+exec "$sqlite3" {loads} "$@"
+"#
+        )
+    }
+
+    pub(crate) fn wrapper_cmd(file_stems: &[&str], init_functions: &[(&str, &str)]) -> String {
+        let loads = file_stems
+            .iter()
+            .map(|stem| {
+                let init_function = init_function_arg(stem, init_functions);
+                format!(r#"-cmd ".load '%dir%{stem}'{init_function}""#)
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+        format!(
+            "@echo off\r\n\
+set \"dir=%~dp0\"\r\n\
+if exist \"%dir%sqlite3.exe\" (set \"sqlite3=%dir%sqlite3.exe\") else (set \"sqlite3=sqlite3\")\r\n\
+\"%sqlite3%\" {loads} %*\r\n"
+        )
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::Result;
+use std::path::Path;
+
+use crate::layout::release_file_name;
+use crate::{create_targz, GeneratedAsset, GeneratedAssetKind, PlatformFile, Project};
+
+/// One `*-debug-symbols-$OS-$CPU.tar.gz` per platform with debug info: the `.dSYM`
+/// bundles and `.pdb` files from the input directories, and the `.debug` copies of
+/// loadables stripped by `[loadables] strip`.
+pub(crate) fn write_debug_symbols(
+    project: &Project,
+    debug_symbols_dir: &Path,
+) -> Result<Vec<GeneratedAsset>> {
+    let mut assets = vec![];
+    for platform_dir in &project.platform_directories {
+        if platform_dir.debug_files.is_empty() {
+            continue;
+        }
+        let _span = platform_dir.span().entered();
+        let data = create_targz(
+            &platform_dir
+                .debug_files
+                .iter()
+                .collect::<Vec<&PlatformFile>>(),
+        )?;
+        let name = release_file_name(
+            project,
+            "debug-symbols",
+            &platform_dir.os,
+            &platform_dir.cpu,
+            "tar.gz",
+        )
+        .unwrap_or_else(|| {
+            format!(
+                "{}-{}-debug-symbols-{}-{}.tar.gz",
+                project.spec.package.name,
+                project.version,
+                platform_dir.os.to_string(),
+                platform_dir.cpu.to_string()
+            )
+        });
+        assets.push(GeneratedAsset::from(
+            GeneratedAssetKind::DebugSymbols((platform_dir.os.clone(), platform_dir.cpu.clone())),
+            &debug_symbols_dir.join(name),
+            &data,
+        )?);
+    }
+    if assets.is_empty() {
+        tracing::warn!(
+            "no debug symbols to release, add .dSYM or .pdb files to the platform directories or set [loadables] strip"
+        );
+    }
+    Ok(assets)
+}
@@ -0,0 +1,226 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::manifest::{fetch_manifest_file, read_manifest_file, ManifestFile};
+
+/// What changed between the manifests of two releases
+pub struct ManifestDiff {
+    pub added_platforms: Vec<String>,
+    pub removed_platforms: Vec<String>,
+    pub added_assets: Vec<String>,
+    pub removed_assets: Vec<String>,
+    /// asset, old size, new size
+    pub size_changes: Vec<(String, u64, u64)>,
+    /// ecosystem, added dependencies, removed dependencies
+    pub dependency_changes: Vec<(String, Vec<String>, Vec<String>)>,
+}
+
+impl ManifestDiff {
+    /// Whether the new release dropped a platform or asset the old one had
+    pub fn has_removals(&self) -> bool {
+        !self.removed_platforms.is_empty() || !self.removed_assets.is_empty()
+    }
+}
+
+fn platforms(manifest: &ManifestFile) -> BTreeSet<String> {
+    manifest
+        .artifacts
+        .iter()
+        .filter_map(|artifact| artifact.platform.clone())
+        .collect()
+}
+
+/// Asset name -> size, with the version replaced so the assets of both releases line up
+fn assets(manifest: &ManifestFile) -> BTreeMap<String, u64> {
+    manifest
+        .artifacts
+        .iter()
+        .map(|artifact| {
+            let name = match &manifest.version {
+                Some(version) => artifact.name.replace(version, "{version}"),
+                None => artifact.name.clone(),
+            };
+            (name, artifact.size)
+        })
+        .collect()
+}
+
+/// Compares the manifests of two builds, each a `sqlite-dist-manifest.json` or the
+/// output directory containing it
+pub fn diff_manifests(old: &Path, new: &Path) -> io::Result<ManifestDiff> {
+    let old = read_manifest_file(old)?;
+    let new = read_manifest_file(new)?;
+
+    let (old_platforms, new_platforms) = (platforms(&old), platforms(&new));
+    let (old_assets, new_assets) = (assets(&old), assets(&new));
+    let size_changes = old_assets
+        .iter()
+        .filter_map(|(name, old_size)| {
+            let new_size = new_assets.get(name)?;
+            (new_size != old_size).then(|| (name.clone(), *old_size, *new_size))
+        })
+        .collect();
+
+    let ecosystems: BTreeSet<&String> = old
+        .dependencies
+        .keys()
+        .chain(new.dependencies.keys())
+        .collect();
+    let dependency_changes = ecosystems
+        .into_iter()
+        .filter_map(|ecosystem| {
+            let old = old.dependencies.get(ecosystem).cloned().unwrap_or_default();
+            let new = new.dependencies.get(ecosystem).cloned().unwrap_or_default();
+            let added: Vec<String> = new.iter().filter(|d| !old.contains(d)).cloned().collect();
+            let removed: Vec<String> = old.iter().filter(|d| !new.contains(d)).cloned().collect();
+            (!added.is_empty() || !removed.is_empty()).then(|| (ecosystem.clone(), added, removed))
+        })
+        .collect();
+
+    Ok(ManifestDiff {
+        added_platforms: new_platforms.difference(&old_platforms).cloned().collect(),
+        removed_platforms: old_platforms.difference(&new_platforms).cloned().collect(),
+        added_assets: new_assets
+            .keys()
+            .filter(|name| !old_assets.contains_key(*name))
+            .cloned()
+            .collect(),
+        removed_assets: old_assets
+            .keys()
+            .filter(|name| !new_assets.contains_key(*name))
+            .cloned()
+            .collect(),
+        size_changes,
+        dependency_changes,
+    })
+}
+
+impl fmt::Display for ManifestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut unchanged = true;
+        for (title, items, sign) in [
+            ("Platforms added", &self.added_platforms, '+'),
+            ("Platforms removed", &self.removed_platforms, '-'),
+            ("Assets added", &self.added_assets, '+'),
+            ("Assets removed", &self.removed_assets, '-'),
+        ] {
+            if items.is_empty() {
+                continue;
+            }
+            unchanged = false;
+            writeln!(f, "{title}:")?;
+            for item in items {
+                writeln!(f, "  {sign} {item}")?;
+            }
+        }
+        if !self.size_changes.is_empty() {
+            unchanged = false;
+            writeln!(f, "Size changes:")?;
+            for (name, old, new) in &self.size_changes {
+                let percent = if *old == 0 {
+                    String::new()
+                } else {
+                    format!(
+                        " ({:+.1}%)",
+                        (*new as f64 - *old as f64) / *old as f64 * 100.0
+                    )
+                };
+                writeln!(f, "  {name}: {old} -> {new} bytes{percent}")?;
+            }
+        }
+        if !self.dependency_changes.is_empty() {
+            unchanged = false;
+            writeln!(f, "Dependencies:")?;
+            for (ecosystem, added, removed) in &self.dependency_changes {
+                for dependency in added {
+                    writeln!(f, "  {ecosystem}: + {dependency}")?;
+                }
+                for dependency in removed {
+                    writeln!(f, "  {ecosystem}: - {dependency}")?;
+                }
+            }
+        }
+        if unchanged {
+            writeln!(f, "No changes")?;
+        }
+        Ok(())
+    }
+}
+
+/// How the assets of a local rebuild compare to a published manifest's
+pub struct ReproducibilityReport {
+    pub matching: Vec<String>,
+    /// asset, published sha256, rebuilt sha256
+    pub mismatched: Vec<(String, String, String)>,
+    /// in the published manifest but not rebuilt
+    pub missing: Vec<String>,
+    /// rebuilt but not in the published manifest
+    pub unpublished: Vec<String>,
+}
+
+impl ReproducibilityReport {
+    /// Whether every published asset was rebuilt with the same checksum
+    pub fn is_reproducible(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Compares the checksums of the build in `rebuilt` to the published manifest at
+/// `published`, a URL or a path to a manifest or output directory
+pub fn compare_checksums(published: &str, rebuilt: &Path) -> io::Result<ReproducibilityReport> {
+    let published = fetch_manifest_file(published)?;
+    let rebuilt = read_manifest_file(rebuilt)?;
+    let rebuilt: BTreeMap<&str, &str> = rebuilt
+        .artifacts
+        .iter()
+        .map(|artifact| (artifact.name.as_str(), artifact.checksum_sha256.as_str()))
+        .collect();
+
+    let mut report = ReproducibilityReport {
+        matching: vec![],
+        mismatched: vec![],
+        missing: vec![],
+        unpublished: vec![],
+    };
+    for artifact in &published.artifacts {
+        match rebuilt.get(artifact.name.as_str()) {
+            None => report.missing.push(artifact.name.clone()),
+            Some(checksum) if *checksum == artifact.checksum_sha256 => {
+                report.matching.push(artifact.name.clone())
+            }
+            Some(checksum) => report.mismatched.push((
+                artifact.name.clone(),
+                artifact.checksum_sha256.clone(),
+                checksum.to_string(),
+            )),
+        }
+    }
+    report.unpublished = rebuilt
+        .keys()
+        .filter(|name| !published.artifacts.iter().any(|a| a.name == **name))
+        .map(|name| name.to_string())
+        .collect();
+    Ok(report)
+}
+
+impl fmt::Display for ReproducibilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in &self.matching {
+            writeln!(f, "ok        {name}")?;
+        }
+        for (name, published, rebuilt) in &self.mismatched {
+            writeln!(f, "mismatch  {name}")?;
+            writeln!(f, "  published {published}")?;
+            writeln!(f, "  rebuilt   {rebuilt}")?;
+        }
+        for name in &self.missing {
+            writeln!(f, "missing   {name}")?;
+        }
+        for name in &self.unpublished {
+            writeln!(f, "extra     {name}")?;
+        }
+        Ok(())
+    }
+}
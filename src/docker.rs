@@ -0,0 +1,168 @@
+use std::io::{self, Result};
+use std::path::Path;
+
+use crate::spec::TargetDocker;
+use crate::{create_targz, Cpu, GeneratedAsset, GeneratedAssetKind, Os, PlatformFile, Project};
+
+/// `$TARGETARCH$TARGETVARIANT` of a docker platform, ex `arm64`, `armv7`
+fn docker_arch(cpu: &Cpu) -> Option<&'static str> {
+    match cpu {
+        Cpu::X86_64 => Some("amd64"),
+        Cpu::Aarch64 => Some("arm64"),
+        Cpu::I686 => Some("386"),
+        Cpu::Armv7a => Some("armv7"),
+        Cpu::Riscv64 => Some("riscv64"),
+        Cpu::Loongarch64 => Some("loong64"),
+        Cpu::S390x => Some("s390x"),
+        Cpu::Universal2 | Cpu::Wasm32 => None,
+    }
+}
+
+/// `--platform` value of a docker arch, ex `linux/arm/v7`
+fn docker_platform(arch: &str) -> String {
+    match arch {
+        "armv7" => "linux/arm/v7".to_owned(),
+        arch => format!("linux/{arch}"),
+    }
+}
+
+/// A build context with every linux platform's loadables under `linux-$ARCH/`,
+/// which the Dockerfile copies from based on the platform being built.
+pub(crate) fn write_docker_context(
+    project: &Project,
+    docker_dir: &Path,
+    config: &TargetDocker,
+) -> Result<Vec<GeneratedAsset>> {
+    let mut files = vec![];
+    let mut archs = vec![];
+    let mut bundles_sqlite3 = true;
+    for platform_dir in &project.platform_directories {
+        if platform_dir.os != Os::Linux {
+            continue;
+        }
+        let Some(arch) = docker_arch(&platform_dir.cpu) else {
+            continue;
+        };
+        for loadable in &platform_dir.loadable_files {
+            files.push(PlatformFile::new(
+                format!("linux-{arch}/{}", loadable.file.name),
+                loadable.file.data.clone(),
+                loadable.file.metadata.clone(),
+            ));
+        }
+        match &platform_dir.sqlite3 {
+            Some(sqlite3) => files.push(PlatformFile::new(
+                format!("linux-{arch}/bin/sqlite3"),
+                sqlite3.data.clone(),
+                sqlite3.metadata.clone(),
+            )),
+            None => bundles_sqlite3 = false,
+        }
+        archs.push(arch);
+    }
+    if archs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the docker target needs at least one linux platform directory",
+        ));
+    }
+    let image_name = config
+        .image_name
+        .as_deref()
+        .unwrap_or(&project.spec.package.name);
+    let platforms = archs
+        .iter()
+        .map(|arch| docker_platform(arch))
+        .collect::<Vec<String>>()
+        .join(",");
+    let file_stems: Vec<&str> = project
+        .platform_directories
+        .iter()
+        .find(|platform_dir| platform_dir.os == Os::Linux)
+        .map(|platform_dir| {
+            platform_dir
+                .loadable_files
+                .iter()
+                .map(|loadable| loadable.file_stem.as_str())
+                .collect()
+        })
+        .unwrap_or_default();
+    let dockerfile = templates::dockerfile(
+        project,
+        config.base_image.as_deref(),
+        image_name,
+        &platforms,
+        &file_stems,
+        bundles_sqlite3,
+    );
+    files.push(PlatformFile::new(
+        "Dockerfile",
+        project.render_template("docker/Dockerfile", None, dockerfile)?,
+        None,
+    ));
+    let targz = create_targz(&files.iter().collect::<Vec<&PlatformFile>>())?;
+    Ok(vec![GeneratedAsset::from(
+        GeneratedAssetKind::Docker,
+        &docker_dir.join(format!(
+            "{}-{}-docker.tar.gz",
+            project.spec.package.name, project.version
+        )),
+        &targz,
+    )?])
+}
+
+mod templates {
+    use crate::Project;
+
+    const EXTENSION_DIR: &str = "/usr/local/lib/sqlite3";
+
+    pub(crate) fn dockerfile(
+        project: &Project,
+        base_image: Option<&str>,
+        image_name: &str,
+        platforms: &str,
+        file_stems: &[&str],
+        bundles_sqlite3: bool,
+    ) -> String {
+        let version = &project.version;
+        let description = &project.spec.package.description;
+        let license = &project.spec.package.license;
+        let repo = &project.spec.package.repo;
+        let from = match (base_image, bundles_sqlite3) {
+            (Some(base_image), _) => format!("FROM {base_image}"),
+            (None, true) => "FROM debian:bookworm-slim".to_owned(),
+            (None, false) => "FROM debian:bookworm-slim\nRUN apt-get update \\\n  && apt-get install -y --no-install-recommends sqlite3 \\\n  && rm -rf /var/lib/apt/lists/*".to_owned(),
+        };
+        let copy_sqlite3 = if bundles_sqlite3 {
+            "\nCOPY linux-${TARGETARCH}${TARGETVARIANT}/bin/sqlite3 /usr/local/bin/sqlite3"
+        } else {
+            ""
+        };
+        let entrypoint = std::iter::once("\"sqlite3\"".to_owned())
+            .chain(
+                file_stems
+                    .iter()
+                    .map(|stem| match project.spec.init_function(stem) {
+                        Some(init_function) => {
+                            format!("\"-cmd\", \".load {EXTENSION_DIR}/{stem} {init_function}\"")
+                        }
+                        None => format!("\"-cmd\", \".load {EXTENSION_DIR}/{stem}\""),
+                    }),
+            )
+            .collect::<Vec<String>>()
+            .join(", ");
+        format!(
+            r#"# docker buildx build --platform {platforms} -t {image_name}:{version} --push .
+{from}
+ARG TARGETARCH
+ARG TARGETVARIANT
+LABEL org.opencontainers.image.version="{version}" \
+  org.opencontainers.image.description="{description}" \
+  org.opencontainers.image.licenses="{license}" \
+  org.opencontainers.image.source="{repo}"
+COPY linux-${{TARGETARCH}}${{TARGETVARIANT}}/*.so {EXTENSION_DIR}/{copy_sqlite3}
+ENTRYPOINT [{entrypoint}]
+"#
+        )
+    }
+}
@@ -0,0 +1,200 @@
+use std::io::Result;
+use std::path::Path;
+
+use crate::{create_output_dir, GeneratedAsset, GeneratedAssetKind, Project};
+
+/// `(ecosystem, command)` installing the package with each package manager the spec targets
+pub(crate) fn install_commands(project: &Project) -> Vec<(&'static str, String)> {
+    let spec = &project.spec;
+    let name = &spec.package.name;
+    let mut commands = vec![];
+    if let Some(pip) = &spec.targets.pip {
+        let package_name = pip.package_name.as_ref().unwrap_or(name);
+        commands.push(("pip", format!("pip install {package_name}")));
+    }
+    if let Some(datasette) = &spec.targets.datasette {
+        let package_name = datasette.package_name(name);
+        commands.push(("Datasette", format!("datasette install {package_name}")));
+    }
+    if let Some(sqlite_utils) = &spec.targets.sqlite_utils {
+        let package_name = sqlite_utils.package_name(name);
+        commands.push((
+            "sqlite-utils",
+            format!("sqlite-utils install {package_name}"),
+        ));
+    }
+    if let Some(npm) = &spec.targets.npm {
+        let package_name = npm.package_name.as_ref().unwrap_or(name);
+        commands.push(("npm", format!("npm install {package_name}")));
+    }
+    if let Some(gem) = &spec.targets.gem {
+        let package_name = gem.package_name.as_ref().unwrap_or(name);
+        commands.push(("RubyGems", format!("gem install {package_name}")));
+    }
+    if spec.targets.sqlpkg.is_some() {
+        // sqlpkg's registry is keyed by the GitHub owner/repo
+        let repo = spec.package.repo.trim_end_matches('/');
+        let owner_repo: Vec<&str> = repo.rsplit('/').take(2).collect();
+        if let [repo, owner] = owner_repo[..] {
+            commands.push(("sqlpkg", format!("sqlpkg install {owner}/{repo}")));
+        }
+    }
+    if spec.targets.spm.is_some() {
+        commands.push((
+            "Swift Package Manager",
+            format!(
+                r#".package(url: "{}", from: "{}")"#,
+                spec.package.repo, project.version
+            ),
+        ));
+    }
+    if spec.targets.github_releases.is_some() {
+        commands.push((
+            "install.sh",
+            format!(
+                "curl -fsSL {} | sh",
+                project.release_download_url("install.sh")
+            ),
+        ));
+    }
+    commands
+}
+
+/// Writes `docs/index.html`, a static installation page for GitHub Pages with the
+/// install commands, the release assets of every platform, and their checksums
+pub(crate) fn write_docs_site(
+    project: &Project,
+    assets: &[GeneratedAsset],
+    output_dir: &Path,
+) -> Result<Vec<GeneratedAsset>> {
+    let docs_dir = output_dir.join("docs");
+    create_output_dir(&docs_dir)?;
+    let html = project.render_template(
+        "docs/index.html",
+        None,
+        templates::index_html(project, &install_commands(project), assets),
+    )?;
+    Ok(vec![GeneratedAsset::from(
+        GeneratedAssetKind::Docs,
+        &docs_dir.join("index.html"),
+        html.as_bytes(),
+    )?])
+}
+
+mod templates {
+    use std::collections::BTreeMap;
+
+    use crate::{GeneratedAsset, Project};
+
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    pub(crate) fn index_html(
+        project: &Project,
+        install_commands: &[(&str, String)],
+        assets: &[GeneratedAsset],
+    ) -> String {
+        let package = &project.spec.package;
+        let name = escape(&package.name);
+        let version = project.version.to_string();
+        let description = escape(&package.description);
+        let license = escape(&package.license);
+        let repo = escape(&package.repo);
+
+        let install = install_commands
+            .iter()
+            .map(|(ecosystem, command)| {
+                format!(
+                    "      <h3>{}</h3>\n      <pre><code>{}</code></pre>\n",
+                    escape(ecosystem),
+                    escape(command)
+                )
+            })
+            .collect::<String>();
+
+        let release_assets: Vec<&GeneratedAsset> = assets
+            .iter()
+            .filter(|asset| asset.kind.is_release_asset())
+            .collect();
+        let link = |asset: &GeneratedAsset| {
+            format!(
+                r#"<a href="{}">{}</a>"#,
+                escape(&project.release_download_url(&asset.name)),
+                escape(&asset.name)
+            )
+        };
+        let mut platforms: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for asset in &release_assets {
+            if let Some((os, cpu)) = asset.kind.platform() {
+                platforms
+                    .entry(format!("{}-{}", os.to_string(), cpu.to_string()))
+                    .or_default()
+                    .push(link(asset));
+            }
+        }
+        let platform_rows = platforms
+            .iter()
+            .map(|(platform, links)| {
+                format!(
+                    "        <tr><td>{platform}</td><td>{}</td></tr>\n",
+                    links.join("<br>")
+                )
+            })
+            .collect::<String>();
+        let checksum_rows = release_assets
+            .iter()
+            .map(|asset| {
+                format!(
+                    "        <tr><td>{}</td><td><code>{}</code></td></tr>\n",
+                    link(asset),
+                    asset.checksum_sha256
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>{name} {version}</title>
+    <style>
+      body {{ font-family: system-ui, sans-serif; max-width: 60rem; margin: 2rem auto; padding: 0 1rem; line-height: 1.5; }}
+      pre {{ background: #f4f4f4; padding: 0.75rem; overflow-x: auto; }}
+      table {{ border-collapse: collapse; width: 100%; }}
+      td, th {{ border-bottom: 1px solid #ddd; padding: 0.4rem; text-align: left; vertical-align: top; }}
+      code {{ word-break: break-all; }}
+    </style>
+  </head>
+  <body>
+    <header>
+      <h1>{name} <small>v{version}</small></h1>
+      <p>{description}</p>
+      <p><a href="{repo}">Source code</a> · {license}</p>
+    </header>
+    <section>
+      <h2>Install</h2>
+{install}    </section>
+    <section>
+      <h2>Platforms</h2>
+      <table>
+        <tr><th>Platform</th><th>Downloads</th></tr>
+{platform_rows}      </table>
+    </section>
+    <section>
+      <h2>Checksums</h2>
+      <table>
+        <tr><th>File</th><th>SHA-256</th></tr>
+{checksum_rows}      </table>
+    </section>
+  </body>
+</html>
+"#
+        )
+    }
+}
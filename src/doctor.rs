@@ -0,0 +1,441 @@
+use std::{fs, path::Path};
+
+use semver::Version;
+use serde::Deserialize;
+
+use crate::{
+    binary::detect_platform, bump::check_version, http, spec::Spec, Cpu, Os, PlatformDirectory,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// One result of `sqlite-dist doctor` or `sqlite-dist lint`
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    /// what to do about it
+    pub fix: Option<String>,
+}
+
+impl Finding {
+    pub(crate) fn ok(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+    pub(crate) fn warning(message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+    pub(crate) fn error(message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Android loadables are plain ELF files, indistinguishable from linux ones
+fn platform_matches(declared: (&Os, &Cpu), detected: (&Os, &Cpu)) -> bool {
+    let os_matches =
+        declared.0 == detected.0 || (*declared.0 == Os::Android && *detected.0 == Os::Linux);
+    os_matches && declared.1 == detected.1
+}
+
+fn check_platform_directory(
+    spec: &Spec,
+    platform_dir: &PlatformDirectory,
+    findings: &mut Vec<Finding>,
+) {
+    let name = format!(
+        "{}-{}",
+        platform_dir.os.to_string(),
+        platform_dir.cpu.to_string()
+    );
+    if platform_dir.loadable_files.is_empty() && platform_dir.static_files.is_empty() {
+        findings.push(Finding::warning(
+            format!("{name} has no loadables or static libraries"),
+            format!(
+                "add the {name} build to {} or remove the directory",
+                platform_dir.path.display()
+            ),
+        ));
+    }
+    for loadable in &platform_dir.loadable_files {
+        match detect_platform(&loadable.file.data) {
+            Some((os, cpu))
+                if !platform_matches((&platform_dir.os, &platform_dir.cpu), (&os, &cpu)) =>
+            {
+                findings.push(Finding::error(
+                    format!(
+                        "{name}/{} is a {}-{} binary",
+                        loadable.file.name,
+                        os.to_string(),
+                        cpu.to_string()
+                    ),
+                    format!(
+                        "move it to a {}-{} directory, or replace it with the {name} build",
+                        os.to_string(),
+                        cpu.to_string()
+                    ),
+                ));
+            }
+            Some(_) => {}
+            None => findings.push(Finding::warning(
+                format!(
+                    "could not read the platform of {name}/{}",
+                    loadable.file.name
+                ),
+                "make sure it is a compiled loadable and not a script or symlink",
+            )),
+        }
+    }
+    for extension in &spec.extensions {
+        if !platform_dir
+            .loadable_files
+            .iter()
+            .any(|l| l.file_stem == extension.name)
+        {
+            findings.push(Finding::warning(
+                format!("{name} has no loadable for extension '{}'", extension.name),
+                format!(
+                    "add {}.{{so,dylib,dll}} to {}",
+                    extension.name,
+                    platform_dir.path.display()
+                ),
+            ));
+        }
+    }
+}
+
+fn check_input_dir(spec: &Spec, input_dir: &Path, findings: &mut Vec<Finding>) {
+    let entries = match fs::read_dir(input_dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            findings.push(Finding::error(
+                format!(
+                    "could not read input directory {}: {err}",
+                    input_dir.display()
+                ),
+                "pass the directory containing the $OS-$CPU build directories",
+            ));
+            return;
+        }
+    };
+    let mut platforms = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() {
+            findings.push(Finding::warning(
+                format!("{} is not a directory and is ignored", path.display()),
+                "put loadables in $OS-$CPU directories, ex linux-x86_64/",
+            ));
+            continue;
+        }
+        if path.file_name().and_then(|n| n.to_str()) == Some("wasm32-emscripten") {
+            continue;
+        }
+        match PlatformDirectory::from_path(path.clone()) {
+            Ok(platform_dir) => {
+                platforms += 1;
+                check_platform_directory(spec, &platform_dir, findings);
+            }
+            Err(err) => findings.push(Finding::error(
+                format!("{}: {err}", path.display()),
+                "rename it to $OS-$CPU, ex macos-aarch64, or move it out of the input directory",
+            )),
+        }
+    }
+    if platforms == 0 {
+        findings.push(Finding::error(
+            format!("{} has no platform directories", input_dir.display()),
+            "add a directory per build, ex linux-x86_64/ with the .so inside",
+        ));
+    } else {
+        findings.push(Finding::ok(format!("{platforms} platform directories")));
+    }
+}
+
+#[derive(Deserialize)]
+struct GithubReleaseResponse {
+    tag_name: String,
+}
+
+/// Compares the version against the repo's GitHub releases
+fn check_releases(spec: &Spec, version: &Version, findings: &mut Vec<Finding>) {
+    let Some(repo) = spec.package.repo.strip_prefix("https://github.com/") else {
+        return;
+    };
+    let repo = repo.trim_end_matches('/');
+    let tag = spec.package.git_tag(version);
+    let agent = match http::agent() {
+        Ok(agent) => agent,
+        Err(err) => {
+            findings.push(Finding::warning(
+                format!("could not check GitHub releases: {err}"),
+                "fix SQLITE_DIST_CA_BUNDLE or SSL_CERT_FILE, or pass --no-network",
+            ));
+            return;
+        }
+    };
+    match agent
+        .get(&format!(
+            "https://api.github.com/repos/{repo}/releases/tags/{tag}"
+        ))
+        .call()
+    {
+        Ok(_) => findings.push(Finding::error(
+            format!("release {tag} already exists on GitHub"),
+            "bump the version with `sqlite-dist bump patch`",
+        )),
+        Err(ureq::Error::Status(404, _)) => {}
+        Err(err) => {
+            findings.push(Finding::warning(
+                format!("could not check GitHub releases: {err}"),
+                "check your network connection, or pass --no-network",
+            ));
+            return;
+        }
+    }
+    let latest = agent
+        .get(&format!(
+            "https://api.github.com/repos/{repo}/releases/latest"
+        ))
+        .call()
+        .ok()
+        .and_then(|response| {
+            serde_json::from_reader::<_, GithubReleaseResponse>(response.into_reader()).ok()
+        });
+    if let Some(latest) = latest {
+        let latest_version = latest.tag_name.trim_start_matches('v');
+        if let Ok(latest_version) = Version::parse(latest_version) {
+            if latest_version >= *version {
+                findings.push(Finding::warning(
+                    format!(
+                        "version {version} is not newer than the latest release {}",
+                        latest.tag_name
+                    ),
+                    "bump the version with `sqlite-dist bump`",
+                ));
+            } else {
+                findings.push(Finding::ok(format!(
+                    "version {version} is newer than {}",
+                    latest.tag_name
+                )));
+            }
+        }
+    }
+}
+
+/// `github.com/foo/sqlite-foo` for any spelling of the repo's URL
+fn normalize_url(url: &str) -> String {
+    let url = url.trim().to_lowercase();
+    let url = url.strip_prefix("git+").unwrap_or(&url);
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let url = url.strip_prefix("www.").unwrap_or(url);
+    url.trim_end_matches('/')
+        .trim_end_matches(".git")
+        .trim_end_matches('/')
+        .to_owned()
+}
+
+/// Repository and homepage URLs of a registry's project metadata
+fn project_urls(registry: &str, metadata: &serde_json::Value) -> Vec<String> {
+    let pointers: &[&str] = match registry {
+        "PyPI" => &["/info/home_page"],
+        "npm" => &["/repository/url", "/repository", "/homepage"],
+        "RubyGems" => &["/source_code_uri", "/homepage_uri"],
+        _ => &["/crate/repository", "/crate/homepage"],
+    };
+    let mut urls: Vec<String> = pointers
+        .iter()
+        .filter_map(|pointer| metadata.pointer(pointer)?.as_str())
+        .map(str::to_owned)
+        .collect();
+    if let Some(project_urls) = metadata
+        .pointer("/info/project_urls")
+        .and_then(|urls| urls.as_object())
+    {
+        urls.extend(
+            project_urls
+                .values()
+                .filter_map(|url| url.as_str())
+                .map(str::to_owned),
+        );
+    }
+    urls
+}
+
+/// Looks up the names the targets will publish under, and warns about the ones
+/// already taken by a project with another repo
+fn check_names(spec: &Spec, findings: &mut Vec<Finding>) {
+    let targets = &spec.targets;
+    let name = spec.package.name.as_str();
+    let mut names: Vec<(&str, String, String)> = vec![];
+    if let Some(pip) = &targets.pip {
+        let pip_name = pip.package_name.as_deref().unwrap_or(name);
+        names.push((
+            "PyPI",
+            pip_name.to_owned(),
+            format!("https://pypi.org/pypi/{pip_name}/json"),
+        ));
+    }
+    if let Some(datasette) = &targets.datasette {
+        let datasette_name = datasette.package_name(name);
+        names.push((
+            "PyPI",
+            datasette_name.clone(),
+            format!("https://pypi.org/pypi/{datasette_name}/json"),
+        ));
+    }
+    if let Some(sqlite_utils) = &targets.sqlite_utils {
+        let sqlite_utils_name = sqlite_utils.package_name(name);
+        names.push((
+            "PyPI",
+            sqlite_utils_name.clone(),
+            format!("https://pypi.org/pypi/{sqlite_utils_name}/json"),
+        ));
+    }
+    if let Some(npm) = &targets.npm {
+        let npm_name = npm.package_name.as_deref().unwrap_or(name);
+        names.push((
+            "npm",
+            npm_name.to_owned(),
+            format!(
+                "https://registry.npmjs.org/{}",
+                npm_name.replace('/', "%2f")
+            ),
+        ));
+    }
+    if let Some(gem) = &targets.gem {
+        let gem_name = gem.package_name.as_deref().unwrap_or(name);
+        names.push((
+            "RubyGems",
+            gem_name.to_owned(),
+            format!("https://rubygems.org/api/v1/gems/{gem_name}.json"),
+        ));
+    }
+    // not published by sqlite-dist, but extensions written in Rust usually are
+    names.push((
+        "crates.io",
+        name.to_owned(),
+        format!("https://crates.io/api/v1/crates/{name}"),
+    ));
+
+    let repo = normalize_url(&spec.package.repo);
+    let agent = match http::agent() {
+        Ok(agent) => agent,
+        Err(err) => {
+            findings.push(Finding::warning(
+                format!("could not look up package names: {err}"),
+                "fix SQLITE_DIST_CA_BUNDLE or SSL_CERT_FILE, or leave out --check-names",
+            ));
+            return;
+        }
+    };
+    for (registry, package, url) in names {
+        let response = agent.get(&url).call();
+        let metadata = match response {
+            Ok(response) => serde_json::from_reader::<_, serde_json::Value>(response.into_reader()),
+            Err(ureq::Error::Status(404, _)) => {
+                findings.push(Finding::ok(format!("{package} is available on {registry}")));
+                continue;
+            }
+            Err(err) => {
+                findings.push(Finding::warning(
+                    format!("could not look up {package} on {registry}: {err}"),
+                    "check your network connection, or leave out --check-names",
+                ));
+                continue;
+            }
+        };
+        let Ok(metadata) = metadata else {
+            findings.push(Finding::warning(
+                format!("could not read {registry}'s response for {package}"),
+                "check the name by hand before publishing",
+            ));
+            continue;
+        };
+        if project_urls(registry, &metadata)
+            .iter()
+            .any(|url| normalize_url(url) == repo)
+        {
+            findings.push(Finding::ok(format!(
+                "{package} on {registry} belongs to {}",
+                spec.package.repo
+            )));
+        } else {
+            findings.push(Finding::warning(
+                format!("{package} is taken on {registry} by a project with another repo"),
+                "rename the package with the target's package_name, or link the existing project to package.repo",
+            ));
+        }
+    }
+}
+
+/// Checks the spec, the input directory, and the version for problems a build would
+/// run into, or that would only show up once the packages are published. With
+/// `names`, also looks up the package names on the registries they'd be published to.
+pub fn doctor(
+    spec_path: &Path,
+    input_dir: Option<&Path>,
+    network: bool,
+    names: bool,
+) -> Vec<Finding> {
+    let network = network && !http::offline();
+    let mut findings = vec![];
+    let spec = match Spec::from_path(spec_path) {
+        Ok(spec) => {
+            findings.push(Finding::ok(format!(
+                "{} is a valid spec",
+                spec_path.display()
+            )));
+            spec
+        }
+        Err(err) => {
+            findings.push(Finding::error(
+                format!("{}: {err}", spec_path.display()),
+                "fix the spec, `sqlite-dist check` validates it on its own",
+            ));
+            return findings;
+        }
+    };
+    match &spec.package.version {
+        Some(version) => {
+            match check_version(&spec, version) {
+                Ok(()) => findings.push(Finding::ok(format!(
+                    "version {version} works for every target"
+                ))),
+                Err(err) => findings.push(Finding::error(
+                    err.to_string(),
+                    "use a pre-release like alpha.N, beta.N, or rc.N",
+                )),
+            }
+            if network {
+                check_releases(&spec, version, &mut findings);
+            }
+        }
+        None => findings.push(Finding::warning(
+            "package.version is not set",
+            "set it in the spec, or pass --version to every build",
+        )),
+    }
+    if network && names {
+        check_names(&spec, &mut findings);
+    }
+    if let Some(input_dir) = input_dir {
+        check_input_dir(&spec, input_dir, &mut findings);
+    }
+    findings
+}
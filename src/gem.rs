@@ -1,14 +1,43 @@
-use crate::spec::TargetGem;
-use crate::{Cpu, Os};
+use crate::capabilities::{capabilities_json, CAPABILITIES_FILE};
+use crate::spec::{Spec, TargetGem};
+use crate::{Cpu, Os, PlatformDirectory};
 use crate::{GeneratedAsset, GeneratedAssetKind, Project};
 use flate2::write::GzEncoder;
 use flate2::Compression;
+use semver::Version;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256, Sha512};
 use std::io::{self, Cursor};
-use std::path::Path;
-use std::{collections::HashMap, io::Write};
+use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+};
 use tar::{Builder, Header};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum GemBuildError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] io::Error),
+    #[error("spec has no gem target")]
+    MissingTarget,
+    #[error("platform directory {0} does not contain any loadable files")]
+    NoLoadableFiles(PathBuf),
+}
+
+/// RubyGems only reads the octal size field of tar headers, not GNU's base-256 sizes
+const MAX_GEM_FILE_SIZE: u64 = 0o77777777777;
+
+fn check_file_size(path: &str, size: u64) -> io::Result<()> {
+    if size > MAX_GEM_FILE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{path} is {size} bytes, RubyGems can't unpack files of 8 GiB or more"),
+        ));
+    }
+    Ok(())
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Gemspec {
@@ -68,6 +97,43 @@ fn ruby_platform(os: &Os, cpu: &Cpu) -> String {
     };
     format!("{cpu}-{os}")
 }
+fn gem_requirement_helper(requirement: &str) -> String {
+    let (op, version) = requirement
+        .trim()
+        .split_once(' ')
+        .map(|(op, version)| (op.trim(), version.trim()))
+        .unwrap_or(("=", requirement.trim()));
+    format!(
+        r#"requirements:
+    - - "{op}"
+      - !ruby/object:Gem::Version
+        version: '{version}'"#
+    )
+}
+
+fn gem_dependencies_helper(dependencies: &BTreeMap<String, String>) -> String {
+    if dependencies.is_empty() {
+        return "dependencies: []".to_owned();
+    }
+    let mut yaml = "dependencies:".to_owned();
+    for (name, requirement) in dependencies {
+        let requirement = gem_requirement_helper(requirement);
+        yaml += format!(
+            r#"
+- !ruby/object:Gem::Dependency
+  name: {name}
+  requirement: !ruby/object:Gem::Requirement
+    {requirement}
+  type: :runtime
+  prerelease: false
+  version_requirements: !ruby/object:Gem::Requirement
+    {requirement}"#
+        )
+        .as_str();
+    }
+    yaml
+}
+
 #[allow(clippy::too_many_arguments)]
 fn gem_metadata_template(
     os: &Os,
@@ -81,12 +147,29 @@ fn gem_metadata_template(
     description: &str,
     summary: &str,
     homepage: &str,
+    dependencies: &BTreeMap<String, String>,
+    requirements: Vec<String>,
+    post_install_message: Option<&str>,
 ) -> String {
+    // JSON strings are valid double-quoted YAML scalars
+    let post_install_message = post_install_message
+        .map(|message| {
+            " ".to_owned()
+                + &serde_json::to_string(message)
+                    .expect("String values should always serialize as JSON")
+        })
+        .unwrap_or_default();
     let ruby_platform = ruby_platform(os, cpu);
+    let dependencies = gem_dependencies_helper(dependencies);
     let date = chrono::offset::Local::now().format("%Y-%m-%d").to_string();
     let authors = gem_metadata_list_helper(authors);
     let files = gem_metadata_list_helper(files);
     let licenses = gem_metadata_list_helper(licenses);
+    let requirements = if requirements.is_empty() {
+        "requirements: []".to_owned()
+    } else {
+        format!("requirements:\n{}", gem_metadata_list_helper(requirements))
+    };
 
     // ?
     let version = version.replace('-', ".");
@@ -102,7 +185,7 @@ autorequire:
 bindir: bin
 cert_chain: []
 date: {date} 00:00:00.000000000 Z
-dependencies: []
+{dependencies}
 description: '{description}'
 summary: '{summary}'
 email:
@@ -115,7 +198,7 @@ files:
 homepage: '{homepage}'
 licenses:
 {licenses}
-post_install_message:
+post_install_message:{post_install_message}
 rdoc_options: []
 require_paths:
 - lib
@@ -129,7 +212,7 @@ required_rubygems_version: !ruby/object:Gem::Requirement
   - - ">="
     - !ruby/object:Gem::Version
       version: '0'
-requirements: []
+{requirements}
 rubygems_version: 3.4.10
 signing_key:
 specification_version: 4
@@ -158,11 +241,39 @@ SHA512:
     encoder.finish()
 }
 
+/// RubyGems version of a semver version. Gem versions have no build metadata,
+/// so it's dropped, and the pre-release becomes dot-separated segments.
+pub(crate) fn gem_version(version: &Version) -> String {
+    Version {
+        build: semver::BuildMetadata::EMPTY,
+        ..version.clone()
+    }
+    .to_string()
+    .replace('-', ".")
+}
+
+/// Name of the gems, package.name unless overriden in the gem target
+fn gem_name(project: &Project) -> &str {
+    project
+        .spec
+        .targets
+        .gem
+        .as_ref()
+        .and_then(|gem| gem.package_name.as_deref())
+        .unwrap_or(&project.spec.package.name)
+}
+
 pub struct Gem {
     library_tarball: Builder<GzEncoder<Vec<u8>>>,
     library_filenames: Vec<String>,
 }
 
+impl Default for Gem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Gem {
     pub fn new() -> Self {
         let tar_gz: Vec<u8> = Vec::new();
@@ -174,6 +285,7 @@ impl Gem {
         }
     }
     pub fn write_library_file(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        check_file_size(path, data.len() as u64)?;
         let mut header = Header::new_gnu();
 
         header.set_path(path)?;
@@ -186,11 +298,18 @@ impl Gem {
     }
 
     fn metadata_gz(&self, os: &Os, cpu: &Cpu, project: &Project) -> io::Result<Vec<u8>> {
+        let dependencies = project
+            .spec
+            .targets
+            .gem
+            .as_ref()
+            .map(|gem| gem.extra_dependencies.clone())
+            .unwrap_or_default();
         let metadata = gem_metadata_template(
             os,
             cpu,
-            &project.spec.package.name,
-            project.version.to_string().as_str(),
+            gem_name(project),
+            gem_version(&project.version).as_str(),
             self.library_filenames.clone(),
             "TODO",
             project.spec.package.authors.clone(),
@@ -198,6 +317,22 @@ impl Gem {
             &project.spec.package.description,
             &project.spec.package.description,
             "https://github.com/TODO",
+            &dependencies
+                .into_iter()
+                .chain(project.spec.requires.iter().map(|require| {
+                    (
+                        require.gem_name().to_owned(),
+                        format!(">= {}", gem_version(&require.min_version)),
+                    )
+                }))
+                .collect::<BTreeMap<String, String>>(),
+            project
+                .spec
+                .min_sqlite_version()
+                .map(|min| format!("SQLite >= {min}"))
+                .into_iter()
+                .collect(),
+            project.spec.post_install_message("gem"),
         );
         let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
         encoder.write_all(metadata.as_bytes())?;
@@ -239,9 +374,8 @@ impl Gem {
         Ok((
             format!(
                 "{}-{}-{}.gem",
-                project.spec.package.name,
-                // ?
-                project.version.to_string().replace('-', "."),
+                gem_name(project),
+                gem_version(&project.version),
                 ruby_platform(os, cpu)
             ),
             gem_tar,
@@ -249,31 +383,88 @@ impl Gem {
     }
 }
 
+/// Builds the platform gems of a spec as a library, ex
+/// `GemTarget::new(spec, version).platform(platform_dir).generate(&output_dir)`
+pub struct GemTarget {
+    project: Project,
+}
+
+impl GemTarget {
+    pub fn new(spec: Spec, version: Version) -> Self {
+        Self {
+            project: Project::new(spec, version),
+        }
+    }
+
+    pub fn platform(mut self, platform: PlatformDirectory) -> Self {
+        self.project = self.project.platform(platform);
+        self
+    }
+
+    pub fn generate(&self, output_dir: &Path) -> Result<Vec<GeneratedAsset>, GemBuildError> {
+        let gem_config = self
+            .project
+            .spec
+            .targets
+            .gem
+            .as_ref()
+            .ok_or(GemBuildError::MissingTarget)?;
+        write_gems(&self.project, output_dir, gem_config)
+    }
+}
+
 pub(crate) fn write_gems(
     project: &Project,
     gem_path: &Path,
     gem_config: &TargetGem,
-) -> io::Result<Vec<GeneratedAsset>> {
+) -> Result<Vec<GeneratedAsset>, GemBuildError> {
     let mut assets = vec![];
     for platform_dir in &project.platform_directories {
+        let _span = platform_dir.span().entered();
         if !(matches!(platform_dir.os, Os::Linux | Os::Macos | Os::Windows)
             && matches!(platform_dir.cpu, Cpu::X86_64 | Cpu::Aarch64))
         {
+            platform_dir.warn_unsupported("gem");
             continue;
         }
         let mut gem = Gem::new();
-        assert!(!platform_dir.loadable_files.is_empty());
-        let loadable_name = platform_dir.loadable_files[0].file.name.clone();
-        let entrypoint = &platform_dir.loadable_files[0].file_stem;
+        if platform_dir.loadable_files.is_empty() {
+            return Err(GemBuildError::NoLoadableFiles(platform_dir.path.clone()));
+        }
+        let mut entrypoints = vec![];
+        for loadable in &platform_dir.loadable_files {
+            gem.write_library_file(
+                format!("lib/{}", loadable.file.name).as_str(),
+                loadable.file.data.as_ref(),
+            )?;
+            entrypoints.push(loadable.file_stem.as_str());
+        }
+        for extra_file in &platform_dir.extra_files {
+            gem.write_library_file(
+                format!("lib/{}", extra_file.name).as_str(),
+                extra_file.data.as_ref(),
+            )?;
+        }
 
-        gem.write_library_file(
-            format!("lib/{}", loadable_name).as_str(),
-            platform_dir.loadable_files[0].file.data.as_ref(),
-        )?;
+        if let Some(capabilities) = capabilities_json(project) {
+            gem.write_library_file(CAPABILITIES_FILE, capabilities.as_bytes())?;
+        }
 
+        let lib_rb = project.render_template(
+            "gem/lib.rb",
+            Some((&platform_dir.os, &platform_dir.cpu)),
+            templates::lib_rb(
+                &project.version,
+                &entrypoints,
+                &project.spec.init_functions(&entrypoints),
+                &gem_config.module_name,
+                project.loader_checks(),
+                &project.spec.requires,
+            ),
+        )?;
         gem.write_library_file(
-            format!("lib/{}.rb", project.spec.package.name.replace('-', "_")).as_str(),
-            templates::lib_rb(&project.version, entrypoint, &gem_config.module_name).as_bytes(),
+            format!("lib/{}.rb", gem_name(project).replace('-', "_")).as_str(),
+            lib_rb.as_bytes(),
         )?;
         let (gem_name, data) = gem.complete(&platform_dir.os, &platform_dir.cpu, project)?;
         assets.push(GeneratedAsset::from(
@@ -288,17 +479,77 @@ pub(crate) fn write_gems(
 mod templates {
     use semver::Version;
 
-    pub(crate) fn lib_rb(version: &Version, entrypoint: &str, module_name: &str) -> String {
+    use crate::{spec::SpecRequire, LoaderChecks};
+
+    pub(crate) fn lib_rb(
+        version: &Version,
+        entrypoints: &[&str],
+        init_functions: &[(&str, &str)],
+        module_name: &str,
+        checks: LoaderChecks,
+        requires: &[SpecRequire],
+    ) -> String {
+        let require_imports: String = requires
+            .iter()
+            .map(|require| format!("require \"{}\"\n", require.gem_name().replace('-', "_")))
+            .collect();
+        let require_loads: String = requires
+            .iter()
+            .map(|require| format!("\n    {}.load(db)", require.gem_module()))
+            .collect();
+        let entrypoints = serde_json::to_string(entrypoints)
+            .expect("String values should always serialize as JSON");
+        let init_functions = init_functions
+            .iter()
+            .map(|(entrypoint, init_function)| format!("\"{entrypoint}\" => \"{init_function}\""))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let (min_sqlite_check, min_sqlite_check_call) = match checks.min_sqlite_version {
+            Some(min) => (
+                format!("\n  MIN_SQLITE_VERSION = \"{min}\""),
+                r##"
+    sqlite_version = db.get_first_value("select sqlite_version()")
+    if Gem::Version.new(sqlite_version) < Gem::Version.new(MIN_SQLITE_VERSION)
+      raise Error, "#{self.name} requires SQLite #{MIN_SQLITE_VERSION} or later, but the database uses SQLite #{sqlite_version}"
+    end"##,
+            ),
+            None => (String::new(), ""),
+        };
+        let (version_check, version_check_call) = match checks.version_check {
+            Some((sql, _)) => (
+                format!(
+                    "\n  VERSION_SQL = '{}'",
+                    sql.replace('\\', "\\\\").replace('\'', "\\'")
+                ),
+                r##"
+    loaded_version = db.get_first_value(VERSION_SQL).to_s
+    unless loaded_version.delete_prefix("v") == VERSION
+      raise Error, "#{self.name} #{VERSION} was loaded, but #{VERSION_SQL} returned #{loaded_version}. Is an older copy of the extension installed?"
+    end"##,
+            ),
+            None => (String::new(), ""),
+        };
         format!(
             r#"
+{require_imports}
 module {module_name}
   class Error < StandardError; end
   VERSION = "{version}"
-  def self.loadable_path
-    File.expand_path('{entrypoint}', File.dirname(__FILE__))
+  ENTRYPOINTS = {entrypoints}
+  # entrypoint -> init function, for loadables SQLite can't derive it from the file name of
+  INIT_FUNCTIONS = {{{init_functions}}}{min_sqlite_check}{version_check}
+  def self.loadable_path(entrypoint = ENTRYPOINTS[0])
+    File.expand_path(entrypoint, File.dirname(__FILE__))
   end
-  def self.load(db)
-    db.load_extension(self.loadable_path)
+  def self.load(db){min_sqlite_check_call}{require_loads}
+    ENTRYPOINTS.each do |entrypoint|
+      if INIT_FUNCTIONS.key?(entrypoint)
+        # Database#load_extension has no entry point argument
+        db.execute("select load_extension(?, ?)", [self.loadable_path(entrypoint), INIT_FUNCTIONS[entrypoint]])
+      else
+        db.load_extension(self.loadable_path(entrypoint))
+      end
+    end{version_check_call}
   end
 end
 
@@ -306,3 +557,20 @@ end
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{check_file_size, MAX_GEM_FILE_SIZE};
+
+    #[test]
+    fn file_size_limit() {
+        check_file_size("lib/sample0.so", u32::MAX as u64 + 1).unwrap();
+        check_file_size("lib/sample0.so", MAX_GEM_FILE_SIZE).unwrap();
+        let err = check_file_size("lib/sample0.so", MAX_GEM_FILE_SIZE + 1).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "lib/sample0.so is 8589934592 bytes, RubyGems can't unpack files of 8 GiB or more"
+        );
+    }
+}
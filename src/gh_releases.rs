@@ -1,18 +1,22 @@
-use crate::PlatformDirectory;
+use crate::capabilities::{capabilities_json, CAPABILITIES_FILE};
 use crate::{
     create_targz, GeneratedAsset, GeneratedAssetKind, GithubRelease, PlatformFile, Project,
 };
+use crate::{layout::release_file_name, Cpu, Os, PlatformDirectory};
 use std::io;
 use std::path::Path;
 
 fn create_loadable_github_release_asset(
     platform_directory: &PlatformDirectory,
+    capabilities: Option<&PlatformFile>,
 ) -> io::Result<Vec<u8>> {
     create_targz(
         &platform_directory
             .loadable_files
             .iter()
             .map(|l| &l.file)
+            .chain(&platform_directory.extra_files)
+            .chain(capabilities)
             .collect::<Vec<&PlatformFile>>(),
     )
 }
@@ -48,7 +52,14 @@ fn github_release_artifact_name_loadable(
     let version = project.version.to_string();
     let os = platform_dir.os.to_string();
     let cpu = platform_dir.cpu.to_string();
-    github_release_artifact_name(name, &version, &os, &cpu, "loadable")
+    release_file_name(
+        project,
+        "loadable",
+        &platform_dir.os,
+        &platform_dir.cpu,
+        "tar.gz",
+    )
+    .unwrap_or_else(|| github_release_artifact_name(name, &version, &os, &cpu, "loadable"))
 }
 fn github_release_artifact_name_static(
     project: &Project,
@@ -58,7 +69,28 @@ fn github_release_artifact_name_static(
     let version = project.version.to_string();
     let os = platform_dir.os.to_string();
     let cpu = platform_dir.cpu.to_string();
-    github_release_artifact_name(name, &version, &os, &cpu, "static")
+    release_file_name(
+        project,
+        "static",
+        &platform_dir.os,
+        &platform_dir.cpu,
+        "tar.gz",
+    )
+    .unwrap_or_else(|| github_release_artifact_name(name, &version, &os, &cpu, "static"))
+}
+
+fn github_release_artifact_name_wasm(project: &Project) -> String {
+    if let Some(name) = release_file_name(project, "wasm", &Os::Emscripten, &Cpu::Wasm32, "tar.gz")
+    {
+        return name;
+    }
+    format!(
+        "{}-{}-{}-{}.tar.gz",
+        project.spec.package.name,
+        project.version,
+        Cpu::Wasm32.to_string(),
+        Os::Emscripten.to_string()
+    )
 }
 
 pub(crate) fn write_platform_files(
@@ -67,21 +99,30 @@ pub(crate) fn write_platform_files(
 ) -> Result<Vec<GeneratedAsset>, io::Error> {
     let mut loadable_assets = vec![];
     let mut static_assets = vec![];
+    let capabilities = capabilities_json(project)
+        .map(|capabilities| PlatformFile::new(CAPABILITIES_FILE, capabilities, None));
 
     for platform_dir in &project.platform_directories {
-        let ghl = create_loadable_github_release_asset(platform_dir)?;
+        let _span = platform_dir.span().entered();
+        let ghl = create_loadable_github_release_asset(platform_dir, capabilities.as_ref())?;
         let lname = github_release_artifact_name_loadable(project, platform_dir);
         loadable_assets.push(GeneratedAsset::from(
             GeneratedAssetKind::GithubReleaseLoadable(GithubRelease {
                 url: project.release_download_url(&lname),
                 platform: (platform_dir.os.clone(), platform_dir.cpu.clone()),
             }),
-            &ghreleases.join(lname),
+            &ghreleases.join(&lname),
             &ghl,
         )?);
 
         if let Some(ghs) = create_static_github_release_asset(platform_dir) {
             let sname = github_release_artifact_name_static(project, platform_dir);
+            if sname == lname {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("output.file_name gives the loadable and static archives of {}-{} the same name, add {{type}}", platform_dir.os.to_string(), platform_dir.cpu.to_string()),
+                ));
+            }
             static_assets.push(GeneratedAsset::from(
                 GeneratedAssetKind::GithubReleaseStatic(GithubRelease {
                     url: project.release_download_url(&sname),
@@ -95,5 +136,18 @@ pub(crate) fn write_platform_files(
     let mut generated_assets = vec![];
     generated_assets.append(&mut loadable_assets);
     generated_assets.append(&mut static_assets);
+
+    if let Some(wasm_directory) = &project.wasm_directory {
+        let wasm = create_targz(&wasm_directory.files.iter().collect::<Vec<&PlatformFile>>())?;
+        let name = github_release_artifact_name_wasm(project);
+        generated_assets.push(GeneratedAsset::from(
+            GeneratedAssetKind::GithubReleaseWasm(GithubRelease {
+                url: project.release_download_url(&name),
+                platform: (Os::Emscripten, Cpu::Wasm32),
+            }),
+            &ghreleases.join(name),
+            &wasm,
+        )?);
+    }
     Ok(generated_assets)
 }
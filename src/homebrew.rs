@@ -0,0 +1,290 @@
+use std::io::Result;
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::spec::TargetHomebrew;
+use crate::{create_targz, Cpu, GeneratedAsset, GeneratedAssetKind, Os, PlatformFile, Project};
+
+mod templates {
+    /// One `on_macos`/`on_linux` block with an `on_arm`/`on_intel` block per
+    /// platform, `platforms` being (on_os, on_cpu, url, sha256)
+    pub(crate) fn platform_blocks(platforms: &[(&str, &str, String, String)]) -> String {
+        let mut blocks = String::new();
+        for on_os in ["on_macos", "on_linux"] {
+            let cpus: String = platforms
+                .iter()
+                .filter(|(os, ..)| *os == on_os)
+                .map(|(_, on_cpu, url, sha256)| {
+                    format!(
+                        "    {on_cpu} do\n      url \"{url}\"\n      sha256 \"{sha256}\"\n    end\n"
+                    )
+                })
+                .collect();
+            if !cpus.is_empty() {
+                blocks += &format!("  {on_os} do\n{cpus}  end\n\n");
+            }
+        }
+        blocks
+    }
+
+    pub(crate) fn bottle_block(root_url: &str, bottles: &[(&str, String)]) -> String {
+        if bottles.is_empty() {
+            return String::new();
+        }
+        let sha256s: String = bottles
+            .iter()
+            .map(|(tag, sha256)| {
+                format!("    sha256 cellar: :any_skip_relocation, {tag}: \"{sha256}\"\n")
+            })
+            .collect();
+        format!("  bottle do\n    root_url \"{root_url}\"\n{sha256s}  end\n\n")
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn formula_rb(
+        class_name: &str,
+        description: &str,
+        homepage: &str,
+        version: &str,
+        license: &str,
+        platform_blocks: &str,
+        bottle_block: &str,
+        entrypoints: &[&str],
+        init_functions: &[(&str, &str)],
+        post_install: Option<&str>,
+    ) -> String {
+        let post_install: String = post_install
+            .map(|message| {
+                message
+                    .lines()
+                    .map(|line| format!("      {line}\n"))
+                    .collect::<String>()
+                    + "\n"
+            })
+            .unwrap_or_default();
+        let description = description.replace('"', "\\\"");
+        let loads: String = entrypoints
+            .iter()
+            .map(
+                |entrypoint| match init_functions.iter().find(|(stem, _)| stem == entrypoint) {
+                    Some((_, init_function)) => {
+                        format!("        .load #{{opt_lib}}/{entrypoint} {init_function}\n")
+                    }
+                    None => format!("        .load #{{opt_lib}}/{entrypoint}\n"),
+                },
+            )
+            .collect();
+        let entrypoint = entrypoints.first().copied().unwrap_or_default();
+        format!(
+            r#"class {class_name} < Formula
+  desc "{description}"
+  homepage "{homepage}"
+  version "{version}"
+  license "{license}"
+
+{platform_blocks}{bottle_block}  def install
+    lib.install Dir["*"]
+  end
+
+  def caveats
+    <<~EOS
+{post_install}      Load the extension into the sqlite3 CLI with:
+{loads}    EOS
+  end
+
+  test do
+    assert_path_exists lib/"{entrypoint}.#{{OS.mac? ? "dylib" : "so"}}"
+  end
+end
+"#
+        )
+    }
+}
+
+/// Homebrew's bottle tag of a platform. macOS bottles are tagged with the oldest
+/// release the loadables support, brew pours them on every later one.
+fn bottle_tag(os: &Os, cpu: &Cpu) -> Option<&'static str> {
+    match (os, cpu) {
+        (Os::Macos, Cpu::Aarch64) => Some("arm64_big_sur"),
+        (Os::Macos, Cpu::X86_64) => Some("big_sur"),
+        (Os::Linux, Cpu::Aarch64) => Some("arm64_linux"),
+        (Os::Linux, Cpu::X86_64) => Some("x86_64_linux"),
+        _ => None,
+    }
+}
+
+/// Formula class of a formula name, ex `sqlite-vec` -> `SqliteVec`
+fn class_name(formula: &str) -> String {
+    formula
+        .split(['-', '_', '.'])
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next().map_or(String::new(), |first| {
+                first.to_ascii_uppercase().to_string() + chars.as_str()
+            })
+        })
+        .collect()
+}
+
+/// `$FORMULA.rb` installing the GitHub release tarballs, and with `bottles`, a
+/// `$FORMULA--$VERSION.$TAG.bottle.tar.gz` per platform in Homebrew's cellar layout
+/// next to the `brew bottle --json` metadata a tap merges into the formula.
+pub(crate) fn write_homebrew(
+    project: &Project,
+    config: &TargetHomebrew,
+    gh_release_assets: &[GeneratedAsset],
+    homebrew_dir: &Path,
+) -> Result<Vec<GeneratedAsset>> {
+    let formula = config
+        .formula_name
+        .as_deref()
+        .unwrap_or(&project.spec.package.name);
+    let version = project.version.to_string();
+    let root_url = project
+        .release_download_url("")
+        .trim_end_matches('/')
+        .to_owned();
+    let platforms: Vec<(&str, &str, String, String)> = gh_release_assets
+        .iter()
+        .filter_map(|asset| match &asset.kind {
+            GeneratedAssetKind::GithubReleaseLoadable(release) => {
+                let (os, cpu) = &release.platform;
+                bottle_tag(os, cpu)?;
+                let on_os = if *os == Os::Macos {
+                    "on_macos"
+                } else {
+                    "on_linux"
+                };
+                let on_cpu = if *cpu == Cpu::Aarch64 {
+                    "on_arm"
+                } else {
+                    "on_intel"
+                };
+                Some((
+                    on_os,
+                    on_cpu,
+                    release.url.clone(),
+                    asset.checksum_sha256.clone(),
+                ))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut assets = vec![];
+    let mut bottles = vec![];
+    if config.bottles {
+        for platform_dir in &project.platform_directories {
+            let Some(tag) = bottle_tag(&platform_dir.os, &platform_dir.cpu) else {
+                platform_dir.warn_unsupported("homebrew");
+                continue;
+            };
+            let _span = platform_dir.span().entered();
+            let keg = format!("{formula}/{version}");
+            let receipt = json!({
+                "homebrew_version": "",
+                "built_as_bottle": true,
+                "poured_from_bottle": true,
+                "installed_as_dependency": false,
+                "installed_on_request": true,
+                "source": {
+                    "tap": config.tap,
+                    "spec": "stable",
+                    "versions": { "stable": version },
+                },
+            });
+            let mut files: Vec<PlatformFile> = platform_dir
+                .loadable_files
+                .iter()
+                .map(|loadable| &loadable.file)
+                .chain(&platform_dir.extra_files)
+                .map(|file| PlatformFile {
+                    name: format!("{keg}/lib/{}", file.name),
+                    ..file.clone()
+                })
+                .collect();
+            files.push(PlatformFile::new(
+                format!("{keg}/INSTALL_RECEIPT.json"),
+                serde_json::to_string_pretty(&receipt)?,
+                None,
+            ));
+            let bottle = create_targz(&files.iter().collect::<Vec<&PlatformFile>>())?;
+            let bottle_name = format!("{formula}--{version}.{tag}.bottle.tar.gz");
+            let kind = GeneratedAssetKind::Homebrew(Some((
+                platform_dir.os.clone(),
+                platform_dir.cpu.clone(),
+            )));
+            let bottle_asset =
+                GeneratedAsset::from(kind.clone(), &homebrew_dir.join(&bottle_name), &bottle)?;
+            let full_name = match &config.tap {
+                Some(tap) => format!("{tap}/{formula}"),
+                None => formula.to_owned(),
+            };
+            let bottle_json = json!({
+                full_name: {
+                    "formula": {
+                        "name": formula,
+                        "pkg_version": version,
+                        "path": format!("Formula/{formula}.rb"),
+                        "tap_git_path": format!("Formula/{formula}.rb"),
+                        "desc": project.spec.package.description,
+                        "license": project.spec.package.license,
+                        "homepage": project.spec.package.homepage,
+                    },
+                    "bottle": {
+                        "root_url": root_url,
+                        "cellar": "any_skip_relocation",
+                        "rebuild": 0,
+                        "tags": {
+                            tag: {
+                                "filename": bottle_name,
+                                "local_filename": bottle_name,
+                                "sha256": bottle_asset.checksum_sha256,
+                            },
+                        },
+                    },
+                },
+            });
+            bottles.push((tag, bottle_asset.checksum_sha256.clone()));
+            assets.push(bottle_asset);
+            assets.push(GeneratedAsset::from(
+                kind,
+                &homebrew_dir.join(format!("{formula}--{version}.{tag}.bottle.json")),
+                serde_json::to_string_pretty(&bottle_json)?.as_bytes(),
+            )?);
+        }
+    }
+
+    let entrypoints: Vec<&str> = project
+        .platform_directories
+        .first()
+        .map(|platform_dir| {
+            platform_dir
+                .loadable_files
+                .iter()
+                .map(|loadable| loadable.file_stem.as_str())
+                .collect()
+        })
+        .unwrap_or_default();
+    let formula_rb = templates::formula_rb(
+        &class_name(formula),
+        &project.spec.package.description,
+        &project.spec.package.homepage,
+        &version,
+        &project.spec.package.license,
+        &templates::platform_blocks(&platforms),
+        &templates::bottle_block(&root_url, &bottles),
+        &entrypoints,
+        &project.spec.init_functions(&entrypoints),
+        project.spec.post_install_message("homebrew"),
+    );
+    assets.push(GeneratedAsset::from(
+        GeneratedAssetKind::Homebrew(None),
+        &homebrew_dir.join(format!("{formula}.rb")),
+        project
+            .render_template("homebrew/formula.rb", None, formula_rb)?
+            .as_bytes(),
+    )?);
+    Ok(assets)
+}
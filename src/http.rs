@@ -0,0 +1,179 @@
+use std::{
+    env,
+    fmt::Display,
+    fs::File,
+    io::{self, BufReader},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Retries after a transient failure, unless `SQLITE_DIST_HTTP_RETRIES` sets another number
+const DEFAULT_RETRIES: u32 = 3;
+
+/// Longest wait between retries, also for a server's `Retry-After`
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+
+/// Set by `--offline`: every step that needs the network fails instead
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Forbids network access for the rest of the process, see [`ensure_online`]
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub(crate) fn offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Fails when `--offline` forbids `what`, which needs the network
+pub(crate) fn ensure_online(what: &str) -> io::Result<()> {
+    if offline() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{what} needs network access, which --offline forbids"),
+        ));
+    }
+    Ok(())
+}
+
+/// Tells commands sqlite-dist runs for the user, like plugins, that they're offline
+pub(crate) fn pass_offline(command: &mut std::process::Command) {
+    if offline() {
+        command.env("SQLITE_DIST_OFFLINE", "1");
+    }
+}
+
+pub(crate) fn retries() -> u32 {
+    env::var("SQLITE_DIST_HTTP_RETRIES")
+        .ok()
+        .and_then(|retries| retries.parse().ok())
+        .unwrap_or(DEFAULT_RETRIES)
+}
+
+/// PEM file of CA certificates to trust next to the bundled roots, for networks
+/// that intercept TLS. `SQLITE_DIST_CA_BUNDLE`, or the `SSL_CERT_FILE` OpenSSL reads.
+pub(crate) fn ca_bundle() -> Option<PathBuf> {
+    env::var_os("SQLITE_DIST_CA_BUNDLE")
+        .or_else(|| env::var_os("SSL_CERT_FILE"))
+        .map(PathBuf::from)
+}
+
+fn tls_config(ca_bundle: &PathBuf) -> io::Result<rustls::ClientConfig> {
+    let invalid = |err: &dyn Display| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("CA bundle {}: {err}", ca_bundle.display()),
+        )
+    };
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let file = File::open(ca_bundle).map_err(|err| invalid(&err))?;
+    let mut added = 0;
+    for cert in rustls_pemfile::certs(&mut BufReader::new(file)) {
+        roots
+            .add(cert.map_err(|err| invalid(&err))?)
+            .map_err(|err| invalid(&err))?;
+        added += 1;
+    }
+    if added == 0 {
+        return Err(invalid(&"no PEM certificates"));
+    }
+    Ok(rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
+
+/// The agent every registry and GitHub request goes through. Proxies come from
+/// `HTTPS_PROXY`, `HTTP_PROXY` or `ALL_PROXY`, extra CA certificates from [`ca_bundle`].
+pub(crate) fn agent() -> io::Result<&'static ureq::Agent> {
+    ensure_online("this HTTP request")?;
+    if let Some(agent) = AGENT.get() {
+        return Ok(agent);
+    }
+    let mut builder = ureq::AgentBuilder::new()
+        .try_proxy_from_env(true)
+        .user_agent(concat!("sqlite-dist/", env!("CARGO_PKG_VERSION")));
+    if let Some(ca_bundle) = ca_bundle() {
+        builder = builder.tls_config(Arc::new(tls_config(&ca_bundle)?));
+    }
+    Ok(AGENT.get_or_init(|| builder.build()))
+}
+
+/// Passes the CA bundle on to the npm and aws CLIs, which read proxies from the
+/// same variables already
+pub(crate) fn configure_command(command: &mut std::process::Command) {
+    if let Some(ca_bundle) = ca_bundle() {
+        for name in ["NODE_EXTRA_CA_CERTS", "AWS_CA_BUNDLE"] {
+            if env::var_os(name).is_none() {
+                command.env(name, &ca_bundle);
+            }
+        }
+    }
+}
+
+/// Runs `attempt`, retrying when `retry_delay` calls an error transient with
+/// exponential backoff from 1 second, or after the delay it asks for if longer
+pub(crate) fn with_backoff<T, E: Display>(
+    action: &str,
+    retry_delay: impl Fn(&E) -> Option<Duration>,
+    mut attempt: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut backoff = Duration::from_secs(1);
+    for _ in 0..retries() {
+        match attempt() {
+            Err(err) => match retry_delay(&err) {
+                Some(requested) => {
+                    let delay = backoff.max(requested).min(MAX_DELAY);
+                    tracing::warn!("{action} failed, retrying in {}s: {err}", delay.as_secs());
+                    thread::sleep(delay);
+                    backoff *= 2;
+                }
+                None => return Err(err),
+            },
+            result => return result,
+        }
+    }
+    attempt()
+}
+
+/// Network errors, rate limits and server errors may pass on a retry, after the
+/// response's `Retry-After` seconds when it has one
+pub(crate) fn retry_delay(err: &ureq::Error) -> Option<Duration> {
+    match err {
+        ureq::Error::Status(status, response) if *status == 429 || *status >= 500 => Some(
+            response
+                .header("Retry-After")
+                .and_then(|seconds| seconds.trim().parse().ok())
+                .map_or(Duration::ZERO, Duration::from_secs),
+        ),
+        ureq::Error::Status(..) => None,
+        ureq::Error::Transport(_) => Some(Duration::ZERO),
+    }
+}
+
+/// Sends a request built by `request`, with `body` when it has one, through
+/// [`with_backoff`]. `ureq::Error` is boxed, it's too large to return as is.
+pub(crate) fn send(
+    shown: &str,
+    request: impl Fn() -> ureq::Request,
+    body: Option<&[u8]>,
+) -> Result<ureq::Response, Box<ureq::Error>> {
+    with_backoff::<_, Box<ureq::Error>>(
+        shown,
+        |err| retry_delay(err),
+        || {
+            match body {
+                Some(body) => request().send_bytes(body),
+                None => request().call(),
+            }
+            .map_err(Box::new)
+        },
+    )
+}
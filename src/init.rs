@@ -0,0 +1,177 @@
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{binary::detect_platform, Cpu, Os};
+
+#[derive(Error, Debug)]
+pub enum InitError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] io::Error),
+    #[error("{0} already exists, pass --force to overwrite it")]
+    AlreadyExists(PathBuf),
+}
+
+/// A loadable found by `init`, with the platform read from its header
+pub struct DetectedLoadable {
+    pub path: PathBuf,
+    pub os: Os,
+    pub cpu: Cpu,
+    /// file stem, ex `foo0` for `foo0.so`
+    pub entrypoint: String,
+}
+
+impl DetectedLoadable {
+    /// Where the loadable goes in the input directory sqlite-dist reads
+    pub fn input_path(&self) -> PathBuf {
+        let file_name = self.path.file_name().unwrap_or_default();
+        PathBuf::from(format!("{}-{}", self.os.to_string(), self.cpu.to_string())).join(file_name)
+    }
+}
+
+/// The `[package]` fields of a new spec
+pub struct InitOptions {
+    pub spec_path: PathBuf,
+    /// directory searched for loadables, recursively
+    pub loadables_dir: Option<PathBuf>,
+    pub name: String,
+    pub version: String,
+    pub authors: Vec<String>,
+    pub license: String,
+    pub description: String,
+    pub homepage: String,
+    pub repo: String,
+    pub force: bool,
+}
+
+fn is_loadable(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("so" | "dylib" | "dll")
+    )
+}
+
+/// Every `.so`, `.dylib`, and `.dll` under `directory` whose platform could be read
+pub fn detect_loadables(directory: &Path) -> io::Result<Vec<DetectedLoadable>> {
+    let mut entries = fs::read_dir(directory)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+    let mut loadables = vec![];
+    for path in entries {
+        if path.is_dir() {
+            loadables.extend(detect_loadables(&path)?);
+            continue;
+        }
+        if !is_loadable(&path) {
+            continue;
+        }
+        let Some((os, cpu)) = detect_platform(&fs::read(&path)?) else {
+            tracing::warn!("could not tell the platform of {}", path.display());
+            continue;
+        };
+        let entrypoint = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        loadables.push(DetectedLoadable {
+            path,
+            os,
+            cpu,
+            entrypoint,
+        });
+    }
+    Ok(loadables)
+}
+
+mod templates {
+    use crate::init::InitOptions;
+
+    fn quote(value: &str) -> String {
+        toml::Value::String(value.to_owned()).to_string()
+    }
+
+    /// `foo-bar` -> `FooBar`, for the gem's Ruby module
+    fn module_name(name: &str) -> String {
+        name.split(['-', '_'])
+            .map(|word| {
+                let mut chars = word.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    pub(crate) fn spec_toml(options: &InitOptions, entrypoints: &[&str]) -> String {
+        let authors = options
+            .authors
+            .iter()
+            .map(|author| quote(author))
+            .collect::<Vec<_>>()
+            .join(", ");
+        // a single loadable is the package itself, more are combined into it
+        let extensions = if entrypoints.len() > 1 {
+            entrypoints
+                .iter()
+                .map(|entrypoint| format!("\n[[extensions]]\nname = {}\n", quote(entrypoint)))
+                .collect::<String>()
+        } else {
+            String::new()
+        };
+        format!(
+            r#"[package]
+name = {name}
+version = {version}
+authors = [{authors}]
+license = {license}
+description = {description}
+homepage = {homepage}
+repo = {repo}
+{extensions}
+[targets]
+github_releases = {{}}
+sqlpkg = {{}}
+pip = {{}}
+datasette = {{}}
+sqlite_utils = {{}}
+npm = {{}}
+gem = {{ module_name = {module_name} }}
+"#,
+            name = quote(&options.name),
+            version = quote(&options.version),
+            license = quote(&options.license),
+            description = quote(&options.description),
+            homepage = quote(&options.homepage),
+            repo = quote(&options.repo),
+            module_name = quote(&module_name(&options.name)),
+        )
+    }
+}
+
+/// Writes a new spec to `options.spec_path`, with an extension for every distinct
+/// loadable found in `options.loadables_dir`. Returns the loadables it found.
+pub fn init(options: &InitOptions) -> Result<Vec<DetectedLoadable>, InitError> {
+    if options.spec_path.exists() && !options.force {
+        return Err(InitError::AlreadyExists(options.spec_path.clone()));
+    }
+    let loadables = match &options.loadables_dir {
+        Some(directory) => detect_loadables(directory)?,
+        None => vec![],
+    };
+    let entrypoints: BTreeSet<&str> = loadables
+        .iter()
+        .map(|loadable| loadable.entrypoint.as_str())
+        .collect();
+    let entrypoints: Vec<&str> = entrypoints.into_iter().collect();
+    fs::write(
+        &options.spec_path,
+        templates::spec_toml(options, &entrypoints),
+    )?;
+    Ok(loadables)
+}
@@ -0,0 +1,97 @@
+use std::{fs, io, path::Path};
+
+use crate::docs_site::install_commands;
+use crate::spec::TargetInstallMd;
+use crate::{dry_run, GeneratedAsset, GeneratedAssetKind, Project};
+
+const SECTION_START: &str = "<!-- sqlite-dist install -->";
+const SECTION_END: &str = "<!-- /sqlite-dist install -->";
+
+/// Replaces the text between the install section markers of a README with `section`
+fn inject_section(readme: &str, section: &str) -> Option<String> {
+    let start = readme.find(SECTION_START)? + SECTION_START.len();
+    let end = start + readme[start..].find(SECTION_END)?;
+    Some(format!(
+        "{}\n{}\n{}",
+        &readme[..start],
+        section.trim(),
+        &readme[end..]
+    ))
+}
+
+/// Writes `INSTALL.md` with the install commands of every target and the release
+/// downloads, and replaces the install section of the configured README with it
+pub(crate) fn write_install_md(
+    project: &Project,
+    config: &TargetInstallMd,
+    assets: &[GeneratedAsset],
+    output_dir: &Path,
+) -> io::Result<Vec<GeneratedAsset>> {
+    let install_md = project.render_template(
+        "INSTALL.md",
+        None,
+        templates::install_md(project, &install_commands(project), assets),
+    )?;
+    if let Some(readme) = &config.readme {
+        let readme_path = project.spec_directory.join(readme);
+        let contents = fs::read_to_string(&readme_path)?;
+        let injected = inject_section(&contents, &install_md).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} has no {SECTION_START} ... {SECTION_END} section",
+                    readme_path.display()
+                ),
+            )
+        })?;
+        if !dry_run() && injected != contents {
+            fs::write(&readme_path, injected)?;
+        }
+    }
+    Ok(vec![GeneratedAsset::from(
+        GeneratedAssetKind::Docs,
+        &output_dir.join("INSTALL.md"),
+        install_md.as_bytes(),
+    )?])
+}
+
+mod templates {
+    use crate::{GeneratedAsset, Project};
+
+    pub(crate) fn install_md(
+        project: &Project,
+        install_commands: &[(&str, String)],
+        assets: &[GeneratedAsset],
+    ) -> String {
+        let mut md = format!(
+            "## Installing {} v{}\n",
+            project.spec.package.name, project.version
+        );
+        for (ecosystem, command) in install_commands {
+            let language = match *ecosystem {
+                "Swift Package Manager" => "swift",
+                _ => "bash",
+            };
+            md.push_str(&format!(
+                "\n### {ecosystem}\n\n```{language}\n{command}\n```\n"
+            ));
+        }
+        let downloads: Vec<String> = assets
+            .iter()
+            .filter(|asset| asset.kind.is_release_asset())
+            .map(|asset| {
+                format!(
+                    "- [`{}`]({})",
+                    asset.name,
+                    project.release_download_url(&asset.name)
+                )
+            })
+            .collect();
+        if !downloads.is_empty() {
+            md.push_str("\n### Downloads\n\n");
+            md.push_str(&downloads.join("\n"));
+            md.push('\n');
+        }
+        md
+    }
+}
@@ -50,10 +50,20 @@ pub(crate) mod templates {
             })
             .collect();
 
+        // latest.txt is only released by the update_api target
+        let latest_url = project
+            .spec
+            .targets
+            .update_api
+            .as_ref()
+            .map(|_| project.latest_download_url("latest.txt"))
+            .unwrap_or_default();
+        let yanked = project.spec.yanked.contains(&project.version);
         let usage = part_usage(project.version.to_string().as_str(), &targets);
         let current_target = part_current_target();
         let process_arguments = part_process_arguments();
-        let main = part_main(cases);
+        let upgrade = part_upgrade(&project.spec.package.name, &latest_url);
+        let main = part_main(&project.version.to_string(), yanked, cases);
         format!(
             r#"#!/bin/sh
 set -e
@@ -72,6 +82,8 @@ fi
 
 {process_arguments}
 
+{upgrade}
+
 {main}
 
 main "$@"
@@ -88,7 +100,7 @@ usage() {{
 sqlite-hello-install {version}
 
 USAGE:
-    $0 [static|loadable] [--target=target] [--prefix=path]
+    $0 [static|loadable] [--target=target] [--prefix=path] [--upgrade]
 
 OPTIONS:
     --target
@@ -96,6 +108,9 @@ OPTIONS:
 
     --prefix
             Specify a different directory to save the binaries. Defaults to the current working directory.
+
+    --upgrade
+            Install the latest release instead of {version}, unless the one already in the prefix is the same.
 EOF
 }}
 
@@ -114,6 +129,14 @@ current_target() {
   "Darwin x86_64") target=macos-x86_64 ;;
   "Darwin arm64") target=macos-aarch64 ;;
   "Linux x86_64") target=linux-x86_64 ;;
+  "Linux armv7l") target=linux-armv7a ;;
+  "Linux riscv64") target=linux-riscv64 ;;
+  "Linux loongarch64") target=linux-loongarch64 ;;
+  "Linux s390x") target=linux-s390x ;;
+  "FreeBSD amd64") target=freebsd-x86_64 ;;
+  "FreeBSD arm64") target=freebsd-aarch64 ;;
+  "OpenBSD amd64") target=openbsd-x86_64 ;;
+  "OpenBSD arm64") target=openbsd-aarch64 ;;
   *) target=$(uname -sm);;
   esac
 }
@@ -135,6 +158,9 @@ process_arguments() {
           --prefix=*)
               prefix="\${1#*=}"
               ;;
+          --upgrade)
+              upgrade=1
+              ;;
           static|loadable)
               type="$1"
               ;;
@@ -166,6 +192,42 @@ process_arguments() {
         .to_owned()
     }
 
+    /// Swaps `url` and `checksum` for the latest release's from latest.txt, and exits
+    /// when the installation in the prefix already is that download. The installed
+    /// version and checksum are kept in a marker file next to the binaries.
+    fn part_upgrade(name: &str, latest_url: &str) -> String {
+        format!(
+            r#"
+marker() {{
+  echo "$prefix/.{name}-install"
+}}
+
+upgrade() {{
+  local latest_url="{latest_url}"
+  if [ -z "$latest_url" ]; then
+    echo "This release has no latest.txt to upgrade from, enable [targets.update_api]" 1>&2
+    exit 1
+  fi
+  latest="$(curl --fail --silent --location "$latest_url")"
+  latest_version="$(echo "$latest" | awk '$1 == "version" {{ print $2 }}')"
+  # one "$target $type $url $sha256" line per download
+  download="$(echo "$latest" | awk -v platform="$target" -v type="$type" '$1 == platform && $2 == type {{ print $3; print $4 }}')"
+  if [ -z "$download" ]; then
+    echo "The latest release $latest_version has no $target $type binaries" 1>&2
+    exit 1
+  fi
+  url="$(echo "$download" | sed -n 1p)"
+  checksum="$(echo "$download" | sed -n 2p)"
+  version="$latest_version"
+  if [ -f "$(marker)" ] && [ "$(cat "$(marker)")" = "$latest_version $checksum" ]; then
+    echo "✅ $target $type binaries at $prefix are already the latest version, $latest_version."
+    exit 0
+  fi
+}}
+"#
+        )
+    }
+
     fn case(case: &Case) -> String {
         format!(
             r#"    "{os}-{cpu}-{t}")
@@ -179,8 +241,19 @@ process_arguments() {
             checksum = case.checksum
         )
     }
-    fn part_main(cases: Vec<Case>) -> String {
+    /// A yanked release installs the latest one instead
+    fn part_main(version: &str, yanked: bool, cases: Vec<Case>) -> String {
         let cases = cases.iter().map(case).collect::<Vec<_>>().join("\n");
+        let yanked = if yanked {
+            format!(
+                r#"
+    echo "{version} was yanked, installing the latest release instead" 1>&2
+    upgrade=1
+"#
+            )
+        } else {
+            String::new()
+        };
         format!(
             r#"
 main() {{
@@ -189,9 +262,11 @@ main() {{
     local prefix=""
     local url=""
     local checksum=""
+    local upgrade=""
+    local version="{version}"
 
     process_arguments "$@"
-
+{yanked}
     echo "${{BOLD}}Type${{RESET}}: $type"
     echo "${{BOLD}}Target${{RESET}}: $target"
     echo "${{BOLD}}Prefix${{RESET}}: $prefix"
@@ -204,6 +279,10 @@ main() {{
       ;;
     esac
 
+    if [ -n "$upgrade" ]; then
+      upgrade
+    fi
+
     extension="\${{url##*.}}"
 
     if [ "$extension" = "zip" ]; then
@@ -228,7 +307,9 @@ main() {{
       rm $tmpfile
     fi
 
-    echo "✅ $target $type binaries installed at $prefix."
+    echo "$version $checksum" > "$(marker)"
+
+    echo "✅ $target $type $version binaries installed at $prefix."
 }}
 
 "#
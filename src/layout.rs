@@ -0,0 +1,94 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    asset_name, create_output_dir, dry_run,
+    spec::{OutputLayout, FILE_NAME_VARIABLES},
+    with_path, Cpu, GeneratedAsset, Os, Project,
+};
+
+/// Name of a GitHub release archive from `output.file_name`, or None for the default names
+pub(crate) fn release_file_name(
+    project: &Project,
+    artifact_type: &str,
+    os: &Os,
+    cpu: &Cpu,
+    ext: &str,
+) -> Option<String> {
+    let pattern = project.spec.output.as_ref()?.file_name.as_ref()?;
+    let values = [
+        project.spec.package.name.clone(),
+        project.version.to_string(),
+        artifact_type.to_owned(),
+        os.to_string(),
+        cpu.to_string(),
+        ext.to_owned(),
+    ];
+    Some(
+        FILE_NAME_VARIABLES
+            .iter()
+            .zip(values)
+            .fold(pattern.clone(), |name, (variable, value)| {
+                name.replace(&format!("{{{variable}}}"), &value)
+            }),
+    )
+}
+
+pub(crate) fn layout(project: &Project) -> OutputLayout {
+    project
+        .spec
+        .output
+        .as_ref()
+        .map(|output| output.layout)
+        .unwrap_or_default()
+}
+
+/// Directory a target writes its assets to
+pub(crate) fn target_dir(project: &Project, output_dir: &Path, target: &str) -> PathBuf {
+    match layout(project) {
+        OutputLayout::ByTarget => output_dir.join(target),
+        OutputLayout::Flat | OutputLayout::ByPlatform => output_dir.to_path_buf(),
+    }
+}
+
+/// Moves the platform specific assets of a target into `$OS-$CPU` directories
+/// for the by-platform layout
+pub(crate) fn relocate(
+    project: &Project,
+    output_dir: &Path,
+    assets: Vec<GeneratedAsset>,
+) -> io::Result<Vec<GeneratedAsset>> {
+    if layout(project) != OutputLayout::ByPlatform {
+        return Ok(assets);
+    }
+    let mut destinations = HashSet::new();
+    let mut relocated = vec![];
+    for mut asset in assets {
+        let Some((os, cpu)) = asset.kind.platform() else {
+            relocated.push(asset);
+            continue;
+        };
+        let directory = output_dir.join(format!("{}-{}", os.to_string(), cpu.to_string()));
+        let source = PathBuf::from(&asset.path);
+        let destination = directory.join(asset_name(&source));
+        if !destinations.insert(destination.clone()) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} would be written twice with this output.layout",
+                    destination.display()
+                ),
+            ));
+        }
+        if !dry_run() {
+            create_output_dir(&directory)?;
+            fs::rename(&source, &destination).map_err(with_path(&source))?;
+        }
+        asset.path = destination.to_string_lossy().into_owned();
+        relocated.push(asset);
+    }
+    Ok(relocated)
+}
@@ -0,0 +1,2272 @@
+mod amalgamation;
+mod archives;
+mod badges;
+mod binary;
+pub mod bump;
+mod cache;
+mod capabilities;
+mod checksum_log;
+pub mod ci;
+mod cli_bundle;
+mod debug_symbols;
+pub mod diff;
+mod docker;
+mod docs_site;
+pub mod doctor;
+pub mod gem;
+mod gh_releases;
+mod homebrew;
+pub mod http;
+pub mod init;
+mod install_md;
+mod installer_sh;
+mod layout;
+pub mod lint;
+mod macho;
+mod manifest;
+mod names;
+pub mod npm;
+pub mod pip;
+mod plugin;
+pub mod publish;
+mod release_notes;
+mod signing;
+pub mod spec;
+mod spm;
+mod sqlpkg;
+mod static_libraries;
+mod strip;
+mod target;
+mod template_overrides;
+mod update_api;
+mod verify;
+pub mod versions;
+pub mod wheels;
+mod workers;
+pub mod workspace;
+mod xcframework;
+
+use cache::{cached, BuildCache};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use gem::GemBuildError;
+use manifest::write_manifest;
+use npm::NpmBuildError;
+use pip::PipBuildError;
+use plugin::PluginError;
+use semver::Version;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use spec::{
+    ByteSize, OutputLayout, Spec, SpecError, SpecLoadables, Targets, WheelCompression,
+    ASSET_URL_VARIABLES,
+};
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    fs::{self, File},
+    io::{self, Cursor, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread::{self, ScopedJoinHandle},
+    time::{Duration, Instant},
+};
+use tar::Header;
+pub use target::{Target, TargetContext, TargetRegistry};
+use template_overrides::TemplateOverrides;
+use verify::VerifyError;
+
+/// One set of packages to generate: a spec at a version, with the platform builds it ships.
+pub struct Project {
+    version: Version,
+    spec: Spec,
+    spec_directory: PathBuf,
+    platform_directories: Vec<PlatformDirectory>,
+    wasm_directory: Option<WasmDirectory>,
+    template_overrides: Option<TemplateOverrides>,
+}
+
+impl Project {
+    /// A project for `spec` at `version` without any platforms yet, for using
+    /// sqlite-dist as a library.
+    pub fn new(spec: Spec, version: Version) -> Self {
+        Self {
+            version,
+            spec,
+            spec_directory: PathBuf::from("."),
+            platform_directories: vec![],
+            wasm_directory: None,
+            template_overrides: None,
+        }
+    }
+
+    /// Directory that relative paths in the spec are resolved against, defaults to `.`
+    pub fn spec_directory(mut self, spec_directory: PathBuf) -> Self {
+        self.spec_directory = spec_directory;
+        self
+    }
+
+    pub fn platform(mut self, platform: PlatformDirectory) -> Self {
+        self.platform_directories.push(platform);
+        self
+    }
+
+    pub fn wasm(mut self, wasm: WasmDirectory) -> Self {
+        self.wasm_directory = Some(wasm);
+        self
+    }
+
+    /// Generates every target of the spec into `output_dir`
+    pub fn generate(&self, output_dir: &Path) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let (assets, _) = generate(self, output_dir, None, &TargetRegistry::builtin(), None)?;
+        Ok(assets)
+    }
+
+    /// URL release asset `name` is downloaded from, on the repo's GitHub
+    /// releases unless `package.asset_url_template` says otherwise
+    pub(crate) fn release_download_url(&self, name: &str) -> String {
+        let tag = self.spec.package.git_tag(&self.version);
+        self.asset_url(&self.version.to_string(), &tag, name)
+            .unwrap_or_else(|| {
+                let gh_base = &self.spec.package.repo;
+                format!("{gh_base}/releases/download/{tag}/{name}")
+            })
+    }
+
+    /// URL of asset `name` of whichever release is the latest, ex `latest.txt` of
+    /// the update API. `package.asset_url_template` gets `latest` as its version and tag.
+    pub(crate) fn latest_download_url(&self, name: &str) -> String {
+        self.asset_url("latest", "latest", name).unwrap_or_else(|| {
+            let gh_base = &self.spec.package.repo;
+            format!("{gh_base}/releases/latest/download/{name}")
+        })
+    }
+
+    fn asset_url(&self, version: &str, tag: &str, name: &str) -> Option<String> {
+        let template = self.spec.package.asset_url_template.as_ref()?;
+        let values = [self.spec.package.name.as_str(), version, tag, name];
+        Some(
+            ASSET_URL_VARIABLES
+                .iter()
+                .zip(values)
+                .fold(template.clone(), |url, (variable, value)| {
+                    url.replace(&format!("{{{variable}}}"), value)
+                }),
+        )
+    }
+}
+
+/// Checks the generated pip, npm, and gem loaders run when loading the extension
+#[derive(Clone, Copy)]
+pub(crate) struct LoaderChecks<'a> {
+    /// fail when the connection's SQLite is older
+    pub(crate) min_sqlite_version: Option<&'a Version>,
+    /// `[verify] sql`, which must return this version after loading
+    pub(crate) version_check: Option<(&'a str, &'a Version)>,
+}
+
+impl Project {
+    pub(crate) fn loader_checks(&self) -> LoaderChecks<'_> {
+        LoaderChecks {
+            min_sqlite_version: self
+                .spec
+                .sqlite
+                .as_ref()
+                .filter(|sqlite| sqlite.runtime_check)
+                .and_then(|sqlite| sqlite.min_version.as_ref()),
+            version_check: self
+                .spec
+                .verify
+                .as_ref()
+                .filter(|verify| verify.runtime_check)
+                .map(|verify| (verify.sql.as_str(), &self.version)),
+        }
+    }
+}
+
+/// The loadables, static libraries, and headers of one `$OS-$CPU` input directory
+#[derive(Debug, Clone)]
+pub struct PlatformDirectory {
+    os: Os,
+    cpu: Cpu,
+    path: PathBuf,
+    loadable_files: Vec<LoadablePlatformFile>,
+    static_files: Vec<PlatformFile>,
+    header_files: Vec<PlatformFile>,
+    /// `sqlite3` shell bundled by the cli target
+    sqlite3: Option<PlatformFile>,
+    /// the spec's `extra_files` for this platform, named by their path in the packages
+    extra_files: Vec<PlatformFile>,
+    /// `.dSYM` bundles and `.pdb` files next to the loadables, and the unstripped
+    /// `.debug` copies of stripped ones
+    debug_files: Vec<PlatformFile>,
+}
+
+/// An Emscripten build of SQLite with the extension compiled in, read from
+/// the `wasm32-emscripten` input directory.
+#[derive(Debug, Clone)]
+pub struct WasmDirectory {
+    files: Vec<PlatformFile>,
+}
+
+impl WasmDirectory {
+    pub fn from_path(base_path: PathBuf) -> Result<Self, PlatformDirectoryError> {
+        let mut files = vec![];
+        for entry in fs::read_dir(&base_path)? {
+            let entry_path = entry?.path();
+            if !entry_path.is_file() {
+                continue;
+            }
+            let name = entry_path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or(PlatformDirectoryError::InvalidCharacters)?
+                .to_string();
+            files.push(PlatformFile {
+                name,
+                data: fs::read(&entry_path)?,
+                metadata: Some(fs::metadata(&entry_path)?),
+                unsigned_sha256: None,
+            });
+        }
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Self { files })
+    }
+
+    /// The JavaScript entrypoint of the Emscripten build, ex `sqlite3.mjs`
+    fn module(&self) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|file| file.name.ends_with(".mjs"))
+            .or_else(|| self.files.iter().find(|file| file.name.ends_with(".js")))
+            .map(|file| file.name.as_str())
+    }
+
+    /// The WebAssembly binary the module loads, ex `sqlite3.wasm`
+    fn wasm(&self) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|file| file.name.ends_with(".wasm"))
+            .map(|file| file.name.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Os {
+    Macos,
+    Linux,
+    Windows,
+    Android,
+    Ios,
+    IosSimulator,
+    Emscripten,
+    Freebsd,
+    Openbsd,
+}
+
+impl Serialize for Os {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Os {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        Os::ALL
+            .iter()
+            .find(|os| os.to_string() == name)
+            .cloned()
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown os '{name}'")))
+    }
+}
+
+impl Os {
+    const ALL: [Os; 9] = [
+        Os::Macos,
+        Os::Linux,
+        Os::Windows,
+        Os::Android,
+        Os::Ios,
+        Os::IosSimulator,
+        Os::Emscripten,
+        Os::Freebsd,
+        Os::Openbsd,
+    ];
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for Os {
+    fn to_string(&self) -> String {
+        match self {
+            Os::Macos => "macos".to_owned(),
+            Os::Linux => "linux".to_owned(),
+            Os::Windows => "windows".to_owned(),
+            Os::Android => "android".to_owned(),
+            Os::Ios => "ios".to_owned(),
+            Os::IosSimulator => "iossimulator".to_owned(),
+            Os::Emscripten => "emscripten".to_owned(),
+            Os::Freebsd => "freebsd".to_owned(),
+            Os::Openbsd => "openbsd".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Cpu {
+    X86_64,
+    Aarch64,
+    I686,
+    Armv7a,
+    Riscv64,
+    Loongarch64,
+    S390x,
+    Wasm32,
+    /// fat x86_64 + arm64 macOS binaries
+    Universal2,
+}
+
+impl Serialize for Cpu {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Cpu {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        Cpu::ALL
+            .iter()
+            .find(|cpu| cpu.to_string() == name)
+            .cloned()
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown cpu '{name}'")))
+    }
+}
+
+impl Cpu {
+    const ALL: [Cpu; 9] = [
+        Cpu::X86_64,
+        Cpu::Aarch64,
+        Cpu::I686,
+        Cpu::Armv7a,
+        Cpu::Riscv64,
+        Cpu::Loongarch64,
+        Cpu::S390x,
+        Cpu::Wasm32,
+        Cpu::Universal2,
+    ];
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for Cpu {
+    fn to_string(&self) -> String {
+        match self {
+            Cpu::X86_64 => "x86_64".to_owned(),
+            Cpu::Aarch64 => "aarch64".to_owned(),
+            Cpu::I686 => "i686".to_owned(),
+            Cpu::Armv7a => "armv7a".to_owned(),
+            Cpu::Riscv64 => "riscv64".to_owned(),
+            Cpu::Loongarch64 => "loongarch64".to_owned(),
+            Cpu::S390x => "s390x".to_owned(),
+            Cpu::Wasm32 => "wasm32".to_owned(),
+            Cpu::Universal2 => "universal2".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GithubRelease {
+    pub url: String,
+    pub platform: (Os, Cpu),
+}
+
+#[derive(Debug, Clone)]
+pub enum GeneratedAssetKind {
+    Npm(Option<(Os, Cpu)>),
+    Gem((Os, Cpu)),
+    Pip((Os, Cpu)),
+    /// the transitional wheel of `[rename]`
+    PipRename,
+    Datasette,
+    SqliteUtils,
+    GithubReleaseLoadable(GithubRelease),
+    GithubReleaseStatic(GithubRelease),
+    GithubReleaseWasm(GithubRelease),
+    Sqlpkg,
+    Spm,
+    Amalgamation,
+    Static((Os, Cpu)),
+    Xcframework,
+    Cli((Os, Cpu)),
+    Archive((Os, Cpu)),
+    /// the formula, or a platform's bottle and its JSON
+    Homebrew(Option<(Os, Cpu)>),
+    Docker,
+    DebugSymbols((Os, Cpu)),
+    Docs,
+    /// shields.io endpoint JSON
+    Badges,
+    /// latest.json and versions.json
+    UpdateApi,
+    ReleaseNotes,
+    /// produced by the `[[plugins]]` entry of this name
+    Plugin(String),
+    Manifest,
+}
+
+/// File extension -> MIME type of the uploaded assets, `.tar.gz` before `.gz`
+const MEDIA_TYPES: [(&str, &str); 13] = [
+    (".tar.gz", "application/gzip"),
+    (".tgz", "application/gzip"),
+    (".gz", "application/gzip"),
+    (".whl", "application/zip"),
+    (".zip", "application/zip"),
+    (".gem", "application/x-tar"),
+    (".wasm", "application/wasm"),
+    (".json", "application/json"),
+    (".sh", "text/x-shellscript"),
+    (".md", "text/markdown; charset=utf-8"),
+    (".html", "text/html; charset=utf-8"),
+    (".txt", "text/plain; charset=utf-8"),
+    (".log", "text/plain; charset=utf-8"),
+];
+
+/// MIME type of a file by its extension, for files no asset kind describes
+pub(crate) fn media_type(name: &str) -> &'static str {
+    MEDIA_TYPES
+        .iter()
+        .find(|(extension, _)| name.ends_with(extension))
+        .map_or("application/octet-stream", |(_, media_type)| media_type)
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for GeneratedAssetKind {
+    fn to_string(&self) -> String {
+        match self {
+            GeneratedAssetKind::Npm(_) => "npm".to_owned(),
+            GeneratedAssetKind::Gem(_) => "gem".to_owned(),
+            GeneratedAssetKind::Pip(_) => "pip".to_owned(),
+            GeneratedAssetKind::PipRename => "pip-rename".to_owned(),
+            GeneratedAssetKind::Datasette => "datasette".to_owned(),
+            GeneratedAssetKind::SqliteUtils => "sqlite-utils".to_owned(),
+            GeneratedAssetKind::GithubReleaseLoadable(_) => "github-release-loadable".to_owned(),
+            GeneratedAssetKind::GithubReleaseStatic(_) => "github-release-static".to_owned(),
+            GeneratedAssetKind::GithubReleaseWasm(_) => "github-release-wasm".to_owned(),
+            GeneratedAssetKind::Sqlpkg => "sqlpkg".to_owned(),
+            GeneratedAssetKind::Spm => "spm".to_owned(),
+            GeneratedAssetKind::Amalgamation => "amalgamation".to_owned(),
+            GeneratedAssetKind::Static(_) => "static".to_owned(),
+            GeneratedAssetKind::Xcframework => "xcframework".to_owned(),
+            GeneratedAssetKind::Cli(_) => "cli".to_owned(),
+            GeneratedAssetKind::Archive(_) => "archive".to_owned(),
+            GeneratedAssetKind::Homebrew(_) => "homebrew".to_owned(),
+            GeneratedAssetKind::Docker => "docker".to_owned(),
+            GeneratedAssetKind::DebugSymbols(_) => "debug-symbols".to_owned(),
+            GeneratedAssetKind::Docs => "docs".to_owned(),
+            GeneratedAssetKind::Badges => "badges".to_owned(),
+            GeneratedAssetKind::UpdateApi => "update-api".to_owned(),
+            GeneratedAssetKind::ReleaseNotes => "release-notes".to_owned(),
+            GeneratedAssetKind::Plugin(name) => name.clone(),
+            GeneratedAssetKind::Manifest => "sqlite-dist-manifest".to_owned(),
+        }
+    }
+}
+impl GeneratedAssetKind {
+    pub fn platform(&self) -> Option<(&Os, &Cpu)> {
+        match self {
+            GeneratedAssetKind::Npm(Some((os, cpu)))
+            | GeneratedAssetKind::Homebrew(Some((os, cpu)))
+            | GeneratedAssetKind::Gem((os, cpu))
+            | GeneratedAssetKind::Pip((os, cpu))
+            | GeneratedAssetKind::Static((os, cpu))
+            | GeneratedAssetKind::Cli((os, cpu))
+            | GeneratedAssetKind::Archive((os, cpu))
+            | GeneratedAssetKind::DebugSymbols((os, cpu)) => Some((os, cpu)),
+            GeneratedAssetKind::GithubReleaseLoadable(gh_release)
+            | GeneratedAssetKind::GithubReleaseStatic(gh_release)
+            | GeneratedAssetKind::GithubReleaseWasm(gh_release) => {
+                Some((&gh_release.platform.0, &gh_release.platform.1))
+            }
+            _ => None,
+        }
+    }
+
+    /// What an asset of this kind is, ex "Python wheel for linux-x86_64"
+    pub fn description(&self) -> String {
+        let description = match self {
+            GeneratedAssetKind::Npm(_) => "npm package",
+            GeneratedAssetKind::Gem(_) => "Ruby gem",
+            GeneratedAssetKind::Pip(_) => "Python wheel",
+            GeneratedAssetKind::PipRename => "transitional Python wheel of the previous name",
+            GeneratedAssetKind::Datasette => "Datasette plugin wheel",
+            GeneratedAssetKind::SqliteUtils => "sqlite-utils plugin wheel",
+            GeneratedAssetKind::GithubReleaseLoadable(_) => "loadable extension archive",
+            GeneratedAssetKind::GithubReleaseStatic(_) => "static library archive",
+            GeneratedAssetKind::GithubReleaseWasm(_) => "WebAssembly build archive",
+            GeneratedAssetKind::Sqlpkg => "sqlpkg package spec",
+            GeneratedAssetKind::Spm => "Swift package",
+            GeneratedAssetKind::Amalgamation => "amalgamation",
+            GeneratedAssetKind::Static(_) => "static library",
+            GeneratedAssetKind::Xcframework => "XCFramework",
+            GeneratedAssetKind::Cli(_) => "SQLite CLI with the extension",
+            GeneratedAssetKind::Archive(_) => "distribution archive",
+            GeneratedAssetKind::Homebrew(None) => "Homebrew formula",
+            GeneratedAssetKind::Homebrew(Some(_)) => "Homebrew bottle",
+            GeneratedAssetKind::Docker => "Docker build context",
+            GeneratedAssetKind::DebugSymbols(_) => "debug symbols",
+            GeneratedAssetKind::Docs => "documentation site",
+            GeneratedAssetKind::Badges => "shields.io badge",
+            GeneratedAssetKind::UpdateApi => "update API",
+            GeneratedAssetKind::ReleaseNotes => "release notes",
+            GeneratedAssetKind::Plugin(name) => return format!("{name} plugin asset"),
+            GeneratedAssetKind::Manifest => "build manifest",
+        };
+        match self.platform() {
+            Some((os, cpu)) => format!("{description} for {}-{}", os.to_string(), cpu.to_string()),
+            None => description.to_owned(),
+        }
+    }
+
+    /// Canonical extension and MIME type of the kind's assets, None for kinds
+    /// with several formats, where the file name decides
+    pub fn format(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            GeneratedAssetKind::Npm(_) => Some(("tgz", "application/gzip")),
+            GeneratedAssetKind::Gem(_) => Some(("gem", "application/x-tar")),
+            GeneratedAssetKind::Pip(_)
+            | GeneratedAssetKind::PipRename
+            | GeneratedAssetKind::Datasette
+            | GeneratedAssetKind::SqliteUtils => Some(("whl", "application/zip")),
+            GeneratedAssetKind::Xcframework => Some(("zip", "application/zip")),
+            GeneratedAssetKind::Badges
+            | GeneratedAssetKind::UpdateApi
+            | GeneratedAssetKind::Manifest => Some(("json", "application/json")),
+            _ => None,
+        }
+    }
+
+    /// MIME type of an asset of this kind named `name`
+    pub fn media_type(&self, name: &str) -> &'static str {
+        self.format()
+            .map_or_else(|| media_type(name), |(_, media_type)| media_type)
+    }
+
+    /// Whether the asset is uploaded to the release and listed in checksums.txt
+    pub(crate) fn is_release_asset(&self) -> bool {
+        matches!(
+            self,
+            GeneratedAssetKind::GithubReleaseLoadable(_)
+                | GeneratedAssetKind::GithubReleaseStatic(_)
+                | GeneratedAssetKind::GithubReleaseWasm(_)
+                | GeneratedAssetKind::Sqlpkg
+                | GeneratedAssetKind::Spm
+                | GeneratedAssetKind::Static(_)
+                | GeneratedAssetKind::Xcframework
+                | GeneratedAssetKind::Cli(_)
+                | GeneratedAssetKind::Archive(_)
+                | GeneratedAssetKind::Homebrew(_)
+                | GeneratedAssetKind::DebugSymbols(_)
+                | GeneratedAssetKind::Badges
+                | GeneratedAssetKind::UpdateApi
+        )
+    }
+}
+
+impl Serialize for GeneratedAssetKind {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_str())
+    }
+}
+
+#[derive(Serialize)]
+pub struct GeneratedAsset {
+    pub kind: GeneratedAssetKind,
+    pub name: String,
+    pub path: String,
+    pub checksum_sha256: String,
+    pub size: usize,
+}
+#[derive(Serialize)]
+pub struct AssetPlatform<'a> {
+    pub os: &'a Os,
+    pub cpu: &'a Cpu,
+}
+
+/// A generated asset as printed by `--output-format json`
+#[derive(Serialize)]
+pub struct AssetSummary<'a> {
+    pub kind: &'a GeneratedAssetKind,
+    pub description: String,
+    pub media_type: &'static str,
+    pub name: &'a str,
+    pub path: &'a str,
+    pub checksum_sha256: &'a str,
+    pub size: usize,
+    pub platform: Option<AssetPlatform<'a>>,
+}
+
+impl<'a> From<&'a GeneratedAsset> for AssetSummary<'a> {
+    fn from(asset: &'a GeneratedAsset) -> Self {
+        Self {
+            kind: &asset.kind,
+            description: asset.kind.description(),
+            media_type: asset.kind.media_type(&asset.name),
+            name: &asset.name,
+            path: &asset.path,
+            checksum_sha256: &asset.checksum_sha256,
+            size: asset.size,
+            platform: asset
+                .kind
+                .platform()
+                .map(|(os, cpu)| AssetPlatform { os, cpu }),
+        }
+    }
+}
+
+/// Set by `--dry-run`: every generator still runs, but nothing is written to disk.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Adds the path to an I/O error, which otherwise doesn't say which file failed
+fn with_path(path: &Path) -> impl FnOnce(io::Error) -> io::Error + '_ {
+    move |err| io::Error::new(err.kind(), format!("{}: {err}", path.display()))
+}
+
+fn create_output_dir(path: &Path) -> io::Result<()> {
+    if dry_run() {
+        return Ok(());
+    }
+    fs::create_dir_all(path).map_err(with_path(path))
+}
+
+/// Where a build writes its assets: an empty sibling of the output directory
+/// that replaces it by `commit` once every target succeeded. A failed build
+/// never leaves a half-written output directory behind.
+struct StagingDir {
+    path: PathBuf,
+    output_dir: PathBuf,
+    committed: bool,
+}
+
+impl StagingDir {
+    fn create(output_dir: &Path) -> io::Result<Self> {
+        let staging = Self {
+            path: sibling_dir(output_dir, "staging"),
+            output_dir: output_dir.to_path_buf(),
+            committed: false,
+        };
+        if staging.path.exists() {
+            fs::remove_dir_all(&staging.path).map_err(with_path(&staging.path))?;
+        }
+        fs::create_dir_all(&staging.path).map_err(with_path(&staging.path))?;
+        Ok(staging)
+    }
+
+    /// `path` of an asset written to the staging directory, once it's moved into place
+    fn final_path(&self, path: &str) -> String {
+        Path::new(path).strip_prefix(&self.path).map_or_else(
+            |_| path.to_owned(),
+            |relative| {
+                self.output_dir
+                    .join(relative)
+                    .to_string_lossy()
+                    .into_owned()
+            },
+        )
+    }
+
+    /// Replaces the output directory with the staging directory. The paths of `keep`,
+    /// relative to the output directory, are moved over from the previous one first,
+    /// unless the build wrote them again. Every rename is undone if one fails.
+    fn commit(mut self, keep: &[PathBuf]) -> io::Result<()> {
+        let mut moved = vec![];
+        for relative in keep {
+            if let Err(err) = self.carry_over(relative, &mut moved) {
+                undo_moves(&moved);
+                return Err(err);
+            }
+        }
+        let previous = sibling_dir(&self.output_dir, "previous");
+        let had_previous = self.output_dir.exists();
+        if had_previous {
+            if let Err(err) = fs::rename(&self.output_dir, &previous) {
+                undo_moves(&moved);
+                return Err(with_path(&self.output_dir)(err));
+            }
+        }
+        if let Err(err) = fs::rename(&self.path, &self.output_dir) {
+            if had_previous {
+                let _ = fs::rename(&previous, &self.output_dir);
+            }
+            undo_moves(&moved);
+            return Err(with_path(&self.output_dir)(err));
+        }
+        self.committed = true;
+        // only the replaced files of the previous build are left in it
+        if had_previous {
+            if let Err(err) = fs::remove_dir_all(&previous) {
+                tracing::warn!("could not remove {}: {err}", previous.display());
+            }
+        }
+        Ok(())
+    }
+
+    fn carry_over(&self, relative: &Path, moved: &mut Vec<(PathBuf, PathBuf)>) -> io::Result<()> {
+        let from = self.output_dir.join(relative);
+        let to = self.path.join(relative);
+        if fs::symlink_metadata(&from).is_err() || fs::symlink_metadata(&to).is_ok() {
+            return Ok(());
+        }
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).map_err(with_path(parent))?;
+        }
+        fs::rename(&from, &to).map_err(with_path(&to))?;
+        moved.push((from, to));
+        Ok(())
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+/// `.{name}.{suffix}-{pid}` next to the output directory, on the same filesystem
+/// so it can be renamed into place
+fn sibling_dir(output_dir: &Path, suffix: &str) -> PathBuf {
+    let name = output_dir
+        .file_name()
+        .map_or_else(|| "dist".into(), |name| name.to_string_lossy());
+    output_dir.with_file_name(format!(".{name}.{suffix}-{}", std::process::id()))
+}
+
+fn undo_moves(moved: &[(PathBuf, PathBuf)]) {
+    for (from, to) in moved.iter().rev() {
+        let _ = fs::rename(to, from);
+    }
+}
+
+/// What a build carries over from the previous output directory, relative to it:
+/// the publish state and cache, the assets it reused from the cache, and the
+/// `previous_artifacts` of the targets a partial build didn't run. The release
+/// files are kept when the build didn't write them again.
+fn kept_paths(
+    output_dir: &Path,
+    staging_dir: &Path,
+    assets: &[GeneratedAsset],
+    previous_artifacts: &[(PathBuf, serde_json::Value)],
+    keep_release_files: bool,
+) -> Vec<PathBuf> {
+    let mut keep: Vec<PathBuf> = [cache::CACHE_FILE, publish::PUBLISH_STATE_FILE]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    if keep_release_files {
+        let log = checksum_log::LOG_FILE;
+        for name in ["checksums.txt", "install.sh", log, &format!("{log}.sig")] {
+            keep.push(PathBuf::from(name));
+        }
+    }
+    // cache hits point into the previous output directory, not the staging one
+    keep.extend(
+        assets
+            .iter()
+            .map(|asset| Path::new(&asset.path))
+            .filter(|path| !path.starts_with(staging_dir))
+            .filter_map(|path| path.strip_prefix(output_dir).ok())
+            .map(Path::to_path_buf),
+    );
+    keep.extend(
+        previous_artifacts
+            .iter()
+            .filter_map(|(path, _)| Some(path.strip_prefix(output_dir).ok()?.to_path_buf())),
+    );
+    keep
+}
+
+/// Destination of an asset that is streamed instead of written in one go,
+/// which is an in-memory buffer during a dry run.
+enum OutputFile {
+    File(File),
+    Buffer(Cursor<Vec<u8>>),
+}
+
+impl OutputFile {
+    fn create(path: &Path) -> io::Result<Self> {
+        if dry_run() {
+            return Ok(Self::Buffer(Cursor::new(vec![])));
+        }
+        Ok(Self::File(File::create(path).map_err(with_path(path))?))
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::File(file) => file.write(buf),
+            Self::Buffer(buffer) => buffer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.flush(),
+            Self::Buffer(buffer) => buffer.flush(),
+        }
+    }
+}
+
+impl Seek for OutputFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::File(file) => file.seek(pos),
+            Self::Buffer(buffer) => buffer.seek(pos),
+        }
+    }
+}
+
+impl GeneratedAsset {
+    fn from(kind: GeneratedAssetKind, path: &PathBuf, contents: &[u8]) -> io::Result<Self> {
+        if !dry_run() {
+            File::create(path)
+                .and_then(|mut file| file.write_all(contents))
+                .map_err(with_path(path))?;
+        }
+        Ok(Self {
+            kind,
+            name: asset_name(path),
+            path: path.to_string_lossy().into_owned(),
+            checksum_sha256: base16ct::lower::encode_string(&Sha256::digest(contents)),
+            size: contents.len(),
+        })
+    }
+
+    /// Like `from`, for assets that were already streamed to `path`
+    fn from_written(
+        kind: GeneratedAssetKind,
+        path: &PathBuf,
+        output: OutputFile,
+    ) -> io::Result<Self> {
+        let mut hasher = Sha256::new();
+        let size = match output {
+            OutputFile::File(_) => File::open(path)
+                .and_then(|mut file| io::copy(&mut file, &mut hasher))
+                .map_err(with_path(path))?,
+            OutputFile::Buffer(buffer) => {
+                io::copy(&mut buffer.into_inner().as_slice(), &mut hasher)?
+            }
+        };
+        Ok(Self {
+            kind,
+            name: asset_name(path),
+            path: path.to_string_lossy().into_owned(),
+            checksum_sha256: base16ct::lower::encode_string(&hasher.finalize()),
+            size: size as usize,
+        })
+    }
+}
+//{"kind": "github_release", "name": "...", "path": "./", "checksum_sha256": ""},
+
+fn asset_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+struct PlatformFile {
+    name: String,
+    data: Vec<u8>,
+    metadata: Option<std::fs::Metadata>,
+    /// sha256 of `data` before it was signed. Signatures are timestamped, so the
+    /// build cache hashes this instead to reuse the assets of signed builds.
+    unsigned_sha256: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+struct LoadablePlatformFile {
+    file_stem: String,
+    file: PlatformFile,
+}
+
+impl PlatformFile {
+    fn new<S: Into<String>, D: Into<Vec<u8>>>(
+        name: S,
+        data: D,
+        metadata: Option<fs::Metadata>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            data: data.into(),
+            metadata,
+            unsigned_sha256: None,
+        }
+    }
+}
+
+use thiserror::Error;
+
+fn create_targz(files: &[&PlatformFile]) -> io::Result<Vec<u8>> {
+    let mut tar_gz = Vec::new();
+    {
+        let enc = GzEncoder::new(&mut tar_gz, Compression::default());
+        let mut tar = tar::Builder::new(enc);
+        for file in files {
+            let mut header = Header::new_gnu();
+            header.set_path(file.name.clone())?;
+            header.set_size(file.data.len() as u64);
+            if let Some(metadata) = &file.metadata {
+                header.set_metadata(metadata);
+            } else {
+                header.set_mode(0o700);
+                header.set_mtime(
+                    std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs(),
+                );
+            }
+            header.set_cksum();
+            tar.append::<&[u8]>(&header, file.data.as_ref())?;
+        }
+        tar.finish()?;
+    };
+    Ok(tar_gz)
+}
+
+/// Options for a zip entry of `size` bytes. Entries of 4 GiB and more need Zip64
+/// extra fields, which have to be requested before the entry is written. Archives
+/// with more than 65535 entries get a Zip64 end of central directory on their own.
+fn zip_options(method: zip::CompressionMethod, size: usize) -> zip::write::FileOptions {
+    zip::write::FileOptions::default()
+        .compression_method(method)
+        .large_file(size as u64 >= u32::MAX as u64)
+}
+
+fn create_zip(files: &[&PlatformFile]) -> io::Result<Vec<u8>> {
+    let mut zipfile = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+    for file in files {
+        let options = zip_options(zip::CompressionMethod::Deflated, file.data.len());
+        zipfile.start_file(file.name.as_str(), options)?;
+        zipfile.write_all(&file.data)?;
+    }
+    Ok(zipfile.finish()?.into_inner())
+}
+
+#[derive(Error, Debug)]
+pub enum PlatformDirectoryError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] io::Error),
+
+    #[error("Expected name of directory")]
+    MissingDirectoryName,
+    #[error("directory or file name must contains only valid UTF-8 characters")]
+    InvalidCharacters,
+    #[error("directory {0} is not a valid platform directory. The format must be $OS-$CPU.")]
+    InvalidDirectoryName(String),
+    #[error("Invalid operating system '{0}'. Must be one of 'macos', 'linux', 'windows', 'android', 'ios', 'iossimulator', 'freebsd', or 'openbsd'")]
+    InvalidOsValue(String),
+    #[error("Invalid CPU name '{0}'. Must be one of 'x86_64', 'aarch64', 'i686', 'armv7a' (or 'armv7'), 'riscv64' (or 'riscv64gc'), 'loongarch64', 's390x', or 'universal2'")]
+    InvalidCpuValue(String),
+}
+
+/// Reads every file of a bundle directory like `foo0.dylib.dSYM`, named by their
+/// path relative to `base_path`
+fn read_bundle(
+    path: &Path,
+    base_path: &Path,
+    files: &mut Vec<PlatformFile>,
+) -> Result<(), PlatformDirectoryError> {
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+    for entry_path in entries {
+        if entry_path.is_dir() {
+            read_bundle(&entry_path, base_path, files)?;
+            continue;
+        }
+        let name = entry_path
+            .strip_prefix(base_path)
+            .expect("bundle files to be inside the platform directory")
+            .components()
+            .map(|component| component.as_os_str().to_str())
+            .collect::<Option<Vec<&str>>>()
+            .ok_or(PlatformDirectoryError::InvalidCharacters)?
+            .join("/");
+        files.push(PlatformFile {
+            name,
+            data: fs::read(&entry_path)?,
+            metadata: Some(fs::metadata(&entry_path)?),
+            unsigned_sha256: None,
+        });
+    }
+    Ok(())
+}
+
+impl PlatformDirectory {
+    pub fn os(&self) -> &Os {
+        &self.os
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Warns that `target` is skipped for this platform. Only used for the BSDs,
+    /// which have loadables but no package format in most registries.
+    fn warn_unsupported(&self, target: &str) {
+        if matches!(self.os, Os::Freebsd | Os::Openbsd) {
+            tracing::warn!(
+                "skipping {}-{} for the {target} target, it has no platform tag for it",
+                self.os.to_string(),
+                self.cpu.to_string()
+            );
+        }
+    }
+
+    /// Span around generating the packages of this platform, shown as a progress bar by the CLI
+    pub(crate) fn span(&self) -> tracing::Span {
+        tracing::info_span!("platform", os = %self.os.to_string(), cpu = %self.cpu.to_string())
+    }
+
+    pub fn from_path(base_path: PathBuf) -> Result<Self, PlatformDirectoryError> {
+        let mut loadable_files = vec![];
+        let mut static_files = vec![];
+        let mut header_files = vec![];
+        let mut sqlite3 = None;
+        let mut debug_files = vec![];
+
+        let dirname = base_path
+            .components()
+            .next_back()
+            .ok_or(PlatformDirectoryError::MissingDirectoryName)?
+            .as_os_str()
+            .to_str()
+            .ok_or(PlatformDirectoryError::InvalidCharacters)?;
+        let mut s = dirname.split('-');
+        let os = match s
+            .next()
+            .ok_or_else(|| PlatformDirectoryError::InvalidDirectoryName(dirname.to_owned()))?
+        {
+            "macos" => Os::Macos,
+            "linux" => Os::Linux,
+            "windows" => Os::Windows,
+            "android" => Os::Android,
+            "ios" => Os::Ios,
+            "iossimulator" => Os::IosSimulator,
+            "freebsd" => Os::Freebsd,
+            "openbsd" => Os::Openbsd,
+            os => return Err(PlatformDirectoryError::InvalidOsValue(os.to_owned())),
+        };
+        let cpu = match s
+            .next()
+            .ok_or_else(|| PlatformDirectoryError::InvalidDirectoryName(dirname.to_owned()))?
+        {
+            "x86_64" => Cpu::X86_64,
+            "aarch64" => Cpu::Aarch64,
+            "i686" => Cpu::I686,
+            // armv7 builds are hard-float (gnueabihf) for linux
+            "armv7a" | "armv7" => Cpu::Armv7a,
+            "riscv64" | "riscv64gc" => Cpu::Riscv64,
+            "loongarch64" => Cpu::Loongarch64,
+            "s390x" => Cpu::S390x,
+            "universal2" => Cpu::Universal2,
+            cpu => return Err(PlatformDirectoryError::InvalidCpuValue(cpu.to_owned())),
+        };
+        if s.next().is_some() {
+            return Err(PlatformDirectoryError::InvalidDirectoryName(
+                dirname.to_owned(),
+            ));
+        }
+
+        let dir = fs::read_dir(&base_path)?;
+        for entry in dir {
+            let entry_path = entry?.path();
+            let file_name = entry_path.file_name().and_then(|name| name.to_str());
+            if entry_path.is_dir() && file_name.is_some_and(|name| name.ends_with(".dSYM")) {
+                read_bundle(&entry_path, &base_path, &mut debug_files)?;
+                continue;
+            }
+            if let Some(name @ ("sqlite3" | "sqlite3.exe")) = file_name {
+                sqlite3 = Some(PlatformFile::new(
+                    name,
+                    fs::read(&entry_path)?,
+                    Some(fs::metadata(&entry_path)?),
+                ));
+                continue;
+            }
+            match entry_path.extension().and_then(|e| e.to_str()) {
+                Some("so") | Some("dll") | Some("dylib") => {
+                    let name = entry_path
+                        .file_name()
+                        .expect("file_name to exist because there is an extension")
+                        .to_str()
+                        .ok_or(PlatformDirectoryError::InvalidCharacters)?
+                        .to_string();
+                    let data = fs::read(&entry_path)?;
+                    let metadata = Some(fs::metadata(&entry_path)?);
+                    let file_stem = entry_path
+                        .file_stem()
+                        .expect("file_stem to exist because there is an extension")
+                        .to_str()
+                        .ok_or(PlatformDirectoryError::InvalidCharacters)?
+                        .to_string();
+                    loadable_files.push(LoadablePlatformFile {
+                        file_stem,
+                        file: PlatformFile {
+                            name: name.to_string(),
+                            data,
+                            metadata,
+                            unsigned_sha256: None,
+                        },
+                    });
+                }
+                Some("a") | Some("lib") => {
+                    let name = entry_path
+                        .file_name()
+                        .expect("file_name to exist because there is an extension")
+                        .to_str()
+                        .ok_or(PlatformDirectoryError::InvalidCharacters)?
+                        .to_string();
+                    let data = fs::read(&entry_path)?;
+                    let metadata = Some(fs::metadata(&entry_path)?);
+                    static_files.push(PlatformFile {
+                        name: name.to_string(),
+                        data,
+                        metadata,
+                        unsigned_sha256: None,
+                    });
+                }
+                Some("pdb") => {
+                    let name = entry_path
+                        .file_name()
+                        .expect("file_name to exist because there is an extension")
+                        .to_str()
+                        .ok_or(PlatformDirectoryError::InvalidCharacters)?
+                        .to_string();
+                    let data = fs::read(&entry_path)?;
+                    let metadata = Some(fs::metadata(&entry_path)?);
+                    debug_files.push(PlatformFile {
+                        name,
+                        data,
+                        metadata,
+                        unsigned_sha256: None,
+                    });
+                }
+                Some("h") => {
+                    let name = entry_path
+                        .file_name()
+                        .expect("file_name to exist because there is an extension")
+                        .to_str()
+                        .ok_or(PlatformDirectoryError::InvalidCharacters)?
+                        .to_string();
+                    let data = fs::read(&entry_path)?;
+                    let metadata = Some(fs::metadata(&entry_path)?);
+                    header_files.push(PlatformFile {
+                        name: name.to_string(),
+                        data,
+                        metadata,
+                        unsigned_sha256: None,
+                    });
+                }
+                _ => {
+                    tracing::warn!(
+                        "unknown file type in platform directory: {}",
+                        entry_path.display()
+                    );
+                }
+            }
+        }
+        Ok(PlatformDirectory {
+            os,
+            cpu,
+            path: base_path,
+            loadable_files,
+            static_files,
+            header_files,
+            sqlite3,
+            extra_files: vec![],
+            debug_files,
+        })
+    }
+}
+
+impl PlatformDirectory {
+    /// Extension the platform's loader expects, without the dot
+    fn loadable_extension(&self) -> &'static str {
+        match self.os {
+            Os::Windows => "dll",
+            Os::Macos | Os::Ios | Os::IosSimulator => "dylib",
+            _ => "so",
+        }
+    }
+
+    /// Renames the loadables by the spec's `[loadables]` rules
+    fn normalize_loadables(&mut self, rules: &SpecLoadables) -> Result<(), BuildError> {
+        let extension = self.loadable_extension();
+        for loadable in &mut self.loadable_files {
+            let mut stem = loadable.file_stem.clone();
+            if rules.strip_lib_prefix && self.os != Os::Windows {
+                if let Some(stripped) = stem.strip_prefix("lib") {
+                    stem = stripped.to_owned();
+                }
+            }
+            if let Some(renamed) = rules.rename.get(&stem) {
+                stem = renamed.clone();
+            }
+            let current_extension = loadable
+                .file
+                .name
+                .rsplit_once('.')
+                .map_or("", |(_, extension)| extension)
+                .to_owned();
+            let extension = if rules.normalize_extension {
+                extension
+            } else {
+                current_extension.as_str()
+            };
+            loadable.file.name = format!("{stem}.{extension}");
+            loadable.file_stem = stem;
+        }
+        for (i, loadable) in self.loadable_files.iter().enumerate() {
+            if self.loadable_files[..i]
+                .iter()
+                .any(|other| other.file_stem == loadable.file_stem)
+            {
+                return Err(BuildError::SpecError(format!(
+                    "more than one loadable in {} is named '{}' after applying [loadables]",
+                    self.path.display(),
+                    loadable.file_stem
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Strips the ELF loadables, keeping the originals as `<name>.debug` debug files
+    fn strip_loadables(&mut self) {
+        for loadable in &mut self.loadable_files {
+            let Some(stripped) = strip::strip_elf(&loadable.file.data) else {
+                continue;
+            };
+            tracing::debug!(
+                "stripped {} from {} to {} bytes",
+                loadable.file.name,
+                loadable.file.data.len(),
+                stripped.len()
+            );
+            let original = std::mem::replace(&mut loadable.file.data, stripped);
+            self.debug_files.push(PlatformFile::new(
+                format!("{}.debug", loadable.file.name),
+                original,
+                loadable.file.metadata.clone(),
+            ));
+        }
+    }
+
+    /// A copy of this platform directory with only the given loadables, in the given order.
+    fn with_loadables(&self, names: &[&str]) -> Result<Self, BuildError> {
+        let loadable_files = names
+            .iter()
+            .map(|name| {
+                self.loadable_files
+                    .iter()
+                    .find(|loadable| loadable.file_stem == *name)
+                    .cloned()
+                    .ok_or_else(|| {
+                        BuildError::SpecError(format!(
+                            "{} does not contain a loadable for the '{name}' extension",
+                            self.path.display()
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            loadable_files,
+            ..self.clone()
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum BuildError {
+    #[error("`{0}` is a required argument")]
+    RequiredArg(String),
+    #[error("Invalid spec file: {0}")]
+    InvalidSpec(#[from] SpecError),
+    #[error("Invalid version '{0}': {1}")]
+    InvalidVersion(String, semver::Error),
+    #[error("specfile error: `{0}`")]
+    SpecError(String),
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Invalid platform directory: {0}")]
+    PlayformDirectoryError(#[from] PlatformDirectoryError),
+
+    #[error("Error building a pip package: {0}")]
+    PipBuildEror(#[from] PipBuildError),
+    #[error("Error building an npm package: {0}")]
+    NpmBuildEror(#[from] NpmBuildError),
+    #[error("Error building a gem: {0}")]
+    GemBuildError(#[from] GemBuildError),
+    #[error("Verification failed: {0}")]
+    VerifyError(#[from] VerifyError),
+    #[error("{0}")]
+    PluginError(#[from] PluginError),
+    #[error("{0}")]
+    BumpError(#[from] bump::BumpError),
+    #[error("{0}")]
+    InitError(#[from] init::InitError),
+    #[error("{0}")]
+    WheelCheckError(#[from] wheels::WheelCheckError),
+    #[error("Error generating CI config: {0}")]
+    CiError(#[from] ci::CiError),
+    #[error("Error writing the checksum log: {0}")]
+    ChecksumLogError(#[from] checksum_log::ChecksumLogError),
+    #[error("Publishing failed: {0}")]
+    PublishError(#[from] publish::PublishError),
+    #[error("Signing failed: {0}")]
+    SigningError(#[from] signing::SigningError),
+    #[error("Invalid workspace: {0}")]
+    WorkspaceError(#[from] workspace::WorkspaceError),
+    #[error(
+        "the version converts inconsistently between ecosystems:{}",
+        .0.iter().map(|problem| format!("\n  {problem}")).collect::<String>()
+    )]
+    InconsistentVersions(Vec<String>),
+    #[error("{asset} is {size}, over the {limit} limit. To fit, {suggestions}")]
+    SizeLimitExceeded {
+        asset: String,
+        size: ByteSize,
+        limit: ByteSize,
+        suggestions: String,
+    },
+    #[error(
+        "{} targets failed:{}",
+        .0.len(),
+        .0.iter().map(|err| format!("\n  {err}")).collect::<String>()
+    )]
+    Failures(Vec<BuildError>),
+    #[error("{package}/{target}: {source}")]
+    TargetError {
+        package: String,
+        target: String,
+        source: Box<BuildError>,
+    },
+}
+
+/// Upload limits of the registries, used unless the spec's `size_limits` sets the target's
+const DEFAULT_SIZE_LIMITS: [(&str, u64); 2] = [
+    // PyPI's default, larger limits have to be requested for the project
+    ("pip", 100 << 20),
+    ("github_releases", 2 << 30),
+];
+
+fn check_size_limits(
+    spec: &Spec,
+    target: &str,
+    assets: &[GeneratedAsset],
+) -> Result<(), BuildError> {
+    let limit = spec.size_limits.get(target).copied().or_else(|| {
+        DEFAULT_SIZE_LIMITS
+            .iter()
+            .find(|(name, _)| *name == target)
+            .map(|(_, limit)| ByteSize(*limit))
+    });
+    let Some(limit) = limit else {
+        return Ok(());
+    };
+    let Some(asset) = assets.iter().find(|asset| asset.size as u64 > limit.0) else {
+        return Ok(());
+    };
+    let mut suggestions = vec![];
+    if !spec.loadables.as_ref().is_some_and(|l| l.strip) {
+        suggestions.push("strip the loadables with `loadables.strip = true`".to_owned());
+    }
+    if target == "pip"
+        && spec
+            .targets
+            .pip
+            .as_ref()
+            .is_some_and(|pip| matches!(pip.compression, WheelCompression::Stored))
+    {
+        suggestions
+            .push("compress the wheels with `targets.pip.compression = \"deflated\"`".to_owned());
+    }
+    suggestions.push(format!("raise `size_limits.{target}`"));
+    Err(BuildError::SizeLimitExceeded {
+        asset: asset.name.clone(),
+        size: ByteSize(asset.size as u64),
+        limit,
+        suggestions: suggestions.join(", or "),
+    })
+}
+
+/// The assets of one target and how long it took
+type TargetOutput = (Vec<GeneratedAsset>, TargetTiming);
+
+fn generate(
+    project: &Project,
+    output_dir: &Path,
+    cache: Option<&Mutex<BuildCache>>,
+    registry: &TargetRegistry,
+    failures: Option<&Mutex<Vec<BuildError>>>,
+) -> Result<(Vec<GeneratedAsset>, Vec<TargetTiming>), BuildError> {
+    // targets don't depend on each other's output, so each one is generated on its own
+    // thread. Handles are joined in spawn order to keep the asset order deterministic.
+    thread::scope(|scope| {
+        let handles: Vec<ScopedJoinHandle<Result<TargetOutput, BuildError>>> = registry
+            .targets()
+            .filter(|target| target.enabled(&project.spec))
+            .map(|target| {
+                scope.spawn(move || {
+                    let _span = tracing::info_span!(
+                        "target",
+                        package = project.spec.package.name.as_str(),
+                        target = target.name()
+                    )
+                    .entered();
+                    let start = Instant::now();
+                    let ctx = TargetContext {
+                        project,
+                        output_dir,
+                    };
+                    let config = target.config(&project.spec);
+                    let cache = cache.filter(|_| target.cacheable());
+                    let assets = cached(cache, project, target.name(), &config, || {
+                        Ok(layout::relocate(
+                            project,
+                            output_dir,
+                            target.generate(&ctx)?,
+                        )?)
+                    })
+                    .and_then(|assets| {
+                        check_size_limits(&project.spec, target.name(), &assets)?;
+                        Ok(assets)
+                    })
+                    .map_err(|err| BuildError::TargetError {
+                        package: project.spec.package.name.clone(),
+                        target: target.name().to_owned(),
+                        source: Box::new(err),
+                    })?;
+                    let timing = TargetTiming {
+                        package: project.spec.package.name.clone(),
+                        target: target.name().to_owned(),
+                        duration: start.elapsed(),
+                        assets: assets.len(),
+                    };
+                    tracing::debug!(
+                        "generated {} assets in {:.2?}",
+                        timing.assets,
+                        timing.duration
+                    );
+                    Ok((assets, timing))
+                })
+            })
+            .collect();
+
+        let mut generated_assets: Vec<GeneratedAsset> = vec![];
+        let mut timings = vec![];
+        for handle in handles {
+            match (handle.join().expect("generator thread panicked"), failures) {
+                (Ok((assets, timing)), _) => {
+                    generated_assets.extend(assets);
+                    timings.push(timing);
+                }
+                (Err(err), Some(failures)) => {
+                    tracing::error!("{err}");
+                    failures.lock().unwrap().push(err);
+                }
+                (Err(err), None) => return Err(err),
+            }
+        }
+        Ok((generated_assets, timings))
+    })
+}
+
+/// Everything a release build needs, normally parsed from the command line
+pub struct BuildOptions {
+    pub input_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub spec_path: PathBuf,
+    /// overrides package.version in the spec
+    pub version: Option<String>,
+    pub verify: bool,
+    pub dry_run: bool,
+    pub no_cache: bool,
+    pub only: Option<Vec<String>>,
+    pub skip: Vec<String>,
+    /// generate the other targets when one fails, and report every failure at the end
+    pub keep_going: bool,
+    /// overrides output.layout in the spec
+    pub layout: Option<OutputLayout>,
+    /// applies the spec's `[profiles.<name>]`
+    pub profile: Option<String>,
+    /// builds the dev version after the spec's version instead
+    pub dev: Option<bump::DevVersionSource>,
+    /// fail when npm, pip, and gem would order the version differently
+    pub strict: bool,
+    /// starts a new checksum log when the spec's `checksum_log.previous` doesn't exist
+    pub init_checksum_log: bool,
+}
+
+/// How long one target took to generate for one package
+pub struct TargetTiming {
+    pub package: String,
+    pub target: String,
+    pub duration: Duration,
+    pub assets: usize,
+}
+
+/// The result of a [`build`]
+pub struct BuildOutput {
+    /// every written asset, the manifest last
+    pub assets: Vec<GeneratedAsset>,
+    /// in generation order, for spotting slow targets
+    pub timings: Vec<TargetTiming>,
+}
+
+/// Generates every package of the spec at `options.spec_path`, plus the
+/// checksums.txt, install.sh, and manifest that cover them. Returns every asset,
+/// the manifest last.
+pub fn build(options: &BuildOptions) -> Result<BuildOutput, BuildError> {
+    build_with_targets(options, TargetRegistry::builtin())
+}
+
+/// Like [`build`], with custom targets registered next to the built-in ones
+pub fn build_with_targets(
+    options: &BuildOptions,
+    mut registry: TargetRegistry,
+) -> Result<BuildOutput, BuildError> {
+    let input_dir = &options.input_dir;
+    let output_dir = &options.output_dir;
+    let input_file = &options.spec_path;
+    let mut spec = Spec::from_path(input_file)?;
+    let profile = options
+        .profile
+        .as_deref()
+        .map(|name| spec.apply_profile(name))
+        .transpose()?;
+    if let Some(layout) = options.layout {
+        spec.output.get_or_insert_with(Default::default).layout = layout;
+    }
+    for plugin in &spec.plugins {
+        registry.register(plugin::PluginTarget::new(plugin.clone()));
+    }
+    let only = options.only.as_deref();
+    let skip = &options.skip;
+    let custom_names = registry.custom_names();
+    let profile_targets = profile
+        .as_ref()
+        .and_then(|profile| profile.targets.as_deref());
+    for name in only
+        .unwrap_or_default()
+        .iter()
+        .chain(skip)
+        .chain(profile_targets.unwrap_or_default())
+    {
+        if !Targets::NAMES.contains(&name.as_str()) && !custom_names.contains(&name.as_str()) {
+            return Err(BuildError::SpecError(format!(
+                "unknown target '{name}', must be one of {}",
+                [Targets::NAMES.as_slice(), custom_names.as_slice()]
+                    .concat()
+                    .join(", ")
+            )));
+        }
+    }
+    if profile_targets.is_some() {
+        spec.targets.select(profile_targets, &[]);
+        registry.select(profile_targets, &[]);
+    }
+    spec.targets.select(only, skip);
+    registry.select(only, skip);
+    for target in registry.targets() {
+        if target.enabled(&spec) {
+            target.validate(&spec)?;
+        }
+    }
+    let partial = only.is_some() || !skip.is_empty() || profile_targets.is_some();
+    // a partial build keeps the checksums.txt and install.sh of the last full one,
+    // unless it regenerated the release assets they cover
+    let write_release_files = !partial || spec.targets.github_releases.is_some();
+    DRY_RUN.store(options.dry_run, Ordering::Relaxed);
+    let mut version = match &options.version {
+        Some(version) => Version::parse(version)
+            .map_err(|err| BuildError::InvalidVersion(version.to_owned(), err))?,
+        None => spec
+            .package
+            .version
+            .clone()
+            .ok_or_else(|| BuildError::RequiredArg("version".to_owned()))?,
+    };
+    if let Some(source) = options.dev {
+        let spec_directory = input_file.parent().unwrap_or(Path::new("."));
+        version = bump::dev_version(&version, source, spec_directory)?;
+        bump::check_version(&spec, &version)?;
+    }
+    if let Some(suffix) = profile
+        .as_ref()
+        .and_then(|profile| profile.version_suffix.as_deref())
+    {
+        let pre = if version.pre.is_empty() {
+            suffix.to_owned()
+        } else {
+            format!("{}.{suffix}", version.pre)
+        };
+        version.pre = semver::Prerelease::new(&pre).map_err(|err| {
+            BuildError::InvalidVersion(
+                format!(
+                    "{}.{}.{}-{pre}",
+                    version.major, version.minor, version.patch
+                ),
+                err,
+            )
+        })?;
+    }
+
+    let report = versions::version_report(&version);
+    tracing::info!(
+        "versions: npm {}, pip {}, gem {}",
+        report.npm,
+        report.pip.as_deref().unwrap_or("-"),
+        report.gem
+    );
+    for problem in &report.problems {
+        tracing::warn!("{problem}");
+    }
+    if options.strict && !report.problems.is_empty() {
+        return Err(BuildError::InconsistentVersions(report.problems));
+    }
+
+    // from the output directory, before the staging directory takes its place
+    let cache = (!options.no_cache && !dry_run()).then(|| Mutex::new(BuildCache::load(output_dir)));
+    let staging = (!dry_run())
+        .then(|| StagingDir::create(output_dir))
+        .transpose()?;
+    let output_dir = staging
+        .as_ref()
+        .map_or(output_dir.as_path(), |s| s.path.as_path());
+
+    let mut entries = fs::read_dir(input_dir)?
+        .map(|entry| {
+            Ok(entry
+                .map_err(|_| {
+                    BuildError::SpecError("Could not read entry in input directory".to_owned())
+                })?
+                .path())
+        })
+        .collect::<Result<Vec<PathBuf>, BuildError>>()?;
+
+    let wasm_directory = entries
+        .iter()
+        .position(|entry| entry.file_name() == Some(OsStr::new("wasm32-emscripten")))
+        .map(|item| WasmDirectory::from_path(entries.remove(item)))
+        .transpose()?;
+    let platform_directories: Result<Vec<PlatformDirectory>, BuildError> = entries
+        .iter()
+        .map(|entry| {
+            PlatformDirectory::from_path(entry.to_owned())
+                .map_err(BuildError::PlayformDirectoryError)
+        })
+        .collect();
+    let mut platform_directories = platform_directories?;
+    if let Some(rules) = &spec.loadables {
+        for platform_dir in &mut platform_directories {
+            platform_dir.normalize_loadables(rules)?;
+            if rules.strip {
+                platform_dir.strip_loadables();
+            }
+        }
+    }
+
+    if spec.macos.as_ref().is_some_and(|macos| macos.universal2) {
+        let find_macos = |cpu: Cpu| {
+            platform_directories
+                .iter()
+                .find(|platform_dir| platform_dir.os == Os::Macos && platform_dir.cpu == cpu)
+        };
+        let (Some(x86_64), Some(aarch64)) = (find_macos(Cpu::X86_64), find_macos(Cpu::Aarch64))
+        else {
+            return Err(BuildError::SpecError(
+                "macos.universal2 requires both macos-x86_64 and macos-aarch64 input directories"
+                    .to_owned(),
+            ));
+        };
+        let universal2 = macho::universal2_platform_directory(x86_64, aarch64)?;
+        platform_directories.push(universal2);
+    }
+    // after universal2, which is lipo'd from the unsigned slices and signed on its own
+    if let Some(sign) = spec.macos.as_ref().and_then(|macos| macos.sign.as_ref()) {
+        let spec_directory = input_file.parent().unwrap_or(Path::new("."));
+        for platform_dir in &mut platform_directories {
+            if platform_dir.os == Os::Macos {
+                signing::sign_macos(platform_dir, sign, spec_directory)?;
+            }
+        }
+    }
+    if let Some(sign) = spec
+        .windows
+        .as_ref()
+        .and_then(|windows| windows.sign.as_ref())
+    {
+        let spec_directory = input_file.parent().unwrap_or(Path::new("."));
+        for platform_dir in &mut platform_directories {
+            if platform_dir.os == Os::Windows {
+                signing::sign_windows(
+                    platform_dir,
+                    sign,
+                    spec_directory,
+                    &spec.package.description,
+                    &spec.package.homepage,
+                )?;
+            }
+        }
+    }
+    for platform_dir in &mut platform_directories {
+        let name = format!(
+            "{}-{}",
+            platform_dir.os.to_string(),
+            platform_dir.cpu.to_string()
+        );
+        for extra_file in &spec.extra_files {
+            if !extra_file.platforms.is_empty() && !extra_file.platforms.contains(&name) {
+                continue;
+            }
+            let path = input_file
+                .parent()
+                .unwrap_or(Path::new("."))
+                .join(&extra_file.path);
+            platform_dir.extra_files.push(PlatformFile::new(
+                extra_file.package_path(),
+                fs::read(&path).map_err(with_path(&path))?,
+                Some(fs::metadata(&path).map_err(with_path(&path))?),
+            ));
+        }
+    }
+
+    if options.verify {
+        verify::verify_host_loadable(&spec, &platform_directories)?;
+    }
+
+    let spec_directory = input_file.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let template_overrides = spec
+        .templates
+        .as_ref()
+        .map(|templates| TemplateOverrides::load(&spec_directory.join(templates)))
+        .transpose()?;
+    let mut projects = spec
+        .extensions
+        .iter()
+        .filter_map(|extension| Some((extension.package.as_ref()?, extension)))
+        .map(|(package, extension)| -> Result<Project, BuildError> {
+            let mut spec = spec.clone();
+            spec.package.name = package.clone();
+            spec.extensions = vec![];
+            Ok(Project {
+                version: version.clone(),
+                spec,
+                spec_directory: spec_directory.clone(),
+                wasm_directory: wasm_directory.clone(),
+                template_overrides: template_overrides.clone(),
+                platform_directories: platform_directories
+                    .iter()
+                    .map(|pd| pd.with_loadables(&[extension.name.as_str()]))
+                    .collect::<Result<Vec<_>, _>>()?,
+            })
+        })
+        .collect::<Result<Vec<Project>, BuildError>>()?;
+    let combined_extensions: Vec<&str> = spec
+        .extensions
+        .iter()
+        .filter(|extension| extension.package.is_none())
+        .map(|extension| extension.name.as_str())
+        .collect();
+    if spec.extensions.is_empty() || !combined_extensions.is_empty() {
+        let platform_directories = if spec.extensions.is_empty() {
+            platform_directories
+        } else {
+            platform_directories
+                .iter()
+                .map(|pd| pd.with_loadables(&combined_extensions))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        projects.insert(
+            0,
+            Project {
+                version,
+                spec,
+                spec_directory,
+                platform_directories,
+                wasm_directory,
+                template_overrides,
+            },
+        );
+    }
+
+    let failures = options.keep_going.then(|| Mutex::new(vec![]));
+    let project_assets = thread::scope(|scope| {
+        let cache = cache.as_ref();
+        let registry = &registry;
+        let failures = failures.as_ref();
+        let handles: Vec<_> = projects
+            .iter()
+            .map(|project| {
+                scope.spawn(move || generate(project, output_dir, cache, registry, failures))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("generator thread panicked"))
+            .collect::<Result<Vec<_>, BuildError>>()
+    })?;
+    let failures = failures
+        .map(|failures| failures.into_inner().unwrap())
+        .unwrap_or_default();
+    if !failures.is_empty() {
+        // the output directory is left as it was. The assets of the targets that did
+        // succeed go away with the staging directory, so only the cache is saved.
+        if let (Some(cache), Some(staging)) = (cache, &staging) {
+            let mut cache = cache.into_inner().unwrap();
+            cache.forget_under(&staging.path);
+            if options.output_dir.is_dir() {
+                cache.save(&options.output_dir)?;
+            }
+        }
+        return Err(BuildError::Failures(failures));
+    }
+    let mut generated_assets: Vec<GeneratedAsset> = vec![];
+    let mut timings = vec![];
+    let mut install_sh = None;
+    let mut docs = vec![];
+    for (project, (assets, project_timings)) in projects.iter().zip(project_assets) {
+        timings.extend(project_timings);
+        // install.sh and the docs only cover the first (main) package
+        if install_sh.is_none() {
+            install_sh = Some(project.render_template(
+                "install.sh",
+                None,
+                crate::installer_sh::templates::install_sh(project, &assets),
+            )?);
+            if project.spec.targets.docs.is_some() {
+                docs = docs_site::write_docs_site(project, &assets, output_dir)?;
+            }
+            if let Some(config) = &project.spec.targets.badges {
+                docs.extend(badges::write_badges(
+                    project,
+                    config.color.as_deref().unwrap_or("blue"),
+                    &assets,
+                    output_dir,
+                )?);
+            }
+            if let Some(config) = &project.spec.targets.update_api {
+                docs.extend(update_api::write_update_api(
+                    project, config, &assets, output_dir,
+                )?);
+            }
+            if let Some(config) = &project.spec.targets.install_md {
+                docs.extend(install_md::write_install_md(
+                    project, config, &assets, output_dir,
+                )?);
+            }
+        }
+        generated_assets.extend(assets);
+    }
+    generated_assets.extend(docs);
+
+    let release_checksums: Vec<(&str, &str)> = generated_assets
+        .iter()
+        .filter(|ga| ga.kind.is_release_asset())
+        .map(|ga| (ga.name.as_str(), ga.checksum_sha256.as_str()))
+        .collect();
+    let github_releases_checksums_txt = release_checksums
+        .iter()
+        .map(|(name, checksum)| format!("{name} {checksum}"))
+        .collect::<Vec<String>>()
+        .join("\n");
+    if dry_run() {
+        return Ok(BuildOutput {
+            assets: generated_assets,
+            timings,
+        });
+    }
+    if write_release_files {
+        File::create(output_dir.join("checksums.txt"))?
+            .write_all(github_releases_checksums_txt.as_bytes())?;
+        if let Some(install_sh) = install_sh {
+            File::create(output_dir.join("install.sh"))?.write_all(install_sh.as_bytes())?;
+        }
+        if let Some(project) = projects.first() {
+            if let Some(checksum_log) = &project.spec.checksum_log {
+                checksum_log::write_checksum_log(
+                    checksum_log,
+                    &project.spec_directory,
+                    &project.version,
+                    &release_checksums,
+                    output_dir,
+                    options.init_checksum_log,
+                )?;
+            }
+        }
+    }
+    let previous_artifacts = match &staging {
+        Some(_) if partial => {
+            let generated: HashSet<String> = generated_assets
+                .iter()
+                .map(|asset| asset.kind.to_string())
+                .collect();
+            manifest::previous_artifacts(&options.output_dir, &generated)
+        }
+        _ => vec![],
+    };
+    let keep = staging.as_ref().map(|staging| {
+        kept_paths(
+            &options.output_dir,
+            &staging.path,
+            &generated_assets,
+            &previous_artifacts,
+            !write_release_files,
+        )
+    });
+    if let Some(staging) = &staging {
+        for asset in &mut generated_assets {
+            asset.path = staging.final_path(&asset.path);
+        }
+    }
+    let kept_artifacts: Vec<serde_json::Value> = previous_artifacts
+        .into_iter()
+        .map(|(_, artifact)| artifact)
+        .collect();
+    let mut manifest = write_manifest(
+        output_dir,
+        projects.first(),
+        &generated_assets,
+        &kept_artifacts,
+    )?;
+    if let Some(cache) = cache {
+        let mut cache = cache.into_inner().unwrap();
+        if let Some(staging) = &staging {
+            cache.relocate(|path| staging.final_path(path));
+        }
+        cache.save(output_dir)?;
+    }
+    if let Some(staging) = staging {
+        manifest.path = staging.final_path(&manifest.path);
+        staging.commit(&keep.unwrap_or_default())?;
+    }
+    generated_assets.push(manifest);
+    Ok(BuildOutput {
+        assets: generated_assets,
+        timings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs::{self, File},
+        io::{self, Read, Write},
+    };
+
+    use super::{
+        build_with_targets, create_zip, zip_options, BuildError, BuildOptions, GeneratedAsset,
+        PlatformDirectory, PlatformDirectoryError, PlatformFile, Target, TargetContext,
+        TargetRegistry,
+    };
+    use crate::spec::Spec;
+
+    /// A spec with pip and datasette targets and a linux-x86_64 loadable, in its own
+    /// temporary directory
+    struct Fixture {
+        dir: std::path::PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("sqlite-dist-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("input/linux-x86_64")).unwrap();
+            fs::write(dir.join("input/linux-x86_64/sample0.so"), b"not really ELF").unwrap();
+            fs::write(
+                dir.join("sqlite-dist.toml"),
+                r#"
+[package]
+name = "sqlite-sample"
+license = "MIT"
+homepage = "https://example.com/sqlite-sample"
+repo = "https://github.com/example/sqlite-sample"
+description = "A sample SQLite extension"
+authors = ["Example"]
+
+[targets]
+pip = {}
+datasette = {}
+"#,
+            )
+            .unwrap();
+            Self { dir }
+        }
+
+        fn options(&self, version: &str) -> BuildOptions {
+            BuildOptions {
+                input_dir: self.dir.join("input"),
+                output_dir: self.dir.join("dist"),
+                spec_path: self.dir.join("sqlite-dist.toml"),
+                version: Some(version.to_owned()),
+                verify: false,
+                dry_run: false,
+                no_cache: false,
+                only: None,
+                skip: vec![],
+                keep_going: false,
+                layout: None,
+                profile: None,
+                dev: None,
+                strict: false,
+                init_checksum_log: false,
+            }
+        }
+
+        fn files(&self, target: &str) -> Vec<String> {
+            let mut files: Vec<String> = fs::read_dir(self.dir.join("dist").join(target))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            files.sort();
+            files
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn build(options: &BuildOptions) -> Result<Vec<GeneratedAsset>, BuildError> {
+        build_with_targets(options, TargetRegistry::builtin()).map(|output| output.assets)
+    }
+
+    fn wheel(assets: &[GeneratedAsset]) -> &GeneratedAsset {
+        assets
+            .iter()
+            .find(|asset| asset.name.ends_with(".whl"))
+            .unwrap()
+    }
+
+    #[test]
+    fn rebuild_reuses_cached_assets() {
+        let fixture = Fixture::new("rebuild");
+        let first = build(&fixture.options("0.1.0")).unwrap();
+        // same size, so it only passes for the cache if it's left alone
+        let marker = vec![b'x'; wheel(&first).size];
+        fs::write(&wheel(&first).path, &marker).unwrap();
+
+        let second = build(&fixture.options("0.1.0")).unwrap();
+        assert_eq!(wheel(&second).path, wheel(&first).path);
+        assert_eq!(fs::read(&wheel(&second).path).unwrap(), marker);
+
+        let mut options = fixture.options("0.1.0");
+        options.no_cache = true;
+        build(&options).unwrap();
+        assert_ne!(fs::read(&wheel(&second).path).unwrap(), marker);
+    }
+
+    #[test]
+    fn version_bump_removes_previous_assets() {
+        let fixture = Fixture::new("bump");
+        build(&fixture.options("0.1.0")).unwrap();
+        build(&fixture.options("0.2.0")).unwrap();
+        assert_eq!(
+            fixture.files("pip"),
+            ["sqlite_sample-0.2.0-py3-none-manylinux_2_17_x86_64.manylinux2014_x86_64.manylinux1_x86_64.whl"]
+        );
+    }
+
+    #[test]
+    fn partial_build_keeps_other_targets() {
+        let fixture = Fixture::new("partial");
+        build(&fixture.options("0.1.0")).unwrap();
+        let datasette = fixture.files("datasette");
+        for version in ["0.2.0", "0.3.0"] {
+            let mut options = fixture.options(version);
+            options.only = Some(vec!["pip".to_owned()]);
+            build(&options).unwrap();
+        }
+        assert_eq!(fixture.files("datasette"), datasette);
+        assert_eq!(fixture.files("pip").len(), 1);
+        let manifest =
+            fs::read_to_string(fixture.dir.join("dist/sqlite-dist-manifest.json")).unwrap();
+        assert!(manifest.contains("\"kind\": \"datasette\""), "{manifest}");
+        assert!(fixture.dir.join("dist/checksums.txt").exists());
+    }
+
+    #[test]
+    fn profile_suffixes_the_version_and_selects_targets() {
+        let fixture = Fixture::new("profile");
+        let spec_path = fixture.dir.join("sqlite-dist.toml");
+        let spec = fs::read_to_string(&spec_path).unwrap();
+        fs::write(
+            &spec_path,
+            format!("{spec}\n[profiles.dev]\nversion_suffix = \"dev\"\ntargets = [\"pip\"]\n"),
+        )
+        .unwrap();
+        let mut options = fixture.options("0.1.0");
+        options.profile = Some("dev".to_owned());
+        build(&options).unwrap();
+        assert_eq!(
+            fixture.files("pip"),
+            ["sqlite_sample-0.1.0.dev0-py3-none-manylinux_2_17_x86_64.manylinux2014_x86_64.manylinux1_x86_64.whl"]
+        );
+        assert!(!fixture.dir.join("dist/datasette").exists());
+    }
+
+    struct FailingTarget;
+
+    impl Target for FailingTarget {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn enabled(&self, _spec: &Spec) -> bool {
+            true
+        }
+
+        fn generate(&self, _ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+            Err(BuildError::SpecError("failing target".to_owned()))
+        }
+    }
+
+    #[test]
+    fn failed_build_leaves_output_alone() {
+        let fixture = Fixture::new("failed");
+        build(&fixture.options("0.1.0")).unwrap();
+        let pip = fixture.files("pip");
+
+        let mut options = fixture.options("0.2.0");
+        options.keep_going = true;
+        let mut registry = TargetRegistry::builtin();
+        registry.register(FailingTarget);
+        let Err(BuildError::Failures(failures)) = build_with_targets(&options, registry) else {
+            panic!("the failing target should fail the build");
+        };
+        assert_eq!(failures.len(), 1);
+        assert_eq!(fixture.files("pip"), pip);
+        let staging: Vec<_> = fs::read_dir(&fixture.dir)
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with('.')
+            })
+            .collect();
+        assert!(staging.is_empty());
+
+        // the cache of the last successful build still applies
+        let second = build(&fixture.options("0.1.0")).unwrap();
+        assert_eq!(
+            fixture.files("pip"),
+            [std::path::Path::new(&wheel(&second).path)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()]
+        );
+    }
+
+    #[test]
+    #[ignore = "zip checks every name for duplicates, ~1 min in debug builds"]
+    fn zip_over_65535_entries() {
+        let files: Vec<PlatformFile> = (0..70_000)
+            .map(|i| PlatformFile::new(format!("{i}.txt"), i.to_string(), None))
+            .collect();
+        let zip = create_zip(&files.iter().collect::<Vec<&PlatformFile>>()).unwrap();
+
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(zip)).unwrap();
+        assert_eq!(archive.len(), 70_000);
+        let mut data = String::new();
+        archive
+            .by_name("69999.txt")
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "69999");
+    }
+
+    /// Streams an entry over 4 GiB to disk, so it needs ~4 GiB free in the temp dir
+    #[test]
+    #[ignore = "writes a 4 GiB file"]
+    fn zip64_entry_over_4_gib() {
+        let size = u32::MAX as usize + 1;
+        let path = env::temp_dir().join(format!("sqlite-dist-zip64-{}.zip", std::process::id()));
+        let mut zipfile = zip::ZipWriter::new(File::create(&path).unwrap());
+        zipfile
+            .start_file(
+                "large.bin",
+                zip_options(zip::CompressionMethod::Stored, size),
+            )
+            .unwrap();
+        let chunk = vec![0u8; 1 << 20];
+        let mut written = 0;
+        while written < size {
+            let n = chunk.len().min(size - written);
+            zipfile.write_all(&chunk[..n]).unwrap();
+            written += n;
+        }
+        zipfile.finish().unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let entry = archive.by_name("large.bin").unwrap();
+        assert_eq!(entry.size(), size as u64);
+        drop(entry);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_cpu_lists_every_cpu() {
+        let dir = env::temp_dir().join(format!("sqlite-dist-cpus-{}", std::process::id()));
+        let err = PlatformDirectory::from_path(dir.join("linux-mips")).unwrap_err();
+        let PlatformDirectoryError::InvalidCpuValue(_) = err else {
+            panic!("expected an invalid CPU, got {err}");
+        };
+        // every quoted name in the message is a CPU the directory name can have
+        let message = err.to_string();
+        let names: Vec<&str> = message.split('\'').skip(3).step_by(2).collect();
+        assert!(names.contains(&"universal2"), "{message}");
+        for name in names {
+            let path = dir.join(format!("macos-{name}"));
+            fs::create_dir_all(&path).unwrap();
+            assert!(PlatformDirectory::from_path(path).is_ok(), "{name}");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
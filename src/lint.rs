@@ -0,0 +1,163 @@
+use std::collections::HashSet;
+
+use semver::Version;
+
+use crate::doctor::Finding;
+use crate::pip::PipPackage;
+use crate::spec::Spec;
+use crate::wheels::metadata_placeholders;
+
+/// PyPI rejects longer summaries, and npm and RubyGems truncate them in search results
+const MAX_SUMMARY_LENGTH: usize = 512;
+
+pub(crate) fn has_placeholder(value: &str) -> bool {
+    let upper = value.to_uppercase();
+    ["TODO", "FIXME", "XXX", "CHANGEME"]
+        .iter()
+        .any(|placeholder| upper.contains(placeholder))
+}
+
+fn lint_summary(field: &str, summary: &str, findings: &mut Vec<Finding>) {
+    if summary.trim().is_empty() {
+        findings.push(Finding::error(
+            format!("{field} is empty"),
+            format!("describe the extension in one sentence in {field}"),
+        ));
+        return;
+    }
+    if summary.contains('\n') {
+        findings.push(Finding::error(
+            format!("{field} spans several lines, PyPI rejects multi-line summaries"),
+            format!("keep {field} to one line"),
+        ));
+    }
+    let length = summary.chars().count();
+    if length > MAX_SUMMARY_LENGTH {
+        findings.push(Finding::warning(
+            format!("{field} is {length} characters, PyPI allows at most {MAX_SUMMARY_LENGTH}"),
+            format!("shorten {field}, longer docs belong in the README"),
+        ));
+    }
+}
+
+/// Renders the METADATA of each wheel the spec builds, with the datasette and
+/// sqlite-utils description overrides, and flags the placeholders `publish pypi` rejects
+fn lint_pip_metadata(spec: &Spec, findings: &mut Vec<Finding>) {
+    let targets = &spec.targets;
+    let mut wheels = vec![];
+    if targets.pip.is_some() {
+        wheels.push(("pip", None));
+    }
+    if let Some(datasette) = &targets.datasette {
+        wheels.push(("datasette", datasette.description.as_ref()));
+    }
+    if let Some(sqlite_utils) = &targets.sqlite_utils {
+        wheels.push(("sqlite_utils", sqlite_utils.description.as_ref()));
+    }
+    let version = spec
+        .package
+        .version
+        .clone()
+        .unwrap_or_else(|| Version::new(0, 0, 0));
+    // the wheels share the spec's fields, each placeholder is reported once
+    let mut reported = HashSet::new();
+    for (target, description) in wheels {
+        let Ok(mut pkg) = PipPackage::new(spec.package.name.as_str(), &version) else {
+            return;
+        };
+        pkg.package = spec.package.clone();
+        if let Some(description) = description {
+            pkg.package.description = description.clone();
+        }
+        let metadata = pkg.default_metadata();
+        for (field, value) in metadata_placeholders(&metadata) {
+            if !reported.insert((field.to_owned(), value.to_owned())) {
+                continue;
+            }
+            findings.push(Finding::error(
+                format!("the {target} wheel's METADATA {field} has a placeholder: '{value}'"),
+                "PyPI shows it on the project page and publish pypi rejects it, replace it in the spec",
+            ));
+        }
+    }
+}
+
+/// Checks the spec's package metadata for what registries reject or render badly:
+/// empty or placeholder fields, a missing repository, and overlong summaries
+pub fn lint(spec: &Spec) -> Vec<Finding> {
+    let package = &spec.package;
+    let mut findings = vec![];
+    lint_summary("package.description", &package.description, &mut findings);
+    if let Some(description) = spec
+        .targets
+        .datasette
+        .as_ref()
+        .and_then(|datasette| datasette.description.as_deref())
+    {
+        lint_summary("targets.datasette.description", description, &mut findings);
+    }
+    if let Some(description) = spec
+        .targets
+        .sqlite_utils
+        .as_ref()
+        .and_then(|sqlite_utils| sqlite_utils.description.as_deref())
+    {
+        lint_summary(
+            "targets.sqlite_utils.description",
+            description,
+            &mut findings,
+        );
+    }
+    if package.license.trim().is_empty() {
+        findings.push(Finding::error(
+            "package.license is empty",
+            "set an SPDX identifier like MIT or Apache-2.0",
+        ));
+    }
+    if package.repo.trim().is_empty() {
+        findings.push(Finding::warning(
+            "package.repo is empty, registries won't link to the source",
+            "set it to the repository URL, ex https://github.com/user/sqlite-ext",
+        ));
+    } else if !package.repo.starts_with("https://") {
+        findings.push(Finding::warning(
+            format!("package.repo '{}' is not an https:// URL", package.repo),
+            "use the repository's https:// URL",
+        ));
+    }
+    if package.homepage.trim().is_empty() {
+        findings.push(Finding::warning(
+            "package.homepage is empty",
+            "set it to the docs or the repository URL",
+        ));
+    }
+
+    let mut fields: Vec<(String, &str)> = vec![
+        ("package.description".to_owned(), &package.description),
+        ("package.license".to_owned(), &package.license),
+        ("package.homepage".to_owned(), &package.homepage),
+        ("package.repo".to_owned(), &package.repo),
+    ];
+    fields.extend(
+        package
+            .authors
+            .iter()
+            .map(|author| ("package.authors".to_owned(), author.as_str())),
+    );
+    fields.extend(spec.functions.iter().filter_map(|function| {
+        Some((
+            format!("description of function {}", function.name),
+            function.description.as_deref()?,
+        ))
+    }));
+    for (field, value) in fields {
+        if has_placeholder(value) {
+            findings.push(Finding::warning(
+                format!("{field} has a placeholder: '{value}'"),
+                format!("replace the placeholder in {field}"),
+            ));
+        }
+    }
+    lint_pip_metadata(spec, &mut findings);
+    findings
+}
@@ -0,0 +1,212 @@
+use std::io;
+
+use crate::{Cpu, LoadablePlatformFile, Os, PlatformDirectory, PlatformFile};
+
+const FAT_MAGIC: u32 = 0xcafe_babe;
+// slices are page aligned, which is what lipo does for arm64
+const FAT_ALIGN: u32 = 14;
+
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const LC_VERSION_MIN_MACOSX: u32 = 0x24;
+const LC_BUILD_VERSION: u32 = 0x32;
+const PLATFORM_MACOS: u32 = 1;
+
+const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+const CPU_SUBTYPE_X86_64_ALL: u32 = 3;
+const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+const CPU_SUBTYPE_ARM64_ALL: u32 = 0;
+
+fn cpu_type(cpu: &Cpu) -> Option<(u32, u32)> {
+    match cpu {
+        Cpu::X86_64 => Some((CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_ALL)),
+        Cpu::Aarch64 => Some((CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL)),
+        _ => None,
+    }
+}
+
+/// Combines single-architecture Mach-O binaries or static archives into one
+/// fat (universal) file, like `lipo -create`.
+pub(crate) fn create_fat_binary(slices: &[(&Cpu, &[u8])]) -> io::Result<Vec<u8>> {
+    let align = 1usize << FAT_ALIGN;
+    let mut fat = vec![];
+    fat.extend(FAT_MAGIC.to_be_bytes());
+    fat.extend(to_u32(slices.len(), "number of slices")?.to_be_bytes());
+
+    let mut offset = 8 + 20 * slices.len();
+    let mut offsets = vec![];
+    for (cpu, data) in slices {
+        let (cputype, cpusubtype) = cpu_type(cpu).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a Mach-O architecture", cpu.to_string()),
+            )
+        })?;
+        offset = offset.next_multiple_of(align);
+        fat.extend(cputype.to_be_bytes());
+        fat.extend(cpusubtype.to_be_bytes());
+        fat.extend(to_u32(offset, "slice offset")?.to_be_bytes());
+        fat.extend(to_u32(data.len(), "slice size")?.to_be_bytes());
+        fat.extend(FAT_ALIGN.to_be_bytes());
+        offsets.push(offset);
+        offset += data.len();
+    }
+    for ((_, data), offset) in slices.iter().zip(offsets) {
+        fat.resize(offset, 0);
+        fat.extend_from_slice(data);
+    }
+    Ok(fat)
+}
+
+/// The 32-bit fat header can't address slices past 4 GiB
+fn to_u32(value: usize, what: &str) -> io::Result<u32> {
+    u32::try_from(value).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{what} {value} doesn't fit in a 32-bit fat binary"),
+        )
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+/// `(major, minor)` of a 64-bit Mach-O's LC_BUILD_VERSION or LC_VERSION_MIN_MACOSX
+fn thin_minimum_macos_version(data: &[u8]) -> Option<(u32, u32)> {
+    if read_u32(data, 0, false)? != MH_MAGIC_64 {
+        return None;
+    }
+    let ncmds = read_u32(data, 16, false)?;
+    // past the 32 byte mach_header_64
+    let mut offset = 32;
+    for _ in 0..ncmds {
+        let cmd = read_u32(data, offset, false)?;
+        let cmdsize = read_u32(data, offset + 4, false)? as usize;
+        // versions are packed as xxxx.yy.zz
+        let version = match cmd {
+            LC_BUILD_VERSION if read_u32(data, offset + 8, false)? == PLATFORM_MACOS => {
+                read_u32(data, offset + 12, false)
+            }
+            LC_VERSION_MIN_MACOSX => read_u32(data, offset + 8, false),
+            _ => None,
+        };
+        if let Some(version) = version {
+            return Some((version >> 16, (version >> 8) & 0xff));
+        }
+        if cmdsize == 0 {
+            return None;
+        }
+        offset += cmdsize;
+    }
+    None
+}
+
+/// The macOS deployment target a dylib was linked for, the newest of its
+/// slices when it's a fat binary. None when it isn't a Mach-O or doesn't say.
+pub(crate) fn minimum_macos_version(data: &[u8]) -> Option<(u32, u32)> {
+    if read_u32(data, 0, true)? != FAT_MAGIC {
+        return thin_minimum_macos_version(data);
+    }
+    let nfat_arch = read_u32(data, 4, true)? as usize;
+    (0..nfat_arch)
+        .filter_map(|i| {
+            let arch = 8 + 20 * i;
+            let offset = read_u32(data, arch + 8, true)? as usize;
+            let size = read_u32(data, arch + 12, true)? as usize;
+            thin_minimum_macos_version(data.get(offset..offset + size)?)
+        })
+        .max()
+}
+
+fn matching_file<'a>(
+    platform_dir: &'a PlatformDirectory,
+    files: &'a [PlatformFile],
+    name: &str,
+) -> io::Result<&'a PlatformFile> {
+    files.iter().find(|file| file.name == name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is missing {name}", platform_dir.path.display()),
+        )
+    })
+}
+
+/// Lipos every loadable and static library of the macos-x86_64 and
+/// macos-aarch64 directories into a single macos-universal2 directory.
+pub(crate) fn universal2_platform_directory(
+    x86_64: &PlatformDirectory,
+    aarch64: &PlatformDirectory,
+) -> io::Result<PlatformDirectory> {
+    let aarch64_loadables: Vec<PlatformFile> = aarch64
+        .loadable_files
+        .iter()
+        .map(|loadable| loadable.file.clone())
+        .collect();
+    let mut loadable_files = vec![];
+    for loadable in &x86_64.loadable_files {
+        let other = matching_file(aarch64, &aarch64_loadables, &loadable.file.name)?;
+        loadable_files.push(LoadablePlatformFile {
+            file_stem: loadable.file_stem.clone(),
+            file: PlatformFile::new(
+                loadable.file.name.clone(),
+                create_fat_binary(&[
+                    (&Cpu::X86_64, loadable.file.data.as_slice()),
+                    (&Cpu::Aarch64, other.data.as_slice()),
+                ])?,
+                None,
+            ),
+        });
+    }
+    let mut static_files = vec![];
+    for static_file in &x86_64.static_files {
+        let other = matching_file(aarch64, &aarch64.static_files, &static_file.name)?;
+        static_files.push(PlatformFile::new(
+            static_file.name.clone(),
+            create_fat_binary(&[
+                (&Cpu::X86_64, static_file.data.as_slice()),
+                (&Cpu::Aarch64, other.data.as_slice()),
+            ])?,
+            None,
+        ));
+    }
+    let sqlite3 = match (&x86_64.sqlite3, &aarch64.sqlite3) {
+        (Some(x86_64_sqlite3), Some(aarch64_sqlite3)) => Some(PlatformFile::new(
+            "sqlite3",
+            create_fat_binary(&[
+                (&Cpu::X86_64, x86_64_sqlite3.data.as_slice()),
+                (&Cpu::Aarch64, aarch64_sqlite3.data.as_slice()),
+            ])?,
+            None,
+        )),
+        _ => None,
+    };
+    // the dSYMs stay per-architecture, under x86_64/ and aarch64/
+    let debug_files = [(Cpu::X86_64, x86_64), (Cpu::Aarch64, aarch64)]
+        .iter()
+        .flat_map(|(cpu, platform_dir)| {
+            platform_dir.debug_files.iter().map(move |file| {
+                PlatformFile::new(
+                    format!("{}/{}", cpu.to_string(), file.name),
+                    file.data.clone(),
+                    file.metadata.clone(),
+                )
+            })
+        })
+        .collect();
+    Ok(PlatformDirectory {
+        os: Os::Macos,
+        cpu: Cpu::Universal2,
+        path: x86_64.path.with_file_name("macos-universal2"),
+        loadable_files,
+        static_files,
+        header_files: x86_64.header_files.clone(),
+        sqlite3,
+        extra_files: vec![],
+        debug_files,
+    })
+}
@@ -1,556 +1,526 @@
-mod amalgamation;
-mod gem;
-mod gh_releases;
-mod installer_sh;
-mod manifest;
-mod npm;
-mod pip;
-mod spec;
-mod spm;
-mod sqlpkg;
-
-use clap::{builder::OsStr, value_parser, Arg, ArgMatches, Command};
-use flate2::write::GzEncoder;
-use flate2::Compression;
-use manifest::write_manifest;
-use npm::NpmBuildError;
-use pip::PipBuildError;
-use semver::Version;
-use serde::{Serialize, Serializer};
-use sha2::{Digest, Sha256};
-use spec::Spec;
 use std::{
-    fs::{self, File},
-    io::{self, Write},
+    cmp::Reverse,
+    env, fs,
+    io::{self, BufRead, IsTerminal, Write},
     path::PathBuf,
 };
-use tar::Header;
 
-struct Project {
-    version: Version,
-    spec: Spec,
-    spec_directory: PathBuf,
-    platform_directories: Vec<PlatformDirectory>,
-}
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use sqlite_dist::{
+    bump::{BumpLevel, DevVersionSource},
+    ci::{CiOptions, CiProvider},
+    doctor::{Finding, Severity},
+    init::InitOptions,
+    publish::{NpmPublishOptions, PypiPublishOptions, RubygemsPublishOptions, S3PublishOptions},
+    spec::{OutputLayout, Spec},
+    AssetSummary, BuildError, BuildOptions, GeneratedAsset, TargetTiming,
+};
+use tracing_indicatif::IndicatifLayer;
+use tracing_subscriber::{
+    filter::LevelFilter, fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt,
+    Layer,
+};
 
-impl Project {
-    pub(crate) fn release_download_url(&self, name: &str) -> String {
-        let gh_base = self.spec.package.repo.clone();
-        format!(
-            "{gh_base}/releases/download/{}/{name}",
-            self.spec.package.git_tag(&self.version)
+/// Logs to stderr at the `-v` level, with a progress bar for every target and platform
+/// when stderr is a terminal
+fn init_logging(verbosity: u8) {
+    let level = match verbosity {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        _ => LevelFilter::DEBUG,
+    };
+    // the bars are hidden anyway when redirected, and tracing-indicatif's debug
+    // assertions fail on hidden bars
+    let indicatif_layer = io::stderr().is_terminal().then(IndicatifLayer::new);
+    let writer = match &indicatif_layer {
+        Some(indicatif_layer) => BoxMakeWriter::new(indicatif_layer.get_stderr_writer()),
+        None => BoxMakeWriter::new(io::stderr),
+    };
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_target(false)
+                .without_time()
+                .with_filter(level),
         )
-    }
-}
-
-#[derive(Debug, Clone)]
-struct PlatformDirectory {
-    os: Os,
-    cpu: Cpu,
-    _path: PathBuf,
-    loadable_files: Vec<LoadablePlatformFile>,
-    static_files: Vec<PlatformFile>,
-    header_files: Vec<PlatformFile>,
-}
-
-#[derive(Debug, Clone)]
-enum Os {
-    Macos,
-    Linux,
-    Windows,
-    Android,
-    Ios,
-    IosSimulator,
+        .with(indicatif_layer.map(|layer| layer.with_filter(LevelFilter::INFO)))
+        .init();
 }
 
-impl Serialize for Os {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        serializer.serialize_str(self.to_string().as_str())
+/// Prints how long every target took to stderr, slowest first
+fn print_timings(timings: &[TargetTiming]) {
+    let mut timings: Vec<&TargetTiming> = timings.iter().collect();
+    timings.sort_by_key(|timing| Reverse(timing.duration));
+    for timing in timings {
+        eprintln!(
+            "{:>8.2?}  {}/{} ({} assets)",
+            timing.duration, timing.package, timing.target, timing.assets
+        );
     }
 }
 
-impl ToString for Os {
-    fn to_string(&self) -> String {
-        match self {
-            Os::Macos => "macos".to_owned(),
-            Os::Linux => "linux".to_owned(),
-            Os::Windows => "windows".to_owned(),
-            Os::Android => "android".to_owned(),
-            Os::Ios => "ios".to_owned(),
-            Os::IosSimulator => "iossimulator".to_owned(),
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-enum Cpu {
-    X86_64,
-    Aarch64,
-    I686,
-    Armv7a,
+fn print_json_summary(assets: &[GeneratedAsset]) -> Result<(), BuildError> {
+    let summary: Vec<AssetSummary> = assets.iter().map(AssetSummary::from).collect();
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&summary).map_err(io::Error::from)?
+    );
+    Ok(())
 }
 
-impl Serialize for Cpu {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        serializer.serialize_str(self.to_string().as_str())
+/// Prints the assets a `--dry-run` would have written
+fn print_plan(assets: &[GeneratedAsset], json: bool) -> Result<(), BuildError> {
+    if json {
+        return print_json_summary(assets);
     }
-}
-
-impl ToString for Cpu {
-    fn to_string(&self) -> String {
-        match self {
-            Cpu::X86_64 => "x86_64".to_owned(),
-            Cpu::Aarch64 => "aarch64".to_owned(),
-            Cpu::I686 => "i686".to_owned(),
-            Cpu::Armv7a => "armv7a".to_owned(),
-        }
+    let kind_width = assets
+        .iter()
+        .map(|asset| asset.kind.to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max("KIND".len());
+    let platforms: Vec<String> = assets
+        .iter()
+        .map(|asset| {
+            asset.kind.platform().map_or("-".to_owned(), |(os, cpu)| {
+                format!("{}-{}", os.to_string(), cpu.to_string())
+            })
+        })
+        .collect();
+    let platform_width = platforms
+        .iter()
+        .map(String::len)
+        .max()
+        .unwrap_or(0)
+        .max("PLATFORM".len());
+    println!(
+        "{:<kind_width$}  {:<platform_width$}  {:>12}  PATH",
+        "KIND", "PLATFORM", "SIZE"
+    );
+    for (asset, platform) in assets.iter().zip(&platforms) {
+        println!(
+            "{:<kind_width$}  {:<platform_width$}  {:>12}  {}",
+            asset.kind.to_string(),
+            platform,
+            asset.size,
+            asset.path
+        );
     }
+    println!(
+        "{} assets, {} bytes",
+        assets.len(),
+        assets.iter().map(|asset| asset.size).sum::<usize>()
+    );
+    Ok(())
 }
 
-#[derive(Debug, Clone)]
-struct GithubRelease {
-    url: String,
-    platform: (Os, Cpu),
-}
-
-#[derive(Debug, Clone)]
-enum GeneratedAssetKind {
-    Npm(Option<(Os, Cpu)>),
-    Gem((Os, Cpu)),
-    Pip((Os, Cpu)),
-    Datasette,
-    SqliteUtils,
-    GithubReleaseLoadable(GithubRelease),
-    GithubReleaseStatic(GithubRelease),
-    Sqlpkg,
-    Spm,
-    Amalgamation,
-    Manifest,
+fn check(matches: &ArgMatches) -> Result<(), BuildError> {
+    let input_file = matches
+        .get_one::<PathBuf>("file")
+        .ok_or_else(|| BuildError::RequiredArg("file".to_owned()))?;
+    let spec = Spec::from_path(input_file)?;
+    println!(
+        "{} is a valid spec for {}",
+        input_file.display(),
+        spec.package.name
+    );
+    Ok(())
 }
 
-impl ToString for GeneratedAssetKind {
-    fn to_string(&self) -> String {
-        match self {
-            GeneratedAssetKind::Npm(_) => "npm".to_owned(),
-            GeneratedAssetKind::Gem(_) => "gem".to_owned(),
-            GeneratedAssetKind::Pip(_) => "pip".to_owned(),
-            GeneratedAssetKind::Datasette => "datasette".to_owned(),
-            GeneratedAssetKind::SqliteUtils => "sqlite-utils".to_owned(),
-            GeneratedAssetKind::GithubReleaseLoadable(_) => "github-release-loadable".to_owned(),
-            GeneratedAssetKind::GithubReleaseStatic(_) => "github-release-static".to_owned(),
-            GeneratedAssetKind::Sqlpkg => "sqlpkg".to_owned(),
-            GeneratedAssetKind::Spm => "spm".to_owned(),
-            GeneratedAssetKind::Amalgamation => "amalgamation".to_owned(),
-            GeneratedAssetKind::Manifest => "sqlite-dist-manifest".to_owned(),
-        }
-    }
-}
-impl Serialize for GeneratedAssetKind {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        serializer.serialize_str(self.to_string().as_str())
+fn diff(matches: &ArgMatches) -> Result<(), BuildError> {
+    let old = matches
+        .get_one::<PathBuf>("old")
+        .ok_or_else(|| BuildError::RequiredArg("old".to_owned()))?;
+    let new = matches
+        .get_one::<PathBuf>("new")
+        .ok_or_else(|| BuildError::RequiredArg("new".to_owned()))?;
+    let diff = sqlite_dist::diff::diff_manifests(old, new)?;
+    print!("{diff}");
+    if matches.get_flag("strict") && diff.has_removals() {
+        return Err(BuildError::SpecError(
+            "the new release removes platforms or assets".to_owned(),
+        ));
     }
+    Ok(())
 }
 
-#[derive(Serialize)]
-struct GeneratedAsset {
-    kind: GeneratedAssetKind,
-    name: String,
-    path: String,
-    checksum_sha256: String,
-    size: usize,
-}
-impl GeneratedAsset {
-    fn from(kind: GeneratedAssetKind, path: &PathBuf, contents: &[u8]) -> io::Result<Self> {
-        File::create(path)?.write_all(contents)?;
-        Ok(Self {
-            kind,
-            name: path.file_name().unwrap().to_str().unwrap().to_string(),
-            path: path.to_str().unwrap().to_string(),
-            checksum_sha256: base16ct::lower::encode_string(&Sha256::digest(contents)),
-            size: contents.len(),
-        })
+/// Rebuilds the release into a temporary directory and compares its checksums to
+/// the published manifest
+fn verify(matches: &ArgMatches) -> Result<(), BuildError> {
+    let against = matches
+        .get_one::<String>("against")
+        .ok_or_else(|| BuildError::RequiredArg("against".to_owned()))?;
+    let output_dir = env::temp_dir().join(format!("sqlite-dist-verify-{}", std::process::id()));
+    let options = BuildOptions {
+        input_dir: matches
+            .get_one::<PathBuf>("input")
+            .cloned()
+            .ok_or_else(|| BuildError::RequiredArg("input".to_owned()))?,
+        output_dir: output_dir.clone(),
+        spec_path: matches
+            .get_one::<PathBuf>("file")
+            .cloned()
+            .ok_or_else(|| BuildError::RequiredArg("file".to_owned()))?,
+        version: matches.get_one::<String>("version").cloned(),
+        verify: false,
+        dry_run: false,
+        no_cache: true,
+        only: None,
+        skip: vec![],
+        keep_going: false,
+        strict: false,
+        init_checksum_log: true,
+        profile: matches.get_one::<String>("profile").cloned(),
+        dev: None,
+        layout: None,
+    };
+    let report = sqlite_dist::build(&options)
+        .and_then(|_| Ok(sqlite_dist::diff::compare_checksums(against, &output_dir)?));
+    // the build may have failed before creating it, its error is the one to report
+    let removed = match fs::remove_dir_all(&output_dir) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        removed => removed,
+    };
+    let report = report?;
+    removed?;
+    print!("{report}");
+    if !report.is_reproducible() {
+        return Err(BuildError::SpecError(format!(
+            "{} of {} published assets don't match the rebuild",
+            report.mismatched.len() + report.missing.len(),
+            report.matching.len() + report.mismatched.len() + report.missing.len()
+        )));
     }
-}
-//{"kind": "github_release", "name": "...", "path": "./", "checksum_sha256": ""},
-
-#[derive(Debug, Clone)]
-struct PlatformFile {
-    name: String,
-    data: Vec<u8>,
-    metadata: Option<std::fs::Metadata>,
+    Ok(())
 }
 
-#[derive(Debug, Clone)]
-struct LoadablePlatformFile {
-    file_stem: String,
-    file: PlatformFile,
+fn bump(matches: &ArgMatches) -> Result<(), BuildError> {
+    let input_file = matches
+        .get_one::<PathBuf>("file")
+        .ok_or_else(|| BuildError::RequiredArg("file".to_owned()))?;
+    let level = match matches.get_one::<String>("level").map(String::as_str) {
+        Some("major") => BumpLevel::Major,
+        Some("minor") => BumpLevel::Minor,
+        Some("prerelease") => BumpLevel::Prerelease,
+        _ => BumpLevel::Patch,
+    };
+    let changelog = matches.get_one::<PathBuf>("changelog");
+    let version = sqlite_dist::bump::bump(input_file, level, changelog.map(PathBuf::as_path))?;
+    println!("Bumped {} to {version}", input_file.display());
+    Ok(())
 }
 
-impl PlatformFile {
-    fn new<S: Into<String>, D: Into<Vec<u8>>>(
-        name: S,
-        data: D,
-        metadata: Option<fs::Metadata>,
-    ) -> Self {
-        Self {
-            name: name.into(),
-            data: data.into(),
-            metadata,
-        }
-    }
+/// Asks for a value on stderr, returning `default` for an empty answer
+fn prompt(label: &str, default: &str) -> io::Result<String> {
+    eprint!("{label} [{default}]: ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_owned())
 }
 
-use thiserror::Error;
-
-fn create_targz(files: &[&PlatformFile]) -> io::Result<Vec<u8>> {
-    let mut tar_gz = Vec::new();
-    {
-        let enc = GzEncoder::new(&mut tar_gz, Compression::default());
-        let mut tar = tar::Builder::new(enc);
-        for file in files {
-            let mut header = Header::new_gnu();
-            header.set_path(file.name.clone())?;
-            header.set_size(file.data.len() as u64);
-            if let Some(metadata) = &file.metadata {
-                header.set_metadata(metadata);
-            } else {
-                header.set_mode(0o700);
-                header.set_mtime(
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                );
-            }
-            header.set_cksum();
-            tar.append::<&[u8]>(&header, file.data.as_ref())?;
+fn init(matches: &ArgMatches) -> Result<(), BuildError> {
+    let interactive = io::stdin().is_terminal() && !matches.get_flag("yes");
+    let field = |id: &str, label: &str, default: &str| -> io::Result<String> {
+        match matches.get_one::<String>(id) {
+            Some(value) => Ok(value.clone()),
+            None if interactive => prompt(label, default),
+            None => Ok(default.to_owned()),
         }
-        tar.finish()?;
     };
-    Ok(tar_gz)
-}
-
-#[derive(Error, Debug)]
-pub enum PlatformDirectoryError {
-    #[error("I/O error: {0}")]
-    IOError(#[from] io::Error),
-
-    #[error("Expected name of directory")]
-    MissingDirectoryName,
-    #[error("directory or file name must contains only valid UTF-8 characters")]
-    InvalidCharacters,
-    #[error("directory {0} is not a valid platform directory. The format must be $OS-$CPU.")]
-    InvalidDirectoryName(String),
-    #[error("Invalid operating system '{0}'. Must be one of 'macos', 'linux', or 'windows'")]
-    InvalidOsValue(String),
-    #[error("Invalid CPU name '{0}'. Must be one of 'x86_64' or 'aarch64'")]
-    InvalidCpuValue(String),
+    let directory_name = std::env::current_dir()?
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = field("name", "Package name", &directory_name)?;
+    let authors = match matches.get_many::<String>("author") {
+        Some(authors) => authors.cloned().collect(),
+        None => vec![field("author", "Author", "")?],
+    };
+    let options = InitOptions {
+        spec_path: matches
+            .get_one::<PathBuf>("file")
+            .cloned()
+            .unwrap_or_else(|| PathBuf::from("sqlite-dist.toml")),
+        loadables_dir: matches.get_one::<PathBuf>("loadables").cloned(),
+        version: field("version", "Version", "0.1.0")?,
+        license: field("license", "License", "MIT OR Apache-2.0")?,
+        description: field("description", "Description", "")?,
+        homepage: field("homepage", "Homepage", "")?,
+        repo: field("repo", "GitHub repository URL", "")?,
+        force: matches.get_flag("force"),
+        name,
+        authors,
+    };
+    let loadables = sqlite_dist::init::init(&options)?;
+    println!("Wrote {}", options.spec_path.display());
+    if !loadables.is_empty() {
+        println!(
+            "Found {} loadables, arrange them in the input directory as:",
+            loadables.len()
+        );
+        for loadable in &loadables {
+            println!(
+                "  {} -> {}",
+                loadable.path.display(),
+                loadable.input_path().display()
+            );
+        }
+    }
+    Ok(())
 }
 
-impl PlatformDirectory {
-    fn from_path(base_path: PathBuf) -> Result<Self, PlatformDirectoryError> {
-        let mut loadable_files = vec![];
-        let mut static_files = vec![];
-        let mut header_files = vec![];
-
-        let dirname = base_path
-            .components()
-            .last()
-            .ok_or(PlatformDirectoryError::MissingDirectoryName)?
-            .as_os_str()
-            .to_str()
-            .ok_or(PlatformDirectoryError::InvalidCharacters)?;
-        let mut s = dirname.split('-');
-        let os = match s
-            .next()
-            .ok_or_else(|| PlatformDirectoryError::InvalidDirectoryName(dirname.to_owned()))?
-        {
-            "macos" => Os::Macos,
-            "linux" => Os::Linux,
-            "windows" => Os::Windows,
-            "android" => Os::Android,
-            "ios" => Os::Ios,
-            "iossimulator" => Os::IosSimulator,
-            os => return Err(PlatformDirectoryError::InvalidOsValue(os.to_owned())),
+fn print_findings(findings: &[Finding]) {
+    for finding in findings {
+        let mark = match finding.severity {
+            Severity::Ok => "ok",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
         };
-        let cpu = match s
-            .next()
-            .ok_or_else(|| PlatformDirectoryError::InvalidDirectoryName(dirname.to_owned()))?
-        {
-            "x86_64" => Cpu::X86_64,
-            "aarch64" => Cpu::Aarch64,
-            "i686" => Cpu::I686,
-            "armv7a" => Cpu::Armv7a,
-            cpu => return Err(PlatformDirectoryError::InvalidCpuValue(cpu.to_owned())),
-        };
-        if s.next().is_some() {
-            return Err(PlatformDirectoryError::InvalidDirectoryName(
-                dirname.to_owned(),
-            ));
-        }
-
-        let dir = fs::read_dir(&base_path)?;
-        for entry in dir {
-            let entry_path = entry?.path();
-            match entry_path.extension().and_then(|e| e.to_str()) {
-                Some("so") | Some("dll") | Some("dylib") => {
-                    let name = entry_path
-                        .file_name()
-                        .expect("file_name to exist because there is an extension")
-                        .to_str()
-                        .ok_or(PlatformDirectoryError::InvalidCharacters)?
-                        .to_string();
-                    let data = fs::read(&entry_path)?;
-                    let metadata = Some(fs::metadata(&entry_path)?);
-                    let file_stem = entry_path
-                        .file_stem()
-                        .expect("file_stem to exist because there is an extension")
-                        .to_str()
-                        .ok_or(PlatformDirectoryError::InvalidCharacters)?
-                        .to_string();
-                    loadable_files.push(LoadablePlatformFile {
-                        file_stem,
-                        file: PlatformFile {
-                            name: name.to_string(),
-                            data,
-                            metadata,
-                        },
-                    });
-                }
-                Some("a") => {
-                    let name = entry_path
-                        .file_name()
-                        .expect("file_name to exist because there is an extension")
-                        .to_str()
-                        .ok_or(PlatformDirectoryError::InvalidCharacters)?
-                        .to_string();
-                    let data = fs::read(&entry_path)?;
-                    let metadata = Some(fs::metadata(&entry_path)?);
-                    static_files.push(PlatformFile {
-                        name: name.to_string(),
-                        data,
-                        metadata,
-                    });
-                }
-                Some("h") => {
-                    let name = entry_path
-                        .file_name()
-                        .expect("file_name to exist because there is an extension")
-                        .to_str()
-                        .ok_or(PlatformDirectoryError::InvalidCharacters)?
-                        .to_string();
-                    let data = fs::read(&entry_path)?;
-                    let metadata = Some(fs::metadata(&entry_path)?);
-                    header_files.push(PlatformFile {
-                        name: name.to_string(),
-                        data,
-                        metadata,
-                    });
-                }
-                _ => {
-                    println!("Warning: unknown file type in platform directory");
-                }
-            }
+        println!("{mark:>7}  {}", finding.message);
+        if let Some(fix) = &finding.fix {
+            println!("         fix: {fix}");
         }
-        Ok(PlatformDirectory {
-            os,
-            cpu,
-            _path: base_path,
-            loadable_files,
-            static_files,
-            header_files,
-        })
     }
 }
 
-#[derive(Error, Debug)]
-pub enum BuildError {
-    #[error("`{0}` is a required argument")]
-    RequiredArg(String),
-    #[error("`{0}` is a required argument")]
-    InvalidSpec(toml::de::Error),
-    #[error("specfile error: `{0}`")]
-    SpecError(String),
-    #[error("I/O error: {0}")]
-    IoError(#[from] io::Error),
-
-    #[error("Invalid platform directory: {0}")]
-    PlayformDirectoryError(#[from] PlatformDirectoryError),
-
-    #[error("Error building a pip package: {0}")]
-    PipBuildEror(#[from] PipBuildError),
-    #[error("Error building an npm package: {0}")]
-    NpmBuildEror(#[from] NpmBuildError),
-}
-
-fn build(matches: ArgMatches) -> Result<(), BuildError> {
-    // Get the values of arguments
-    let input_dir = matches
-        .get_one::<PathBuf>("input")
-        .ok_or_else(|| BuildError::RequiredArg("input".to_owned()))?;
-    let output_dir = matches
-        .get_one::<PathBuf>("output")
-        .ok_or_else(|| BuildError::RequiredArg("output".to_owned()))?;
+fn lint(matches: &ArgMatches) -> Result<(), BuildError> {
     let input_file = matches
         .get_one::<PathBuf>("file")
         .ok_or_else(|| BuildError::RequiredArg("file".to_owned()))?;
-    let version = matches
-        .get_one::<String>("version")
-        .ok_or_else(|| BuildError::RequiredArg("version".to_owned()))?;
-    let version = Version::parse(version).unwrap();
-
-    std::fs::create_dir_all(output_dir)?;
-
-    let spec: Spec = match toml::from_str(fs::read_to_string(input_file)?.as_str()) {
-        Ok(spec) => spec,
-        Err(err) => {
-            eprintln!("{}", err);
-            return Err(BuildError::InvalidSpec(err));
-        }
+    let spec = Spec::from_path(input_file)?;
+    let findings = sqlite_dist::lint::lint(&spec);
+    print_findings(&findings);
+    let denied = match matches.get_one::<String>("deny").map(String::as_str) {
+        Some("warnings") => Severity::Warning,
+        _ => Severity::Error,
     };
+    let problems = findings
+        .iter()
+        .filter(|finding| finding.severity >= denied)
+        .count();
+    if problems > 0 {
+        return Err(BuildError::SpecError(format!(
+            "lint found {problems} problems"
+        )));
+    }
+    if findings.is_empty() {
+        println!("{} has no lint problems", input_file.display());
+    }
+    Ok(())
+}
 
-    if spec.targets.sqlpkg.is_some() && spec.targets.github_releases.is_none() {
-        return Err(BuildError::SpecError(
-            "sqlpkg target requires the github_releases target".to_owned(),
-        ));
+fn doctor(matches: &ArgMatches) -> Result<(), BuildError> {
+    let input_file = matches
+        .get_one::<PathBuf>("file")
+        .ok_or_else(|| BuildError::RequiredArg("file".to_owned()))?;
+    let findings = sqlite_dist::doctor::doctor(
+        input_file,
+        matches.get_one::<PathBuf>("input").map(PathBuf::as_path),
+        !matches.get_flag("no-network"),
+        matches.get_flag("check-names"),
+    );
+    print_findings(&findings);
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(BuildError::SpecError(format!(
+            "doctor found {errors} problems"
+        )));
     }
-    if spec.targets.spm.is_some() && spec.targets.github_releases.is_none() {
-        return Err(BuildError::SpecError(
-            "spm target requires the github_releases target".to_owned(),
-        ));
+    Ok(())
+}
+
+fn verify_wheels(matches: &ArgMatches) -> Result<(), BuildError> {
+    let mut wheels = vec![];
+    for path in matches.get_many::<PathBuf>("paths").into_iter().flatten() {
+        wheels.extend(sqlite_dist::wheels::find_wheels(path)?);
     }
-    if spec.targets.datasette.is_some() && spec.targets.pip.is_none() {
-        return Err(BuildError::SpecError(
-            "datasette target requires the pip target".to_owned(),
-        ));
+    let mut invalid = 0;
+    for wheel in &wheels {
+        let problems = sqlite_dist::wheels::check_wheel(wheel)?;
+        if problems.is_empty() {
+            println!("ok  {}", wheel.display());
+            continue;
+        }
+        invalid += 1;
+        println!("invalid  {}", wheel.display());
+        for problem in problems {
+            println!("  {problem}");
+        }
     }
-    if spec.targets.sqlite_utils.is_some() && spec.targets.pip.is_none() {
-        return Err(BuildError::SpecError(
-            "sqlite_utils target requires the pip target".to_owned(),
-        ));
+    if invalid > 0 {
+        return Err(BuildError::SpecError(format!(
+            "{invalid} of {} wheels are invalid",
+            wheels.len()
+        )));
     }
+    Ok(())
+}
 
-    let mut entries = fs::read_dir(input_dir)?
-        .map(|entry| {
-            Ok(entry
-                .map_err(|_| {
-                    BuildError::SpecError("Could not read entry in input directory".to_owned())
-                })?
-                .path())
-        })
-        .collect::<Result<Vec<PathBuf>, BuildError>>()?;
-
-    let emscripten_dir = entries
-        .iter()
-        .position(|entry| entry.file_name() == Some(&OsStr::from("wasm32-emscripten")))
-        .map(|item| entries.remove(item));
-    let platform_directories: Result<Vec<PlatformDirectory>, BuildError> = entries
-        .iter()
-        .map(|entry| {
-            PlatformDirectory::from_path(entry.to_owned())
-                .map_err(BuildError::PlayformDirectoryError)
-        })
-        .collect();
-    let platform_directories = platform_directories?;
-
-    let project = Project {
-        version,
-        spec,
-        spec_directory: input_file.parent().unwrap().to_path_buf(),
-        platform_directories,
+fn generate_ci(matches: &ArgMatches) -> Result<(), BuildError> {
+    let provider = match matches.get_one::<String>("provider").map(String::as_str) {
+        Some("gitlab") => CiProvider::Gitlab,
+        Some("circleci") => CiProvider::Circleci,
+        Some("buildkite") => CiProvider::Buildkite,
+        _ => CiProvider::Github,
     };
-
-    let mut generated_assets: Vec<GeneratedAsset> = vec![];
-    if project.spec.targets.github_releases.is_some() {
-        let path = output_dir.join("github_releases");
-        std::fs::create_dir(&path)?;
-        let gh_release_assets = gh_releases::write_platform_files(&project, &path)?;
-
-        if project.spec.targets.sqlpkg.is_some() {
-            let sqlpkg_dir = output_dir.join("sqlpkg");
-            std::fs::create_dir(&sqlpkg_dir)?;
-            generated_assets.extend(sqlpkg::write_sqlpkg(&project, &sqlpkg_dir)?);
-        };
-
-        if project.spec.targets.spm.is_some() {
-            let path = output_dir.join("spm");
-            std::fs::create_dir(&path)?;
-            generated_assets.extend(spm::write_spm(&project.spec, &gh_release_assets, &path)?);
-        };
-
-        if let Some(amalgamation_config) = &project.spec.targets.amalgamation {
-            let amalgamation_path = output_dir.join("amalgamation");
-            std::fs::create_dir(&amalgamation_path)?;
-            generated_assets.extend(amalgamation::write_amalgamation(
-                &project,
-                &amalgamation_path,
-                amalgamation_config,
-            )?);
-        };
-
-        generated_assets.extend(gh_release_assets);
+    let options = CiOptions {
+        spec_path: matches
+            .get_one::<PathBuf>("file")
+            .cloned()
+            .unwrap_or_else(|| PathBuf::from("sqlite-dist.toml")),
+        build_command: matches
+            .get_one::<String>("build-command")
+            .cloned()
+            .unwrap_or_else(|| "make loadable".to_owned()),
+        loadables: matches
+            .get_one::<String>("loadables")
+            .cloned()
+            .unwrap_or_else(|| "dist/*".to_owned()),
+        platforms: matches
+            .get_many::<String>("platform")
+            .map(|platforms| platforms.cloned().collect()),
     };
+    let config = sqlite_dist::ci::generate_ci(provider, &options)?;
+    if matches.get_flag("stdout") {
+        print!("{config}");
+        return Ok(());
+    }
+    let path = matches
+        .get_one::<PathBuf>("output")
+        .cloned()
+        .unwrap_or_else(|| sqlite_dist::ci::default_path(provider).to_path_buf());
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, config)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
 
-    if project.spec.targets.pip.is_some() {
-        let pip_path = output_dir.join("pip");
-        std::fs::create_dir(&pip_path)?;
-        generated_assets.extend(pip::write_base_packages(&project, &pip_path)?);
-        if project.spec.targets.datasette.is_some() {
-            let datasette_path = output_dir.join("datasette");
-            std::fs::create_dir(&datasette_path)?;
-            generated_assets.push(pip::write_datasette(&project, &datasette_path)?);
-        }
-        if project.spec.targets.sqlite_utils.is_some() {
-            let sqlite_utils_path = output_dir.join("sqlite_utils");
-            std::fs::create_dir(&sqlite_utils_path)?;
-            generated_assets.push(pip::write_sqlite_utils(&project, &sqlite_utils_path)?);
-        }
+fn publish(matches: &ArgMatches) -> Result<(), BuildError> {
+    let (registry, matches) = matches
+        .subcommand()
+        .ok_or_else(|| BuildError::RequiredArg("registry".to_owned()))?;
+    let output_dir = matches
+        .get_one::<PathBuf>("dir")
+        .ok_or_else(|| BuildError::RequiredArg("dir".to_owned()))?;
+    let published = match registry {
+        "npm" => sqlite_dist::publish::publish_npm(
+            output_dir,
+            &NpmPublishOptions {
+                provenance: matches.get_flag("provenance"),
+                tag: matches.get_one::<String>("tag").cloned(),
+                registry: matches.get_one::<String>("registry").cloned(),
+                dry_run: matches.get_flag("dry-run"),
+            },
+        )?,
+        "rubygems" => sqlite_dist::publish::publish_rubygems(
+            output_dir,
+            &RubygemsPublishOptions {
+                otp: matches.get_one::<String>("otp").cloned(),
+                host: matches.get_one::<String>("host").cloned(),
+                dry_run: matches.get_flag("dry-run"),
+            },
+        )?,
+        "pypi" => sqlite_dist::publish::publish_pypi(
+            output_dir,
+            &PypiPublishOptions {
+                repository_url: matches.get_one::<String>("repository-url").cloned(),
+                username: matches.get_one::<String>("username").cloned(),
+                dry_run: matches.get_flag("dry-run"),
+            },
+        )?,
+        "s3" => sqlite_dist::publish::publish_s3(
+            output_dir,
+            &S3PublishOptions {
+                destination: matches
+                    .get_one::<String>("destination")
+                    .cloned()
+                    .ok_or_else(|| BuildError::RequiredArg("destination".to_owned()))?,
+                endpoint_url: matches.get_one::<String>("endpoint-url").cloned(),
+                public_url: matches.get_one::<String>("public-url").cloned(),
+                dry_run: matches.get_flag("dry-run"),
+            },
+        )?,
+        registry => return Err(BuildError::RequiredArg(registry.to_owned())),
     };
-    if project.spec.targets.npm.is_some() {
-        let npm_output_directory = output_dir.join("npm");
-        std::fs::create_dir(&npm_output_directory)?;
-        generated_assets.extend(npm::write_npm_packages(
-            &project,
-            &npm_output_directory,
-            &emscripten_dir,
-        )?);
+    for package in published {
+        println!("Published {package}");
+    }
+    Ok(())
+}
+
+fn build(matches: ArgMatches) -> Result<(), BuildError> {
+    let workspace = matches.get_flag("workspace");
+    let options = BuildOptions {
+        // every workspace member has its own input directory
+        input_dir: match matches.get_one::<PathBuf>("input") {
+            Some(input) => input.clone(),
+            None if workspace => PathBuf::new(),
+            None => return Err(BuildError::RequiredArg("input".to_owned())),
+        },
+        output_dir: matches
+            .get_one::<PathBuf>("output")
+            .cloned()
+            .ok_or_else(|| BuildError::RequiredArg("output".to_owned()))?,
+        spec_path: matches
+            .get_one::<PathBuf>("file")
+            .cloned()
+            .ok_or_else(|| BuildError::RequiredArg("file".to_owned()))?,
+        version: matches.get_one::<String>("version").cloned(),
+        verify: matches.get_flag("verify"),
+        dry_run: matches.get_flag("dry-run"),
+        no_cache: matches.get_flag("no-cache"),
+        only: matches
+            .get_many::<String>("only")
+            .map(|only| only.cloned().collect()),
+        skip: matches
+            .get_many::<String>("skip")
+            .map(|skip| skip.cloned().collect())
+            .unwrap_or_default(),
+        keep_going: matches.get_flag("keep-going"),
+        strict: matches.get_flag("strict"),
+        init_checksum_log: matches.get_flag("init-checksum-log"),
+        profile: matches.get_one::<String>("profile").cloned(),
+        dev: matches
+            .get_one::<String>("dev")
+            .map(|source| match source.as_str() {
+                "git" => DevVersionSource::Git,
+                "date" => DevVersionSource::Date,
+                _ => DevVersionSource::RunNumber,
+            }),
+        layout: matches
+            .get_one::<String>("layout")
+            .map(|layout| match layout.as_str() {
+                "flat" => OutputLayout::Flat,
+                "by-platform" => OutputLayout::ByPlatform,
+                _ => OutputLayout::ByTarget,
+            }),
     };
-    if let Some(gem_config) = &project.spec.targets.gem {
-        let gem_path = output_dir.join("gem");
-        std::fs::create_dir(&gem_path)?;
-        generated_assets.extend(gem::write_gems(&project, &gem_path, gem_config)?);
+    let output = if workspace {
+        sqlite_dist::workspace::build_workspace(&options)?
+    } else {
+        sqlite_dist::build(&options)?
     };
-
-    let github_releases_checksums_txt = generated_assets
-        .iter()
-        .filter(|ga| {
-            matches!(
-                ga.kind,
-                GeneratedAssetKind::GithubReleaseLoadable(_)
-                    | GeneratedAssetKind::GithubReleaseStatic(_)
-                    | GeneratedAssetKind::Sqlpkg
-                    | GeneratedAssetKind::Spm
-            )
-        })
-        .map(|ga| format!("{} {}", ga.name, ga.checksum_sha256))
-        .collect::<Vec<String>>()
-        .join("\n");
-    File::create(output_dir.join("checksums.txt"))?
-        .write_all(github_releases_checksums_txt.as_bytes())?;
-    File::create(output_dir.join("install.sh"))?.write_all(
-        crate::installer_sh::templates::install_sh(&project, &generated_assets).as_bytes(),
-    )?;
-    write_manifest(output_dir, &generated_assets)?;
-    Ok(())
+    let assets = output.assets;
+    print_timings(&output.timings);
+    let json_output = matches
+        .get_one::<String>("output-format")
+        .map(String::as_str)
+        == Some("json");
+    if options.dry_run {
+        print_plan(&assets, json_output || matches.get_flag("json"))
+    } else if json_output {
+        print_json_summary(&assets)
+    } else {
+        Ok(())
+    }
 }
 
 fn main() {
@@ -576,8 +546,113 @@ fn main() {
             Arg::new("version")
                 .long("version")
                 .value_name("VERSION")
-                .help("Set the version ")
-                .required(true),
+                .help("Set the version, defaults to package.version in the spec"),
+        )
+        .arg(
+            Arg::new("verify")
+                .long("verify")
+                .help("Load the host platform's loadable into SQLite and run the spec's verification SQL before generating")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Run every generator and print the assets that would be produced, without writing anything")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("Print the --dry-run plan as JSON")
+                .requires("dry-run")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("output-format")
+                .long("output-format")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .help("Print a JSON summary of every generated asset to stdout when set to 'json'"),
+        )
+        .arg(
+            Arg::new("only")
+                .long("only")
+                .value_name("TARGETS")
+                .value_delimiter(',')
+                .action(ArgAction::Append)
+                .help("Only generate these targets, ex `--only pip,npm`"),
+        )
+        .arg(
+            Arg::new("skip")
+                .long("skip")
+                .value_name("TARGETS")
+                .value_delimiter(',')
+                .action(ArgAction::Append)
+                .help("Don't generate these targets, ex `--skip datasette`"),
+        )
+        .arg(
+            Arg::new("keep-going")
+                .long("keep-going")
+                .help("Keep generating the other targets when one fails, and report every failure at the end")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Fail when npm, pip, and RubyGems would order the version differently than semver does")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("init-checksum-log")
+                .long("init-checksum-log")
+                .help("Start a new checksum log when the spec's checksum_log.previous doesn't exist yet, for the first release")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("workspace")
+                .long("workspace")
+                .help("FILE is a workspace spec listing the specs and input directories of several extensions, built into one output directory")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dev")
+                .long("dev")
+                .value_name("SOURCE")
+                .value_parser(["git", "date", "run-number"])
+                .help("Build a dev version of the next release, numbered by commits since the last tag, the date, or the CI run number"),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .value_name("PROFILE")
+                .help("Apply the spec's [profiles.PROFILE], ex `--profile nightly`"),
+        )
+        .arg(
+            Arg::new("layout")
+                .long("layout")
+                .value_parser(["by-target", "flat", "by-platform"])
+                .help("How assets are arranged in the output directory, overrides output.layout in the spec"),
+        )
+        .arg(
+            Arg::new("no-cache")
+                .long("no-cache")
+                .help("Regenerate every target, even the ones whose inputs didn't change since the last build")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .global(true)
+                .help("Log what every target does, -vv for debug output")
+                .action(ArgAction::Count),
+        )
+        .arg(
+            Arg::new("offline")
+                .long("offline")
+                .global(true)
+                .help("Fail any step that needs network access instead of reaching the network")
+                .action(ArgAction::SetTrue),
         )
         .arg(
             Arg::new("file")
@@ -588,9 +663,418 @@ fn main() {
                 .value_parser(value_parser!(PathBuf)),
         )
         .disable_version_flag(true)
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("check")
+                .about("Validate a spec file without generating anything")
+                .arg(
+                    Arg::new("file")
+                        .value_name("FILE")
+                        .help("The spec file to validate")
+                        .required(true)
+                        .index(1)
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("bump")
+                .about("Bump package.version in the spec, checking the new version works for every target")
+                .arg(
+                    Arg::new("level")
+                        .value_name("LEVEL")
+                        .required(true)
+                        .index(1)
+                        .value_parser(["major", "minor", "patch", "prerelease"]),
+                )
+                .arg(
+                    Arg::new("file")
+                        .value_name("FILE")
+                        .help("The spec file to update")
+                        .required(true)
+                        .index(2)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("changelog")
+                        .long("changelog")
+                        .value_name("CHANGELOG")
+                        .help("Add an entry for the new version to this changelog, ex CHANGELOG.md")
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("init")
+                .about("Write a new spec, asking for any package field not given as a flag")
+                .arg(
+                    Arg::new("file")
+                        .value_name("FILE")
+                        .help("Where to write the spec, defaults to sqlite-dist.toml")
+                        .index(1)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("loadables")
+                        .long("loadables")
+                        .value_name("DIR")
+                        .help("Directory of compiled loadables, their platforms are read from their headers")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(Arg::new("name").long("name"))
+                .arg(Arg::new("version").long("version"))
+                .arg(Arg::new("author").long("author").action(ArgAction::Append))
+                .arg(Arg::new("license").long("license"))
+                .arg(Arg::new("description").long("description"))
+                .arg(Arg::new("homepage").long("homepage"))
+                .arg(Arg::new("repo").long("repo"))
+                .arg(
+                    Arg::new("yes")
+                        .long("yes")
+                        .short('y')
+                        .help("Don't ask, use defaults for the fields not given as flags")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Overwrite an existing spec")
+                        .action(ArgAction::SetTrue),
+                )
+                .disable_version_flag(true),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check the spec, input directory, and version for problems, with suggested fixes")
+                .arg(
+                    Arg::new("file")
+                        .value_name("FILE")
+                        .help("The spec file to check")
+                        .required(true)
+                        .index(1)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("input")
+                        .long("input")
+                        .value_name("INPUT_DIR")
+                        .help("Check the platform directories and binaries in this directory")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("no-network")
+                        .long("no-network")
+                        .help("Don't compare the version against the repo's GitHub releases")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("check-names")
+                        .long("check-names")
+                        .help("Warn when PyPI, npm, RubyGems, or crates.io already have a package of another project under a name the targets publish")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("Check the spec's package metadata for empty fields, placeholders, and summaries registries reject")
+                .arg(
+                    Arg::new("file")
+                        .value_name("FILE")
+                        .help("The spec file to lint")
+                        .required(true)
+                        .index(1)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("deny")
+                        .long("deny")
+                        .value_name("LEVEL")
+                        .help("Fail on warnings too with `--deny warnings`, by default only errors fail")
+                        .value_parser(["warnings", "errors"]),
+                ),
+        )
+        .subcommand(
+            Command::new("verify-wheels")
+                .about("Check wheels' RECORD hashes and sizes, and their METADATA and WHEEL fields")
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATH")
+                        .help("Wheels, or directories searched for wheels")
+                        .required(true)
+                        .num_args(1..)
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Rebuild a release from the same inputs and compare the checksums to its published manifest")
+                .arg(
+                    Arg::new("file")
+                        .value_name("FILE")
+                        .help("The spec file the release was built from")
+                        .required(true)
+                        .index(1)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("input")
+                        .long("input")
+                        .value_name("INPUT_DIR")
+                        .help("The input directory the release was built from")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("against")
+                        .long("against")
+                        .value_name("MANIFEST")
+                        .help("URL or path of the release's sqlite-dist-manifest.json")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("version")
+                        .long("version")
+                        .value_name("VERSION")
+                        .help("Set the version, defaults to package.version in the spec"),
+                )
+                .arg(
+                    Arg::new("profile")
+                        .long("profile")
+                        .value_name("PROFILE")
+                        .help("Apply the spec's [profiles.PROFILE] the release was built with"),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Compare the manifests of two releases: platforms, assets, sizes, and dependencies")
+                .arg(
+                    Arg::new("old")
+                        .value_name("OLD_MANIFEST")
+                        .help("sqlite-dist-manifest.json of the previous release, or its output directory")
+                        .required(true)
+                        .index(1)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("new")
+                        .value_name("NEW_MANIFEST")
+                        .help("sqlite-dist-manifest.json of the new release, or its output directory")
+                        .required(true)
+                        .index(2)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("strict")
+                        .long("strict")
+                        .help("Fail when the new release drops a platform or asset")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("generate-ci")
+                .about("Write a CI pipeline building the extension on every platform, running sqlite-dist, and publishing the targets")
+                .arg(
+                    Arg::new("provider")
+                        .value_name("PROVIDER")
+                        .required(true)
+                        .index(1)
+                        .value_parser(["github", "gitlab", "circleci", "buildkite"]),
+                )
+                .arg(
+                    Arg::new("file")
+                        .long("spec")
+                        .value_name("FILE")
+                        .help("The spec file, defaults to sqlite-dist.toml")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("build-command")
+                        .long("build-command")
+                        .value_name("COMMAND")
+                        .help("Command compiling the loadables, defaults to `make loadable`"),
+                )
+                .arg(
+                    Arg::new("loadables")
+                        .long("loadables")
+                        .value_name("GLOB")
+                        .help("Where the build command leaves the loadables, defaults to dist/*"),
+                )
+                .arg(
+                    Arg::new("platform")
+                        .long("platform")
+                        .value_name("OS-CPU")
+                        .help("Platform to build on, ex linux-x86_64. Repeat for more")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .value_name("PATH")
+                        .help("Where to write the pipeline, defaults to the provider's usual location")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("stdout")
+                        .long("stdout")
+                        .help("Print the pipeline instead of writing it")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("publish")
+                .about("Upload the assets of a previous build to a package registry")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("npm")
+                        .about("Publish the npm packages, platform packages first. Reads the token from NPM_TOKEN")
+                        .arg(
+                            Arg::new("dir")
+                                .value_name("OUTPUT_DIR")
+                                .help("The --output directory of the build")
+                                .required(true)
+                                .index(1)
+                                .value_parser(value_parser!(PathBuf)),
+                        )
+                        .arg(
+                            Arg::new("provenance")
+                                .long("provenance")
+                                .help("Publish with a provenance attestation, from GitHub Actions or GitLab CI")
+                                .action(ArgAction::SetTrue),
+                        )
+                        .arg(
+                            Arg::new("tag")
+                                .long("tag")
+                                .value_name("TAG")
+                                .help("dist-tag, defaults to latest, or next for prereleases"),
+                        )
+                        .arg(
+                            Arg::new("registry")
+                                .long("registry")
+                                .value_name("URL")
+                                .help("Defaults to https://registry.npmjs.org/"),
+                        )
+                        .arg(
+                            Arg::new("dry-run")
+                                .long("dry-run")
+                                .help("Pass --dry-run to npm publish")
+                                .action(ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    Command::new("rubygems")
+                        .about("Push the gems through the RubyGems API. Reads the API key from GEM_HOST_API_KEY")
+                        .arg(
+                            Arg::new("dir")
+                                .value_name("OUTPUT_DIR")
+                                .help("The --output directory of the build")
+                                .required(true)
+                                .index(1)
+                                .value_parser(value_parser!(PathBuf)),
+                        )
+                        .arg(
+                            Arg::new("otp")
+                                .long("otp")
+                                .value_name("CODE")
+                                .help("One-time password for accounts with MFA, defaults to GEM_HOST_OTP_CODE"),
+                        )
+                        .arg(
+                            Arg::new("host")
+                                .long("host")
+                                .value_name("URL")
+                                .help("Defaults to https://rubygems.org"),
+                        )
+                        .arg(
+                            Arg::new("dry-run")
+                                .long("dry-run")
+                                .help("List the gems without pushing them")
+                                .action(ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    Command::new("pypi")
+                        .about("Upload the wheels to PyPI or an internal index. Reads the token or password from PYPI_TOKEN")
+                        .arg(
+                            Arg::new("dir")
+                                .value_name("OUTPUT_DIR")
+                                .help("The --output directory of the build")
+                                .required(true)
+                                .index(1)
+                                .value_parser(value_parser!(PathBuf)),
+                        )
+                        .arg(
+                            Arg::new("repository-url")
+                                .long("repository-url")
+                                .value_name("URL")
+                                .help("Upload endpoint of a devpi, Artifactory, or other internal index"),
+                        )
+                        .arg(
+                            Arg::new("username")
+                                .long("username")
+                                .value_name("USER")
+                                .help("For basic auth, defaults to __token__ for API tokens"),
+                        )
+                        .arg(
+                            Arg::new("dry-run")
+                                .long("dry-run")
+                                .help("List the wheels without uploading them")
+                                .action(ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    Command::new("s3")
+                        .about("Upload every asset to an S3-compatible bucket with the aws CLI")
+                        .arg(
+                            Arg::new("dir")
+                                .value_name("OUTPUT_DIR")
+                                .help("The --output directory of the build")
+                                .required(true)
+                                .index(1)
+                                .value_parser(value_parser!(PathBuf)),
+                        )
+                        .arg(
+                            Arg::new("destination")
+                                .value_name("S3_URL")
+                                .help("Where to upload to, ex s3://bucket/foo/v1.2.3")
+                                .required(true)
+                                .index(2),
+                        )
+                        .arg(
+                            Arg::new("endpoint-url")
+                                .long("endpoint-url")
+                                .value_name("URL")
+                                .help("Endpoint of an S3-compatible service"),
+                        )
+                        .arg(
+                            Arg::new("public-url")
+                                .long("public-url")
+                                .value_name("URL")
+                                .help("URL the destination is served from, written to the uploaded manifest"),
+                        )
+                        .arg(
+                            Arg::new("dry-run")
+                                .long("dry-run")
+                                .help("Pass --dryrun to aws s3 cp")
+                                .action(ArgAction::SetTrue),
+                        ),
+                ),
+        )
         .get_matches();
 
-    match build(matches) {
+    init_logging(matches.get_count("verbose"));
+    sqlite_dist::http::set_offline(matches.get_flag("offline"));
+    let result = match matches.subcommand() {
+        Some(("check", matches)) => check(matches),
+        Some(("bump", matches)) => bump(matches),
+        Some(("init", matches)) => init(matches),
+        Some(("doctor", matches)) => doctor(matches),
+        Some(("lint", matches)) => lint(matches),
+        Some(("verify-wheels", matches)) => verify_wheels(matches),
+        Some(("diff", matches)) => diff(matches),
+        Some(("verify", matches)) => verify(matches),
+        Some(("generate-ci", matches)) => generate_ci(matches),
+        Some(("publish", matches)) => publish(matches),
+        _ => build(matches),
+    };
+    match result {
         Ok(_) => std::process::exit(0),
         Err(error) => {
             eprintln!("Build error: {error}");
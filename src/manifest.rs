@@ -1,7 +1,14 @@
-use crate::{GeneratedAsset, GeneratedAssetKind};
+use crate::capabilities::Capabilities;
+use crate::http;
+use crate::spec::Spec;
+use crate::{GeneratedAsset, GeneratedAssetKind, Project};
+use semver::Version;
 use serde::{Deserialize, Serialize};
-use std::io::Result;
-use std::path::Path;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Result};
+use std::path::{Path, PathBuf};
+
+const MANIFEST_FILE: &str = "sqlite-dist-manifest.json";
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ManifestBuildInfo {
@@ -11,24 +18,279 @@ pub struct ManifestBuildInfo {
 #[derive(Serialize)]
 pub struct Manifest<'a> {
     build_info: ManifestBuildInfo,
+    /// version of the main package
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_sqlite_version: Option<String>,
+    /// ecosystem -> the extra dependencies the spec gives its packages
+    dependencies: BTreeMap<&'static str, Vec<String>>,
+    /// versions `publish` retracts from the registries
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    yanked: Vec<String>,
+    /// functions, virtual tables and collations the spec declares
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capabilities: Option<Capabilities<'a>>,
+    /// the package's previous name, deprecated by `publish`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rename: Option<ManifestRename>,
+
+    artifacts: Vec<ManifestArtifactEntry<'a>>,
+}
 
-    artifacts: &'a [GeneratedAsset],
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ManifestArtifactEntry<'a> {
+    Generated(ManifestEntry<'a>),
+    /// an artifact of the previous manifest a partial build kept, as it was listed
+    Kept(&'a serde_json::Value),
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct ManifestRename {
+    pub(crate) npm_name: String,
+    pub(crate) message: String,
+}
+
+#[derive(Serialize)]
+struct ManifestEntry<'a> {
+    #[serde(flatten)]
+    asset: &'a GeneratedAsset,
+    /// `$OS-$CPU` of platform specific assets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+    description: String,
+    /// Content-Type the asset is uploaded with
+    media_type: String,
+}
+
+fn media_type(project: Option<&Project>, asset: &GeneratedAsset) -> String {
+    let plugin_media_type = match (&asset.kind, project) {
+        (GeneratedAssetKind::Plugin(name), Some(project)) => project
+            .spec
+            .plugins
+            .iter()
+            .find(|plugin| &plugin.name == name)
+            .and_then(|plugin| plugin.media_type.clone()),
+        _ => None,
+    };
+    plugin_media_type.unwrap_or_else(|| asset.kind.media_type(&asset.name).to_owned())
+}
+
+fn dependencies(spec: &Spec) -> BTreeMap<&'static str, Vec<String>> {
+    let targets = &spec.targets;
+    let mut dependencies = BTreeMap::new();
+    if let Some(pip) = &targets.pip {
+        let mut pip_dependencies = pip.extra_dependencies.clone();
+        pip_dependencies.extend(
+            spec.requires
+                .iter()
+                .map(|require| format!("{}>={}", require.pip_name(), require.min_version)),
+        );
+        dependencies.insert("pip", pip_dependencies);
+    }
+    if let Some(datasette) = &targets.datasette {
+        if !datasette.extra_dependencies.is_empty() {
+            dependencies.insert("datasette", datasette.extra_dependencies.clone());
+        }
+    }
+    if let Some(sqlite_utils) = &targets.sqlite_utils {
+        if !sqlite_utils.extra_dependencies.is_empty() {
+            dependencies.insert("sqlite_utils", sqlite_utils.extra_dependencies.clone());
+        }
+    }
+    if let Some(npm) = &targets.npm {
+        let mut npm_dependencies: Vec<String> = npm
+            .extra_dependencies
+            .iter()
+            .map(|(name, range)| format!("{name}@{range}"))
+            .chain(
+                spec.requires
+                    .iter()
+                    .map(|require| format!("{}@>={}", require.npm_name(), require.min_version)),
+            )
+            .collect();
+        npm_dependencies.sort();
+        dependencies.insert("npm", npm_dependencies);
+    }
+    if let Some(gem) = &targets.gem {
+        let gem_dependencies = gem
+            .extra_dependencies
+            .iter()
+            .map(|(name, requirement)| format!("{name} {requirement}"))
+            .chain(
+                spec.requires
+                    .iter()
+                    .map(|require| format!("{} >= {}", require.gem_name(), require.min_version)),
+            )
+            .collect();
+        dependencies.insert("gem", gem_dependencies);
+    }
+    dependencies
 }
 
 pub(crate) fn write_manifest(
     manifest_dir: &Path,
+    project: Option<&Project>,
     generated_assets: &[GeneratedAsset],
+    kept_artifacts: &[serde_json::Value],
 ) -> Result<GeneratedAsset> {
     let manifest = Manifest {
         build_info: ManifestBuildInfo {
             sqlite_dist_version: "TODO".to_owned(),
         },
-        artifacts: generated_assets,
+        version: project.map(|project| project.version.to_string()),
+        min_sqlite_version: project
+            .and_then(|project| project.spec.min_sqlite_version())
+            .map(Version::to_string),
+        dependencies: project
+            .map(|project| dependencies(&project.spec))
+            .unwrap_or_default(),
+        yanked: project
+            .map(|project| project.spec.yanked.iter().map(Version::to_string).collect())
+            .unwrap_or_default(),
+        capabilities: project.and_then(Capabilities::new),
+        rename: project.and_then(|project| {
+            let rename = project.spec.rename.as_ref()?;
+            Some(ManifestRename {
+                npm_name: rename.npm_name().to_owned(),
+                message: project.spec.rename_message()?,
+            })
+        }),
+        artifacts: generated_assets
+            .iter()
+            .map(|asset| {
+                ManifestArtifactEntry::Generated(ManifestEntry {
+                    asset,
+                    platform: asset
+                        .kind
+                        .platform()
+                        .map(|(os, cpu)| format!("{}-{}", os.to_string(), cpu.to_string())),
+                    description: asset.kind.description(),
+                    media_type: media_type(project, asset),
+                })
+            })
+            .chain(kept_artifacts.iter().map(ManifestArtifactEntry::Kept))
+            .collect(),
     };
     let asset = GeneratedAsset::from(
         GeneratedAssetKind::Sqlpkg,
-        &manifest_dir.join("sqlite-dist-manifest.json"),
+        &manifest_dir.join(MANIFEST_FILE),
         serde_json::to_string_pretty(&manifest)?.as_bytes(),
     )?;
     Ok(asset)
 }
+
+/// An asset listed in the manifest of a previous build
+#[derive(Debug, Deserialize)]
+pub(crate) struct ManifestArtifact {
+    pub(crate) kind: String,
+    pub(crate) name: String,
+    pub(crate) path: String,
+    #[serde(default)]
+    pub(crate) size: u64,
+    #[serde(default)]
+    pub(crate) checksum_sha256: String,
+    pub(crate) platform: Option<String>,
+    /// missing from the manifests of older builds
+    pub(crate) media_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ManifestFile {
+    pub(crate) version: Option<String>,
+    #[serde(default)]
+    pub(crate) dependencies: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub(crate) yanked: Vec<String>,
+    pub(crate) rename: Option<ManifestRename>,
+    pub(crate) artifacts: Vec<ManifestArtifact>,
+}
+
+/// Reads a manifest file, or the manifest of an output directory
+pub(crate) fn read_manifest_file(path: &Path) -> Result<ManifestFile> {
+    let path = if path.is_dir() {
+        path.join(MANIFEST_FILE)
+    } else {
+        path.to_path_buf()
+    };
+    let contents = std::fs::read(&path)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))?;
+    Ok(serde_json::from_slice(&contents)?)
+}
+
+/// The artifacts of the manifest in `output_dir` that are still there and whose kind
+/// isn't in `generated`, with their path. A partial build keeps them, so the output
+/// directory still has the targets it didn't run.
+pub(crate) fn previous_artifacts(
+    output_dir: &Path,
+    generated: &HashSet<String>,
+) -> Vec<(PathBuf, serde_json::Value)> {
+    let Ok(contents) = std::fs::read(output_dir.join(MANIFEST_FILE)) else {
+        return vec![];
+    };
+    let Ok(serde_json::Value::Object(mut manifest)) = serde_json::from_slice(&contents) else {
+        return vec![];
+    };
+    let Some(serde_json::Value::Array(artifacts)) = manifest.remove("artifacts") else {
+        return vec![];
+    };
+    artifacts
+        .into_iter()
+        .filter_map(|value| {
+            let artifact: ManifestArtifact = serde_json::from_value(value.clone()).ok()?;
+            if generated.contains(&artifact.kind) {
+                return None;
+            }
+            Some((artifact.locate(output_dir).ok()?, value))
+        })
+        .collect()
+}
+
+/// Reads a manifest from a URL, like a release's `sqlite-dist-manifest.json`
+/// download, or like `read_manifest_file` from a path
+pub(crate) fn fetch_manifest_file(source: &str) -> Result<ManifestFile> {
+    if !(source.starts_with("https://") || source.starts_with("http://")) {
+        return read_manifest_file(Path::new(source));
+    }
+    let agent = http::agent()?;
+    let contents = http::send(source, || agent.get(source), None)
+        .map_err(|err| io::Error::other(format!("{source}: {err}")))?
+        .into_string()?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+impl ManifestArtifact {
+    /// The asset on disk. Paths are recorded relative to where the build ran, so when
+    /// ran from somewhere else the asset is looked up by name in the output directory.
+    pub(crate) fn locate(&self, output_dir: &Path) -> Result<PathBuf> {
+        let path = PathBuf::from(&self.path);
+        if path.is_file() {
+            return Ok(path);
+        }
+        find_file(output_dir, &self.name)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not in {}", self.name, output_dir.display()),
+            )
+        })
+    }
+}
+
+fn find_file(dir: &Path, name: &str) -> Result<Option<PathBuf>> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if let Some(found) = find_file(&path, name)? {
+                return Ok(Some(found));
+            }
+        } else if path.file_name().is_some_and(|file_name| file_name == name) {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+/// The assets of the build that wrote `output_dir`
+pub(crate) fn read_manifest(output_dir: &Path) -> Result<Vec<ManifestArtifact>> {
+    Ok(read_manifest_file(&output_dir.join(MANIFEST_FILE))?.artifacts)
+}
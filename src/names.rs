@@ -0,0 +1,77 @@
+/// npm's limit on the full name, scope included
+const MAX_NPM_NAME_LENGTH: usize = 214;
+
+/// PEP 503 normalized form PyPI compares project names by, ex `Sqlite_Vec` -> `sqlite-vec`
+pub(crate) fn normalize_pypi_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if "-_.".contains(c) {
+            if !normalized.ends_with('-') {
+                normalized.push('-');
+            }
+        } else {
+            normalized.push(c.to_ascii_lowercase());
+        }
+    }
+    normalized
+}
+
+/// PyPI's project name rule, checked before normalization
+pub(crate) fn pypi_name_problem(name: &str) -> Option<String> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+    (!valid).then(|| {
+        format!("name '{name}' must be letters, digits, ., - or _, starting and ending with a letter or digit")
+    })
+}
+
+/// npm's rules for new packages, ex `sqlite-vec` or `@scope/sqlite-vec`
+pub(crate) fn npm_name_problem(name: &str) -> Option<String> {
+    let unscoped = match name.strip_prefix('@') {
+        Some(scoped) => match scoped.split_once('/') {
+            Some((scope, unscoped)) if !scope.is_empty() && !scope.starts_with('.') => unscoped,
+            _ => return Some(format!("scoped name '{name}' must be @scope/name")),
+        },
+        None => name,
+    };
+    let valid = !unscoped.is_empty()
+        && !unscoped.starts_with(['.', '_'])
+        && name == name.to_lowercase()
+        && !["node_modules", "favicon.ico"].contains(&unscoped)
+        && name
+            .trim_start_matches('@')
+            .replacen('/', "", 1)
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c));
+    if !valid {
+        return Some(format!(
+            "name '{name}' must be lowercase letters, digits, -, ., _ or ~, not starting with . or _"
+        ));
+    }
+    (name.len() > MAX_NPM_NAME_LENGTH)
+        .then(|| format!("name '{name}' is longer than npm's {MAX_NPM_NAME_LENGTH} characters"))
+}
+
+/// RubyGems' rule, which also rejects names without a letter
+pub(crate) fn gem_name_problem(name: &str) -> Option<String> {
+    let valid = name.chars().any(|c| c.is_ascii_alphabetic())
+        && !name.starts_with(['-', '.', '_'])
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+    (!valid).then(|| {
+        format!("name '{name}' must be letters, digits, ., - or _, not starting with one of ._-")
+    })
+}
+
+/// Homebrew formula names are lowercase and used as the file name, ex `sqlite-vec`
+pub(crate) fn formula_name_problem(name: &str) -> Option<String> {
+    let valid = name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-+.@_".contains(c));
+    (!valid).then(|| format!("name '{name}' must be lowercase letters, digits, -, +, ., @ or _"))
+}
@@ -1,8 +1,4 @@
-use std::{
-    collections::HashMap,
-    fs, io,
-    path::{Path, PathBuf},
-};
+use std::{collections::HashMap, io, path::Path};
 
 use serde::{Deserialize, Serialize};
 
@@ -16,6 +12,12 @@ pub struct Repository {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ExportTarget {
+    // for Cloudflare Workers, the most specific condition goes first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workerd: Option<String>,
+    // for bundlers targeting browsers, before import so it takes precedence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub browser: Option<String>,
     // for CJS, should end in .cjs
     #[serde(skip_serializing_if = "Option::is_none")]
     pub require: Option<String>,
@@ -46,6 +48,9 @@ pub struct PackageJson {
 
     pub exports: HashMap<String, ExportTarget>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scripts: Option<HashMap<String, String>>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub files: Option<Vec<String>>,
 
@@ -68,12 +73,18 @@ pub struct PackageJson {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cpu: Option<Vec<String>>,
+
+    /// only informational for `sqlite`, npm only enforces node and npm
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub engines: Option<HashMap<String, String>>,
 }
 
+use crate::capabilities::{capabilities_json, CAPABILITIES_FILE};
 use crate::{
-    create_targz, Cpu, GeneratedAsset, GeneratedAssetKind, Os, PlatformDirectory, PlatformFile,
-    Project,
+    create_targz, spec::Spec, Cpu, GeneratedAsset, GeneratedAssetKind, Os, PlatformDirectory,
+    PlatformFile, Project, WasmDirectory,
 };
+use semver::Version;
 
 use thiserror::Error;
 
@@ -83,6 +94,13 @@ pub enum NpmBuildError {
     IOError(#[from] io::Error),
     #[error("JSON serialization error: {0}")]
     JsonError(#[from] serde_json::Error),
+    #[error("no platform directory has a loadable for npm, which supports macos, linux, windows, freebsd, and openbsd")]
+    NoPlatforms,
+}
+
+/// Tarball file name for a package, with scoped names like `@foo/bar` flattened to `foo-bar`
+fn tarball_name(package_name: &str) -> String {
+    format!("{}.tar.gz", package_name.replace('@', "").replace('/', "-"))
 }
 
 struct NpmPlatformPackage {
@@ -91,28 +109,72 @@ struct NpmPlatformPackage {
     cpu: Cpu,
     data: Vec<u8>,
 }
+/// Builds the npm packages of a spec as a library, ex
+/// `NpmTarget::new(spec, version).platform(platform_dir).generate(&output_dir)`
+pub struct NpmTarget {
+    project: Project,
+}
+
+impl NpmTarget {
+    pub fn new(spec: Spec, version: Version) -> Self {
+        Self {
+            project: Project::new(spec, version),
+        }
+    }
+
+    pub fn platform(mut self, platform: PlatformDirectory) -> Self {
+        self.project = self.project.platform(platform);
+        self
+    }
+
+    pub fn wasm(mut self, wasm: WasmDirectory) -> Self {
+        self.project = self.project.wasm(wasm);
+        self
+    }
+
+    pub fn generate(&self, output_dir: &Path) -> Result<Vec<GeneratedAsset>, NpmBuildError> {
+        write_npm_packages(&self.project, output_dir)
+    }
+}
+
 pub(crate) fn write_npm_packages(
     project: &Project,
     npm_ouput_directory: &Path,
-    emscripten_dir: &Option<PathBuf>,
 ) -> Result<Vec<GeneratedAsset>, NpmBuildError> {
     let mut assets = vec![];
-    let author = project.spec.package.authors.first().unwrap();
+    let author = project
+        .spec
+        .package
+        .authors
+        .first()
+        .cloned()
+        .unwrap_or_default();
+    let npm_config = project.spec.targets.npm.as_ref();
+    let package_name = npm_config
+        .and_then(|npm| npm.package_name.as_deref())
+        .unwrap_or(&project.spec.package.name);
     let npm_platform_directories: Vec<&PlatformDirectory> = project
         .platform_directories
         .iter()
         .filter(|platform_dir| {
-            matches!(platform_dir.os, Os::Linux | Os::Macos | Os::Windows)
-                && matches!(platform_dir.cpu, Cpu::X86_64 | Cpu::Aarch64)
+            matches!(
+                platform_dir.os,
+                Os::Linux | Os::Macos | Os::Windows | Os::Freebsd | Os::Openbsd
+            ) && (matches!(platform_dir.cpu, Cpu::X86_64 | Cpu::Aarch64)
+                || (platform_dir.os == Os::Linux
+                    && matches!(
+                        platform_dir.cpu,
+                        Cpu::Armv7a | Cpu::Riscv64 | Cpu::Loongarch64 | Cpu::S390x
+                    )))
         })
         .collect();
-    let entrypoint = &npm_platform_directories
+    let entrypoints: Vec<&str> = npm_platform_directories
         .first()
-        .unwrap()
+        .ok_or(NpmBuildError::NoPlatforms)?
         .loadable_files
-        .first()
-        .unwrap()
-        .file_stem;
+        .iter()
+        .map(|loadable| loadable.file_stem.as_str())
+        .collect();
 
     let platform_pkgs: Vec<PackageJson> = npm_platform_directories
         .iter()
@@ -121,6 +183,8 @@ pub(crate) fn write_npm_packages(
                 Os::Linux => "linux",
                 Os::Macos => "darwin",
                 Os::Windows => "win32",
+                Os::Freebsd => "freebsd",
+                Os::Openbsd => "openbsd",
                 _ => unreachable!(
                     "Invalid npm OS {:?}, should be filtered from above.",
                     platform_dir.os
@@ -130,6 +194,8 @@ pub(crate) fn write_npm_packages(
                 Os::Linux => "linux",
                 Os::Macos => "darwin",
                 Os::Windows => "windows",
+                Os::Freebsd => "freebsd",
+                Os::Openbsd => "openbsd",
                 _ => unreachable!(
                     "Invalid npm OS {:?}, should be filtered from above.",
                     platform_dir.os
@@ -138,6 +204,10 @@ pub(crate) fn write_npm_packages(
             let npm_cpu = match platform_dir.cpu {
                 Cpu::X86_64 => "x64",
                 Cpu::Aarch64 => "arm64",
+                Cpu::Armv7a => "arm",
+                Cpu::Riscv64 => "riscv64",
+                Cpu::Loongarch64 => "loong64",
+                Cpu::S390x => "s390x",
                 _ => unreachable!(
                     "Invalid npm CPU {:?} should be filtered from above.",
                     platform_dir.cpu
@@ -146,7 +216,7 @@ pub(crate) fn write_npm_packages(
             PackageJson {
                 name: format!(
                     "{pkg}-{os}-{cpu}",
-                    pkg = project.spec.package.name,
+                    pkg = package_name,
                     os = npm_pkg_os_name,
                     cpu = npm_cpu
                 ),
@@ -165,11 +235,14 @@ pub(crate) fn write_npm_packages(
                 exports: HashMap::from([(
                     ".".to_owned(),
                     ExportTarget {
+                        workerd: None,
+                        browser: None,
                         require: Some("./index.cjs".to_owned()),
                         import: "./index.mjs".to_owned(),
                         types: Some("./index.d.ts".to_owned()),
                     },
                 )]),
+                scripts: None,
                 files: vec![].into(),
                 keywords: vec![].into(),
                 dependencies: None,
@@ -177,6 +250,7 @@ pub(crate) fn write_npm_packages(
                 dev_dependencies: None,
                 os: Some(vec![npm_os.to_owned()]),
                 cpu: Some(vec![npm_cpu.to_owned()]),
+                engines: None,
             }
         })
         .collect();
@@ -185,6 +259,7 @@ pub(crate) fn write_npm_packages(
         .iter()
         .zip(&npm_platform_directories)
         .map(|(pkg, platform_dir)| {
+            let _span = platform_dir.span().entered();
             let mut files = vec![
                 PlatformFile::new("package/README.md", "TODO", None),
                 PlatformFile::new("package/package.json", serde_json::to_string(&pkg)?, None),
@@ -196,6 +271,13 @@ pub(crate) fn write_npm_packages(
                     loadable_file.file.metadata.clone(),
                 ));
             }
+            for extra_file in &platform_dir.extra_files {
+                files.push(PlatformFile::new(
+                    format!("package/{}", extra_file.name),
+                    extra_file.data.clone(),
+                    extra_file.metadata.clone(),
+                ));
+            }
 
             Ok(NpmPlatformPackage {
                 name: pkg.name.clone(),
@@ -207,8 +289,9 @@ pub(crate) fn write_npm_packages(
         .collect();
     let pkg_targzs = pkg_targzs?;
 
+    let post_install = project.spec.post_install_message("npm");
     let top_pkg = PackageJson {
-        name: project.spec.package.name.clone(),
+        name: package_name.to_owned(),
         version: project.version.to_string(),
         author: author.clone(),
         license: project.spec.package.license.clone(),
@@ -224,14 +307,32 @@ pub(crate) fn write_npm_packages(
         exports: HashMap::from([(
             ".".to_owned(),
             ExportTarget {
+                workerd: None,
+                browser: None,
                 require: Some("./index.cjs".to_owned()),
                 import: "./index.mjs".to_owned(),
                 types: Some("./index.d.ts".to_owned()),
             },
         )]),
+        scripts: post_install.map(|_| {
+            HashMap::from([("postinstall".to_owned(), "node postinstall.cjs".to_owned())])
+        }),
         files: vec![].into(),
         keywords: vec![].into(),
-        dependencies: None,
+        dependencies: Some(
+            npm_config
+                .map(|npm| npm.extra_dependencies.clone())
+                .unwrap_or_default()
+                .into_iter()
+                .chain(project.spec.requires.iter().map(|require| {
+                    (
+                        require.npm_name().to_owned(),
+                        format!(">={}", require.min_version),
+                    )
+                }))
+                .collect::<HashMap<String, String>>(),
+        )
+        .filter(|dependencies| !dependencies.is_empty()),
         optional_dependencies: Some(HashMap::from_iter(
             platform_pkgs
                 .iter()
@@ -240,15 +341,33 @@ pub(crate) fn write_npm_packages(
         dev_dependencies: None,
         os: None,
         cpu: None,
+        engines: project
+            .spec
+            .min_sqlite_version()
+            .map(|min| HashMap::from([("sqlite".to_owned(), format!(">={min}"))])),
     };
 
     let platforms = npm_platform_directories
         .iter()
         .map(|pd| (pd.os.clone(), pd.cpu.clone()))
         .collect::<Vec<(Os, Cpu)>>();
-    let pkg_name = project.spec.package.name.clone();
-    let top_pkg_targz_files = vec![
-        PlatformFile::new("package/README.md", "TODO", None),
+    let pkg_name = package_name.to_owned();
+    let init_functions = project.spec.init_functions(&entrypoints);
+    let index_js = templates::IndexJs {
+        pkg_name: pkg_name.clone(),
+        entrypoints: &entrypoints,
+        init_functions: &init_functions,
+        supported_platforms: &platforms,
+        checks: project.loader_checks(),
+        requires: &project.spec.requires,
+        functions: &project.spec.functions,
+    };
+    let mut top_pkg_targz_files = vec![
+        PlatformFile::new(
+            "package/README.md",
+            project.render_template("npm/README.md", None, "TODO".to_owned())?,
+            None,
+        ),
         PlatformFile::new(
             "package/package.json",
             serde_json::to_string(&top_pkg)?,
@@ -256,19 +375,51 @@ pub(crate) fn write_npm_packages(
         ),
         PlatformFile::new(
             "package/index.mjs",
-            templates::index_js(pkg_name.clone(), entrypoint, &platforms, JsFormat::ESM),
+            project.render_template(
+                "npm/index.mjs",
+                None,
+                templates::index_js(&index_js, JsFormat::ESM),
+            )?,
             None,
         ),
         PlatformFile::new(
             "package/index.cjs",
-            templates::index_js(pkg_name.clone(), entrypoint, &platforms, JsFormat::CJS),
+            project.render_template(
+                "npm/index.cjs",
+                None,
+                templates::index_js(&index_js, JsFormat::CJS),
+            )?,
+            None,
+        ),
+        PlatformFile::new(
+            "package/index.d.ts",
+            project.render_template(
+                "npm/index.d.ts",
+                None,
+                templates::index_dts(&project.spec.functions),
+            )?,
             None,
         ),
-        PlatformFile::new("package/index.d.ts", templates::index_dts(), None),
     ];
-    if let Some(emscripten_dir) = emscripten_dir {
+    if let Some(capabilities) = capabilities_json(project) {
+        top_pkg_targz_files.push(PlatformFile::new(
+            format!("package/{CAPABILITIES_FILE}"),
+            capabilities,
+            None,
+        ));
+    }
+    if let Some(message) = post_install {
+        top_pkg_targz_files.push(PlatformFile::new(
+            "package/postinstall.cjs",
+            templates::postinstall_cjs(message),
+            None,
+        ));
+    }
+    if let Some(wasm_directory) = &project.wasm_directory {
+        let module = wasm_directory.module().unwrap_or("sqlite3.mjs");
+        let wasm = wasm_directory.wasm().unwrap_or("sqlite3.wasm");
         let wasm_pkg_json = PackageJson {
-            name: format!("{}-wasm-demo", project.spec.package.name),
+            name: format!("{}-wasm", package_name),
             version: project.version.to_string(),
             author: author.clone(),
             license: project.spec.package.license.clone(),
@@ -279,16 +430,19 @@ pub(crate) fn write_npm_packages(
                 directory: None,
             },
             main: None,
-            module: "./sqlite3.mjs".to_owned(),
-            types: None,
+            module: "./index.mjs".to_owned(),
+            types: Some("./index.d.ts".to_owned()),
             exports: HashMap::from([(
                 ".".to_owned(),
                 ExportTarget {
+                    workerd: None,
+                    browser: Some("./index.mjs".to_owned()),
                     require: None,
-                    import: "./sqlite3.mjs".to_owned(),
-                    types: None,
+                    import: "./index.mjs".to_owned(),
+                    types: Some("./index.d.ts".to_owned()),
                 },
             )]),
+            scripts: None,
             files: vec![].into(),
             keywords: vec![].into(),
             dependencies: None,
@@ -296,8 +450,9 @@ pub(crate) fn write_npm_packages(
             dev_dependencies: None,
             os: None,
             cpu: None,
+            engines: None,
         };
-        let wasm_pkg_targz_files = vec![
+        let mut wasm_pkg_targz_files = vec![
             PlatformFile::new("package/README.md", "TODO", None),
             PlatformFile::new(
                 "package/package.json",
@@ -305,21 +460,36 @@ pub(crate) fn write_npm_packages(
                 None,
             ),
             PlatformFile::new(
-                "package/sqlite3.mjs",
-                fs::read(emscripten_dir.join("sqlite3.mjs"))?,
+                "package/index.mjs",
+                project.render_template(
+                    "npm/wasm/index.mjs",
+                    None,
+                    templates::wasm_index_js(package_name, module, wasm),
+                )?,
                 None,
             ),
             PlatformFile::new(
-                "package/sqlite3.wasm",
-                fs::read(emscripten_dir.join("sqlite3.wasm"))?,
+                "package/index.d.ts",
+                project.render_template(
+                    "npm/wasm/index.d.ts",
+                    None,
+                    templates::wasm_index_dts(),
+                )?,
                 None,
             ),
         ];
+        for file in &wasm_directory.files {
+            wasm_pkg_targz_files.push(PlatformFile::new(
+                format!("package/{}", file.name),
+                file.data.clone(),
+                file.metadata.clone(),
+            ));
+        }
         let wasm_pkg_targz =
             create_targz(&wasm_pkg_targz_files.iter().collect::<Vec<&PlatformFile>>())?;
         assets.push(GeneratedAsset::from(
-            GeneratedAssetKind::Npm(None),
-            &npm_ouput_directory.join(format!("{}.tar.gz", wasm_pkg_json.name)),
+            GeneratedAssetKind::Npm(Some((Os::Emscripten, Cpu::Wasm32))),
+            &npm_ouput_directory.join(tarball_name(&wasm_pkg_json.name)),
             &wasm_pkg_targz,
         )?);
     }
@@ -328,13 +498,13 @@ pub(crate) fn write_npm_packages(
     for pkg in pkg_targzs {
         assets.push(GeneratedAsset::from(
             GeneratedAssetKind::Npm(Some((pkg.os.clone(), pkg.cpu.clone()))),
-            &npm_ouput_directory.join(format!("{}.tar.gz", pkg.name)),
+            &npm_ouput_directory.join(tarball_name(&pkg.name)),
             &pkg.data,
         )?);
     }
     assets.push(GeneratedAsset::from(
         GeneratedAssetKind::Npm(None),
-        &npm_ouput_directory.join(format!("{}.tar.gz", top_pkg.name)),
+        &npm_ouput_directory.join(tarball_name(&top_pkg.name)),
         &top_pkg_targz?,
     )?);
     Ok(assets)
@@ -346,41 +516,217 @@ enum JsFormat {
     ESM,
 }
 mod templates {
-    use crate::{Cpu, Os};
+    use std::collections::BTreeMap;
+
+    use crate::{
+        spec::{SpecFunction, SpecRequire},
+        Cpu, LoaderChecks, Os,
+    };
 
     use super::JsFormat;
-    pub(crate) fn index_dts() -> String {
+
+    fn functions_dts(functions: &[SpecFunction]) -> String {
+        if functions.is_empty() {
+            return String::new();
+        }
+        let members: String = functions
+            .iter()
+            .map(|function| {
+                let description = function
+                    .description
+                    .as_ref()
+                    .map(|d| format!("\n     *\n     * {}", d.replace("*/", "*\\/")))
+                    .unwrap_or_default();
+                format!(
+                    "    /**\n     * `{}`, {}{description}\n     */\n    readonly {}: \"{}\";\n",
+                    function.signature(),
+                    function.kind.description(),
+                    function.name,
+                    function.name
+                )
+            })
+            .collect();
+        format!(
+            r#"
+/**
+ * SQL functions and virtual tables provided by the extension
+ */
+export declare const functions: {{
+{members}}};
+
+export type FunctionName = (typeof functions)[keyof typeof functions];
+"#
+        )
+    }
+
+    pub(crate) fn postinstall_cjs(message: &str) -> String {
+        format!(
+            "console.log({});\n",
+            serde_json::to_string(message).expect("String values should always serialize as JSON")
+        )
+    }
+
+    pub(crate) fn index_dts(functions: &[SpecFunction]) -> String {
         r#"
 
 /**
  * TODO JSDoc
  */
-export declare function getLoadablePath(): string;
+export declare function getLoadablePath(entrypoint?: string): string;
 
+/**
+ * The loadable for another platform, ex `getLoadablePathFor({ platform: "win32", arch: "x64" })`.
+ * Inside an Electron app.asar, returns the copy in app.asar.unpacked.
+ */
+export declare function getLoadablePathFor(
+    target: { platform: string; arch: string },
+    entrypoint?: string
+): string;
+
+
+/** A better-sqlite3 `Database` */
+interface BetterSqlite3Database {
+    loadExtension(file: string, entrypoint?: string | undefined): unknown;
+}
 
-interface Db {
-    loadExtension(file: string, entrypoint?: string | undefined): void;
+/** A node:sqlite `DatabaseSync`, opened with `allowExtension: true` */
+interface NodeSqliteDatabase {
+    loadExtension(path: string, entryPoint?: string | undefined): void;
+    enableLoadExtension(allow: boolean): void;
+}
+
+/** A sqlite3 (callback API) `Database` */
+interface Sqlite3Database {
+    loadExtension(filename: string, callback?: (err: Error | null) => void): unknown;
+    serialize(callback?: () => void): void;
+    each(sql: string, ...params: any[]): unknown;
 }
 
 /**
- * TODO JSDoc
+ * Loads the extension into a sqlite3 database, resolving once it's loaded.
+ */
+export declare function load(db: Sqlite3Database): Promise<void>;
+/**
+ * Loads the extension into a better-sqlite3 or node:sqlite database.
  */
-export declare function load(db: Db): void;
+export declare function load(db: BetterSqlite3Database | NodeSqliteDatabase): void;
+"#
+        .to_string()
+            + &functions_dts(functions)
+    }
+    /// Browser entrypoint of the wasm package, no Node APIs. The SQLite build has the
+    /// extension compiled in, so "loading" it means running SQLite from this build.
+    pub(crate) fn wasm_index_js(pkg_name: &str, module: &str, wasm: &str) -> String {
+        let module = serde_json::to_string(&format!("./{module}"))
+            .expect("String values should always serialize as JSON");
+        let wasm =
+            serde_json::to_string(wasm).expect("String values should always serialize as JSON");
+        format!(
+            r#"import factory from {module};
 
+const WASM_FILE = {wasm};
+
+function locateFile(file) {{
+  return new URL(file, import.meta.url).href;
+}}
+
+/**
+ * Initializes the SQLite build bundled with this package, with {pkg_name} compiled in.
+ */
+export async function init(config = {{}}) {{
+  return factory({{ locateFile, ...config }});
+}}
+
+/**
+ * Initializes sql.js or wa-sqlite with this package's wasm build, which has
+ * {pkg_name} compiled in. `SQL` is sql.js' `initSqlJs`, or a wa-sqlite module
+ * factory like `SQLiteESMFactory`, of the version the wasm build was made from.
+ */
+export async function load(SQL, config = {{}}) {{
+  if (typeof SQL !== "function") {{
+    throw new TypeError("load() expects sql.js' initSqlJs or a wa-sqlite module factory");
+  }}
+  return SQL({{
+    ...config,
+    locateFile: (file) => (file.endsWith(".wasm") ? locateFile(WASM_FILE) : file),
+  }});
+}}
+"#
+        )
+    }
+
+    pub(crate) fn wasm_index_dts() -> String {
+        r#"
+export declare function init(config?: object): Promise<any>;
+
+export declare function load<T>(SQL: (config?: object) => Promise<T>, config?: object): Promise<T>;
 "#
         .to_string()
     }
-    pub(crate) fn index_js(
-        pkg_name: String,
-        entrypoint: &str,
-        supported_platforms: &[(Os, Cpu)],
-        format: JsFormat,
-    ) -> String {
+
+    /// What the generated `index.mjs` and `index.cjs` load and check
+    pub(crate) struct IndexJs<'a> {
+        pub(crate) pkg_name: String,
+        pub(crate) entrypoints: &'a [&'a str],
+        pub(crate) init_functions: &'a [(&'a str, &'a str)],
+        pub(crate) supported_platforms: &'a [(Os, Cpu)],
+        pub(crate) checks: LoaderChecks<'a>,
+        pub(crate) requires: &'a [SpecRequire],
+        pub(crate) functions: &'a [SpecFunction],
+    }
+
+    pub(crate) fn index_js(loader: &IndexJs, format: JsFormat) -> String {
+        let IndexJs {
+            pkg_name,
+            entrypoints,
+            init_functions,
+            supported_platforms,
+            checks,
+            requires,
+            functions,
+        } = loader;
+        let functions_js = if functions.is_empty() {
+            String::new()
+        } else {
+            let names: serde_json::Map<String, serde_json::Value> = functions
+                .iter()
+                .map(|f| (f.name.clone(), serde_json::Value::String(f.name.clone())))
+                .collect();
+            format!(
+                "\nconst functions = Object.freeze({});\n",
+                serde_json::to_string(&names)
+                    .expect("String values should always serialize as JSON")
+            )
+        };
+        let require_imports: String = requires
+            .iter()
+            .enumerate()
+            .map(|(i, require)| {
+                let name = serde_json::to_string(require.npm_name())
+                    .expect("String values should always serialize as JSON");
+                match format {
+                    JsFormat::CJS => format!("const required{i} = require({name});\n"),
+                    JsFormat::ESM => format!("import * as required{i} from {name};\n"),
+                }
+            })
+            .collect();
+        let require_loads: String = (0..requires.len())
+            .map(|i| format!("  required{i}.load(db);\n"))
+            .collect();
+        let require_awaits: String = (0..requires.len())
+            .map(|i| format!("  await required{i}.load(db);\n"))
+            .collect();
         let base_package_name = serde_json::to_string(&serde_json::Value::String(pkg_name.clone()))
             .expect("String value should always serialize as JSON");
-        let entrypoint_base_name =
-            serde_json::to_string(&serde_json::Value::String(entrypoint.to_owned()))
-                .expect("String value should always serialize as JSON");
+        let entrypoint_base_names = serde_json::to_string(entrypoints)
+            .expect("String values should always serialize as JSON");
+        let init_functions = serde_json::to_string(
+            &init_functions
+                .iter()
+                .copied()
+                .collect::<BTreeMap<&str, &str>>(),
+        )
+        .expect("String values should always serialize as JSON");
 
         let supported_platforms: Vec<Vec<String>> = supported_platforms
             .iter()
@@ -392,7 +738,7 @@ export declare function load(db: Db): void;
         let imports = match format {
             JsFormat::CJS => {
                 r#"
-const { join } = require("node:path");
+const { join, win32 } = require("node:path");
 const { fileURLToPath } = require("node:url");
 const { arch, platform } = require("node:process");
 const { statSync } = require("node:fs");
@@ -400,18 +746,76 @@ const { statSync } = require("node:fs");
             }
             JsFormat::ESM => {
                 r#"
-import { join } from "node:path";
+import { join, win32 } from "node:path";
 import { fileURLToPath } from "node:url";
 import { arch, platform } from "node:process";
 import { statSync } from "node:fs";
 "#
             }
         };
+        let imports = format!("{imports}{require_imports}");
 
         let exports = match format {
-            JsFormat::CJS => r#"module.exports = {getLoadablePath, load};"#,
-            JsFormat::ESM => r#"export {getLoadablePath, load};"#,
+            JsFormat::CJS => "module.exports = {getLoadablePath, getLoadablePathFor, load",
+            JsFormat::ESM => "export {getLoadablePath, getLoadablePathFor, load",
+        };
+        let exports = if functions.is_empty() {
+            format!("{exports}}};")
+        } else {
+            format!("{exports}, functions}};")
+        };
+        let (min_sqlite_check, min_sqlite_check_call) = match checks.min_sqlite_version {
+            Some(min) => (
+                format!(
+                    r#"
+const MIN_SQLITE_VERSION = [{}, {}, {}];
+
+function checkSqliteVersion(row) {{
+  if (row === undefined) return;
+  const {{ version }} = row;
+  const parts = version.split(".").map(Number);
+  for (let i = 0; i < MIN_SQLITE_VERSION.length; i++) {{
+    if ((parts[i] ?? 0) > MIN_SQLITE_VERSION[i]) return;
+    if ((parts[i] ?? 0) < MIN_SQLITE_VERSION[i]) {{
+      throw new Error(`${{BASE_PACKAGE_NAME}} requires SQLite {min} or later, but the database uses SQLite ${{version}}`);
+    }}
+  }}
+}}
+"#,
+                    min.major, min.minor, min.patch
+                ),
+                "  checkSqliteVersion(ROW(db, \"select sqlite_version() as version\"));\n",
+            ),
+            None => (String::new(), ""),
+        };
+        let (version_check, version_check_call) = match checks.version_check {
+            Some((sql, expected)) => (
+                format!(
+                    r#"
+const VERSION_SQL = {};
+
+function checkVersion(row) {{
+  if (row === undefined) return;
+  const loadedVersion = String(Object.values(row)[0]);
+  if (loadedVersion.replace(/^v/, "") !== "{expected}") {{
+    throw new Error(`${{BASE_PACKAGE_NAME}} {expected} was loaded, but ${{VERSION_SQL}} returned ${{loadedVersion}}. Is an older copy of the extension installed?`);
+  }}
+}}
+"#,
+                    serde_json::to_string(sql)
+                        .expect("String values should always serialize as JSON")
+                ),
+                "  checkVersion(ROW(db, VERSION_SQL));\n",
+            ),
+            None => (String::new(), ""),
         };
+        // the same checks for sqlite3's callback API, awaiting the row
+        let row = "getRow";
+        let callback_row = "await getCallbackRow";
+        let callback_min_sqlite_check_call = min_sqlite_check_call.replace("ROW", callback_row);
+        let callback_version_check_call = version_check_call.replace("ROW", callback_row);
+        let min_sqlite_check_call = min_sqlite_check_call.replace("ROW", row);
+        let version_check_call = version_check_call.replace("ROW", row);
         let current_directory = match format {
             JsFormat::CJS => r#"__dirname"#,
             // the "join()" is needed because webpack likes to re-write this sometimes??? unbelievable
@@ -423,9 +827,11 @@ import { statSync } from "node:fs";
 {imports}
 
 const BASE_PACKAGE_NAME = {base_package_name};
-const ENTRYPOINT_BASE_NAME = {entrypoint_base_name};
+const ENTRYPOINT_BASE_NAMES = {entrypoint_base_names};
+// entrypoint -> init function, for loadables SQLite can't derive it from the file name of
+const INIT_FUNCTIONS = {init_functions};
 const supportedPlatforms = {supported_platforms};
-
+{functions_js}
 const invalidPlatformErrorMessage = `Unsupported platform for ${{BASE_PACKAGE_NAME}}, on a ${{platform}}-${{arch}} machine. Supported platforms are (${{supportedPlatforms
   .map(([p, a]) => `${{p}}-${{a}}`)
   .join(",")}}). Consult the ${{BASE_PACKAGE_NAME}} NPM package README for details.`;
@@ -447,32 +853,200 @@ function platformPackageName(platform, arch) {{
   return `${{BASE_PACKAGE_NAME}}-${{os}}-${{arch}}`;
 }}
 
-function getLoadablePath() {{
-  if (!validPlatform(platform, arch)) {{
+// Electron apps packaged into app.asar can't dlopen() from inside the archive. With
+// `asarUnpack: ["**/node_modules/{{name}}-*/**"]` in electron-builder
+// (or `unpack` in electron-forge) the loadables are next to it in app.asar.unpacked.
+function unpackedPath(loadablePath) {{
+  return loadablePath.replace(/([\\/])app\.asar([\\/])/, "$1app.asar.unpacked$2");
+}}
+
+const isAscii = (s) => /^[\x00-\x7f]*$/.test(s);
+
+// A \\?\ extended-length path, like Electron may give, is turned back into a plain
+// one, which every SQLite build loads. A non-ASCII path is passed in the \\?\ form
+// instead, so it reaches LoadLibraryW as is rather than through the ANSI code page.
+// The file name is kept, SQLite derives the entrypoint from it.
+function windowsPath(loadablePath) {{
+  const path = loadablePath.replace(/^\\\\\?\\UNC\\/, "\\\\").replace(/^\\\\\?\\/, "");
+  return isAscii(path) ? path : win32.toNamespacedPath(path);
+}}
+
+// `target` is anything with a Node platform and arch, like process, or
+// {{ platform: "win32", arch: "x64" }} when packaging an Electron app for another OS
+function getLoadablePathFor(target, entrypoint = ENTRYPOINT_BASE_NAMES[0]) {{
+  if (!validPlatform(target.platform, target.arch)) {{
     throw new Error(
       invalidPlatformErrorMessage
     );
   }}
-  const packageName = platformPackageName(platform, arch);
+  const packageName = platformPackageName(target.platform, target.arch);
   const loadablePath = join(
     {current_directory},
     "..",
     packageName,
-    `${{ENTRYPOINT_BASE_NAME}}.${{extensionSuffix(platform)}}`
+    `${{entrypoint}}.${{extensionSuffix(target.platform)}}`
   );
+  const unpacked = unpackedPath(loadablePath);
+  if (unpacked !== loadablePath && statSync(unpacked, {{ throwIfNoEntry: false }})) {{
+    return platform === "win32" ? windowsPath(unpacked) : unpacked;
+  }}
   if (!statSync(loadablePath, {{ throwIfNoEntry: false }})) {{
     throw new Error(extensionNotFoundErrorMessage(packageName));
   }}
 
-  return loadablePath;
+  return platform === "win32" ? windowsPath(loadablePath) : loadablePath;
 }}
 
-function load(db) {{
-  db.loadExtension(getLoadablePath());
+function getLoadablePath(entrypoint = ENTRYPOINT_BASE_NAMES[0]) {{
+  return getLoadablePathFor({{ platform, arch }}, entrypoint);
+}}
+
+{min_sqlite_check}{version_check}
+// sqlite3's Database has a callback-only API, where prepare() can't be used synchronously
+function isCallbackDatabase(db) {{
+  return typeof db.serialize === "function" && typeof db.each === "function";
+}}
+
+// first row of `sql`, for drivers with a synchronous prepare(), like better-sqlite3 and node:sqlite
+function getRow(db, sql) {{
+  if (typeof db.prepare !== "function") return undefined;
+  return db.prepare(sql).get();
+}}
+
+function getCallbackRow(db, sql) {{
+  return new Promise((resolve, reject) =>
+    db.get(sql, (err, row) => (err ? reject(err) : resolve(row)))
+  );
+}}
+
+// loading extensions stays off outside of load(), like the Python and Ruby loaders
+function disableLoadExtension(db) {{
+  if (typeof db.enableLoadExtension === "function") {{
+    db.enableLoadExtension(false);
+  }}
+}}
+
+function loadExtension(db, entrypoint) {{
+  // node:sqlite only allows loadExtension() after enableLoadExtension(true), which
+  // itself requires the DatabaseSync to be opened with `allowExtension: true`
+  if (typeof db.enableLoadExtension === "function") {{
+    db.enableLoadExtension(true);
+  }}
+  // node:sqlite rejects an undefined entry point
+  if (entrypoint in INIT_FUNCTIONS) {{
+    db.loadExtension(getLoadablePath(entrypoint), INIT_FUNCTIONS[entrypoint]);
+  }} else {{
+    db.loadExtension(getLoadablePath(entrypoint));
+  }}
 }}
 
+async function loadCallbackDatabase(db) {{
+{callback_min_sqlite_check_call}{require_awaits}  for (const entrypoint of ENTRYPOINT_BASE_NAMES) {{
+    if (entrypoint in INIT_FUNCTIONS) {{
+      throw new Error(`sqlite3's loadExtension() can't pass the ${{INIT_FUNCTIONS[entrypoint]}} init function ${{BASE_PACKAGE_NAME}} needs, use better-sqlite3 or node:sqlite`);
+    }}
+    await new Promise((resolve, reject) =>
+      db.loadExtension(getLoadablePath(entrypoint), (err) => (err ? reject(err) : resolve()))
+    );
+  }}
+{callback_version_check_call}}}
+
+function load(db) {{
+  if (isCallbackDatabase(db)) {{
+    return loadCallbackDatabase(db);
+  }}
+{min_sqlite_check_call}{require_loads}  try {{
+    for (const entrypoint of ENTRYPOINT_BASE_NAMES) {{
+      loadExtension(db, entrypoint);
+    }}
+  }} finally {{
+    disableLoadExtension(db);
+  }}
+{version_check_call}}}
+
 {exports}
 "#
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::templates::{index_js, IndexJs};
+    use super::JsFormat;
+    use crate::{Cpu, LoaderChecks, Os};
+
+    /// `windowsPath()` of the generated loader, called on every path under node
+    fn windows_paths(paths: &[&str]) -> Option<Vec<String>> {
+        let loader = index_js(
+            &IndexJs {
+                pkg_name: "sqlite-sample".to_owned(),
+                entrypoints: &["sample0"],
+                init_functions: &[],
+                supported_platforms: &[(Os::Windows, Cpu::X86_64)],
+                checks: LoaderChecks {
+                    min_sqlite_version: None,
+                    version_check: None,
+                },
+                requires: &[],
+                functions: &[],
+            },
+            JsFormat::CJS,
+        );
+        let start = loader.find("const isAscii").unwrap();
+        let end = start + loader[start..].find("\n}\n").unwrap() + 3;
+        let script = format!(
+            "const {{ win32 }} = require(\"node:path\");\n{}\nfor (const path of JSON.parse(process.argv[1])) console.log(windowsPath(path));",
+            &loader[start..end]
+        );
+        let output = Command::new("node")
+            .arg("-e")
+            .arg(script)
+            .arg(serde_json::to_string(paths).unwrap())
+            .output()
+            .ok()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Some(
+            String::from_utf8(output.stdout)
+                .unwrap()
+                .lines()
+                .map(str::to_owned)
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn windows_path() {
+        let paths = [
+            r"C:\app\node_modules\sqlite-sample-windows-x64\sample0.dll",
+            r"\\?\C:\app\node_modules\sqlite-sample-windows-x64\sample0.dll",
+            r"\\?\UNC\server\share\sqlite-sample-windows-x64\sample0.dll",
+            r"C:\Users\Zoë\app\sample0.dll",
+            r"\\?\C:\Users\Zoë\app\sample0.dll",
+            r"\\server\share\Zoë\sample0.dll",
+            r"C:\Users\100% done\sample0.dll",
+        ];
+        let Some(resolved) = windows_paths(&paths) else {
+            eprintln!("node is not installed, skipping");
+            return;
+        };
+        assert_eq!(
+            resolved,
+            [
+                r"C:\app\node_modules\sqlite-sample-windows-x64\sample0.dll",
+                r"C:\app\node_modules\sqlite-sample-windows-x64\sample0.dll",
+                r"\\server\share\sqlite-sample-windows-x64\sample0.dll",
+                r"\\?\C:\Users\Zoë\app\sample0.dll",
+                r"\\?\C:\Users\Zoë\app\sample0.dll",
+                r"\\?\UNC\server\share\Zoë\sample0.dll",
+                r"C:\Users\100% done\sample0.dll",
+            ]
+        );
+    }
+}
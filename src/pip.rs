@@ -1,152 +1,22 @@
 use std::{
-    collections::HashMap,
-    io::{self, Cursor, Write},
-    path::Path,
+    io::{self, Cursor, Seek, Write},
+    path::{Path, PathBuf},
 };
 
-use crate::{Cpu, GeneratedAsset, GeneratedAssetKind, Os, Project};
+use crate::capabilities::{capabilities_json, CAPABILITIES_FILE};
+use crate::macho::minimum_macos_version;
+use crate::names::normalize_pypi_name;
+use crate::{
+    spec::{PipBuildMetadata, Spec, SpecPackage, WheelCompression},
+    zip_options, Cpu, GeneratedAsset, GeneratedAssetKind, Os, OutputFile, PlatformDirectory,
+    Project,
+};
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use semver::Version;
 use sha2::{Digest, Sha256};
-use zip::{result::ZipError, write::FileOptions, ZipWriter};
-
-mod templates {
-    use crate::{pip::platform_target_tag, Cpu, Os};
-
-    use super::PipPackage;
-
-    pub(crate) fn dist_info_metadata(pkg: &PipPackage) -> String {
-        let name = &pkg.package_name;
-        let version = &pkg.package_version;
-        let extra_metadata: String = if pkg.extra_metadata.len() > 1 {
-            let mut s = String::new();
-            for (key, value) in &pkg.extra_metadata {
-                s += format!("{key}: {value}\n").as_str();
-            }
-            s
-        } else {
-            "".to_owned()
-        };
-        format!(
-            "Metadata-Version: 2.1
-Name: {name}
-Version: {version}
-Home-page: https://TODO.com
-Author: TODO
-License: MIT License, Apache License, Version 2.0
-Description-Content-Type: text/markdown
-{extra_metadata}
-
-TODO readme"
-        )
-    }
-
-    pub(crate) fn dist_info_entrypoints(entrypoints: &Vec<(String, String)>) -> String {
-        let mut txt = String::new();
-        for (key, value) in entrypoints {
-            txt += format!("[{key}]\n").as_str();
-            txt += value;
-            txt += "\n\n";
-        }
-
-        txt
-    }
-    pub(crate) fn dist_info_wheel(platform: Option<(&Os, &Cpu)>) -> String {
-        let name = env!("CARGO_PKG_NAME");
-        let version = env!("CARGO_PKG_VERSION");
-        let platform_tag = match platform {
-            Some((os, cpu)) => platform_target_tag(os, cpu),
-            None => "any".to_owned(),
-        };
-        let tag = format!("py3-none-{platform_tag}");
-        format!(
-            "Wheel-Version: 1.0
-Generator: {name} {version}
-Root-Is-Purelib: false
-Tag: {tag}",
-        )
-    }
-    pub(crate) fn dist_info_top_level_txt(pkg: &PipPackage) -> String {
-        format!("{}\n", pkg.python_package_name)
-    }
-
-    pub(crate) fn dist_info_record(pkg: &PipPackage, record_path: &str) -> String {
-        let mut record = String::new();
-        for file in &pkg.written_files {
-            record.push_str(format!("{},sha256={},{}\n", file.path, file.hash, file.size).as_str());
-        }
-
-        // RECORD one can be empty
-        record.push_str(format!("{},,\n", record_path).as_str());
+use zip::{result::ZipError, ZipWriter};
 
-        record
-    }
-    pub(crate) fn base_init_py(pkg: &PipPackage, entrypoint: &str) -> String {
-        let version = &pkg.package_version;
-        let package_name = &pkg.package_name;
-        format!(
-            r#"
-from os import path
-import sqlite3
-
-__version__ = "{version}"
-__version_info__ = tuple(__version__.split("."))
-
-def loadable_path():
-  """ Returns the full path to the {package_name} loadable SQLite extension bundled with this package """
-
-  loadable_path = path.join(path.dirname(__file__), "{entrypoint}")
-  return path.normpath(loadable_path)
-
-def load(conn: sqlite3.Connection)  -> None:
-  """ Load the {package_name} SQLite extension into the given database connection. """
-
-  conn.load_extension(loadable_path())
-
-"#,
-        )
-    }
-
-    pub(crate) fn sqlite_utils_init_py(dep_pkg: &PipPackage) -> String {
-        let dep_library = dep_pkg.python_package_name.clone();
-        let version = dep_pkg.package_version.clone();
-        format!(
-            r#"
-from sqlite_utils import hookimpl
-import {dep_library}
-
-__version__ = "{version}"
-__version_info__ = tuple(__version__.split("."))
-
-@hookimpl
-def prepare_connection(conn):
-  conn.enable_load_extension(True)
-  {dep_library}.load(conn)
-  conn.enable_load_extension(False)
-"#
-        )
-    }
-
-    pub(crate) fn datasette_init_py(dep_pkg: &PipPackage) -> String {
-        let dep_library = dep_pkg.python_package_name.clone();
-        let version = dep_pkg.package_version.clone();
-        format!(
-            r#"
-from datasette import hookimpl
-import {dep_library}
-
-__version__ = "{version}"
-__version_info__ = tuple(__version__.split("."))
-
-@hookimpl
-def prepare_connection(conn):
-  conn.enable_load_extension(True)
-  {dep_library}.load(conn)
-  conn.enable_load_extension(False)
-"#,
-        )
-    }
-}
+mod templates;
 
 pub struct PipPackageFile {
     path: String,
@@ -165,40 +35,59 @@ impl PipPackageFile {
     }
 }
 
-fn semver_to_pip_version(v: &Version) -> String {
-    match (
-        (!v.pre.is_empty()).then(|| v.pre.clone()),
-        (!v.build.is_empty()).then(|| v.build.clone()),
-    ) {
-        (None, None) => v.to_string(),
-        // ???
-        (None, Some(_build)) => v.to_string(),
-        (Some(pre), None) => {
-            let base = Version::new(v.major, v.minor, v.patch).to_string();
-            let (a, b) = pre.split_once('.').unwrap();
-            match a {
-                "alpha" => format!("{base}a{b}"),
-                "beta" => format!("{base}b{b}"),
-                "rc" => format!("{base}rc{b}"),
-                _ => todo!(),
-            }
-        }
-        (Some(_pre), Some(_build)) => todo!(),
+/// PEP 440 version of a semver version. Build metadata is dropped, PyPI rejects
+/// local versions.
+pub(crate) fn semver_to_pip_version(v: &Version) -> Result<String, PipBuildError> {
+    let base = Version::new(v.major, v.minor, v.patch).to_string();
+    if v.pre.is_empty() {
+        return Ok(base);
+    }
+    let (tag, number) = v.pre.split_once('.').unwrap_or((v.pre.as_str(), "0"));
+    if number.parse::<u64>().is_err() {
+        return Err(PipBuildError::UnsupportedVersion(v.to_string()));
     }
-    /*if v.pre.is_empty() && v.build.is_empty() {
-        v.to_string()
-    } else if v.build.is_empty() {
-    }*/
+    match tag {
+        "alpha" => Ok(format!("{base}a{number}")),
+        "beta" => Ok(format!("{base}b{number}")),
+        "rc" => Ok(format!("{base}rc{number}")),
+        "dev" => Ok(format!("{base}.dev{number}")),
+        _ => Err(PipBuildError::UnsupportedVersion(v.to_string())),
+    }
+}
+
+/// Version of the spec's wheels, with the build metadata as a local version when
+/// `targets.pip.build_metadata` keeps it
+fn pip_version(project: &Project) -> Result<String, PipBuildError> {
+    let version = semver_to_pip_version(&project.version)?;
+    let local = project
+        .spec
+        .targets
+        .pip
+        .as_ref()
+        .is_some_and(|pip| matches!(pip.build_metadata, PipBuildMetadata::Local));
+    if !local || project.version.build.is_empty() {
+        return Ok(version);
+    }
+    // local version segments are separated by dots, `-` normalizes to `.`
+    Ok(format!(
+        "{version}+{}",
+        project.version.build.as_str().replace('-', ".")
+    ))
 }
 
 pub fn platform_target_tag(os: &Os, cpu: &Cpu) -> String {
     match (os, cpu) {
         (Os::Macos, Cpu::X86_64) => "macosx_10_6_x86_64".to_owned(),
         (Os::Macos, Cpu::Aarch64) => "macosx_11_0_arm64".to_owned(),
+        (Os::Macos, Cpu::Universal2) => "macosx_10_9_universal2".to_owned(),
         (Os::Linux, Cpu::X86_64) => {
             "manylinux_2_17_x86_64.manylinux2014_x86_64.manylinux1_x86_64".to_owned()
         }
-        (Os::Linux, Cpu::Aarch64) => "manylinux_2_17_aarch64.manylinux2014_aarch64.whl".to_owned(),
+        (Os::Linux, Cpu::Aarch64) => "manylinux_2_17_aarch64.manylinux2014_aarch64".to_owned(),
+        (Os::Linux, Cpu::Armv7a) => "manylinux_2_17_armv7l.manylinux2014_armv7l".to_owned(),
+        (Os::Linux, Cpu::Riscv64) => "manylinux_2_31_riscv64".to_owned(),
+        (Os::Linux, Cpu::Loongarch64) => "manylinux_2_36_loongarch64".to_owned(),
+        (Os::Linux, Cpu::S390x) => "manylinux_2_17_s390x.manylinux2014_s390x".to_owned(),
         (Os::Windows, Cpu::X86_64) => "win_amd64".to_owned(),
         _ => {
             unreachable!(
@@ -209,8 +98,84 @@ pub fn platform_target_tag(os: &Os, cpu: &Cpu) -> String {
     }
 }
 
-pub struct PipPackage {
-    pub zipfile: ZipWriter<Cursor<Vec<u8>>>,
+/// `platform_target_tag`, with a newer macOS deployment target when the loadables'
+/// load commands ask for one
+fn detected_platform_tag(platform_dir: &PlatformDirectory) -> String {
+    let tag = platform_target_tag(&platform_dir.os, &platform_dir.cpu);
+    if platform_dir.os != Os::Macos {
+        return tag;
+    }
+    let Some((major, minor)) = platform_dir
+        .loadable_files
+        .iter()
+        .filter_map(|loadable| minimum_macos_version(&loadable.file.data))
+        .max()
+    else {
+        return tag;
+    };
+    // pip only matches macOS 11 and later on the major version
+    let minor = if major >= 11 { 0 } else { minor };
+    // ex `macosx_10_6_x86_64`
+    let mut parts = tag.splitn(4, '_').skip(1);
+    let (Some(tag_major), Some(tag_minor), Some(arch)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return tag;
+    };
+    let hardcoded = (
+        tag_major.parse().unwrap_or(0),
+        tag_minor.parse().unwrap_or(0),
+    );
+    if (major, minor) <= hardcoded {
+        return tag;
+    }
+    let detected = format!("macosx_{major}_{minor}_{arch}");
+    tracing::warn!(
+        "the loadables in {} need macOS {major}.{minor}, tagging their wheel {detected} instead of {tag}",
+        platform_dir.path.display()
+    );
+    detected
+}
+
+/// The compressed tag set of a platform's wheel: `detected_platform_tag`, with the pip
+/// target's `platform_tags` for the platform applied
+fn wheel_platform_tag(project: &Project, platform_dir: &PlatformDirectory) -> String {
+    let name = format!(
+        "{}-{}",
+        platform_dir.os.to_string(),
+        platform_dir.cpu.to_string()
+    );
+    let Some(overrides) = project
+        .spec
+        .targets
+        .pip
+        .as_ref()
+        .and_then(|pip| pip.platform_tags.get(&name))
+    else {
+        return detected_platform_tag(platform_dir);
+    };
+    let mut tags = overrides.tags.clone().unwrap_or_else(|| {
+        detected_platform_tag(platform_dir)
+            .split('.')
+            .map(str::to_owned)
+            .collect()
+    });
+    for tag in &overrides.extra_tags {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    tags.join(".")
+}
+
+fn wheel_name(python_package_name: &str, version: &str, platform_tag: Option<&str>) -> String {
+    let python_tag = "py3";
+    let abi_tag = "none";
+    let platform_tag = platform_tag.unwrap_or("any");
+    format!("{python_package_name}-{version}-{python_tag}-{abi_tag}-{platform_tag}.whl")
+}
+
+pub struct PipPackage<W: Write + Seek = Cursor<Vec<u8>>> {
+    pub zipfile: ZipWriter<W>,
     // as-is, with dashes, not python code safe
     pub package_name: String,
     // dashes replaced with underscores
@@ -222,21 +187,61 @@ pub struct PipPackage {
 
     pub entrypoints: Vec<(String, String)>,
     pub extra_metadata: Vec<(String, String)>,
+    // the spec's description, license and links, rendered into METADATA
+    pub package: SpecPackage,
+    pub compression_method: zip::CompressionMethod,
+    // replaces the templated METADATA file when set
+    pub metadata: Option<String>,
 }
 
 impl PipPackage {
-    pub fn new<S: Into<String>>(package_name: S, package_version: &Version) -> Self {
-        let buffer = Cursor::new(Vec::new());
-        let zipfile = zip::ZipWriter::new(buffer);
+    pub fn new<S: Into<String>>(
+        package_name: S,
+        package_version: &Version,
+    ) -> Result<Self, PipBuildError> {
+        Ok(Self::with_writer(
+            Cursor::new(Vec::new()),
+            package_name,
+            semver_to_pip_version(package_version)?,
+        ))
+    }
+}
+
+impl PipPackage<OutputFile> {
+    /// Streams the wheel straight into `directory` instead of buffering it in memory,
+    /// so large loadables aren't held twice per platform. Returns the wheel's path.
+    pub(crate) fn create<S: Into<String>>(
+        package_name: S,
+        package_version: String,
+        directory: &Path,
+        platform_tag: Option<&str>,
+    ) -> Result<(Self, PathBuf), PipBuildError> {
+        let package_name = package_name.into();
+        let path = directory.join(wheel_name(
+            &package_name.replace('-', "_"),
+            &package_version,
+            platform_tag,
+        ));
+        let pkg = Self::with_writer(OutputFile::create(&path)?, package_name, package_version);
+        Ok((pkg, path))
+    }
+}
+
+impl<W: Write + Seek> PipPackage<W> {
+    fn with_writer<S: Into<String>>(writer: W, package_name: S, package_version: String) -> Self {
+        let zipfile = zip::ZipWriter::new(writer);
         let package_name = package_name.into();
         Self {
             zipfile,
             package_name: package_name.clone(),
             python_package_name: package_name.replace('-', "_"),
-            package_version: semver_to_pip_version(package_version),
+            package_version,
             written_files: vec![],
             entrypoints: vec![],
             extra_metadata: vec![],
+            package: SpecPackage::default(),
+            compression_method: zip::CompressionMethod::Stored,
+            metadata: None,
         }
     }
 
@@ -244,20 +249,8 @@ impl PipPackage {
         self.entrypoints.push((key.to_owned(), value.to_owned()));
     }
 
-    pub fn wheel_name(&self, platform: Option<(&Os, &Cpu)>) -> String {
-        let name = &self.python_package_name;
-        let version = &self.package_version;
-        let python_tag = "py3";
-        let abi_tag = "none";
-        let platform_tag = match platform {
-            Some((os, cpu)) => platform_target_tag(os, cpu),
-            None => "any".to_owned(),
-        };
-        format!("{name}-{version}-{python_tag}-{abi_tag}-{platform_tag}.whl")
-    }
-
     fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), ZipError> {
-        let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let options = zip_options(self.compression_method, data.len());
         self.zipfile.start_file(path, options)?;
         self.zipfile.write_all(data)?;
         self.written_files.push(PipPackageFile::new(path, data));
@@ -278,47 +271,55 @@ impl PipPackage {
         )
     }
 
-    fn write_dist_info_metadata(&mut self) -> Result<(), ZipError> {
-        self.write_file(
-            self.dist_info_file("METADATA").as_str(),
-            templates::dist_info_metadata(self).as_bytes(),
-        )
+    fn template_context(&self) -> templates::Context<'_> {
+        templates::Context {
+            package_name: &self.package_name,
+            python_package_name: &self.python_package_name,
+            version: &self.package_version,
+        }
     }
 
-    fn write_dist_info_record(&mut self) -> Result<(), ZipError> {
-        let record_path = self.dist_info_file("RECORD");
-        self.write_file(
-            &record_path,
-            templates::dist_info_record(self, &record_path).as_bytes(),
-        )
-    }
-    fn write_dist_info_top_level_txt(&mut self) -> Result<(), ZipError> {
-        self.write_file(
-            self.dist_info_file("top_level.txt").as_str(),
-            templates::dist_info_top_level_txt(self).as_bytes(),
-        )
-    }
-    fn write_dist_info_wheel(&mut self, platform: Option<(&Os, &Cpu)>) -> Result<(), ZipError> {
-        self.write_file(
-            self.dist_info_file("WHEEL").as_str(),
-            templates::dist_info_wheel(platform).as_bytes(),
-        )
-    }
-    fn write_dist_info_entrypoints(&mut self) -> Result<(), ZipError> {
-        self.write_file(
-            self.dist_info_file("entry_points.txt").as_str(),
-            templates::dist_info_entrypoints(&self.entrypoints).as_bytes(),
-        )
+    pub fn default_metadata(&self) -> String {
+        templates::dist_info_metadata(self.template_context(), &self.package, &self.extra_metadata)
     }
 
-    pub fn end(mut self, platform: Option<(&Os, &Cpu)>) -> Result<Cursor<Vec<u8>>, ZipError> {
-        self.write_dist_info_metadata()?;
-        self.write_dist_info_wheel(platform)?;
+    /// Every `.dist-info` file but RECORD, which lists them
+    fn dist_info_files(&self, platform_tag: Option<&str>) -> Vec<(String, String)> {
+        let metadata = self
+            .metadata
+            .clone()
+            .unwrap_or_else(|| self.default_metadata());
+        let mut files = vec![
+            (self.dist_info_file("METADATA"), metadata),
+            (
+                self.dist_info_file("WHEEL"),
+                templates::dist_info_wheel(platform_tag),
+            ),
+        ];
         if !self.entrypoints.is_empty() {
-            self.write_dist_info_entrypoints()?;
+            files.push((
+                self.dist_info_file("entry_points.txt"),
+                templates::dist_info_entrypoints(&self.entrypoints),
+            ));
+        }
+        files.push((
+            self.dist_info_file("top_level.txt"),
+            templates::dist_info_top_level_txt(self.template_context()),
+        ));
+        files
+    }
+
+    /// Writes the `.dist-info` files, then RECORD with the hash and size of every
+    /// other file in the wheel. RECORD can't hash itself, so its own line has neither.
+    pub fn end(mut self, platform_tag: Option<&str>) -> Result<W, ZipError> {
+        for (path, contents) in self.dist_info_files(platform_tag) {
+            self.write_file(&path, contents.as_bytes())?;
         }
-        self.write_dist_info_top_level_txt()?;
-        self.write_dist_info_record()?;
+        let record_path = self.dist_info_file("RECORD");
+        let record = templates::dist_info_record(&self.written_files, &record_path);
+        let options = zip_options(self.compression_method, record.len());
+        self.zipfile.start_file(&record_path, options)?;
+        self.zipfile.write_all(record.as_bytes())?;
         self.zipfile.finish()
     }
 }
@@ -331,6 +332,64 @@ pub enum PipBuildError {
     ZipError(#[from] ZipError),
     #[error("I/O error: {0}")]
     IOError(#[from] io::Error),
+    #[error("version {0} has no PEP 440 equivalent, pre-releases must look like alpha.N, beta.N, rc.N, or dev.N")]
+    UnsupportedVersion(String),
+    #[error("platform directory {0} does not contain any loadable files")]
+    NoLoadableFiles(PathBuf),
+}
+
+/// `Requires-Dist` entries of a plugin wheel: the host package unless an extra
+/// dependency already constrains it, then the extra dependencies
+fn plugin_requirements(host: &str, extra_dependencies: &[String]) -> Vec<(String, String)> {
+    let requirement_name = |requirement: &str| {
+        let end = requirement
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "-_.".contains(c)))
+            .unwrap_or(requirement.len());
+        normalize_pypi_name(&requirement[..end])
+    };
+    let pinned = extra_dependencies
+        .iter()
+        .any(|dependency| requirement_name(dependency) == normalize_pypi_name(host));
+    (!pinned)
+        .then(|| host.to_owned())
+        .into_iter()
+        .chain(extra_dependencies.iter().cloned())
+        .map(|requirement| ("Requires-Dist".to_owned(), requirement))
+        .collect()
+}
+
+/// Name of the base wheel, package.name unless overriden in the pip target
+fn base_package_name(project: &Project) -> &str {
+    project
+        .spec
+        .targets
+        .pip
+        .as_ref()
+        .and_then(|pip| pip.package_name.as_deref())
+        .unwrap_or(&project.spec.package.name)
+}
+
+/// Builds the base wheels of a spec as a library, ex
+/// `PipTarget::new(spec, version).platform(platform_dir).generate(&output_dir)`
+pub struct PipTarget {
+    project: Project,
+}
+
+impl PipTarget {
+    pub fn new(spec: Spec, version: Version) -> Self {
+        Self {
+            project: Project::new(spec, version),
+        }
+    }
+
+    pub fn platform(mut self, platform: PlatformDirectory) -> Self {
+        self.project = self.project.platform(platform);
+        self
+    }
+
+    pub fn generate(&self, output_dir: &Path) -> Result<Vec<GeneratedAsset>, PipBuildError> {
+        write_base_packages(&self.project, output_dir)
+    }
 }
 
 pub(crate) fn write_base_packages(
@@ -339,21 +398,93 @@ pub(crate) fn write_base_packages(
 ) -> Result<Vec<GeneratedAsset>, PipBuildError> {
     let mut assets = vec![];
     for platform_dir in &project.platform_directories {
+        let _span = platform_dir.span().entered();
         // only a subset of platforms are supported in pip
         match (&platform_dir.os, &platform_dir.cpu) {
             (Os::Macos, Cpu::X86_64)
             | (Os::Macos, Cpu::Aarch64)
+            | (Os::Macos, Cpu::Universal2)
             | (Os::Linux, Cpu::X86_64)
             | (Os::Linux, Cpu::Aarch64)
+            | (Os::Linux, Cpu::Armv7a)
+            | (Os::Linux, Cpu::Riscv64)
+            | (Os::Linux, Cpu::Loongarch64)
+            | (Os::Linux, Cpu::S390x)
             | (Os::Windows, Cpu::X86_64) => (),
             //(Os::Linux, Cpu::Aarch64) => todo!(),
             //(Os::Windows, Cpu::Aarch64) => todo!(),
-            _ => continue,
+            _ => {
+                platform_dir.warn_unsupported("pip");
+                continue;
+            }
+        }
+        let platform = Some((&platform_dir.os, &platform_dir.cpu));
+        let platform_tag = wheel_platform_tag(project, platform_dir);
+        let (mut pkg, wheel_path) = PipPackage::create(
+            base_package_name(project),
+            pip_version(project)?,
+            pip_path,
+            Some(&platform_tag),
+        )?;
+        pkg.package = project.spec.package.clone();
+        if let Some(pip) = &project.spec.targets.pip {
+            pkg.compression_method = match pip.compression {
+                WheelCompression::Stored => zip::CompressionMethod::Stored,
+                WheelCompression::Deflated => zip::CompressionMethod::Deflated,
+            };
+            for dependency in &pip.extra_dependencies {
+                pkg.extra_metadata
+                    .push(("Requires-Dist".to_owned(), dependency.clone()));
+            }
+            for require in &project.spec.requires {
+                pkg.extra_metadata.push((
+                    "Requires-Dist".to_owned(),
+                    format!(
+                        "{}>={}",
+                        require.pip_name(),
+                        semver_to_pip_version(&require.min_version)?
+                    ),
+                ));
+            }
+            if let Some(min) = project.spec.min_sqlite_version() {
+                pkg.extra_metadata
+                    .push(("Requires-External".to_owned(), format!("sqlite (>={min})")));
+            }
+            if pip.apsw {
+                pkg.extra_metadata
+                    .push(("Provides-Extra".to_owned(), "apsw".to_owned()));
+                pkg.extra_metadata.push((
+                    "Requires-Dist".to_owned(),
+                    "apsw; extra == \"apsw\"".to_owned(),
+                ));
+            }
+            if pip.django {
+                pkg.extra_metadata
+                    .push(("Provides-Extra".to_owned(), "django".to_owned()));
+                pkg.extra_metadata.push((
+                    "Requires-Dist".to_owned(),
+                    "django; extra == \"django\"".to_owned(),
+                ));
+            }
+        }
+        pkg.metadata =
+            Some(project.render_template("pip/METADATA", platform, pkg.default_metadata())?);
+        if platform_dir.loadable_files.is_empty() {
+            return Err(PipBuildError::NoLoadableFiles(platform_dir.path.clone()));
         }
-        let mut pkg = PipPackage::new(&project.spec.package.name, &project.version);
-        assert!(!platform_dir.loadable_files.is_empty());
-        let entrypoint = &platform_dir.loadable_files.first().expect("TODO").file_stem;
-        let mut init_py = templates::base_init_py(&pkg, entrypoint);
+        let entrypoints: Vec<&str> = platform_dir
+            .loadable_files
+            .iter()
+            .map(|loadable| loadable.file_stem.as_str())
+            .collect();
+        let mut init_py = templates::base_init_py(
+            pkg.template_context(),
+            &entrypoints,
+            &project.spec.init_functions(&entrypoints),
+            project.spec.min_sqlite_version(),
+            project.loader_checks(),
+            &project.spec.requires,
+        );
         if let Some(extra_init_py) = project
             .spec
             .targets
@@ -364,35 +495,153 @@ pub(crate) fn write_base_packages(
             let contents = std::fs::read_to_string(project.spec_directory.join(extra_init_py))?;
             init_py += &contents;
         }
+        let init_py = project.render_template("pip/__init__.py", platform, init_py)?;
         pkg.write_library_file("__init__.py", init_py.as_bytes())?;
+        if !project.spec.functions.is_empty() {
+            let functions_py = project.render_template(
+                "pip/functions.py",
+                platform,
+                templates::functions_py(&pkg.package_name, &project.spec.functions),
+            )?;
+            pkg.write_library_file("functions.py", functions_py.as_bytes())?;
+            pkg.write_library_file("py.typed", b"")?;
+        }
+        if let Some(capabilities) = capabilities_json(project) {
+            pkg.write_library_file(CAPABILITIES_FILE, capabilities.as_bytes())?;
+        }
+        if project
+            .spec
+            .targets
+            .pip
+            .as_ref()
+            .is_some_and(|pip| pip.apsw)
+        {
+            let apsw_py = project.render_template(
+                "pip/apsw.py",
+                platform,
+                templates::apsw_py(
+                    pkg.template_context(),
+                    project.loader_checks(),
+                    &project.spec.requires,
+                ),
+            )?;
+            pkg.write_library_file("apsw.py", apsw_py.as_bytes())?;
+        }
+        if project
+            .spec
+            .targets
+            .pip
+            .as_ref()
+            .is_some_and(|pip| pip.django)
+        {
+            let django_py = project.render_template(
+                "pip/django.py",
+                platform,
+                templates::django_py(pkg.template_context()),
+            )?;
+            pkg.write_library_file("django.py", django_py.as_bytes())?;
+        }
 
         for f in &platform_dir.loadable_files {
             pkg.write_library_file(f.file.name.as_str(), &f.file.data)?;
         }
-        let platform = Some((&platform_dir.os, &platform_dir.cpu));
-        let wheel_name = pkg.wheel_name(platform);
-        let result = pkg.end(platform)?.into_inner();
-        let wheel_path = pip_path.join(wheel_name);
-        assets.push(GeneratedAsset::from(
+        for f in &platform_dir.extra_files {
+            pkg.write_library_file(f.name.as_str(), &f.data)?;
+        }
+        let output = pkg.end(Some(&platform_tag))?;
+        assets.push(GeneratedAsset::from_written(
             GeneratedAssetKind::Pip((platform_dir.os.clone(), platform_dir.cpu.clone())),
             &wheel_path,
-            &result,
+            output,
         )?);
     }
+    if project.spec.rename.is_some() && !assets.is_empty() {
+        assets.push(write_rename_package(project, pip_path)?);
+    }
     Ok(assets)
 }
 
+/// A wheel under the spec's `rename.from` name that depends on this version of the
+/// package and warns on import, so installs of the old name keep getting updates
+fn write_rename_package(
+    project: &Project,
+    pip_path: &Path,
+) -> Result<GeneratedAsset, PipBuildError> {
+    let rename = project
+        .spec
+        .rename
+        .as_ref()
+        .expect("write_rename_package is only called with a rename");
+    let dep_pkg = PipPackage::with_writer(
+        Cursor::new(Vec::new()),
+        base_package_name(project),
+        pip_version(project)?,
+    );
+    let (mut pkg, wheel_path) =
+        PipPackage::create(rename.pip_name(), pip_version(project)?, pip_path, None)?;
+    pkg.package = project.spec.package.clone();
+    let init_py = project.render_template(
+        "pip/rename/__init__.py",
+        None,
+        templates::rename_init_py(
+            dep_pkg.template_context(),
+            &project.spec.rename_message().unwrap_or_default(),
+        ),
+    )?;
+    pkg.write_library_file("__init__.py", init_py.as_bytes())?;
+    pkg.extra_metadata.push((
+        "Requires-Dist".to_owned(),
+        format!(
+            "{} (=={})",
+            base_package_name(project),
+            dep_pkg.package_version
+        ),
+    ));
+    pkg.extra_metadata.push((
+        "Classifier".to_owned(),
+        "Development Status :: 7 - Inactive".to_owned(),
+    ));
+    pkg.metadata =
+        Some(project.render_template("pip/rename/METADATA", None, pkg.default_metadata())?);
+
+    let output = pkg.end(None)?;
+    Ok(GeneratedAsset::from_written(
+        GeneratedAssetKind::PipRename,
+        &wheel_path,
+        output,
+    )?)
+}
+
 pub(crate) fn write_datasette(
     project: &Project,
     datasette_path: &Path,
 ) -> Result<GeneratedAsset, PipBuildError> {
-    let datasette_package_name = format!("datasette-{}", project.spec.package.name);
-    let dep_pkg = PipPackage::new(&project.spec.package.name, &project.version);
-    let mut pkg = PipPackage::new(datasette_package_name.clone(), &project.version);
-    pkg.write_library_file(
-        "__init__.py",
-        templates::datasette_init_py(&dep_pkg).as_bytes(),
+    let datasette_config = project.spec.targets.datasette.as_ref();
+    let datasette_package_name = datasette_config.map_or_else(
+        || format!("datasette-{}", project.spec.package.name),
+        |config| config.package_name(&project.spec.package.name),
+    );
+    let dep_pkg = PipPackage::with_writer(
+        Cursor::new(Vec::new()),
+        base_package_name(project),
+        pip_version(project)?,
+    );
+    let (mut pkg, wheel_path) = PipPackage::create(
+        datasette_package_name,
+        pip_version(project)?,
+        datasette_path,
+        None,
+    )?;
+    pkg.package = project.spec.package.clone();
+    let init_py = project.render_template(
+        "datasette/__init__.py",
+        None,
+        templates::datasette_init_py(
+            dep_pkg.template_context(),
+            datasette_config.map_or(&[], |config| config.configure_sql.as_slice()),
+        ),
     )?;
+    pkg.write_library_file("__init__.py", init_py.as_bytes())?;
 
     pkg.add_entrypoint(
         "datasette",
@@ -402,19 +651,27 @@ pub(crate) fn write_datasette(
         )
         .as_str(),
     );
+    pkg.extra_metadata.extend(plugin_requirements(
+        "datasette",
+        datasette_config.map_or(&[], |config| config.extra_dependencies.as_slice()),
+    ));
     pkg.extra_metadata
-        .push(("Requires-Dist".to_owned(), "datasette".to_owned()));
+        .push(("Classifier".to_owned(), "Framework :: Datasette".to_owned()));
+    if let Some(description) = datasette_config.and_then(|config| config.description.as_ref()) {
+        pkg.package.description = description.clone();
+    }
     pkg.extra_metadata.push((
         "Requires-Dist".to_owned(),
-        format!("{} (=={})", &project.spec.package.name, &project.version),
+        format!("{} (=={})", base_package_name(project), &project.version),
     ));
+    pkg.metadata =
+        Some(project.render_template("datasette/METADATA", None, pkg.default_metadata())?);
 
-    let wheel_name = pkg.wheel_name(None);
-    let result = pkg.end(None)?.into_inner();
-    Ok(GeneratedAsset::from(
+    let output = pkg.end(None)?;
+    Ok(GeneratedAsset::from_written(
         GeneratedAssetKind::Datasette,
-        &datasette_path.join(wheel_name),
-        &result,
+        &wheel_path,
+        output,
     )?)
 }
 
@@ -422,13 +679,32 @@ pub(crate) fn write_sqlite_utils(
     project: &Project,
     sqlite_utils_path: &Path,
 ) -> Result<GeneratedAsset, PipBuildError> {
-    let sqlite_utils_name = format!("sqlite-utils-{}", project.spec.package.name);
-    let dep_pkg = PipPackage::new(&project.spec.package.name, &project.version);
-    let mut pkg = PipPackage::new(sqlite_utils_name.clone(), &project.version);
-    pkg.write_library_file(
-        "__init__.py",
-        templates::sqlite_utils_init_py(&dep_pkg).as_bytes(),
+    let sqlite_utils_config = project.spec.targets.sqlite_utils.as_ref();
+    let sqlite_utils_name = sqlite_utils_config.map_or_else(
+        || format!("sqlite-utils-{}", project.spec.package.name),
+        |config| config.package_name(&project.spec.package.name),
+    );
+    let dep_pkg = PipPackage::with_writer(
+        Cursor::new(Vec::new()),
+        base_package_name(project),
+        pip_version(project)?,
+    );
+    let (mut pkg, wheel_path) = PipPackage::create(
+        sqlite_utils_name,
+        pip_version(project)?,
+        sqlite_utils_path,
+        None,
     )?;
+    pkg.package = project.spec.package.clone();
+    let init_py = project.render_template(
+        "sqlite_utils/__init__.py",
+        None,
+        templates::sqlite_utils_init_py(
+            dep_pkg.template_context(),
+            sqlite_utils_config.map_or(&[], |config| config.commands.as_slice()),
+        ),
+    )?;
+    pkg.write_library_file("__init__.py", init_py.as_bytes())?;
 
     pkg.add_entrypoint(
         "sqlite_utils",
@@ -439,19 +715,151 @@ pub(crate) fn write_sqlite_utils(
         .as_str(),
     );
 
-    pkg.extra_metadata
-        .push(("Requires-Dist".to_owned(), "sqlite-utils".to_owned()));
+    pkg.extra_metadata.extend(plugin_requirements(
+        "sqlite-utils",
+        sqlite_utils_config.map_or(&[], |config| config.extra_dependencies.as_slice()),
+    ));
+    if let Some(description) = sqlite_utils_config.and_then(|config| config.description.as_ref()) {
+        pkg.package.description = description.clone();
+    }
     pkg.extra_metadata.push((
         "Requires-Dist".to_owned(),
-        format!("{} (=={})", &project.spec.package.name, &project.version),
+        format!("{} (=={})", base_package_name(project), &project.version),
     ));
+    pkg.metadata =
+        Some(project.render_template("sqlite_utils/METADATA", None, pkg.default_metadata())?);
 
-    let wheel_name = pkg.wheel_name(None);
-
-    let result = pkg.end(None)?.into_inner();
-    Ok(GeneratedAsset::from(
+    let output = pkg.end(None)?;
+    Ok(GeneratedAsset::from_written(
         GeneratedAssetKind::SqliteUtils,
-        &sqlite_utils_path.join(wheel_name),
-        &result,
+        &wheel_path,
+        output,
     )?)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use semver::Version;
+
+    use super::{platform_target_tag, PipPackage};
+    use crate::{
+        spec::SpecPackage,
+        wheels::{check_wheel, metadata_placeholders},
+        Cpu, Os,
+    };
+
+    /// Writes a wheel of `sqlite-sample` the way the targets do, then returns the
+    /// problems `check_wheel` finds in it and its RECORD
+    fn build_wheel(name: &str, platform_tag: Option<&str>) -> (Vec<String>, String) {
+        let dir = env::temp_dir().join(format!("sqlite-dist-pip-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (mut pkg, path) =
+            PipPackage::create("sqlite-sample", "1.2.3a4".to_owned(), &dir, platform_tag).unwrap();
+        pkg.extra_metadata
+            .push(("Requires-Dist".to_owned(), "sqlite-base>=0.2.0".to_owned()));
+        pkg.add_entrypoint("datasette", "sqlite_sample = datasette_sqlite_sample");
+        pkg.write_library_file("__init__.py", b"print()\n").unwrap();
+        pkg.write_library_file("sample0.so", &[0x7f, b'E', b'L', b'F'])
+            .unwrap();
+        pkg.end(platform_tag).unwrap();
+
+        let problems = check_wheel(&path).unwrap();
+        let mut archive = zip::ZipArchive::new(fs::File::open(&path).unwrap()).unwrap();
+        let mut record = String::new();
+        std::io::Read::read_to_string(
+            &mut archive
+                .by_name("sqlite_sample-1.2.3a4.dist-info/RECORD")
+                .unwrap(),
+            &mut record,
+        )
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        (problems, record)
+    }
+
+    #[test]
+    fn end_records_every_file() {
+        let platform_tag = platform_target_tag(&Os::Linux, &Cpu::X86_64);
+        for (name, platform_tag) in [("any", None), ("linux", Some(platform_tag.as_str()))] {
+            let (problems, record) = build_wheel(name, platform_tag);
+            assert_eq!(problems, Vec::<String>::new(), "{name} wheel");
+
+            let files: Vec<&str> = record
+                .lines()
+                .map(|line| line.split(',').next().unwrap())
+                .collect();
+            assert_eq!(
+                files,
+                [
+                    "sqlite_sample/__init__.py",
+                    "sqlite_sample/sample0.so",
+                    "sqlite_sample-1.2.3a4.dist-info/METADATA",
+                    "sqlite_sample-1.2.3a4.dist-info/WHEEL",
+                    "sqlite_sample-1.2.3a4.dist-info/entry_points.txt",
+                    "sqlite_sample-1.2.3a4.dist-info/top_level.txt",
+                    "sqlite_sample-1.2.3a4.dist-info/RECORD",
+                ]
+            );
+            // only RECORD, which can't hash itself, has no hash and size
+            for line in record.lines() {
+                assert_eq!(
+                    line.ends_with(",,"),
+                    line.starts_with("sqlite_sample-1.2.3a4.dist-info/RECORD,"),
+                    "{line}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dist_info_files_without_entrypoints() {
+        let pkg = PipPackage::new("sqlite-sample", &Version::new(1, 2, 3)).unwrap();
+        let files: Vec<String> = pkg
+            .dist_info_files(None)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "sqlite_sample-1.2.3.dist-info/METADATA",
+                "sqlite_sample-1.2.3.dist-info/WHEEL",
+                "sqlite_sample-1.2.3.dist-info/top_level.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn metadata_override() {
+        let mut pkg = PipPackage::new("sqlite-sample", &Version::new(1, 2, 3)).unwrap();
+        pkg.metadata = Some("Metadata-Version: 2.1\nName: sqlite-sample\n".to_owned());
+        let (_, metadata) = pkg.dist_info_files(None).remove(0);
+        assert_eq!(metadata, "Metadata-Version: 2.1\nName: sqlite-sample\n");
+    }
+
+    #[test]
+    fn metadata_placeholders_are_found() {
+        let mut pkg = PipPackage::new("sqlite-sample", &Version::new(1, 2, 3)).unwrap();
+        pkg.package = SpecPackage {
+            authors: vec!["Sample Author".to_owned()],
+            license: "MIT".to_owned(),
+            description: "A sample SQLite extension".to_owned(),
+            homepage: "https://example.com".to_owned(),
+            ..SpecPackage::default()
+        };
+        assert!(metadata_placeholders(&pkg.default_metadata()).is_empty());
+
+        pkg.package.homepage = "https://TODO.com".to_owned();
+        pkg.package.description = "TODO readme".to_owned();
+        assert_eq!(
+            metadata_placeholders(&pkg.default_metadata()),
+            [
+                ("Summary", "TODO readme"),
+                ("Home-page", "https://TODO.com"),
+                ("description", "TODO readme"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,700 @@
+use std::collections::BTreeMap;
+
+use semver::Version;
+
+use crate::{
+    spec::{SpecFunction, SpecPackage, SpecRequire, SqliteUtilsCommand},
+    LoaderChecks,
+};
+
+use super::PipPackageFile;
+
+/// The names and version of a wheel, everything the templates read from a `PipPackage`
+#[derive(Clone, Copy)]
+pub(crate) struct Context<'a> {
+    /// as-is, with dashes, not python code safe
+    pub(crate) package_name: &'a str,
+    /// dashes replaced with underscores
+    pub(crate) python_package_name: &'a str,
+    /// the pip version string, ex 1.2a3
+    pub(crate) version: &'a str,
+}
+
+/// METADATA from the spec's package fields. The description's first line is the
+/// Summary, the whole description is the body PyPI renders on the project page.
+pub(crate) fn dist_info_metadata(
+    ctx: Context,
+    package: &SpecPackage,
+    extra_metadata: &[(String, String)],
+) -> String {
+    let mut headers = vec![
+        ("Metadata-Version", "2.1".to_owned()),
+        ("Name", ctx.package_name.to_owned()),
+        ("Version", ctx.version.to_owned()),
+        (
+            "Summary",
+            package.description.lines().next().unwrap_or("").to_owned(),
+        ),
+        ("Home-page", package.homepage.clone()),
+        ("Author", package.authors.join(", ")),
+        ("License", package.license.clone()),
+    ];
+    if !package.repo.is_empty() {
+        headers.push(("Project-URL", format!("Repository, {}", package.repo)));
+    }
+    headers.push(("Description-Content-Type", "text/markdown".to_owned()));
+    let mut metadata = String::new();
+    for (key, value) in headers {
+        if !value.trim().is_empty() {
+            metadata += format!("{key}: {value}\n").as_str();
+        }
+    }
+    for (key, value) in extra_metadata {
+        metadata += format!("{key}: {value}\n").as_str();
+    }
+    metadata += "\n";
+    metadata += &package.description;
+    metadata
+}
+
+pub(crate) fn dist_info_entrypoints(entrypoints: &[(String, String)]) -> String {
+    let mut txt = String::new();
+    for (key, value) in entrypoints {
+        txt += format!("[{key}]\n").as_str();
+        txt += value;
+        txt += "\n\n";
+    }
+
+    txt
+}
+pub(crate) fn dist_info_wheel(platform_tag: Option<&str>) -> String {
+    let name = env!("CARGO_PKG_NAME");
+    let version = env!("CARGO_PKG_VERSION");
+    // a compressed tag set like manylinux_2_17_x86_64.manylinux2014_x86_64
+    // is only allowed in the file name, WHEEL lists each tag on its own line
+    let tags = platform_tag
+        .unwrap_or("any")
+        .split('.')
+        .map(|platform_tag| format!("Tag: py3-none-{platform_tag}"))
+        .collect::<Vec<String>>()
+        .join("\n");
+    format!(
+        "Wheel-Version: 1.0
+Generator: {name} {version}
+Root-Is-Purelib: false
+{tags}",
+    )
+}
+pub(crate) fn dist_info_top_level_txt(ctx: Context) -> String {
+    format!("{}\n", ctx.python_package_name)
+}
+
+pub(crate) fn dist_info_record(written_files: &[PipPackageFile], record_path: &str) -> String {
+    let mut record = String::new();
+    for file in written_files {
+        record.push_str(format!("{},sha256={},{}\n", file.path, file.hash, file.size).as_str());
+    }
+    record.push_str(format!("{},,\n", record_path).as_str());
+
+    record
+}
+pub(crate) fn base_init_py(
+    ctx: Context,
+    entrypoints: &[&str],
+    init_functions: &[(&str, &str)],
+    min_sqlite_version: Option<&Version>,
+    checks: LoaderChecks,
+    requires: &[SpecRequire],
+) -> String {
+    let version = ctx.version;
+    let require_imports: String = requires
+        .iter()
+        .map(|require| {
+            format!(
+                "import {}
+",
+                require.python_module()
+            )
+        })
+        .collect();
+    let require_loads: String = requires
+        .iter()
+        .map(|require| {
+            format!(
+                "  {}.load(conn)
+",
+                require.python_module()
+            )
+        })
+        .collect();
+    let package_name = ctx.package_name;
+    let python_package_name = ctx.python_package_name;
+    let entrypoints =
+        serde_json::to_string(entrypoints).expect("String values should always serialize as JSON");
+    let init_functions = serde_json::to_string(
+        &init_functions
+            .iter()
+            .copied()
+            .collect::<BTreeMap<&str, &str>>(),
+    )
+    .expect("String values should always serialize as JSON");
+    let requires = min_sqlite_version
+        .map(|min| format!("\n\n  Requires SQLite {min} or later."))
+        .unwrap_or_default();
+    let (min_sqlite_check, min_sqlite_check_call) = match checks.min_sqlite_version {
+        Some(min) => (
+            format!(
+                r#"
+MIN_SQLITE_VERSION = ({}, {}, {})
+
+def _check_sqlite_version(version: str) -> None:
+  if tuple(int(part) for part in version.split(".")[:3]) < MIN_SQLITE_VERSION:
+    raise RuntimeError(f"{package_name} requires SQLite {min} or later, but the connection uses SQLite {{version}}")
+"#,
+                min.major, min.minor, min.patch
+            ),
+            "  _check_sqlite_version(conn.execute(\"select sqlite_version()\").fetchone()[0])\n",
+        ),
+        None => (String::new(), ""),
+    };
+    let (version_check, version_check_call) = match checks.version_check {
+        Some((sql, expected)) => (
+            format!(
+                r#"
+VERSION_SQL = {}
+
+def _check_version(loaded_version) -> None:
+  if str(loaded_version).lstrip("v") != "{expected}":
+    raise RuntimeError(f"{package_name} {expected} was loaded, but {{VERSION_SQL}} returned {{loaded_version}}. Is an older copy of the extension installed?")
+"#,
+                serde_json::to_string(sql).expect("String values should always serialize as JSON")
+            ),
+            "  _check_version(conn.execute(VERSION_SQL).fetchone()[0])\n",
+        ),
+        None => (String::new(), ""),
+    };
+    format!(
+        r#"
+from contextlib import contextmanager
+from os import path
+import sqlite3
+import sys
+{require_imports}
+__version__ = "{version}"
+__version_info__ = tuple(__version__.split("."))
+
+ENTRYPOINTS = {entrypoints}
+# entrypoint -> init function, for loadables SQLite can't derive it from the file name of
+INIT_FUNCTIONS = {init_functions}
+{min_sqlite_check}{version_check}
+def _windows_path(loadable_path: str) -> str:
+  # SQLite's LoadLibrary fails on \\?\ extended-length paths and on some non-ASCII
+  # ones, so drop the prefix and use the 8.3 short name of the directory. The file
+  # name is kept, SQLite derives the entrypoint from it.
+  if loadable_path.startswith("\\\\?\\UNC\\"):
+    loadable_path = "\\\\" + loadable_path[8:]
+  elif loadable_path.startswith("\\\\?\\"):
+    loadable_path = loadable_path[4:]
+  directory, file_name = path.split(loadable_path)
+  if not directory.isascii():
+    import ctypes
+    buffer = ctypes.create_unicode_buffer(32768)
+    if ctypes.windll.kernel32.GetShortPathNameW(directory, buffer, len(buffer)) and buffer.value.isascii():
+      loadable_path = path.join(buffer.value, file_name)
+  return loadable_path
+
+def loadable_path(entrypoint: str = ENTRYPOINTS[0]):
+  """ Returns the full path to the {package_name} loadable SQLite extension bundled with this package """
+
+  loadable_path = path.normpath(path.join(path.dirname(path.abspath(__file__)), entrypoint))
+  if sys.platform == "win32":
+    return _windows_path(loadable_path)
+  return loadable_path
+
+def load(conn: sqlite3.Connection)  -> None:
+  """ Load the {package_name} SQLite extension into the given database connection.{requires} """
+
+{min_sqlite_check_call}{require_loads}  for entrypoint in ENTRYPOINTS:
+    if entrypoint in INIT_FUNCTIONS:
+      # Connection.load_extension() only takes an entrypoint since Python 3.12
+      conn.execute("select load_extension(?, ?)", (loadable_path(entrypoint), INIT_FUNCTIONS[entrypoint]))
+    else:
+      conn.load_extension(loadable_path(entrypoint))
+{version_check_call}
+@contextmanager
+def extension(conn: sqlite3.Connection):
+  """ Loads the {package_name} SQLite extension into conn, with extension loading only enabled while it loads.
+
+  with {python_package_name}.extension(conn):
+    conn.execute(...)
+  """
+
+  conn.enable_load_extension(True)
+  try:
+    load(conn)
+  finally:
+    conn.enable_load_extension(False)
+  yield conn
+
+def connect(database, **kwargs) -> sqlite3.Connection:
+  """ Like sqlite3.connect(), with the {package_name} SQLite extension already loaded. """
+
+  conn = sqlite3.connect(database, **kwargs)
+  try:
+    with extension(conn):
+      pass
+  except Exception:
+    conn.close()
+    raise
+  return conn
+
+"#,
+    )
+}
+
+/// Typed constants for the spec's SQL functions, documented with attribute docstrings
+pub(crate) fn functions_py(package_name: &str, functions: &[SpecFunction]) -> String {
+    let constants: String = functions
+        .iter()
+        .map(|function| {
+            let description = function
+                .description
+                .as_ref()
+                .map(|d| format!("\n\n{}", d.replace(r#"""""#, r#"\""""#)))
+                .unwrap_or_default();
+            format!(
+                "{}: Final = \"{}\"\n\"\"\" `{}`, {}{description} \"\"\"\n\n",
+                function.name,
+                function.name,
+                function.signature(),
+                function.kind.description()
+            )
+        })
+        .collect();
+    let names: String = functions
+        .iter()
+        .map(|function| format!("  {},\n", function.name))
+        .collect();
+    format!(
+        r#"""" SQL functions and virtual tables provided by the {package_name} SQLite extension """
+
+from typing import Final
+
+{constants}FUNCTIONS: Final = (
+{names})
+"#
+    )
+}
+
+pub(crate) fn apsw_py(ctx: Context, checks: LoaderChecks, requires: &[SpecRequire]) -> String {
+    let package_name = ctx.package_name;
+    let require_imports: String = requires
+        .iter()
+        .map(|require| {
+            format!(
+                "import {}
+",
+                require.python_module()
+            )
+        })
+        .collect();
+    // the required wheels may not have an apsw.py, their loadables are always there
+    let require_loads: String = requires
+            .iter()
+            .map(|require| {
+                let module = require.python_module();
+                format!(
+                    "    for entrypoint in {module}.ENTRYPOINTS:
+      conn.loadextension({module}.loadable_path(entrypoint), getattr({module}, \"INIT_FUNCTIONS\", {{}}).get(entrypoint))
+"
+                )
+            })
+            .collect();
+    let (import_check, check_call) = match checks.min_sqlite_version {
+        Some(_) => (
+            ", _check_sqlite_version",
+            "  _check_sqlite_version(apsw.sqlite_lib_version())\n",
+        ),
+        None => ("", ""),
+    };
+    let (import_version_check, version_check_call) = match checks.version_check {
+        Some(_) => (
+            ", VERSION_SQL, _check_version",
+            "  _check_version(next(conn.execute(VERSION_SQL))[0])\n",
+        ),
+        None => ("", ""),
+    };
+    format!(
+        r#"
+import apsw
+{require_imports}
+from . import ENTRYPOINTS, INIT_FUNCTIONS, loadable_path{import_check}{import_version_check}
+
+def load(conn: apsw.Connection) -> None:
+  """ Load the {package_name} SQLite extension into the given APSW connection. """
+
+{check_call}  conn.enableloadextension(True)
+  try:
+{require_loads}    for entrypoint in ENTRYPOINTS:
+      conn.loadextension(loadable_path(entrypoint), INIT_FUNCTIONS.get(entrypoint))
+  finally:
+    conn.enableloadextension(False)
+{version_check_call}
+def connect(filename: str, *args, **kwargs) -> apsw.Connection:
+  """ Like apsw.Connection(), with the {package_name} SQLite extension already loaded. """
+
+  conn = apsw.Connection(filename, *args, **kwargs)
+  load(conn)
+  return conn
+"#
+    )
+}
+
+pub(crate) fn django_py(ctx: Context) -> String {
+    let package_name = ctx.package_name;
+    let python_package_name = ctx.python_package_name;
+    format!(
+        r#"
+""" Loads the {package_name} SQLite extension into every SQLite connection Django opens.
+Import it once, ex in your AppConfig.ready():
+
+  import {python_package_name}.django
+"""
+
+from django.db.backends.signals import connection_created
+
+from . import load
+
+def _load_extension(sender, connection, **kwargs):
+  if connection.vendor != "sqlite":
+    return
+  connection.connection.enable_load_extension(True)
+  try:
+    load(connection.connection)
+  finally:
+    connection.connection.enable_load_extension(False)
+
+connection_created.connect(_load_extension, dispatch_uid="{python_package_name}")
+"#
+    )
+}
+
+pub(crate) fn sqlite_utils_init_py(dep: Context, commands: &[SqliteUtilsCommand]) -> String {
+    let dep_library = dep.python_package_name;
+    let version = dep.version;
+    let register_commands = if commands.is_empty() {
+        String::new()
+    } else {
+        let commands: Vec<(&str, &str, String)> = commands
+            .iter()
+            .map(|command| {
+                let help = command
+                    .help
+                    .clone()
+                    .unwrap_or_else(|| format!("Runs `{}`", command.sql));
+                (command.name.as_str(), command.sql.as_str(), help)
+            })
+            .collect();
+        let commands = serde_json::to_string(&commands)
+            .expect("String values should always serialize as JSON");
+        format!(
+            r#"
+import json
+import click
+import sqlite_utils
+
+# (name, sql, help)
+COMMANDS = {commands}
+
+def _register_command(cli, name, sql, help):
+  @cli.command(name=name, help=help)
+  @click.argument("path", type=click.Path(dir_okay=False))
+  @click.argument("params", nargs=-1)
+  def command(path, params):
+    db = sqlite_utils.Database(path)
+    for row in db.query(sql, params):
+      click.echo(json.dumps(row))
+
+@hookimpl
+def register_commands(cli):
+  for name, sql, help in COMMANDS:
+    _register_command(cli, name, sql, help)
+"#
+        )
+    };
+    format!(
+        r#"
+from sqlite_utils import hookimpl
+import {dep_library}
+
+__version__ = "{version}"
+__version_info__ = tuple(__version__.split("."))
+
+@hookimpl
+def prepare_connection(conn):
+  conn.enable_load_extension(True)
+  {dep_library}.load(conn)
+  conn.enable_load_extension(False)
+{register_commands}"#
+    )
+}
+
+/// `__init__.py` of the package under its previous name, re-exporting the new one
+pub(crate) fn rename_init_py(dep: Context, message: &str) -> String {
+    let dep_library = dep.python_package_name;
+    let message =
+        serde_json::to_string(message).expect("String values should always serialize as JSON");
+    format!(
+        r#"
+import warnings
+
+warnings.warn({message}, DeprecationWarning, stacklevel=2)
+
+from {dep_library} import *
+from {dep_library} import __version__, __version_info__
+"#,
+    )
+}
+
+pub(crate) fn datasette_init_py(dep: Context, configure_sql: &[String]) -> String {
+    let dep_library = dep.python_package_name;
+    let version = dep.version;
+    let configure_sql = serde_json::to_string(configure_sql)
+        .expect("String values should always serialize as JSON");
+    format!(
+        r#"
+from datasette import hookimpl
+import {dep_library}
+
+__version__ = "{version}"
+__version_info__ = tuple(__version__.split("."))
+
+CONFIGURE_SQL = {configure_sql}
+
+@hookimpl
+def prepare_connection(conn):
+  conn.enable_load_extension(True)
+  {dep_library}.load(conn)
+  conn.enable_load_extension(False)
+  for sql in CONFIGURE_SQL:
+    conn.execute(sql)
+"#,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use semver::Version;
+
+    use super::*;
+    use crate::{
+        pip::{platform_target_tag, wheel_name},
+        spec::SqlFunctionKind,
+        Cpu, Os,
+    };
+
+    const CTX: Context = Context {
+        package_name: "sqlite-sample",
+        python_package_name: "sqlite_sample",
+        version: "1.2.3a4",
+    };
+
+    /// Compares against `tests/golden/pip/{name}`, rewriting it instead when
+    /// `UPDATE_GOLDEN` is set
+    fn assert_golden(name: &str, actual: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden/pip")
+            .join(name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_else(|err| {
+            panic!(
+                "{}: {err}, run with UPDATE_GOLDEN=1 to create it",
+                path.display()
+            )
+        });
+        assert_eq!(
+            actual,
+            expected,
+            "{} is out of date, run with UPDATE_GOLDEN=1 if the change is intended",
+            path.display()
+        );
+    }
+
+    fn require() -> SpecRequire {
+        SpecRequire {
+            name: "sqlite-base".to_owned(),
+            min_version: Version::new(0, 2, 0),
+            pip_name: None,
+            npm_name: None,
+            gem_name: None,
+            gem_module: None,
+        }
+    }
+
+    #[test]
+    fn metadata() {
+        let extra_metadata = [
+            ("Requires-Dist".to_owned(), "sqlite-base>=0.2.0".to_owned()),
+            (
+                "Requires-External".to_owned(),
+                "sqlite (>=3.41.0)".to_owned(),
+            ),
+        ];
+        let package = SpecPackage {
+            name: "sample".to_owned(),
+            authors: vec!["Sample Author".to_owned()],
+            license: "MIT OR Apache-2.0".to_owned(),
+            description: "A sample SQLite extension".to_owned(),
+            homepage: "https://example.com/sqlite-sample".to_owned(),
+            repo: "https://github.com/example/sqlite-sample".to_owned(),
+            ..SpecPackage::default()
+        };
+        assert_golden(
+            "METADATA",
+            &dist_info_metadata(CTX, &package, &extra_metadata),
+        );
+    }
+
+    #[test]
+    fn wheel() {
+        // the generator line changes with every release of sqlite-dist
+        let render = |platform_tag: Option<&str>| {
+            dist_info_wheel(platform_tag).replace(env!("CARGO_PKG_VERSION"), "VERSION")
+        };
+        assert_golden("WHEEL.any", &render(None));
+        for (os, cpu, name) in [
+            (Os::Linux, Cpu::X86_64, "linux-x86_64"),
+            (Os::Linux, Cpu::Aarch64, "linux-aarch64"),
+            (Os::Macos, Cpu::Aarch64, "macos-aarch64"),
+            (Os::Windows, Cpu::X86_64, "windows-x86_64"),
+        ] {
+            let platform_tag = platform_target_tag(&os, &cpu);
+            assert!(
+                !platform_tag.ends_with(".whl"),
+                "{name} tag {platform_tag} includes the file extension"
+            );
+            assert_golden(&format!("WHEEL.{name}"), &render(Some(&platform_tag)));
+        }
+    }
+
+    #[test]
+    fn wheel_file_name() {
+        assert_eq!(
+            wheel_name(
+                CTX.python_package_name,
+                CTX.version,
+                Some(&platform_target_tag(&Os::Linux, &Cpu::Aarch64))
+            ),
+            "sqlite_sample-1.2.3a4-py3-none-manylinux_2_17_aarch64.manylinux2014_aarch64.whl"
+        );
+    }
+
+    #[test]
+    fn entry_points_and_top_level() {
+        let entrypoints = [(
+            "datasette".to_owned(),
+            "sqlite_sample = datasette_sqlite_sample".to_owned(),
+        )];
+        assert_golden("entry_points.txt", &dist_info_entrypoints(&entrypoints));
+        assert_golden("top_level.txt", &dist_info_top_level_txt(CTX));
+    }
+
+    #[test]
+    fn record() {
+        let files = [
+            PipPackageFile::new("sqlite_sample/__init__.py", b"print()\n"),
+            PipPackageFile::new("sqlite_sample-1.2.3a4.dist-info/METADATA", b""),
+        ];
+        assert_golden(
+            "RECORD",
+            &dist_info_record(&files, "sqlite_sample-1.2.3a4.dist-info/RECORD"),
+        );
+    }
+
+    #[test]
+    fn init_py() {
+        let checks = LoaderChecks {
+            min_sqlite_version: None,
+            version_check: None,
+        };
+        assert_golden(
+            "__init__.py",
+            &base_init_py(CTX, &["sample0"], &[], None, checks, &[]),
+        );
+
+        let min = Version::new(3, 41, 0);
+        let version = Version::new(1, 2, 3);
+        let checks = LoaderChecks {
+            min_sqlite_version: Some(&min),
+            version_check: Some(("select sample_version()", &version)),
+        };
+        assert_golden(
+            "__init__.py.checks",
+            &base_init_py(
+                CTX,
+                &["sample0", "sample1"],
+                &[("sample1", "sqlite3_sample_init")],
+                Some(&min),
+                checks,
+                &[require()],
+            ),
+        );
+    }
+
+    #[test]
+    fn functions() {
+        let functions = [
+            SpecFunction {
+                name: "sample_version".to_owned(),
+                kind: SqlFunctionKind::Scalar,
+                arguments: vec![],
+                description: Some("The version, ex \"v1.2.3\"".to_owned()),
+            },
+            SpecFunction {
+                name: "sample_each".to_owned(),
+                kind: SqlFunctionKind::Table,
+                arguments: vec!["input".to_owned()],
+                description: None,
+            },
+        ];
+        assert_golden("functions.py", &functions_py(CTX.package_name, &functions));
+    }
+
+    #[test]
+    fn apsw_and_django() {
+        let version = Version::new(1, 2, 3);
+        let checks = LoaderChecks {
+            min_sqlite_version: None,
+            version_check: Some(("select sample_version()", &version)),
+        };
+        assert_golden("apsw.py", &apsw_py(CTX, checks, &[require()]));
+        assert_golden("django.py", &django_py(CTX));
+    }
+
+    #[test]
+    fn plugins() {
+        let commands = [SqliteUtilsCommand {
+            name: "sample-version".to_owned(),
+            sql: "select sample_version()".to_owned(),
+            help: None,
+        }];
+        assert_golden(
+            "sqlite_utils/__init__.py",
+            &sqlite_utils_init_py(CTX, &commands),
+        );
+        assert_golden(
+            "datasette/__init__.py",
+            &datasette_init_py(CTX, &["select sample_configure()".to_owned()]),
+        );
+        assert_golden(
+            "rename/__init__.py",
+            &rename_init_py(CTX, "sqlite-old was renamed to sqlite-sample"),
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    asset_name, create_output_dir, dry_run, http,
+    spec::{Spec, SpecPlugin},
+    target::{Target, TargetContext},
+    BuildError, Cpu, GeneratedAsset, GeneratedAssetKind, Os, Project,
+};
+
+/// Version of the JSON document plugins receive on stdin
+const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum PluginError {
+    #[error("could not run plugin '{0}': {1}")]
+    Spawn(String, io::Error),
+    #[error("plugin '{0}' exited with {1}")]
+    Failed(String, std::process::ExitStatus),
+}
+
+#[derive(Serialize)]
+struct PluginPlatform<'a> {
+    os: &'a Os,
+    cpu: &'a Cpu,
+    path: &'a Path,
+    loadables: Vec<&'a str>,
+    #[serde(rename = "static")]
+    static_: Vec<&'a str>,
+    headers: Vec<&'a str>,
+}
+
+/// What a plugin command reads from stdin
+#[derive(Serialize)]
+struct PluginInput<'a> {
+    protocol: u32,
+    name: &'a str,
+    package: &'a str,
+    version: String,
+    output_dir: &'a Path,
+    dry_run: bool,
+    config: &'a Option<toml::Value>,
+    spec: &'a Spec,
+    platforms: Vec<PluginPlatform<'a>>,
+}
+
+/// A `[[plugins]]` entry of the spec: an external command that writes its
+/// assets to a directory, which sqlite-dist then collects.
+pub(crate) struct PluginTarget {
+    plugin: SpecPlugin,
+}
+
+impl PluginTarget {
+    pub(crate) fn new(plugin: SpecPlugin) -> Self {
+        Self { plugin }
+    }
+
+    fn input<'a>(&'a self, project: &'a Project, output_dir: &'a Path) -> PluginInput<'a> {
+        PluginInput {
+            protocol: PROTOCOL_VERSION,
+            name: &self.plugin.name,
+            package: &project.spec.package.name,
+            version: project.version.to_string(),
+            output_dir,
+            dry_run: dry_run(),
+            config: &self.plugin.config,
+            spec: &project.spec,
+            // synthesized directories like macos-universal2 only exist in memory
+            platforms: project
+                .platform_directories
+                .iter()
+                .filter(|pd| pd.path.is_dir())
+                .map(|pd| PluginPlatform {
+                    os: &pd.os,
+                    cpu: &pd.cpu,
+                    path: &pd.path,
+                    loadables: pd
+                        .loadable_files
+                        .iter()
+                        .map(|f| f.file.name.as_str())
+                        .collect(),
+                    static_: pd.static_files.iter().map(|f| f.name.as_str()).collect(),
+                    headers: pd.header_files.iter().map(|f| f.name.as_str()).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    fn run(&self, project: &Project, output_dir: &Path) -> Result<(), PluginError> {
+        let name = &self.plugin.name;
+        let input = serde_json::to_vec(&self.input(project, output_dir))
+            .map_err(|err| PluginError::Spawn(name.clone(), err.into()))?;
+        let mut command = Command::new(&self.plugin.command[0]);
+        http::pass_offline(&mut command);
+        let mut child = command
+            .args(&self.plugin.command[1..])
+            .current_dir(&project.spec_directory)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| PluginError::Spawn(name.clone(), err))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(&input)
+                .map_err(|err| PluginError::Spawn(name.clone(), err))?;
+        }
+        let status = child
+            .wait()
+            .map_err(|err| PluginError::Spawn(name.clone(), err))?;
+        if !status.success() {
+            return Err(PluginError::Failed(name.clone(), status));
+        }
+        Ok(())
+    }
+}
+
+/// Every file under `dir`, sorted so assets are reported in a stable order
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+impl Target for PluginTarget {
+    fn name(&self) -> &str {
+        &self.plugin.name
+    }
+
+    fn enabled(&self, _spec: &Spec) -> bool {
+        true
+    }
+
+    fn config(&self, _spec: &Spec) -> serde_json::Value {
+        serde_json::to_value(&self.plugin).unwrap_or_default()
+    }
+
+    // the output depends on an executable sqlite-dist can't see into
+    fn cacheable(&self) -> bool {
+        false
+    }
+
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let project = ctx.project;
+        let output_dir = ctx
+            .output_dir
+            .join(self.plugin.output_dir.as_ref().unwrap_or(&self.plugin.name))
+            .join(&project.spec.package.name);
+        // in a dry run the plugin writes to a scratch directory instead, so the
+        // plan can still list its assets
+        let run_dir = if dry_run() {
+            std::env::temp_dir().join(format!(
+                "sqlite-dist-{}-{}-{}",
+                self.plugin.name,
+                project.spec.package.name,
+                std::process::id()
+            ))
+        } else {
+            output_dir.clone()
+        };
+        // stale files of a previous build would otherwise be reported as assets
+        if run_dir.exists() {
+            fs::remove_dir_all(&run_dir)?;
+        }
+        if dry_run() {
+            fs::create_dir_all(&run_dir)?;
+        } else {
+            create_output_dir(&run_dir)?;
+        }
+        self.run(project, &run_dir)?;
+
+        let mut files = vec![];
+        collect_files(&run_dir, &mut files)?;
+        let mut assets = vec![];
+        for file in files {
+            let contents = fs::read(&file)?;
+            let path = output_dir.join(file.strip_prefix(&run_dir).unwrap_or(&file));
+            assets.push(GeneratedAsset {
+                kind: GeneratedAssetKind::Plugin(self.plugin.name.clone()),
+                name: asset_name(&path),
+                path: path.to_string_lossy().into_owned(),
+                checksum_sha256: base16ct::lower::encode_string(&Sha256::digest(&contents)),
+                size: contents.len(),
+            });
+        }
+        if dry_run() {
+            fs::remove_dir_all(&run_dir)?;
+        }
+        Ok(assets)
+    }
+}
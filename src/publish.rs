@@ -0,0 +1,1065 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    thread,
+    time::Duration,
+};
+
+use base64::Engine;
+use flate2::read::GzDecoder;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use thiserror::Error;
+
+use crate::manifest::{read_manifest, read_manifest_file};
+use crate::wheels::{
+    check_wheel, header, headers, metadata_placeholders, python_tag, wheel_metadata,
+};
+use crate::{gem, http};
+
+#[derive(Error, Debug)]
+pub enum PublishError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] io::Error),
+    #[error("could not run `{0}`: {1}")]
+    Spawn(String, io::Error),
+    /// with the command's output, to tell transient failures apart
+    #[error("`{0}` exited with {1}")]
+    Failed(String, ExitStatus, String),
+    #[error("set {0} to publish")]
+    MissingToken(&'static str),
+    #[error("the build in {1} has no {0} assets")]
+    NoAssets(&'static str, PathBuf),
+    #[error("{0} is not an npm package: {1}")]
+    InvalidNpmPackage(PathBuf, String),
+    #[error("uploading {0} failed: {1}")]
+    Upload(String, String),
+    #[error("the manifest version {0} is not a semver version: {1}")]
+    InvalidVersion(String, semver::Error),
+    #[error("{0} is not a valid wheel, check it with verify-wheels")]
+    InvalidWheel(PathBuf),
+    #[error("reading {0}: {1}")]
+    WheelError(PathBuf, crate::wheels::WheelCheckError),
+    #[error(
+        "the registry would reject {} problems:{}",
+        .0.len(),
+        .0.iter().map(|problem| format!("\n  {problem}")).collect::<String>()
+    )]
+    Invalid(Vec<String>),
+}
+
+/// Copies `from` to `to` as it arrives, so prompts and progress still show,
+/// and returns everything copied
+fn tee(mut from: impl Read, mut to: impl Write) -> String {
+    let mut copied = vec![];
+    let mut buffer = [0; 8192];
+    while let Ok(read @ 1..) = from.read(&mut buffer) {
+        let _ = to.write_all(&buffer[..read]).and_then(|_| to.flush());
+        copied.extend_from_slice(&buffer[..read]);
+    }
+    String::from_utf8_lossy(&copied).into_owned()
+}
+
+/// Runs `command`, with `shown` standing in for it in errors so tokens aren't printed
+fn run(command: &mut Command, shown: &str) -> Result<(), PublishError> {
+    tracing::info!("{shown}");
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| PublishError::Spawn(shown.to_owned(), err))?;
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let output = thread::scope(|scope| {
+        let stdout = scope.spawn(|| stdout.map(|stdout| tee(stdout, io::stdout())));
+        let stderr = stderr.map(|stderr| tee(stderr, io::stderr()));
+        let stdout = stdout.join().ok().flatten();
+        stdout.unwrap_or_default() + &stderr.unwrap_or_default()
+    });
+    let status = child
+        .wait()
+        .map_err(|err| PublishError::Spawn(shown.to_owned(), err))?;
+    if !status.success() {
+        return Err(PublishError::Failed(shown.to_owned(), status, output));
+    }
+    Ok(())
+}
+
+/// Whether a failed command's output blames a rate limit, a server error or the
+/// network, the failures that may pass on a retry. npm reports HTTP statuses as
+/// `E503`, twine, gem and aws as the bare status or its reason.
+fn is_transient_failure(output: &str) -> bool {
+    const STATUSES: [&str; 5] = ["429", "500", "502", "503", "504"];
+    const ERRORS: [&str; 13] = [
+        "ETIMEDOUT",
+        "ECONNRESET",
+        "ECONNREFUSED",
+        "EAI_AGAIN",
+        "socket hang up",
+        "Too Many Requests",
+        "Internal Server Error",
+        "Bad Gateway",
+        "Service Unavailable",
+        "Gateway Timeout",
+        "timed out",
+        "Read timeout",
+        "Could not connect to the endpoint URL",
+    ];
+    output
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|word| STATUSES.contains(&word.strip_prefix('E').unwrap_or(word)))
+        || ERRORS.iter().any(|error| output.contains(error))
+}
+
+pub(crate) const PUBLISH_STATE_FILE: &str = ".sqlite-dist-publish-state.json";
+
+/// Uploads that already succeeded, kept in the output directory so re-running a
+/// failed publish skips them. Keyed by destination, then asset name, with the
+/// asset's sha256 so a rebuilt asset is uploaded again.
+#[derive(Default, Serialize, Deserialize)]
+struct PublishState {
+    uploaded: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl PublishState {
+    /// A missing or unreadable state file means nothing was uploaded yet
+    fn load(output_dir: &Path) -> Self {
+        fs::read(output_dir.join(PUBLISH_STATE_FILE))
+            .ok()
+            .and_then(|contents| serde_json::from_slice(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn is_uploaded(&self, destination: &str, name: &str, sha256: &str) -> bool {
+        self.uploaded
+            .get(destination)
+            .and_then(|assets| assets.get(name))
+            .is_some_and(|uploaded| uploaded == sha256)
+    }
+
+    /// Records an upload, saving right away so it survives a later failure
+    fn record(
+        &mut self,
+        output_dir: &Path,
+        destination: &str,
+        name: &str,
+        sha256: String,
+    ) -> io::Result<()> {
+        self.uploaded
+            .entry(destination.to_owned())
+            .or_default()
+            .insert(name.to_owned(), sha256);
+        fs::write(
+            output_dir.join(PUBLISH_STATE_FILE),
+            serde_json::to_vec_pretty(self)?,
+        )
+    }
+}
+
+fn file_sha256(path: &Path) -> io::Result<String> {
+    Ok(base16ct::lower::encode_string(&sha2::Sha256::digest(
+        fs::read(path)?,
+    )))
+}
+
+/// npm refuses to publish a version twice, E403 from the npm registry and
+/// EPUBLISHCONFLICT or E409 from others
+fn npm_already_published(output: &str) -> bool {
+    output.to_lowercase().contains("cannot publish over") || output.contains("EPUBLISHCONFLICT")
+}
+
+/// `run` with retries, for commands that upload. Only transient failures are
+/// retried, the others would fail the same way again.
+fn run_with_backoff(command: &mut Command, display: &str) -> Result<(), PublishError> {
+    http::ensure_online(display)?;
+    http::configure_command(command);
+    http::with_backoff(
+        display,
+        |err| match err {
+            PublishError::Failed(_, _, output) => {
+                is_transient_failure(output).then_some(Duration::ZERO)
+            }
+            _ => None,
+        },
+        || run(command, display),
+    )
+}
+
+/// Paths of the assets of `kind` the build in `output_dir` generated
+fn manifest_assets(output_dir: &Path, kind: &'static str) -> Result<Vec<PathBuf>, PublishError> {
+    let paths = read_manifest(output_dir)?
+        .iter()
+        .filter(|artifact| artifact.kind == kind)
+        .map(|artifact| artifact.locate(output_dir))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    if paths.is_empty() {
+        return Err(PublishError::NoAssets(kind, output_dir.to_path_buf()));
+    }
+    Ok(paths)
+}
+
+pub struct NpmPublishOptions {
+    /// publish with a provenance attestation, only possible from a supported CI
+    pub provenance: bool,
+    /// dist-tag, defaults to `latest`, or `next` for prereleases
+    pub tag: Option<String>,
+    /// defaults to https://registry.npmjs.org/
+    pub registry: Option<String>,
+    pub dry_run: bool,
+}
+
+/// `package/package.json` of an npm tarball
+fn npm_package_json(path: &Path) -> Result<serde_json::Value, PublishError> {
+    let file = fs::File::open(path)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_ref() == Path::new("package/package.json") {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents)?;
+            return serde_json::from_str(&contents).map_err(|err| {
+                PublishError::InvalidNpmPackage(path.to_path_buf(), err.to_string())
+            });
+        }
+    }
+    Err(PublishError::InvalidNpmPackage(
+        path.to_path_buf(),
+        "missing package/package.json".to_owned(),
+    ))
+}
+
+/// Fails with every problem found, so they're all fixed before anything is uploaded
+fn check_problems(problems: Vec<String>) -> Result<(), PublishError> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+    for problem in &problems {
+        tracing::error!("{problem}");
+    }
+    Err(PublishError::Invalid(problems))
+}
+
+/// npm's rules for package.json, the ones `npm publish` only reports once it gets there
+fn npm_problems(path: &Path, package_json: &serde_json::Value) -> Vec<String> {
+    let file_name = crate::asset_name(path);
+    let mut problems = vec![];
+    match package_json["name"].as_str() {
+        Some(name) => {
+            let unscoped = match name.strip_prefix('@') {
+                Some(scoped) => scoped.split_once('/').map_or("", |(_, name)| name),
+                None => name,
+            };
+            let valid = name.len() <= 214
+                && !unscoped.is_empty()
+                && !unscoped.starts_with(['.', '_'])
+                && name == name.to_lowercase()
+                && unscoped
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c));
+            if !valid {
+                problems.push(format!(
+                    "{file_name}: name '{name}' must be lowercase letters, digits, -, ., _ or ~, at most 214 characters"
+                ));
+            }
+        }
+        None => problems.push(format!("{file_name}: package.json has no name")),
+    }
+    match package_json["version"].as_str() {
+        Some(version) if semver::Version::parse(version).is_err() => problems.push(format!(
+            "{file_name}: version '{version}' isn't a semver version"
+        )),
+        Some(_) => {}
+        None => problems.push(format!("{file_name}: package.json has no version")),
+    }
+    for key in ["os", "cpu", "files"] {
+        let value = &package_json[key];
+        if !value.is_null()
+            && !value
+                .as_array()
+                .is_some_and(|values| values.iter().all(|v| v.is_string()))
+        {
+            problems.push(format!("{file_name}: {key} must be an array of strings"));
+        }
+    }
+    for key in ["dependencies", "optionalDependencies", "exports"] {
+        let value = &package_json[key];
+        let valid = value.is_null() || value.is_object() || (key == "exports" && value.is_string());
+        if !valid {
+            problems.push(format!("{file_name}: {key} must be an object"));
+        }
+    }
+    problems
+}
+
+/// A normalized PEP 440 public version, the only ones PyPI accepts:
+/// `N(.N)*[{a|b|rc}N][.postN][.devN]`
+fn is_pep440(version: &str) -> bool {
+    fn number(s: &str) -> Option<&str> {
+        let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        (end > 0).then(|| &s[end..])
+    }
+    let mut rest = version;
+    loop {
+        match number(rest) {
+            Some(after) => rest = after,
+            None => return false,
+        }
+        match rest.strip_prefix('.') {
+            Some(after) if after.starts_with(|c: char| c.is_ascii_digit()) => rest = after,
+            _ => break,
+        }
+    }
+    for prefixes in [&["a", "b", "rc"][..], &[".post"][..], &[".dev"][..]] {
+        if let Some(after) = prefixes.iter().find_map(|p| rest.strip_prefix(p)) {
+            match number(after) {
+                Some(after) => rest = after,
+                None => return false,
+            }
+        }
+    }
+    rest.is_empty()
+}
+
+/// check_wheel's problems, and the METADATA PyPI would reject
+fn pypi_problems(path: &Path) -> Result<Vec<String>, PublishError> {
+    let file_name = crate::asset_name(path);
+    let mut problems: Vec<String> = check_wheel(path)
+        .map_err(|err| PublishError::WheelError(path.to_path_buf(), err))?
+        .into_iter()
+        .map(|problem| format!("{file_name}: {problem}"))
+        .collect();
+    let Some(metadata) =
+        wheel_metadata(path).map_err(|err| PublishError::WheelError(path.to_path_buf(), err))?
+    else {
+        return Ok(problems);
+    };
+    let metadata_headers = headers(&metadata);
+    if let Some(name) = header(&metadata_headers, "Name") {
+        if let Some(problem) = crate::names::pypi_name_problem(name) {
+            problems.push(format!("{file_name}: {problem}"));
+        }
+    }
+    if let Some(version) = header(&metadata_headers, "Version") {
+        if !is_pep440(version) {
+            problems.push(format!(
+                "{file_name}: Version '{version}' isn't a normalized PEP 440 version"
+            ));
+        }
+    }
+    for (field, value) in metadata_placeholders(&metadata) {
+        problems.push(format!("{file_name}: {field} has a placeholder: '{value}'"));
+    }
+    // PyPI rejects descriptions it can't render, and renders them as reStructuredText
+    // unless told otherwise
+    let has_description = metadata
+        .split_once("\n\n")
+        .is_some_and(|(_, description)| !description.trim().is_empty());
+    match header(&metadata_headers, "Description-Content-Type") {
+        Some(content_type) => {
+            let mut params = content_type.split(';').map(str::trim);
+            let mime = params.next().unwrap_or_default();
+            if !["text/plain", "text/x-rst", "text/markdown"].contains(&mime) {
+                problems.push(format!(
+                    "{file_name}: Description-Content-Type must be text/plain, text/x-rst, or text/markdown"
+                ));
+            }
+            if let Some(variant) = params.find_map(|param| param.strip_prefix("variant=")) {
+                if mime == "text/markdown" && !["GFM", "CommonMark"].contains(&variant) {
+                    problems.push(format!(
+                        "{file_name}: Markdown variant must be GFM or CommonMark"
+                    ));
+                }
+            }
+        }
+        None if has_description => problems.push(format!(
+            "{file_name}: the README needs a Description-Content-Type, or PyPI renders it as reStructuredText"
+        )),
+        None => {}
+    }
+    Ok(problems)
+}
+
+/// RubyGems' name and version rules, checked through the gem's file name
+fn gem_problems(file_name: &str, gem_version: &str) -> Vec<String> {
+    let mut problems = vec![];
+    match file_name
+        .strip_suffix(".gem")
+        .and_then(|stem| stem.split_once(&format!("-{gem_version}-")))
+    {
+        Some((name, _)) => {
+            let valid = name.chars().any(|c| c.is_ascii_alphabetic())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+            if !valid {
+                problems.push(format!(
+                    "{file_name}: name '{name}' must be letters, digits, ., - or _"
+                ));
+            }
+        }
+        None => problems.push(format!(
+            "{file_name}: must be named $NAME-{gem_version}-$PLATFORM.gem"
+        )),
+    }
+    let valid_version = gem_version.split('.').enumerate().all(|(i, segment)| {
+        !segment.is_empty()
+            && segment.chars().all(|c| c.is_ascii_alphanumeric())
+            && (i > 0 || segment.chars().all(|c| c.is_ascii_digit()))
+    });
+    if !valid_version {
+        problems.push(format!(
+            "{file_name}: version '{gem_version}' must be dot-separated letters and digits, starting with a number"
+        ));
+    }
+    problems
+}
+
+/// The spec's `yanked` versions, as recorded by the build in `output_dir`
+fn yanked_versions(output_dir: &Path) -> Result<Vec<String>, PublishError> {
+    Ok(read_manifest_file(output_dir)?.yanked)
+}
+
+/// Publishes the npm packages of the build in `output_dir`. Platform packages
+/// go first, the packages depending on them can't be installed until they exist.
+/// Then the spec's `yanked` versions of every package are deprecated, and with
+/// `[rename]`, every version of the package's previous name.
+/// Reads the token from `NPM_TOKEN` or `NODE_AUTH_TOKEN`.
+pub fn publish_npm(
+    output_dir: &Path,
+    options: &NpmPublishOptions,
+) -> Result<Vec<String>, PublishError> {
+    let token_var = ["NPM_TOKEN", "NODE_AUTH_TOKEN"]
+        .into_iter()
+        .find(|name| env::var(name).is_ok());
+    if token_var.is_none() && !options.dry_run {
+        return Err(PublishError::MissingToken("NPM_TOKEN"));
+    }
+    let mut packages = vec![];
+    let mut problems = vec![];
+    for path in manifest_assets(output_dir, "npm")? {
+        let package_json = npm_package_json(&path)?;
+        problems.extend(npm_problems(&path, &package_json));
+        let is_platform_package = package_json.get("os").is_some();
+        let name = package_json["name"].as_str().unwrap_or_default().to_owned();
+        let version = package_json["version"]
+            .as_str()
+            .unwrap_or_default()
+            .to_owned();
+        packages.push((is_platform_package, name, version, path));
+    }
+    check_problems(problems)?;
+    let manifest = read_manifest_file(output_dir)?;
+    let yanked = manifest.yanked;
+    // stable, so platform packages keep the build's order
+    packages.sort_by_key(|(is_platform_package, ..)| !is_platform_package);
+
+    let registry = options
+        .registry
+        .clone()
+        .unwrap_or_else(|| "https://registry.npmjs.org/".to_owned());
+    // npm expands ${VAR} in its config, so the token itself is never written to disk
+    let userconfig = env::temp_dir().join(format!("sqlite-dist-{}.npmrc", std::process::id()));
+    let registry_host = registry
+        .split_once("://")
+        .map_or(registry.as_str(), |(_, host)| host);
+    fs::write(
+        &userconfig,
+        format!(
+            "registry={registry}\n//{registry_host}:_authToken=${{{}}}\n",
+            token_var.unwrap_or("NPM_TOKEN")
+        ),
+    )?;
+
+    let destination = format!("npm:{registry}");
+    let mut state = PublishState::load(output_dir);
+    let mut published = vec![];
+    let result = packages.iter().try_for_each(|(_, name, version, path)| {
+        let file_name = crate::asset_name(path);
+        let sha256 = file_sha256(path)?;
+        if state.is_uploaded(&destination, &file_name, &sha256) {
+            tracing::info!("{name}@{version} was already published, skipping");
+            published.push(format!("{name}@{version}"));
+            return Ok(());
+        }
+        let tag = options.tag.clone().unwrap_or_else(|| {
+            if version.contains('-') {
+                "next"
+            } else {
+                "latest"
+            }
+            .to_owned()
+        });
+        let mut command = Command::new("npm");
+        command
+            .arg("publish")
+            .arg(path)
+            .args(["--access", "public", "--tag", &tag])
+            .arg("--userconfig")
+            .arg(&userconfig);
+        if options.provenance {
+            command.arg("--provenance");
+        }
+        if options.dry_run {
+            command.arg("--dry-run");
+        }
+        match run_with_backoff(&mut command, &format!("npm publish {name}@{version}")) {
+            // published before a crash kept it from being recorded
+            Err(PublishError::Failed(_, _, output)) if npm_already_published(&output) => {
+                tracing::warn!("{name}@{version} is already on the registry, skipping");
+            }
+            result => result?,
+        }
+        if !options.dry_run {
+            state.record(output_dir, &destination, &file_name, sha256)?;
+        }
+        published.push(format!("{name}@{version}"));
+        Ok::<(), PublishError>(())
+    });
+    let result = result.and_then(|_| {
+        packages.iter().try_for_each(|(_, name, _, _)| {
+            for version in &yanked {
+                let shown = format!("npm deprecate {name}@{version}");
+                if options.dry_run {
+                    tracing::info!("{shown}");
+                    continue;
+                }
+                run_with_backoff(
+                    Command::new("npm")
+                        .arg("deprecate")
+                        .arg(format!("{name}@{version}"))
+                        .arg(format!("{version} was yanked, upgrade to a later version"))
+                        .arg("--userconfig")
+                        .arg(&userconfig),
+                    &shown,
+                )?;
+            }
+            Ok(())
+        })
+    });
+    let result = result.and_then(|_| {
+        let Some(rename) = &manifest.rename else {
+            return Ok(());
+        };
+        let shown = format!("npm deprecate {}", rename.npm_name);
+        if options.dry_run {
+            tracing::info!("{shown}");
+            return Ok(());
+        }
+        run_with_backoff(
+            Command::new("npm")
+                .arg("deprecate")
+                .arg(&rename.npm_name)
+                .arg(&rename.message)
+                .arg("--userconfig")
+                .arg(&userconfig),
+            &shown,
+        )
+    });
+    fs::remove_file(&userconfig)?;
+    result.map(|_| published)
+}
+
+pub struct RubygemsPublishOptions {
+    /// one-time password, for accounts with MFA on the API
+    pub otp: Option<String>,
+    /// defaults to https://rubygems.org
+    pub host: Option<String>,
+    pub dry_run: bool,
+}
+
+/// RubyGems version of a version recorded in the manifest
+fn manifest_gem_version(version: &str) -> Result<String, PublishError> {
+    let version = Version::parse(version)
+        .map_err(|err| PublishError::InvalidVersion(version.to_owned(), err))?;
+    Ok(gem::gem_version(&version))
+}
+
+/// Pushes every gem of the build in `output_dir` through the RubyGems API, then
+/// yanks the spec's `yanked` versions of each gem name and platform.
+/// Reads the API key from `GEM_HOST_API_KEY`, and the OTP from `GEM_HOST_OTP_CODE`
+/// when it isn't in the options, the same variables `gem push` reads.
+pub fn publish_rubygems(
+    output_dir: &Path,
+    options: &RubygemsPublishOptions,
+) -> Result<Vec<String>, PublishError> {
+    let api_key = env::var("GEM_HOST_API_KEY").ok();
+    if api_key.is_none() && !options.dry_run {
+        return Err(PublishError::MissingToken("GEM_HOST_API_KEY"));
+    }
+    let otp = options
+        .otp
+        .clone()
+        .or_else(|| env::var("GEM_HOST_OTP_CODE").ok());
+    let host = options
+        .host
+        .as_deref()
+        .unwrap_or("https://rubygems.org")
+        .trim_end_matches('/');
+    let manifest = read_manifest_file(output_dir)?;
+    // `$NAME-$VERSION-$PLATFORM.gem`, with the version gem.rs wrote them with
+    let gem_version = manifest_gem_version(manifest.version.as_deref().unwrap_or_default())?;
+    let gems = manifest_assets(output_dir, "gem")?;
+    check_problems(
+        gems.iter()
+            .flat_map(|path| gem_problems(&crate::asset_name(path), &gem_version))
+            .collect(),
+    )?;
+    let destination = format!("rubygems:{host}");
+    let mut state = PublishState::load(output_dir);
+    let mut published = vec![];
+    for path in gems {
+        let name = crate::asset_name(&path);
+        let data = fs::read(&path)?;
+        let sha256 = base16ct::lower::encode_string(&sha2::Sha256::digest(&data));
+        if state.is_uploaded(&destination, &name, &sha256) {
+            tracing::info!("{name} was already pushed, skipping");
+            published.push(name);
+            continue;
+        }
+        tracing::info!("gem push {name}");
+        if options.dry_run {
+            published.push(name);
+            continue;
+        }
+        let agent = http::agent()?;
+        let response = http::send(
+            &format!("gem push {name}"),
+            || {
+                let request = agent
+                    .post(&format!("{host}/api/v1/gems"))
+                    .set("Authorization", api_key.as_deref().unwrap_or_default())
+                    .set("Content-Type", "application/octet-stream");
+                match &otp {
+                    Some(otp) => request.set("OTP", otp),
+                    None => request,
+                }
+            },
+            Some(&data),
+        );
+        match response.map_err(|err| *err) {
+            Ok(_) => {
+                state.record(output_dir, &destination, &name, sha256)?;
+                published.push(name);
+            }
+            Err(ureq::Error::Status(status, response)) => {
+                let message = response.into_string().unwrap_or_default();
+                // pushed before a crash kept it from being recorded
+                if status == 409 && message.contains("Repushing of gem versions") {
+                    tracing::warn!("{name} is already on {host}, skipping");
+                    state.record(output_dir, &destination, &name, sha256)?;
+                    published.push(name);
+                    continue;
+                }
+                return Err(PublishError::Upload(name, message.trim().to_owned()));
+            }
+            Err(err) => return Err(PublishError::Upload(name, err.to_string())),
+        }
+    }
+
+    let gems: Vec<(String, String)> = published
+        .iter()
+        .filter_map(|file_name| {
+            let (gem_name, platform) = file_name
+                .strip_suffix(".gem")?
+                .split_once(&format!("-{gem_version}-"))?;
+            Some((gem_name.to_owned(), platform.to_owned()))
+        })
+        .collect();
+    for version in &manifest.yanked {
+        let version = manifest_gem_version(version)?;
+        for (gem_name, platform) in &gems {
+            let shown = format!("gem yank {gem_name} -v {version} --platform {platform}");
+            tracing::info!("{shown}");
+            if options.dry_run {
+                continue;
+            }
+            let agent = http::agent()?;
+            let response = http::send(
+                &shown,
+                || {
+                    let request = agent
+                        .delete(&format!("{host}/api/v1/gems/yank"))
+                        .set("Authorization", api_key.as_deref().unwrap_or_default())
+                        .query("gem_name", gem_name)
+                        .query("version", &version)
+                        .query("platform", platform);
+                    match &otp {
+                        Some(otp) => request.set("OTP", otp),
+                        None => request,
+                    }
+                },
+                None,
+            );
+            match response.map_err(|err| *err) {
+                Ok(_) => {}
+                Err(ureq::Error::Status(_, response)) => {
+                    let message = response.into_string().unwrap_or_default();
+                    return Err(PublishError::Upload(shown, message.trim().to_owned()));
+                }
+                Err(err) => return Err(PublishError::Upload(shown, err.to_string())),
+            }
+        }
+    }
+    Ok(published)
+}
+
+pub struct S3PublishOptions {
+    /// `s3://bucket/prefix` the output directory is uploaded to
+    pub destination: String,
+    /// for S3-compatible services, ex `https://<account>.r2.cloudflarestorage.com`
+    pub endpoint_url: Option<String>,
+    /// URL the destination is publicly served from, defaults to the bucket's S3 website
+    pub public_url: Option<String>,
+    pub dry_run: bool,
+}
+
+/// Versioned assets never change once uploaded, the files describing the latest
+/// build are overwritten by the next one
+fn cache_control(name: &str) -> &'static str {
+    match name {
+        "sqlite-dist-manifest.json"
+        | "checksums.txt"
+        | "checksums.log"
+        | "checksums.log.sig"
+        | "install.sh" => "public, max-age=300",
+        _ => "public, max-age=31536000, immutable",
+    }
+}
+
+/// Uploads every asset of the build in `output_dir` to an S3-compatible bucket with
+/// the `aws` CLI, keeping their paths relative to the output directory. The manifest is
+/// uploaded last, with the public URL of every asset added.
+pub fn publish_s3(
+    output_dir: &Path,
+    options: &S3PublishOptions,
+) -> Result<Vec<String>, PublishError> {
+    let destination = options.destination.trim_end_matches('/');
+    let public_url = match &options.public_url {
+        Some(public_url) => public_url.trim_end_matches('/').to_owned(),
+        None => {
+            let bucket_path = destination.strip_prefix("s3://").unwrap_or(destination);
+            match bucket_path.split_once('/') {
+                Some((bucket, prefix)) => format!("https://{bucket}.s3.amazonaws.com/{prefix}"),
+                None => format!("https://{bucket_path}.s3.amazonaws.com"),
+            }
+        }
+    };
+    let manifest_path = output_dir.join("sqlite-dist-manifest.json");
+    let mut manifest: serde_json::Value =
+        serde_json::from_slice(&fs::read(&manifest_path)?).map_err(io::Error::from)?;
+    let mut uploads = vec![];
+    for artifact in read_manifest(output_dir)? {
+        let path = artifact.locate(output_dir)?;
+        let key = path
+            .strip_prefix(output_dir)
+            .map(|relative| relative.to_string_lossy().replace('\\', "/"))
+            .unwrap_or(artifact.name);
+        let media_type = artifact
+            .media_type
+            .unwrap_or_else(|| crate::media_type(&key).to_owned());
+        uploads.push((path, key, media_type));
+    }
+    for name in [
+        "checksums.txt",
+        "checksums.log",
+        "checksums.log.sig",
+        "install.sh",
+    ] {
+        let path = output_dir.join(name);
+        if path.is_file() {
+            uploads.push((path, name.to_owned(), crate::media_type(name).to_owned()));
+        }
+    }
+    if let Some(artifacts) = manifest
+        .get_mut("artifacts")
+        .and_then(|artifacts| artifacts.as_array_mut())
+    {
+        for (artifact, (_, key, _)) in artifacts.iter_mut().zip(&uploads) {
+            artifact["url"] = format!("{public_url}/{key}").into();
+        }
+    }
+    let published_manifest =
+        env::temp_dir().join(format!("sqlite-dist-{}-manifest.json", std::process::id()));
+    fs::write(
+        &published_manifest,
+        serde_json::to_vec_pretty(&manifest).map_err(io::Error::from)?,
+    )?;
+    uploads.push((
+        published_manifest.clone(),
+        "sqlite-dist-manifest.json".to_owned(),
+        crate::media_type("sqlite-dist-manifest.json").to_owned(),
+    ));
+
+    let state_destination = format!("s3:{destination}");
+    let mut state = PublishState::load(output_dir);
+    let mut published = vec![];
+    let result = uploads.iter().try_for_each(|(path, key, media_type)| {
+        let sha256 = file_sha256(path)?;
+        if state.is_uploaded(&state_destination, key, &sha256) {
+            tracing::info!("{key} was already uploaded, skipping");
+            published.push(format!("{public_url}/{key}"));
+            return Ok(());
+        }
+        let mut command = Command::new("aws");
+        command
+            .args(["s3", "cp"])
+            .arg(path)
+            .arg(format!("{destination}/{key}"))
+            .args(["--content-type", media_type.as_str()])
+            .args(["--cache-control", cache_control(key)]);
+        if let Some(endpoint_url) = &options.endpoint_url {
+            command.args(["--endpoint-url", endpoint_url]);
+        }
+        if options.dry_run {
+            command.arg("--dryrun");
+        }
+        run_with_backoff(&mut command, &format!("aws s3 cp {key}"))?;
+        if !options.dry_run {
+            state.record(output_dir, &state_destination, key, sha256)?;
+        }
+        published.push(format!("{public_url}/{key}"));
+        Ok::<(), PublishError>(())
+    });
+    fs::remove_file(&published_manifest)?;
+    result.map(|_| published)
+}
+
+pub struct PypiPublishOptions {
+    /// upload endpoint of the index, defaults to PyPI's, ex
+    /// `https://devpi.example.com/team/prod/` or an Artifactory `/api/pypi/<repo>` URL
+    pub repository_url: Option<String>,
+    /// defaults to `__token__`, for API tokens
+    pub username: Option<String>,
+    pub dry_run: bool,
+}
+
+/// `multipart/form-data` body of a legacy upload API request
+fn multipart_body(
+    boundary: &str,
+    fields: &[(&str, &str)],
+    file_name: &str,
+    data: &[u8],
+) -> Vec<u8> {
+    let mut body = vec![];
+    for (name, value) in fields {
+        body.extend(
+            format!("--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n")
+                .as_bytes(),
+        );
+    }
+    body.extend(
+        format!("--{boundary}\r\nContent-Disposition: form-data; name=\"content\"; filename=\"{file_name}\"\r\nContent-Type: application/octet-stream\r\n\r\n")
+            .as_bytes(),
+    );
+    body.extend(data);
+    body.extend(format!("\r\n--{boundary}--\r\n").as_bytes());
+    body
+}
+
+/// Uploads the wheels of the build in `output_dir` (pip, datasette, and sqlite-utils)
+/// through the legacy upload API PyPI, devpi, and Artifactory all implement.
+/// Reads the password or API token from `PYPI_TOKEN`, or `TWINE_PASSWORD`.
+pub fn publish_pypi(
+    output_dir: &Path,
+    options: &PypiPublishOptions,
+) -> Result<Vec<String>, PublishError> {
+    let password = ["PYPI_TOKEN", "TWINE_PASSWORD"]
+        .iter()
+        .find_map(|name| env::var(name).ok());
+    if password.is_none() && !options.dry_run {
+        return Err(PublishError::MissingToken("PYPI_TOKEN"));
+    }
+    let username = options
+        .username
+        .clone()
+        .or_else(|| env::var("TWINE_USERNAME").ok())
+        .unwrap_or_else(|| "__token__".to_owned());
+    let authorization = format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD
+            .encode(format!("{username}:{}", password.unwrap_or_default()))
+    );
+    let repository_url = options
+        .repository_url
+        .as_deref()
+        .unwrap_or("https://upload.pypi.org/legacy/");
+
+    let mut wheels = vec![];
+    for kind in ["pip", "pip-rename", "datasette", "sqlite-utils"] {
+        match manifest_assets(output_dir, kind) {
+            Ok(paths) => wheels.extend(paths),
+            Err(PublishError::NoAssets(..)) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    if wheels.is_empty() {
+        return Err(PublishError::NoAssets("pip", output_dir.to_path_buf()));
+    }
+    let mut problems = vec![];
+    for path in &wheels {
+        problems.extend(pypi_problems(path)?);
+    }
+    check_problems(problems)?;
+
+    let destination = format!("pypi:{repository_url}");
+    let mut state = PublishState::load(output_dir);
+    let mut published = vec![];
+    for path in wheels {
+        let file_name = crate::asset_name(&path);
+        let data = fs::read(&path)?;
+        let sha256_digest = base16ct::lower::encode_string(&sha2::Sha256::digest(&data));
+        if state.is_uploaded(&destination, &file_name, &sha256_digest) {
+            tracing::info!("{file_name} was already uploaded, skipping");
+            published.push(file_name);
+            continue;
+        }
+        let metadata = wheel_metadata(&path)
+            .map_err(|err| PublishError::WheelError(path.clone(), err))?
+            .ok_or_else(|| PublishError::InvalidWheel(path.clone()))?;
+        let metadata_headers = headers(&metadata);
+        let (Some(name), Some(version), Some(metadata_version), Some(pyversion)) = (
+            header(&metadata_headers, "Name"),
+            header(&metadata_headers, "Version"),
+            header(&metadata_headers, "Metadata-Version"),
+            python_tag(&path),
+        ) else {
+            return Err(PublishError::InvalidWheel(path));
+        };
+        tracing::info!("uploading {file_name} to {repository_url}");
+        if options.dry_run {
+            published.push(file_name);
+            continue;
+        }
+        let boundary = format!("sqlite-dist-{sha256_digest}");
+        let body = multipart_body(
+            &boundary,
+            &[
+                (":action", "file_upload"),
+                ("protocol_version", "1"),
+                ("name", name),
+                ("version", version),
+                ("metadata_version", metadata_version),
+                ("filetype", "bdist_wheel"),
+                ("pyversion", &pyversion),
+                ("sha256_digest", &sha256_digest),
+            ],
+            &file_name,
+            &data,
+        );
+        let agent = http::agent()?;
+        let response = http::send(
+            &format!("uploading {file_name}"),
+            || {
+                agent
+                    .post(repository_url)
+                    .set("Authorization", &authorization)
+                    .set(
+                        "Content-Type",
+                        &format!("multipart/form-data; boundary={boundary}"),
+                    )
+            },
+            Some(&body),
+        );
+        match response.map_err(|err| *err) {
+            Ok(_) => {
+                state.record(output_dir, &destination, &file_name, sha256_digest)?;
+                published.push(file_name);
+            }
+            Err(ureq::Error::Status(status, response)) => {
+                let message = response.status_text().to_owned();
+                return Err(PublishError::Upload(
+                    file_name,
+                    format!("{status} {message}"),
+                ));
+            }
+            Err(err) => return Err(PublishError::Upload(file_name, err.to_string())),
+        }
+    }
+    // PyPI only yanks from the project's web UI, its upload API has no action for it
+    for version in yanked_versions(output_dir)? {
+        tracing::warn!(
+            "{version} is yanked in the spec, yank it on PyPI at https://pypi.org/manage/projects/"
+        );
+    }
+    Ok(published)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process::Command};
+
+    use super::{is_transient_failure, npm_already_published, run, PublishError, PublishState};
+
+    #[test]
+    #[cfg(unix)]
+    fn failed_command_keeps_its_output() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo uploading; echo 'code E503' >&2; exit 1"]);
+        match run(&mut command, "upload") {
+            Err(PublishError::Failed(shown, _, output)) => {
+                assert_eq!(shown, "upload");
+                assert_eq!(output, "uploading\ncode E503\n");
+                assert!(is_transient_failure(&output));
+            }
+            result => panic!("expected a failure, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn transient_failures() {
+        for output in [
+            "npm ERR! code E503\nnpm ERR! 503 Service Unavailable - PUT https://registry.npmjs.org/sqlite-sample",
+            "npm ERR! code ETIMEDOUT\nnpm ERR! network request to https://registry.npmjs.org/ failed",
+            "ERROR    HTTPError: 429 Too Many Requests from https://upload.pypi.org/legacy/",
+            "upload failed: ./sample.tar.gz to s3://bucket/sample.tar.gz Read timeout on endpoint URL",
+        ] {
+            assert!(is_transient_failure(output), "{output}");
+        }
+        for output in [
+            "npm ERR! code E403\nnpm ERR! 403 You cannot publish over the previously published versions: 0.1.0.",
+            "npm ERR! code ENEEDAUTH",
+            "ERROR    HTTPError: 400 Bad Request from https://upload.pypi.org/legacy/\nInvalid value for license",
+            "Repushing of gem versions is not allowed.",
+        ] {
+            assert!(!is_transient_failure(output), "{output}");
+        }
+    }
+
+    #[test]
+    fn already_published() {
+        assert!(npm_already_published(
+            "npm ERR! code E403\nnpm ERR! 403 403 Forbidden - PUT https://registry.npmjs.org/sqlite-sample - You cannot publish over the previously published versions: 0.1.0."
+        ));
+        assert!(npm_already_published("npm ERR! code EPUBLISHCONFLICT"));
+        assert!(!npm_already_published(
+            "npm ERR! code E403\nnpm ERR! 403 403 Forbidden - You do not have permission to publish \"sqlite-sample\""
+        ));
+    }
+
+    #[test]
+    fn publish_state() {
+        let dir = env::temp_dir().join(format!("sqlite-dist-publish-state-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // no state file yet, nothing was uploaded
+        let mut state = PublishState::load(&dir);
+        assert!(!state.is_uploaded("npm", "sample.tgz", "aaaa"));
+
+        state
+            .record(&dir, "npm", "sample.tgz", "aaaa".to_owned())
+            .unwrap();
+        let state = PublishState::load(&dir);
+        assert!(state.is_uploaded("npm", "sample.tgz", "aaaa"));
+        // a rebuilt asset is uploaded again, and each destination is tracked apart
+        assert!(!state.is_uploaded("npm", "sample.tgz", "bbbb"));
+        assert!(!state.is_uploaded("pypi", "sample.tgz", "aaaa"));
+
+        // a corrupt state file is treated as missing
+        fs::write(dir.join(super::PUBLISH_STATE_FILE), "{").unwrap();
+        assert!(!PublishState::load(&dir).is_uploaded("npm", "sample.tgz", "aaaa"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
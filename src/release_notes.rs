@@ -0,0 +1,64 @@
+use std::{fs, io, path::Path};
+
+use semver::Version;
+
+use crate::{GeneratedAsset, GeneratedAssetKind, Project};
+
+/// Whether a Keep a Changelog heading, ex `## [1.2.0] - 2024-03-01`, is for `version`
+fn is_version_heading(line: &str, version: &Version) -> bool {
+    let Some(heading) = line.strip_prefix("## ") else {
+        return false;
+    };
+    let title = heading.split(" - ").next().unwrap_or_default().trim();
+    let title = title.trim_start_matches('[').trim_end_matches(']');
+    let title = title.strip_prefix('v').unwrap_or(title);
+    title == version.to_string()
+}
+
+/// The notes under the `version` heading of a changelog, without the heading
+pub(crate) fn changelog_section(changelog: &str, version: &Version) -> Option<String> {
+    let mut lines = changelog.lines();
+    lines.find(|line| is_version_heading(line, version))?;
+    let section: Vec<&str> = lines
+        .take_while(|line| !line.starts_with("## "))
+        // link reference definitions at the end of the file, ex `[1.2.0]: https://...`
+        .filter(|line| !(line.starts_with('[') && line.contains("]: ")))
+        .collect();
+    Some(section.join("\n").trim().to_owned())
+}
+
+/// Writes the changelog notes of the project's version as `release-notes.md`
+/// and `release-notes.html`, ex for `gh release create --notes-file`.
+pub(crate) fn write_release_notes(
+    project: &Project,
+    changelog: &str,
+    directory: &Path,
+) -> io::Result<Vec<GeneratedAsset>> {
+    let changelog_path = project.spec_directory.join(changelog);
+    let contents = fs::read_to_string(&changelog_path)?;
+    let notes = changelog_section(&contents, &project.version).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} has no entry for version {}",
+                changelog_path.display(),
+                project.version
+            ),
+        )
+    })?;
+    let notes = project.render_template("release-notes.md", None, notes + "\n")?;
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, pulldown_cmark::Parser::new(&notes));
+    Ok(vec![
+        GeneratedAsset::from(
+            GeneratedAssetKind::ReleaseNotes,
+            &directory.join("release-notes.md"),
+            notes.as_bytes(),
+        )?,
+        GeneratedAsset::from(
+            GeneratedAssetKind::ReleaseNotes,
+            &directory.join("release-notes.html"),
+            html.as_bytes(),
+        )?,
+    ])
+}
@@ -0,0 +1,204 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::spec::{MacosSigningTool, SpecMacosSign, SpecWindowsSign};
+use crate::{create_zip, dry_run, http, PlatformDirectory, PlatformFile};
+
+/// Password of `[macos.sign] p12_file`
+const P12_PASSWORD_ENV: &str = "SQLITE_DIST_P12_PASSWORD";
+
+/// Password of `[windows.sign] certificate`
+const PFX_PASSWORD_ENV: &str = "SQLITE_DIST_PFX_PASSWORD";
+
+const DEFAULT_TIMESTAMP_URL: &str = "http://timestamp.digicert.com";
+
+#[derive(Error, Debug)]
+pub enum SigningError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] io::Error),
+    #[error("could not run `{0}`: {1}")]
+    Spawn(String, io::Error),
+    #[error("`{0}` exited with {1}")]
+    Failed(String, ExitStatus),
+}
+
+fn run(command: &mut Command) -> Result<(), SigningError> {
+    let shown = format!("{command:?}");
+    tracing::info!("{shown}");
+    let status = command
+        .status()
+        .map_err(|err| SigningError::Spawn(shown.clone(), err))?;
+    if !status.success() {
+        return Err(SigningError::Failed(shown, status));
+    }
+    Ok(())
+}
+
+/// Scratch directory the files of one platform are signed in
+fn signing_dir(platform_dir: &PlatformDirectory) -> Result<PathBuf, SigningError> {
+    let dir = env::temp_dir().join(format!(
+        "sqlite-dist-sign-{}-{}-{}",
+        platform_dir.os.to_string(),
+        platform_dir.cpu.to_string(),
+        std::process::id()
+    ));
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Swaps in the signed binary, keeping the hash of the unsigned one for the build cache
+fn replace_signed(binary: &mut PlatformFile, signed: Vec<u8>) {
+    if binary.unsigned_sha256.is_none() {
+        binary.unsigned_sha256 = Some(Sha256::digest(&binary.data).to_vec());
+    }
+    binary.data = signed;
+}
+
+/// The loadables and the `sqlite3` shell, the binaries users download and run
+fn binaries(platform_dir: &mut PlatformDirectory) -> Vec<&mut PlatformFile> {
+    platform_dir
+        .loadable_files
+        .iter_mut()
+        .map(|loadable| &mut loadable.file)
+        .chain(platform_dir.sqlite3.as_mut())
+        .collect()
+}
+
+/// Signs the loadables and `sqlite3` shell of a macos platform with a Developer ID,
+/// with the hardened runtime and a secure timestamp, then optionally notarizes them.
+/// Dylibs can't be stapled, Gatekeeper looks the notarization ticket up online.
+pub(crate) fn sign_macos(
+    platform_dir: &mut PlatformDirectory,
+    config: &SpecMacosSign,
+    spec_directory: &Path,
+) -> Result<(), SigningError> {
+    if dry_run() {
+        tracing::info!("dry run, not signing");
+        return Ok(());
+    }
+    // codesign and rcodesign timestamp with Apple's server
+    http::ensure_online("macos.sign")?;
+    let _span = platform_dir.span().entered();
+    let dir = signing_dir(platform_dir)?;
+    let mut binaries = binaries(platform_dir);
+    for binary in &mut binaries {
+        let path = dir.join(&binary.name);
+        fs::write(&path, &binary.data)?;
+        let mut command = match config.tool {
+            MacosSigningTool::Codesign => {
+                let mut command = Command::new("codesign");
+                command
+                    .args(["--force", "--timestamp", "--options", "runtime", "--sign"])
+                    .arg(config.identity.as_deref().unwrap_or_default());
+                command
+            }
+            MacosSigningTool::Rcodesign => {
+                let mut command = Command::new("rcodesign");
+                command
+                    .args(["sign", "--code-signature-flags", "runtime", "--p12-file"])
+                    .arg(spec_directory.join(config.p12_file.as_deref().unwrap_or_default()));
+                if env::var_os(P12_PASSWORD_ENV).is_some() {
+                    command.args(["--p12-password-env", P12_PASSWORD_ENV]);
+                }
+                command
+            }
+        };
+        run(command.arg(&path))?;
+        replace_signed(binary, fs::read(&path)?);
+    }
+
+    if let Some(notarize) = &config.notarize {
+        // notarytool only accepts zips, pkgs, and dmgs
+        let zip_path = dir.join("notarize.zip");
+        fs::write(
+            &zip_path,
+            create_zip(&binaries.iter().map(|binary| &**binary).collect::<Vec<_>>())?,
+        )?;
+        let mut command = match config.tool {
+            MacosSigningTool::Codesign => {
+                let mut command = Command::new("xcrun");
+                command
+                    .args(["notarytool", "submit", "--wait", "--keychain-profile"])
+                    .arg(notarize.keychain_profile.as_deref().unwrap_or_default());
+                command
+            }
+            MacosSigningTool::Rcodesign => {
+                let mut command = Command::new("rcodesign");
+                command
+                    .args(["notary-submit", "--wait", "--api-key-file"])
+                    .arg(spec_directory.join(notarize.api_key_file.as_deref().unwrap_or_default()));
+                command
+            }
+        };
+        run(command.arg(&zip_path))?;
+    }
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+/// Authenticode signs the loadables and `sqlite3.exe` of a windows platform, with
+/// the spec's command or osslsigncode
+pub(crate) fn sign_windows(
+    platform_dir: &mut PlatformDirectory,
+    config: &SpecWindowsSign,
+    spec_directory: &Path,
+    description: &str,
+    homepage: &str,
+) -> Result<(), SigningError> {
+    if dry_run() {
+        tracing::info!("dry run, not signing");
+        return Ok(());
+    }
+    let _span = platform_dir.span().entered();
+    let dir = signing_dir(platform_dir)?;
+    // osslsigncode reads the password from a file, so it isn't in the process list
+    let password_file = dir.join("password");
+    if let Some(password) = env::var_os(PFX_PASSWORD_ENV) {
+        fs::write(&password_file, password.as_encoded_bytes())?;
+    }
+    for binary in binaries(platform_dir) {
+        let path = dir.join(&binary.name);
+        fs::write(&path, &binary.data)?;
+        if let Some(command) = &config.command {
+            let args: Vec<String> = command
+                .iter()
+                .map(|arg| arg.replace("{file}", &path.to_string_lossy()))
+                .collect();
+            let mut command = Command::new(&args[0]);
+            http::pass_offline(&mut command);
+            run(command.args(&args[1..]).current_dir(spec_directory))?;
+            replace_signed(binary, fs::read(&path)?);
+            continue;
+        }
+        // osslsigncode timestamps with timestamp_url
+        http::ensure_online("windows.sign.certificate")?;
+        let signed_path = dir.join(format!("{}.signed", binary.name));
+        let mut command = Command::new("osslsigncode");
+        command
+            .args(["sign", "-h", "sha256", "-pkcs12"])
+            .arg(spec_directory.join(config.certificate.as_deref().unwrap_or_default()))
+            .args(["-n", description, "-i", homepage, "-ts"])
+            .arg(
+                config
+                    .timestamp_url
+                    .as_deref()
+                    .unwrap_or(DEFAULT_TIMESTAMP_URL),
+            );
+        if password_file.exists() {
+            command.arg("-readpass").arg(&password_file);
+        }
+        run(command.arg("-in").arg(&path).arg("-out").arg(&signed_path))?;
+        replace_signed(binary, fs::read(&signed_path)?);
+    }
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
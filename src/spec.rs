@@ -1,15 +1,175 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
 use semver::Version;
-use serde::Deserialize;
+use serde::{
+    de::{MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use thiserror::Error;
+use toml::Spanned;
+
+use crate::names::{
+    formula_name_problem, gem_name_problem, normalize_pypi_name, npm_name_problem,
+    pypi_name_problem,
+};
+
+#[derive(Error, Debug)]
+pub enum SpecError {
+    #[error("could not read {0}: {1}")]
+    IOError(PathBuf, io::Error),
+    // toml's Display already includes the line, column, and offending snippet
+    #[error("{0}")]
+    ParseError(#[from] toml::de::Error),
+    #[error("{0}")]
+    InvalidSpec(String),
+    /// the message of a semantic error in the value at a dotted key, with array
+    /// indexes as numbers (ex `functions.2.name`), pointed at by `from_path`
+    #[error("{1}")]
+    InvalidValue(String, String),
+}
+
+fn invalid(key: impl Into<String>, message: impl Into<String>) -> SpecError {
+    SpecError::InvalidValue(key.into(), message.into())
+}
+
+/// A TOML value with the span of each of its children, the spans `toml::Value` drops
+enum SpannedValue {
+    Table(Vec<(String, Spanned<SpannedValue>)>),
+    Array(Vec<Spanned<SpannedValue>>),
+    Scalar,
+}
+
+impl<'de> Deserialize<'de> for SpannedValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SpannedVisitor;
+        impl<'de> Visitor<'de> for SpannedVisitor {
+            type Value = SpannedValue;
 
-#[derive(Deserialize)]
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a TOML value")
+            }
+            fn visit_bool<E>(self, _: bool) -> Result<SpannedValue, E> {
+                Ok(SpannedValue::Scalar)
+            }
+            fn visit_i64<E>(self, _: i64) -> Result<SpannedValue, E> {
+                Ok(SpannedValue::Scalar)
+            }
+            fn visit_u64<E>(self, _: u64) -> Result<SpannedValue, E> {
+                Ok(SpannedValue::Scalar)
+            }
+            fn visit_f64<E>(self, _: f64) -> Result<SpannedValue, E> {
+                Ok(SpannedValue::Scalar)
+            }
+            fn visit_str<E>(self, _: &str) -> Result<SpannedValue, E> {
+                Ok(SpannedValue::Scalar)
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<SpannedValue, A::Error> {
+                let mut values = vec![];
+                while let Some(value) = seq.next_element()? {
+                    values.push(value);
+                }
+                Ok(SpannedValue::Array(values))
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SpannedValue, A::Error> {
+                let mut entries = vec![];
+                while let Some(key) = map.next_key()? {
+                    entries.push((key, map.next_value()?));
+                }
+                Ok(SpannedValue::Table(entries))
+            }
+        }
+        deserializer.deserialize_any(SpannedVisitor)
+    }
+}
+
+/// The span of the value at `key` in the spec, or of its closest parent that's
+/// there when the value is missing
+fn key_span(contents: &str, key: &str) -> Option<Range<usize>> {
+    let mut value = toml::from_str::<SpannedValue>(contents).ok()?;
+    let mut span = None;
+    for segment in key.split('.') {
+        let child = match value {
+            SpannedValue::Table(entries) => entries
+                .into_iter()
+                .find_map(|(key, value)| (key == segment).then_some(value)),
+            SpannedValue::Array(mut values) => segment
+                .parse()
+                .ok()
+                .filter(|i| *i < values.len())
+                .map(|i| values.swap_remove(i)),
+            SpannedValue::Scalar => None,
+        };
+        let Some(child) = child else {
+            break;
+        };
+        span = Some(child.span());
+        value = child.into_inner();
+    }
+    span
+}
+
+impl SpecError {
+    /// Points an `InvalidValue` at its key in `contents`, the way toml shows parse errors
+    fn located(self, contents: &str) -> Self {
+        let SpecError::InvalidValue(key, message) = self else {
+            return self;
+        };
+        let Some(span) = key_span(contents, &key) else {
+            return SpecError::InvalidValue(key, message);
+        };
+        let line_start = contents[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line = contents[line_start..].lines().next().unwrap_or_default();
+        let line_number = contents[..span.start].matches('\n').count() + 1;
+        let column = contents[line_start..span.start].chars().count();
+        let underlined = contents[span.start..span.end.min(line_start + line.len())]
+            .chars()
+            .count()
+            .max(1);
+        let gutter = " ".repeat(line_number.to_string().len() + 1);
+        SpecError::InvalidSpec(format!(
+            "invalid value at line {line_number}, column {}\n{gutter}|\n{line_number} | {line}\n{gutter}| {}{}\n{message}\n",
+            column + 1,
+            " ".repeat(column),
+            "^".repeat(underlined),
+        ))
+    }
+}
+
+fn deserialize_git_tag_format<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let format = Option::<String>::deserialize(deserializer)?;
+    if let Some(format) = &format {
+        if !format.contains("$VERSION") {
+            return Err(serde::de::Error::custom(format!(
+                "git_tag_format '{format}' must contain $VERSION"
+            )));
+        }
+    }
+    Ok(format)
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
 pub struct SpecPackage {
     pub name: String,
+    pub version: Option<Version>,
     pub authors: Vec<String>,
     pub license: String,
     pub description: String,
     pub homepage: String,
     pub repo: String,
+    #[serde(default, deserialize_with = "deserialize_git_tag_format")]
     pub git_tag_format: Option<String>,
+    /// where release assets are downloaded from when not hosted on the repo's GitHub
+    /// releases, ex `https://downloads.example.com/{name}/{tag}/{file}`
+    pub asset_url_template: Option<String>,
 }
 
 impl SpecPackage {
@@ -22,36 +182,338 @@ impl SpecPackage {
     }
 }
 
-#[derive(Deserialize)]
-pub struct TargetGithubRelease {}
-#[derive(Deserialize)]
-pub struct TargetSqlpkg {}
-#[derive(Deserialize)]
-pub struct TargetSpm {}
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetGithubRelease {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Keep a Changelog file whose entry for the version becomes the release notes
+    pub changelog: Option<String>,
+}
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetSqlpkg {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetSpm {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
 
-#[derive(Deserialize)]
-pub struct TargetDatasette {}
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetDatasette {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// SQL ran on every connection after the extension is loaded, ex `select foo_configure('bar')`
+    #[serde(default)]
+    pub configure_sql: Vec<String>,
+    /// Summary of the plugin's package, shown in Datasette's plugin directory
+    pub description: Option<String>,
+    /// overrides `datasette-{package.name}`, ex when the plugin is already published
+    /// under another name
+    pub package_name: Option<String>,
+    /// PEP 508 requirements added as `Requires-Dist`, ex `datasette>=1.0a1` in place
+    /// of the unpinned `datasette`
+    #[serde(default)]
+    pub extra_dependencies: Vec<String>,
+}
+
+impl TargetDatasette {
+    pub(crate) fn package_name(&self, name: &str) -> String {
+        self.package_name
+            .clone()
+            .unwrap_or_else(|| format!("datasette-{name}"))
+    }
+}
+
+/// What the wheels' version does with semver build metadata, the `+...` of a version
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PipBuildMetadata {
+    #[default]
+    Drop,
+    /// kept as a PEP 440 local version, ex `1.2.3+cuda12`. PyPI rejects these,
+    /// they're for private indexes
+    Local,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WheelCompression {
+    #[default]
+    Stored,
+    Deflated,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct TargetPip {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
     pub(crate) extra_init_py: Option<String>,
+    /// overrides package.name for the base wheel, ex `foo-py`
+    pub package_name: Option<String>,
+    /// PEP 508 requirements added as `Requires-Dist` to the base wheel
+    #[serde(default)]
+    pub extra_dependencies: Vec<String>,
+    #[serde(default)]
+    pub compression: WheelCompression,
+    #[serde(default)]
+    pub build_metadata: PipBuildMetadata,
+    /// adds an `apsw` submodule loading the extension into APSW connections
+    #[serde(default)]
+    pub apsw: bool,
+    /// adds a `django` submodule loading the extension into Django's SQLite connections
+    #[serde(default)]
+    pub django: bool,
+    /// `$OS-$CPU` -> wheel platform tags, for builds the built-in tags don't describe,
+    /// ex a newer macOS deployment target
+    #[serde(default)]
+    pub platform_tags: BTreeMap<String, WheelPlatformTags>,
+}
+
+/// Replaces or extends the platform tags of one platform's wheel
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WheelPlatformTags {
+    /// replaces the built-in tags, ex `["macosx_12_0_arm64"]`
+    pub tags: Option<Vec<String>>,
+    /// added after the tags, ex a `manylinux_2_28_x86_64` alias
+    #[serde(default)]
+    pub extra_tags: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetSqliteUtils {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub commands: Vec<SqliteUtilsCommand>,
+    /// Summary of the plugin's package
+    pub description: Option<String>,
+    /// overrides `sqlite-utils-{package.name}`
+    pub package_name: Option<String>,
+    /// PEP 508 requirements added as `Requires-Dist`, ex `sqlite-utils>=3.30`
+    #[serde(default)]
+    pub extra_dependencies: Vec<String>,
+}
+
+impl TargetSqliteUtils {
+    pub(crate) fn package_name(&self, name: &str) -> String {
+        self.package_name
+            .clone()
+            .unwrap_or_else(|| format!("sqlite-utils-{name}"))
+    }
 }
 
-#[derive(Deserialize)]
-pub struct TargetSqliteUtils {}
+/// A `sqlite-utils <name> db.sqlite [params...]` command running `sql` with the
+/// extension loaded, the params bound to its `?` placeholders
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SqliteUtilsCommand {
+    pub name: String,
+    pub sql: String,
+    pub help: Option<String>,
+}
 
-#[derive(Deserialize)]
-pub struct TargetNpm {}
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetNpm {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// overrides package.name for the npm packages, ex `@foo/sqlite`
+    pub package_name: Option<String>,
+    /// package name -> version range, added to the top-level package's dependencies
+    #[serde(default)]
+    pub extra_dependencies: HashMap<String, String>,
+}
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct TargetGem {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
     pub module_name: String,
+    /// overrides package.name for the gems
+    pub package_name: Option<String>,
+    /// gem name -> requirement, ex `sqlite3 = ">= 1.4"`
+    #[serde(default)]
+    pub extra_dependencies: BTreeMap<String, String>,
 }
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct TargetAmalgamation {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
     pub include: Vec<String>,
+    /// added as README.md, defaults to generated build instructions
+    pub readme: Option<String>,
+    /// added as LICENSE, ex `LICENSE-MIT`
+    pub license_file: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetStatic {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// extra linker flags for the generated pkg-config and CMake files, ex `["-lm"]`
+    #[serde(default)]
+    pub libs: Vec<String>,
+    /// extra compiler flags for the generated pkg-config and CMake files
+    #[serde(default)]
+    pub cflags: Vec<String>,
+    /// name passed to CMake's `find_package()`, defaults to package.name
+    pub cmake_name: Option<String>,
+}
+
+/// Per-platform archives of the loadables with a wrapper script that starts
+/// `sqlite3` with them loaded. A `sqlite3` shell in the platform directory is bundled too.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetCli {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// name of the wrapper script, defaults to package.name
+    pub command_name: Option<String>,
+}
+
+/// A `docker buildx` context building a multi-arch image of `sqlite3` with the
+/// extension loaded, from the linux platforms
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetDocker {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// glibc based image with `sqlite3` on its PATH, defaults to debian:bookworm-slim with sqlite3 installed
+    pub base_image: Option<String>,
+    /// ex `ghcr.io/foo/sqlite-foo`, defaults to package.name
+    pub image_name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArchiveFormat {
+    TarGz,
+    Zip,
 }
 
-#[derive(Deserialize)]
+fn default_archive_formats() -> Vec<ArchiveFormat> {
+    vec![ArchiveFormat::TarGz, ArchiveFormat::Zip]
+}
+
+/// Plain per-platform archives of the loadables, for installing by hand
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetArchives {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// defaults to both `["tar-gz", "zip"]`
+    #[serde(default = "default_archive_formats")]
+    pub formats: Vec<ArchiveFormat>,
+    /// added as README.md
+    pub readme: Option<String>,
+    /// added as LICENSE, ex `LICENSE-MIT`
+    pub license_file: Option<String>,
+}
+
+/// A Homebrew formula installing the GitHub release tarballs, for a tap
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetHomebrew {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// defaults to package.name
+    pub formula_name: Option<String>,
+    /// tap the formula is published in, ex `asg017/homebrew-tap`
+    pub tap: Option<String>,
+    /// also builds bottles, uploaded to the release, so brew pours them instead of
+    /// installing from the tarballs
+    #[serde(default)]
+    pub bottles: bool,
+}
+
+/// An npm package of the wasm build for Cloudflare Workers and other edge runtimes,
+/// which can't load native extensions
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetWorkers {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// defaults to the npm package name with `-workers` appended
+    pub package_name: Option<String>,
+}
+
+/// Per-platform archives of the `.dSYM`, `.pdb`, and stripped `.debug` files,
+/// for symbolicating crash reports against the released binaries
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetDebugSymbols {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// A static installation page, `docs/index.html`, for publishing to GitHub Pages
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetDocs {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// `INSTALL.md` with the install commands of every target and the release downloads
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetInstallMd {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// README relative to the spec whose `<!-- sqlite-dist install -->` ...
+    /// `<!-- /sqlite-dist install -->` section is replaced with INSTALL.md
+    pub readme: Option<String>,
+}
+
+/// shields.io endpoint badges of the version, platforms, and download size,
+/// uploaded with the release
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetBadges {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// shields.io color name or hex, defaults to blue
+    pub color: Option<String>,
+}
+
+/// `latest.json`, `latest.txt`, and `versions.json`, for auto-updaters and install.sh to poll
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetUpdateApi {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// `versions.json` of the previous release relative to the spec, the new
+    /// version is added to it. Without it versions.json only lists this version.
+    pub versions: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetXcframework {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// name of the `.xcframework` bundle, defaults to package.name
+    pub framework_name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Targets {
     pub github_releases: Option<TargetGithubRelease>,
     pub sqlpkg: Option<TargetSqlpkg>,
@@ -62,9 +524,1292 @@ pub struct Targets {
     pub npm: Option<TargetNpm>,
     pub gem: Option<TargetGem>,
     pub amalgamation: Option<TargetAmalgamation>,
+    #[serde(rename = "static")]
+    pub static_: Option<TargetStatic>,
+    pub xcframework: Option<TargetXcframework>,
+    pub cli: Option<TargetCli>,
+    pub archives: Option<TargetArchives>,
+    pub homebrew: Option<TargetHomebrew>,
+    pub workers: Option<TargetWorkers>,
+    pub docker: Option<TargetDocker>,
+    pub debug_symbols: Option<TargetDebugSymbols>,
+    pub docs: Option<TargetDocs>,
+    pub install_md: Option<TargetInstallMd>,
+    pub badges: Option<TargetBadges>,
+    pub update_api: Option<TargetUpdateApi>,
+}
+
+fn remove_if_disabled<T>(target: &mut Option<T>, enabled: impl Fn(&T) -> bool) {
+    if target.as_ref().is_some_and(|target| !enabled(target)) {
+        *target = None;
+    }
+}
+
+/// Targets that are generated together with the target they depend on
+const TARGET_DEPENDENCIES: [(&str, &str); 7] = [
+    ("sqlpkg", "github_releases"),
+    ("homebrew", "github_releases"),
+    ("update_api", "github_releases"),
+    ("spm", "github_releases"),
+    ("amalgamation", "github_releases"),
+    ("datasette", "pip"),
+    ("sqlite_utils", "pip"),
+];
+
+impl Targets {
+    pub(crate) const NAMES: [&'static str; 21] = [
+        "github_releases",
+        "sqlpkg",
+        "spm",
+        "pip",
+        "datasette",
+        "sqlite_utils",
+        "npm",
+        "gem",
+        "amalgamation",
+        "static",
+        "xcframework",
+        "cli",
+        "archives",
+        "homebrew",
+        "workers",
+        "docker",
+        "debug_symbols",
+        "docs",
+        "install_md",
+        "badges",
+        "update_api",
+    ];
+
+    /// Applies the `--only` and `--skip` flags. `--only` also keeps the targets
+    /// a selected target depends on, `--skip` also drops the skipped target's dependents.
+    pub(crate) fn select(&mut self, only: Option<&[String]>, skip: &[String]) {
+        let only: Option<Vec<&str>> = only.map(|only| {
+            let mut only: Vec<&str> = only.iter().map(String::as_str).collect();
+            for (dependent, dependency) in TARGET_DEPENDENCIES {
+                if only.contains(&dependent) {
+                    only.push(dependency);
+                }
+            }
+            only
+        });
+        let mut skip: Vec<&str> = skip.iter().map(String::as_str).collect();
+        for (dependent, dependency) in TARGET_DEPENDENCIES {
+            if skip.contains(&dependency) {
+                skip.push(dependent);
+            }
+        }
+        let keep = |name: &str| {
+            only.as_ref().is_none_or(|only| only.contains(&name)) && !skip.contains(&name)
+        };
+        remove_if_disabled(&mut self.github_releases, |_| keep("github_releases"));
+        remove_if_disabled(&mut self.sqlpkg, |_| keep("sqlpkg"));
+        remove_if_disabled(&mut self.spm, |_| keep("spm"));
+        remove_if_disabled(&mut self.pip, |_| keep("pip"));
+        remove_if_disabled(&mut self.datasette, |_| keep("datasette"));
+        remove_if_disabled(&mut self.sqlite_utils, |_| keep("sqlite_utils"));
+        remove_if_disabled(&mut self.npm, |_| keep("npm"));
+        remove_if_disabled(&mut self.gem, |_| keep("gem"));
+        remove_if_disabled(&mut self.amalgamation, |_| keep("amalgamation"));
+        remove_if_disabled(&mut self.static_, |_| keep("static"));
+        remove_if_disabled(&mut self.xcframework, |_| keep("xcframework"));
+        remove_if_disabled(&mut self.cli, |_| keep("cli"));
+        remove_if_disabled(&mut self.archives, |_| keep("archives"));
+        remove_if_disabled(&mut self.homebrew, |_| keep("homebrew"));
+        remove_if_disabled(&mut self.workers, |_| keep("workers"));
+        remove_if_disabled(&mut self.docker, |_| keep("docker"));
+        remove_if_disabled(&mut self.debug_symbols, |_| keep("debug_symbols"));
+        remove_if_disabled(&mut self.docs, |_| keep("docs"));
+        remove_if_disabled(&mut self.install_md, |_| keep("install_md"));
+        remove_if_disabled(&mut self.badges, |_| keep("badges"));
+        remove_if_disabled(&mut self.update_api, |_| keep("update_api"));
+    }
+
+    /// Drops every target with `enabled = false`, so generation only has to
+    /// check whether a target is present.
+    fn remove_disabled(&mut self) {
+        remove_if_disabled(&mut self.github_releases, |t| t.enabled);
+        remove_if_disabled(&mut self.sqlpkg, |t| t.enabled);
+        remove_if_disabled(&mut self.spm, |t| t.enabled);
+        remove_if_disabled(&mut self.pip, |t| t.enabled);
+        remove_if_disabled(&mut self.datasette, |t| t.enabled);
+        remove_if_disabled(&mut self.sqlite_utils, |t| t.enabled);
+        remove_if_disabled(&mut self.npm, |t| t.enabled);
+        remove_if_disabled(&mut self.gem, |t| t.enabled);
+        remove_if_disabled(&mut self.amalgamation, |t| t.enabled);
+        remove_if_disabled(&mut self.static_, |t| t.enabled);
+        remove_if_disabled(&mut self.xcframework, |t| t.enabled);
+        remove_if_disabled(&mut self.cli, |t| t.enabled);
+        remove_if_disabled(&mut self.archives, |t| t.enabled);
+        remove_if_disabled(&mut self.homebrew, |t| t.enabled);
+        remove_if_disabled(&mut self.workers, |t| t.enabled);
+        remove_if_disabled(&mut self.docker, |t| t.enabled);
+        remove_if_disabled(&mut self.debug_symbols, |t| t.enabled);
+        remove_if_disabled(&mut self.docs, |t| t.enabled);
+        remove_if_disabled(&mut self.install_md, |t| t.enabled);
+        remove_if_disabled(&mut self.badges, |t| t.enabled);
+        remove_if_disabled(&mut self.update_api, |t| t.enabled);
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecVerify {
+    /// SQL statement ran after loading the extension, ex `select foo_version()`
+    pub sql: String,
+    /// makes the generated loaders run `sql` after loading and fail unless it returns
+    /// the package version, catching an older copy of the extension being loaded instead
+    #[serde(default)]
+    pub runtime_check: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SpecMacos {
+    /// lipo the macos-x86_64 and macos-aarch64 builds into an extra macos-universal2 platform
+    #[serde(default)]
+    pub universal2: bool,
+    /// signs the macos loadables and `sqlite3` shell before they're packaged
+    pub sign: Option<SpecMacosSign>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MacosSigningTool {
+    /// Apple's `codesign` and `xcrun notarytool`, macOS only
+    #[default]
+    Codesign,
+    /// `rcodesign` from apple-codesign, runs on any OS
+    Rcodesign,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecMacosSign {
+    #[serde(default)]
+    pub tool: MacosSigningTool,
+    /// codesign: keychain identity, ex `Developer ID Application: Foo (TEAMID)`
+    pub identity: Option<String>,
+    /// rcodesign: PKCS#12 Developer ID certificate relative to the spec, with
+    /// its password in `SQLITE_DIST_P12_PASSWORD`
+    pub p12_file: Option<String>,
+    /// submits the signed binaries to Apple's notary service and waits for the result
+    pub notarize: Option<SpecNotarize>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecNotarize {
+    /// codesign: profile stored with `xcrun notarytool store-credentials`
+    pub keychain_profile: Option<String>,
+    /// rcodesign: App Store Connect API key from `rcodesign encode-app-store-connect-api-key`, relative to the spec
+    pub api_key_file: Option<String>,
+}
+
+/// The SQLite the extension runs on
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecSqlite {
+    /// minimum SQLite version the extension requires, ex `3.41.0`, added to the package metadata
+    pub min_version: Option<Version>,
+    /// makes the generated loaders check the connection's SQLite version before loading
+    #[serde(default)]
+    pub runtime_check: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SpecWindows {
+    /// Authenticode signs the windows loadables and `sqlite3.exe` before they're packaged
+    pub sign: Option<SpecWindowsSign>,
+}
+
+/// Either a `command` ran for every binary, or a `certificate` signed with osslsigncode
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecWindowsSign {
+    /// ran from the spec's directory with `{file}` replaced by the binary, which is
+    /// signed in place, ex `["signtool", "sign", "/a", "/fd", "sha256", "{file}"]`
+    pub command: Option<Vec<String>>,
+    /// PKCS#12 code signing certificate relative to the spec, with its password in
+    /// `SQLITE_DIST_PFX_PASSWORD`
+    pub certificate: Option<String>,
+    /// RFC 3161 timestamp server, defaults to http://timestamp.digicert.com
+    pub timestamp_url: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecExtension {
+    /// file stem of the extension's loadable in every platform directory, ex `foo0`
+    pub name: String,
+    /// When set, the extension gets its own packages under this name instead of
+    /// being combined into the main package.
+    pub package: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputLayout {
+    /// `$OUTPUT/pip/*.whl`, `$OUTPUT/npm/*.tar.gz`, ...
+    #[default]
+    ByTarget,
+    /// every asset directly in the output directory
+    Flat,
+    /// `$OUTPUT/$OS-$CPU/*`, with platform independent assets in the output directory
+    ByPlatform,
+}
+
+/// Variables `output.file_name` can use
+pub(crate) const FILE_NAME_VARIABLES: [&str; 6] = ["name", "version", "type", "os", "cpu", "ext"];
+
+/// Variables `package.asset_url_template` can use
+pub(crate) const ASSET_URL_VARIABLES: [&str; 4] = ["name", "version", "tag", "file"];
+
+/// Checks every `{variable}` of the `field` template is one of `variables`
+fn check_template_variables(
+    field: &str,
+    template: &str,
+    variables: &[&str],
+) -> Result<(), SpecError> {
+    let mut rest = template;
+    while let Some((_, after)) = rest.split_once('{') {
+        let (variable, after) = after.split_once('}').unwrap_or((after, ""));
+        if !variables.contains(&variable) {
+            return Err(invalid(
+                field,
+                format!(
+                    "{field} has unknown variable '{{{variable}}}', must be one of {}",
+                    variables.join(", ")
+                ),
+            ));
+        }
+        rest = after;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SpecOutput {
+    #[serde(default)]
+    pub layout: OutputLayout,
+    /// file name of the GitHub release archives, ex `{name}-{version}-{type}-{os}-{cpu}.{ext}`.
+    /// `type` is loadable, static, or wasm.
+    pub file_name: Option<String>,
+}
+
+/// Rules normalizing the loadables' file names before anything is packaged,
+/// so entrypoints are the same on every platform
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SpecLoadables {
+    /// `libfoo0.so` -> `foo0.so`, for build systems adding the prefix on unix
+    #[serde(default)]
+    pub strip_lib_prefix: bool,
+    /// gives every loadable its platform's extension, `.dll` on windows, `.dylib` on macos and ios, `.so` elsewhere
+    #[serde(default)]
+    pub normalize_extension: bool,
+    /// file stem -> new file stem, applied after the other rules, ex `foo = "foo0"`
+    #[serde(default)]
+    pub rename: BTreeMap<String, String>,
+    /// strips DWARF and static symbols from ELF loadables, the debug_symbols target releases the originals as `.debug` files
+    #[serde(default)]
+    pub strip: bool,
+    /// file stem -> init function the loaders pass to `load_extension()`, for loadables
+    /// SQLite can't derive it from the file name of, ex `vec0 = "sqlite3_vec_init"`
+    #[serde(default)]
+    pub init_functions: BTreeMap<String, String>,
+}
+
+/// A file bundled next to the loadables in every platform's packages, ex a NOTICE or a model
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecExtraFile {
+    /// relative to the spec
+    pub path: String,
+    /// path inside the packages, defaults to the file name, ex `models/foo.bin`
+    pub name: Option<String>,
+    /// `$OS-$CPU` platforms to bundle it for, defaults to every platform
+    #[serde(default)]
+    pub platforms: Vec<String>,
+}
+
+impl SpecExtraFile {
+    pub(crate) fn package_path(&self) -> &str {
+        self.name.as_deref().unwrap_or_else(|| {
+            Path::new(&self.path)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or(&self.path)
+        })
+    }
+}
+
+/// An append-only log of the checksums of every release, for clients to detect
+/// assets of a past release being replaced
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecChecksumLog {
+    /// the log of the previous release, a path or URL, ex
+    /// `https://github.com/foo/sqlite-foo/releases/latest/download/checksums.log`
+    pub previous: Option<String>,
+    /// OpenSSH private key the log is signed with into `checksums.log.sig`
+    pub signing_key: Option<String>,
 }
-#[derive(Deserialize)]
+
+/// An external command generating assets of its own. It reads a JSON description
+/// of the project on stdin and writes its assets to the `output_dir` it's given.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecPlugin {
+    /// target name for `--only`/`--skip`, and the kind of its assets
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// executable and arguments, ran from the spec's directory, ex `["python3", "deb.py"]`
+    pub command: Vec<String>,
+    /// directory under the output directory the assets go in, defaults to `name`
+    pub output_dir: Option<String>,
+    /// passed to the command as-is
+    pub config: Option<toml::Value>,
+    /// MIME type of the assets, guessed from their extensions by default
+    pub media_type: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Spec {
     pub package: SpecPackage,
+    #[serde(default)]
+    pub extensions: Vec<SpecExtension>,
     pub targets: Targets,
+    pub verify: Option<SpecVerify>,
+    pub macos: Option<SpecMacos>,
+    pub windows: Option<SpecWindows>,
+    pub sqlite: Option<SpecSqlite>,
+    /// directory of Tera templates overriding the built-in generated text files
+    pub templates: Option<String>,
+    #[serde(default)]
+    pub plugins: Vec<SpecPlugin>,
+    pub output: Option<SpecOutput>,
+    pub checksum_log: Option<SpecChecksumLog>,
+    #[serde(default)]
+    pub extra_files: Vec<SpecExtraFile>,
+    pub loadables: Option<SpecLoadables>,
+    /// other sqlite-dist extensions this one needs, loaded before it
+    #[serde(default)]
+    pub requires: Vec<SpecRequire>,
+    /// distribution channels selected with `--profile`, ex `[profiles.nightly]`
+    #[serde(default)]
+    pub profiles: BTreeMap<String, SpecProfile>,
+    /// broken releases to retract: marked in versions.json, deprecated on npm and
+    /// yanked from RubyGems by `publish`
+    #[serde(default)]
+    pub yanked: Vec<Version>,
+    /// SQL functions and virtual tables the extension registers, exported as
+    /// constants with types from the npm and pip packages and listed in capabilities.json
+    #[serde(default)]
+    pub functions: Vec<SpecFunction>,
+    /// collating sequences the extension registers, listed in capabilities.json
+    #[serde(default)]
+    pub collations: Vec<SpecCollation>,
+    /// largest asset each target may generate, by target name, ex `pip = "100MB"`.
+    /// pip and github_releases default to PyPI's and GitHub's upload limits
+    #[serde(default)]
+    pub size_limits: BTreeMap<String, ByteSize>,
+    pub post_install: Option<SpecPostInstall>,
+    pub rename: Option<SpecRename>,
+}
+
+/// A size in bytes, written as a number or with a unit, ex `"100MB"` or `"2GiB"`
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    fn parse(s: &str) -> Option<ByteSize> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: f64 = number.parse().ok()?;
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" => 1000,
+            "kib" => 1 << 10,
+            "mb" => 1000 * 1000,
+            "mib" => 1 << 20,
+            "gb" => 1000 * 1000 * 1000,
+            "gib" => 1 << 30,
+            _ => return None,
+        };
+        Some(ByteSize((number * multiplier as f64) as u64))
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bytes(u64),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Bytes(bytes) => Ok(ByteSize(bytes)),
+            Raw::Text(text) => ByteSize::parse(&text).ok_or_else(|| {
+                serde::de::Error::custom(format!(
+                    "invalid size '{text}', expected a number of bytes or ex \"100MB\", \"2GiB\""
+                ))
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let units = [(1 << 30, "GiB"), (1 << 20, "MiB"), (1 << 10, "KiB")];
+        match units.iter().find(|(size, _)| self.0 >= *size) {
+            Some((size, unit)) => write!(f, "{:.1} {unit}", self.0 as f64 / *size as f64),
+            None => write!(f, "{} bytes", self.0),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SqlFunctionKind {
+    #[default]
+    Scalar,
+    Aggregate,
+    Window,
+    /// table-valued function, ex `select * from json_each(?)`
+    Table,
+    /// virtual table module, ex `create virtual table t using fts5(...)`
+    Module,
+}
+
+impl SqlFunctionKind {
+    pub(crate) fn description(&self) -> &'static str {
+        match self {
+            SqlFunctionKind::Scalar => "scalar function",
+            SqlFunctionKind::Aggregate => "aggregate function",
+            SqlFunctionKind::Window => "window function",
+            SqlFunctionKind::Table => "table-valued function",
+            SqlFunctionKind::Module => "virtual table module",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecFunction {
+    pub name: String,
+    #[serde(default)]
+    pub kind: SqlFunctionKind,
+    /// argument names, ex `["vector", "i"]`
+    #[serde(default)]
+    pub arguments: Vec<String>,
+    pub description: Option<String>,
+}
+
+impl SpecFunction {
+    /// How it's called from SQL, ex `vec_slice(vector, start, end)`
+    pub(crate) fn signature(&self) -> String {
+        match self.kind {
+            SqlFunctionKind::Module => format!("create virtual table x using {}(...)", self.name),
+            _ => format!("{}({})", self.name, self.arguments.join(", ")),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecCollation {
+    /// as used in `collate`, ex `unicode_nocase`
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// Another extension published with sqlite-dist that has to be loaded first. The
+/// packages depend on its packages, and their `load()` loads it before this extension.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecRequire {
+    /// package.name of the other extension, ex `sqlite-vec`
+    pub name: String,
+    /// oldest compatible version
+    pub min_version: Version,
+    /// overrides name on PyPI
+    pub pip_name: Option<String>,
+    /// overrides name on npm
+    pub npm_name: Option<String>,
+    /// overrides name on RubyGems
+    pub gem_name: Option<String>,
+    /// module of the gem's `lib/*.rb`, defaults to the gem name in CamelCase, ex `SqliteVec`
+    pub gem_module: Option<String>,
+}
+
+impl SpecRequire {
+    pub(crate) fn pip_name(&self) -> &str {
+        self.pip_name.as_deref().unwrap_or(&self.name)
+    }
+    /// Module the wheel installs, like `PipPackage::python_package_name`
+    pub(crate) fn python_module(&self) -> String {
+        self.pip_name().replace('-', "_")
+    }
+    pub(crate) fn npm_name(&self) -> &str {
+        self.npm_name.as_deref().unwrap_or(&self.name)
+    }
+    pub(crate) fn gem_name(&self) -> &str {
+        self.gem_name.as_deref().unwrap_or(&self.name)
+    }
+    pub(crate) fn gem_module(&self) -> String {
+        self.gem_module.clone().unwrap_or_else(|| {
+            self.gem_name()
+                .split(['-', '_'])
+                .map(|part| {
+                    let mut chars = part.chars();
+                    chars.next().map_or(String::new(), |first| {
+                        first.to_ascii_uppercase().to_string() + chars.as_str()
+                    })
+                })
+                .collect()
+        })
+    }
+}
+
+/// A short note shown after installing, ex "load it with `.load ./vec0`, see
+/// https://example.com/docs". `message` is used by every target without its own.
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SpecPostInstall {
+    pub message: Option<String>,
+    /// printed by the package's `postinstall` script
+    pub npm: Option<String>,
+    /// the gem's post_install_message
+    pub gem: Option<String>,
+    /// added to the formula's caveats
+    pub homebrew: Option<String>,
+}
+
+/// The package's previous name. The pip target also builds a wheel under the old
+/// name depending on the new one, and `publish npm` deprecates the old npm package.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecRename {
+    /// previous package.name
+    pub from: String,
+    /// overrides from on PyPI
+    pub pip_name: Option<String>,
+    /// overrides from on npm
+    pub npm_name: Option<String>,
+    /// shown by the deprecation and on import, defaults to "$FROM has been renamed to $NAME"
+    pub message: Option<String>,
+}
+
+impl SpecRename {
+    pub(crate) fn pip_name(&self) -> &str {
+        self.pip_name.as_deref().unwrap_or(&self.from)
+    }
+    pub(crate) fn npm_name(&self) -> &str {
+        self.npm_name.as_deref().unwrap_or(&self.from)
+    }
+}
+
+/// Overrides for one distribution channel, so nightly or internal builds come
+/// from the same spec as releases
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpecProfile {
+    /// pre-release appended to the version, ex `nightly` builds 1.2.3 as
+    /// `1.2.3-nightly`, and 1.2.3-alpha.1 as `1.2.3-alpha.1.nightly`
+    pub version_suffix: Option<String>,
+    /// only these targets are generated, ex `["github_releases", "npm"]`
+    pub targets: Option<Vec<String>>,
+    /// overrides package.asset_url_template
+    pub asset_url_template: Option<String>,
+    /// `false` leaves the binaries unsigned, ignoring macos.sign and windows.sign
+    #[serde(default = "default_enabled")]
+    pub sign: bool,
+    /// replaces macos.sign
+    pub macos_sign: Option<SpecMacosSign>,
+    /// replaces windows.sign
+    pub windows_sign: Option<SpecWindowsSign>,
+}
+
+/// Where a package name is set, its key, the name, and the rules of its registry
+type NameCheck<'a> = (&'a str, &'a str, String, fn(&str) -> Option<String>);
+
+impl Spec {
+    /// Applies the overrides of `[profiles.<name>]`, and returns it for the
+    /// version suffix and targets, which the build applies
+    pub(crate) fn apply_profile(&mut self, name: &str) -> Result<SpecProfile, SpecError> {
+        let profile = self.profiles.get(name).cloned().ok_or_else(|| {
+            SpecError::InvalidSpec(format!(
+                "unknown profile '{name}', the spec has {}",
+                if self.profiles.is_empty() {
+                    "no [profiles]".to_owned()
+                } else {
+                    self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                }
+            ))
+        })?;
+        if let Some(template) = &profile.asset_url_template {
+            self.package.asset_url_template = Some(template.clone());
+        }
+        if let Some(sign) = &profile.macos_sign {
+            self.macos.get_or_insert_with(Default::default).sign = Some(sign.clone());
+        }
+        if let Some(sign) = &profile.windows_sign {
+            self.windows.get_or_insert_with(Default::default).sign = Some(sign.clone());
+        }
+        if !profile.sign {
+            if let Some(macos) = &mut self.macos {
+                macos.sign = None;
+            }
+            if let Some(windows) = &mut self.windows {
+                windows.sign = None;
+            }
+        }
+        self.validate()?;
+        Ok(profile)
+    }
+
+    /// Init function of the loadable with `file_stem`, when `loadables.init_functions` declares one
+    pub(crate) fn init_function(&self, file_stem: &str) -> Option<&str> {
+        self.loadables
+            .as_ref()?
+            .init_functions
+            .get(file_stem)
+            .map(String::as_str)
+    }
+
+    /// `(file stem, init function)` of the `entrypoints` declaring an init function
+    pub(crate) fn init_functions<'a>(&'a self, entrypoints: &[&'a str]) -> Vec<(&'a str, &'a str)> {
+        entrypoints
+            .iter()
+            .filter_map(|entrypoint| Some((*entrypoint, self.init_function(entrypoint)?)))
+            .collect()
+    }
+
+    pub(crate) fn min_sqlite_version(&self) -> Option<&Version> {
+        self.sqlite.as_ref()?.min_version.as_ref()
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self, SpecError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| SpecError::IOError(path.to_path_buf(), err))?;
+        let mut spec: Spec = toml::from_str(&contents)?;
+        spec.targets.remove_disabled();
+        spec.plugins.retain(|plugin| plugin.enabled);
+        spec.validate().map_err(|err| err.located(&contents))?;
+        Ok(spec)
+    }
+
+    /// The deprecation message of the package's previous name
+    pub(crate) fn rename_message(&self) -> Option<String> {
+        let rename = self.rename.as_ref()?;
+        Some(rename.message.clone().unwrap_or_else(|| {
+            format!(
+                "{} has been renamed to {}, install {} instead",
+                rename.from, self.package.name, self.package.name
+            )
+        }))
+    }
+
+    /// The post-install note of `target`, one of npm, gem, or homebrew
+    pub(crate) fn post_install_message(&self, target: &str) -> Option<&str> {
+        let post_install = self.post_install.as_ref()?;
+        let message = match target {
+            "npm" => &post_install.npm,
+            "gem" => &post_install.gem,
+            "homebrew" => &post_install.homebrew,
+            _ => &None,
+        };
+        message
+            .as_ref()
+            .or(post_install.message.as_ref())
+            .map(String::as_str)
+    }
+
+    /// Checks the name every enabled target publishes under against its registry's
+    /// rules, so a bad name fails here instead of at upload
+    fn check_package_names(&self) -> Result<(), SpecError> {
+        let name = &self.package.name;
+        let targets = &self.targets;
+        // the key the name comes from, the override when it's set
+        let key = |overridden: bool, key: &'static str, default: &'static str| {
+            if overridden {
+                key
+            } else {
+                default
+            }
+        };
+        let npm_name = targets
+            .npm
+            .as_ref()
+            .and_then(|npm| npm.package_name.clone())
+            .unwrap_or_else(|| name.clone());
+        let npm_key = key(
+            targets
+                .npm
+                .as_ref()
+                .is_some_and(|npm| npm.package_name.is_some()),
+            "targets.npm.package_name",
+            "package.name",
+        );
+        let mut names: Vec<NameCheck> = vec![];
+        if let Some(pip) = &targets.pip {
+            let pip_name = pip.package_name.clone().unwrap_or_else(|| name.clone());
+            let pip_key = key(
+                pip.package_name.is_some(),
+                "targets.pip.package_name",
+                "package.name",
+            );
+            if let Some(rename) = &self.rename {
+                let rename_key = key(rename.pip_name.is_some(), "rename.pip_name", "rename.from");
+                if normalize_pypi_name(rename.pip_name()) == normalize_pypi_name(&pip_name) {
+                    return Err(invalid(
+                        rename_key,
+                        format!(
+                            "rename.pip_name '{}' is the same PyPI project as '{pip_name}'",
+                            rename.pip_name()
+                        ),
+                    ));
+                }
+                names.push((
+                    "rename.pip_name",
+                    rename_key,
+                    rename.pip_name().to_owned(),
+                    pypi_name_problem,
+                ));
+            }
+            names.push(("pip target", pip_key, pip_name, pypi_name_problem));
+        }
+        if let Some(datasette) = &targets.datasette {
+            names.push((
+                "datasette target",
+                key(
+                    datasette.package_name.is_some(),
+                    "targets.datasette.package_name",
+                    "package.name",
+                ),
+                datasette.package_name(name),
+                pypi_name_problem,
+            ));
+        }
+        if let Some(sqlite_utils) = &targets.sqlite_utils {
+            names.push((
+                "sqlite_utils target",
+                key(
+                    sqlite_utils.package_name.is_some(),
+                    "targets.sqlite_utils.package_name",
+                    "package.name",
+                ),
+                sqlite_utils.package_name(name),
+                pypi_name_problem,
+            ));
+        }
+        if targets.npm.is_some() {
+            if let Some(rename) = &self.rename {
+                names.push((
+                    "rename.npm_name",
+                    key(rename.npm_name.is_some(), "rename.npm_name", "rename.from"),
+                    rename.npm_name().to_owned(),
+                    npm_name_problem,
+                ));
+            }
+            names.push(("npm target", npm_key, npm_name.clone(), npm_name_problem));
+        }
+        if let Some(workers) = &targets.workers {
+            let workers_name = workers
+                .package_name
+                .clone()
+                .unwrap_or_else(|| format!("{npm_name}-workers"));
+            names.push((
+                "workers target",
+                key(
+                    workers.package_name.is_some(),
+                    "targets.workers.package_name",
+                    npm_key,
+                ),
+                workers_name,
+                npm_name_problem,
+            ));
+        }
+        if let Some(gem) = &targets.gem {
+            let gem_name = gem.package_name.clone().unwrap_or_else(|| name.clone());
+            names.push((
+                "gem target",
+                key(
+                    gem.package_name.is_some(),
+                    "targets.gem.package_name",
+                    "package.name",
+                ),
+                gem_name,
+                gem_name_problem,
+            ));
+        }
+        if let Some(homebrew) = &targets.homebrew {
+            let formula_name = homebrew
+                .formula_name
+                .clone()
+                .unwrap_or_else(|| name.clone());
+            names.push((
+                "homebrew target",
+                key(
+                    homebrew.formula_name.is_some(),
+                    "targets.homebrew.formula_name",
+                    "package.name",
+                ),
+                formula_name,
+                formula_name_problem,
+            ));
+        }
+        for (source, key, name, problem) in names {
+            if let Some(problem) = problem(&name) {
+                return Err(invalid(key, format!("{source}: {problem}")));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), SpecError> {
+        if self.package.name.is_empty() {
+            return Err(invalid("package.name", "package.name must not be empty"));
+        }
+        if let Some(rename) = &self.rename {
+            if rename.from.is_empty() || rename.from == self.package.name {
+                return Err(invalid(
+                    "rename.from",
+                    "rename.from must be the package's previous name",
+                ));
+            }
+        }
+        if self.package.authors.is_empty() {
+            return Err(invalid(
+                "package.authors",
+                "package.authors must list at least one author",
+            ));
+        }
+        for (i, extension) in self.extensions.iter().enumerate() {
+            if self
+                .extensions
+                .iter()
+                .filter(|e| e.name == extension.name)
+                .count()
+                > 1
+            {
+                return Err(invalid(
+                    format!("extensions.{i}.name"),
+                    format!("extension '{}' is declared more than once", extension.name),
+                ));
+            }
+        }
+        self.check_package_names()?;
+        let targets = &self.targets;
+        if targets.sqlpkg.is_some() && targets.github_releases.is_none() {
+            return Err(invalid(
+                "targets.sqlpkg",
+                "sqlpkg target requires the github_releases target",
+            ));
+        }
+        if targets.spm.is_some() && targets.github_releases.is_none() {
+            return Err(invalid(
+                "targets.spm",
+                "spm target requires the github_releases target",
+            ));
+        }
+        if targets.homebrew.is_some() && targets.github_releases.is_none() {
+            return Err(invalid(
+                "targets.homebrew",
+                "homebrew target requires the github_releases target",
+            ));
+        }
+        if targets.datasette.is_some() && targets.pip.is_none() {
+            return Err(invalid(
+                "targets.datasette",
+                "datasette target requires the pip target",
+            ));
+        }
+        if targets.sqlite_utils.is_some() && targets.pip.is_none() {
+            return Err(invalid(
+                "targets.sqlite_utils",
+                "sqlite_utils target requires the pip target",
+            ));
+        }
+        for (platform, platform_tags) in targets.pip.iter().flat_map(|pip| &pip.platform_tags) {
+            let tags = platform_tags.tags.iter().flatten();
+            if platform_tags.tags.as_ref().is_some_and(Vec::is_empty) {
+                return Err(invalid(
+                    format!("targets.pip.platform_tags.{platform}.tags"),
+                    format!("pip.platform_tags.{platform}.tags must not be empty"),
+                ));
+            }
+            for tag in tags.chain(&platform_tags.extra_tags) {
+                let valid = !tag.is_empty()
+                    && tag
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+                if !valid {
+                    return Err(invalid(
+                        format!("targets.pip.platform_tags.{platform}"),
+                        format!("pip.platform_tags.{platform} tag '{tag}' must be lowercase letters, digits or _"),
+                    ));
+                }
+            }
+        }
+        for (i, command) in targets
+            .sqlite_utils
+            .iter()
+            .flat_map(|s| &s.commands)
+            .enumerate()
+        {
+            let valid = !command.name.is_empty()
+                && command
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                return Err(invalid(
+                    format!("targets.sqlite_utils.commands.{i}.name"),
+                    format!(
+                        "sqlite_utils command name '{}' must be letters, digits, - or _",
+                        command.name
+                    ),
+                ));
+            }
+        }
+        if let Some(file_name) = self.output.as_ref().and_then(|o| o.file_name.as_ref()) {
+            check_template_variables("output.file_name", file_name, &FILE_NAME_VARIABLES)?;
+            if !file_name.contains("{os}") || !file_name.contains("{cpu}") {
+                return Err(invalid(
+                    "output.file_name",
+                    "output.file_name must contain {os} and {cpu}",
+                ));
+            }
+        }
+        if let Some(template) = &self.package.asset_url_template {
+            check_template_variables("package.asset_url_template", template, &ASSET_URL_VARIABLES)?;
+            // sqlpkg points at the directory of the assets, the URL without its file
+            if !template.ends_with("/{file}") || template.matches("{file}").count() != 1 {
+                return Err(invalid(
+                    "package.asset_url_template",
+                    "package.asset_url_template must end with {file} as its last path segment, ex https://downloads.example.com/{name}/{tag}/{file}",
+                ));
+            }
+        }
+        if let Some(sign) = self.macos.as_ref().and_then(|macos| macos.sign.as_ref()) {
+            let notarize = sign.notarize.as_ref();
+            let missing = match sign.tool {
+                MacosSigningTool::Codesign if sign.identity.is_none() => Some("identity"),
+                MacosSigningTool::Rcodesign if sign.p12_file.is_none() => Some("p12_file"),
+                MacosSigningTool::Codesign
+                    if notarize.is_some_and(|n| n.keychain_profile.is_none()) =>
+                {
+                    Some("notarize.keychain_profile")
+                }
+                MacosSigningTool::Rcodesign
+                    if notarize.is_some_and(|n| n.api_key_file.is_none()) =>
+                {
+                    Some("notarize.api_key_file")
+                }
+                _ => None,
+            };
+            if let Some(missing) = missing {
+                return Err(invalid(
+                    "macos.sign",
+                    format!(
+                        "macos.sign.{missing} is required with the {} tool",
+                        match sign.tool {
+                            MacosSigningTool::Codesign => "codesign",
+                            MacosSigningTool::Rcodesign => "rcodesign",
+                        }
+                    ),
+                ));
+            }
+        }
+        if let Some(sign) = self
+            .windows
+            .as_ref()
+            .and_then(|windows| windows.sign.as_ref())
+        {
+            match (&sign.command, &sign.certificate) {
+                (Some(command), None) => {
+                    if command.is_empty() || !command.iter().any(|arg| arg.contains("{file}")) {
+                        return Err(invalid(
+                            "windows.sign.command",
+                            "windows.sign.command must pass {file} to a command",
+                        ));
+                    }
+                }
+                (None, Some(_)) => {}
+                _ => {
+                    return Err(invalid(
+                        "windows.sign",
+                        "windows.sign needs exactly one of command or certificate",
+                    ))
+                }
+            }
+        }
+        for target in self.size_limits.keys() {
+            if !Targets::NAMES.contains(&target.as_str())
+                && !self.plugins.iter().any(|plugin| &plugin.name == target)
+            {
+                return Err(invalid(
+                    format!("size_limits.{target}"),
+                    format!("size_limits.{target} is not a target"),
+                ));
+            }
+        }
+        for (i, function) in self.functions.iter().enumerate() {
+            // also used as JS property and Python attribute names
+            let valid = function
+                .name
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && function
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Err(invalid(
+                    format!("functions.{i}.name"),
+                    format!(
+                        "function name '{}' must be letters, digits or _",
+                        function.name
+                    ),
+                ));
+            }
+            if self
+                .functions
+                .iter()
+                .filter(|f| f.name == function.name)
+                .count()
+                > 1
+            {
+                return Err(invalid(
+                    format!("functions.{i}.name"),
+                    format!("function '{}' is declared more than once", function.name),
+                ));
+            }
+        }
+        for (i, collation) in self.collations.iter().enumerate() {
+            if collation.name.is_empty() || collation.name.contains(char::is_whitespace) {
+                return Err(invalid(
+                    format!("collations.{i}.name"),
+                    format!(
+                        "collation name '{}' must be non-empty without whitespace",
+                        collation.name
+                    ),
+                ));
+            }
+            if self
+                .collations
+                .iter()
+                .filter(|c| c.name.eq_ignore_ascii_case(&collation.name))
+                .count()
+                > 1
+            {
+                return Err(invalid(
+                    format!("collations.{i}.name"),
+                    format!("collation '{}' is declared more than once", collation.name),
+                ));
+            }
+        }
+        for (file_stem, init_function) in self
+            .loadables
+            .iter()
+            .flat_map(|loadables| &loadables.init_functions)
+        {
+            let valid = init_function
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && init_function
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Err(invalid(
+                    format!("loadables.init_functions.{file_stem}"),
+                    format!(
+                        "loadables.init_functions.{file_stem} '{init_function}' must be a C identifier"
+                    ),
+                ));
+            }
+        }
+        for (i, extra_file) in self.extra_files.iter().enumerate() {
+            let package_path = Path::new(extra_file.package_path());
+            let relative = package_path
+                .components()
+                .all(|component| matches!(component, std::path::Component::Normal(_)));
+            if !relative {
+                return Err(invalid(
+                    format!("extra_files.{i}"),
+                    format!(
+                        "extra file name '{}' must be a relative path inside the package",
+                        package_path.display()
+                    ),
+                ));
+            }
+        }
+        for (i, plugin) in self.plugins.iter().enumerate() {
+            if plugin.name.is_empty() || Targets::NAMES.contains(&plugin.name.as_str()) {
+                return Err(invalid(
+                    format!("plugins.{i}.name"),
+                    format!(
+                        "plugin name '{}' must not be empty or a built-in target",
+                        plugin.name
+                    ),
+                ));
+            }
+            if self
+                .plugins
+                .iter()
+                .filter(|p| p.name == plugin.name)
+                .count()
+                > 1
+            {
+                return Err(invalid(
+                    format!("plugins.{i}.name"),
+                    format!("plugin '{}' is declared more than once", plugin.name),
+                ));
+            }
+            if plugin.command.is_empty() {
+                return Err(invalid(
+                    format!("plugins.{i}"),
+                    format!("plugin '{}' must have a command", plugin.name),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::{Spec, SpecError};
+
+    const PACKAGE: &str = r#"[package]
+name = "sqlite-sample"
+license = "MIT"
+homepage = "https://example.com"
+repo = "https://github.com/example/sqlite-sample"
+description = "A sample SQLite extension"
+authors = ["Sample Author"]
+"#;
+
+    /// Loads `contents` through a spec file, the way the commands do
+    fn load(name: &str, contents: &str) -> Result<Spec, SpecError> {
+        let path = env::temp_dir().join(format!(
+            "sqlite-dist-spec-{name}-{}.toml",
+            std::process::id()
+        ));
+        fs::write(&path, contents).unwrap();
+        let spec = Spec::from_path(&path);
+        fs::remove_file(&path).unwrap();
+        spec
+    }
+
+    #[test]
+    fn validation_errors_point_at_their_key() {
+        let spec = format!(
+            "{PACKAGE}\n[targets]\npip = {{}}\n\n[[functions]]\nname = \"sample_version\"\n\n[[functions]]\nname = \"sample-get\"\n"
+        );
+        let err = load("function-name", &spec).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            r#"invalid value at line 16, column 8
+   |
+16 | name = "sample-get"
+   |        ^^^^^^^^^^^^
+function name 'sample-get' must be letters, digits or _
+"#
+        );
+
+        // a target missing the target it requires is pointed at
+        let spec = format!("{PACKAGE}\n[targets]\nsqlpkg = {{}}\n");
+        let err = load("requires", &spec).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid value at line 10, column 10\n   |\n10 | sqlpkg = {}\n   |          ^^\nsqlpkg target requires the github_releases target\n"
+        );
+    }
+
+    fn targets(spec: &Spec) -> Vec<&'static str> {
+        let json = serde_json::to_value(&spec.targets).unwrap();
+        super::Targets::NAMES
+            .into_iter()
+            .filter(|name| !json[name].is_null())
+            .collect()
+    }
+
+    #[test]
+    fn select_keeps_dependencies_and_drops_dependents() {
+        let spec = format!(
+            "{PACKAGE}\n[targets]\ngithub_releases = {{}}\nsqlpkg = {{}}\npip = {{}}\ndatasette = {{}}\nnpm = {{}}\n"
+        );
+        let spec = load("select", &spec).unwrap();
+        assert_eq!(
+            targets(&spec),
+            ["github_releases", "sqlpkg", "pip", "datasette", "npm"]
+        );
+
+        let mut only = spec.clone();
+        only.targets.select(Some(&["sqlpkg".to_owned()]), &[]);
+        assert_eq!(targets(&only), ["github_releases", "sqlpkg"]);
+
+        let mut skip = spec.clone();
+        skip.targets.select(None, &["pip".to_owned()]);
+        assert_eq!(targets(&skip), ["github_releases", "sqlpkg", "npm"]);
+
+        let mut both = spec;
+        both.targets.select(
+            Some(&["datasette".to_owned(), "npm".to_owned()]),
+            &["npm".to_owned()],
+        );
+        assert_eq!(targets(&both), ["pip", "datasette"]);
+    }
+
+    #[test]
+    fn apply_profile() {
+        let spec = format!(
+            r#"{PACKAGE}
+[targets]
+pip = {{}}
+
+[macos.sign]
+identity = "Developer ID Application: Example (TEAMID)"
+
+[profiles.nightly]
+version_suffix = "nightly"
+targets = ["pip"]
+asset_url_template = "https://downloads.example.com/{{name}}/{{tag}}/{{file}}"
+sign = false
+"#
+        );
+        let mut spec = load("profile", &spec).unwrap();
+        let err = spec.clone().apply_profile("beta").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "unknown profile 'beta', the spec has nightly"
+        );
+
+        let profile = spec.apply_profile("nightly").unwrap();
+        assert_eq!(profile.version_suffix.as_deref(), Some("nightly"));
+        assert_eq!(profile.targets, Some(vec!["pip".to_owned()]));
+        assert_eq!(
+            spec.package.asset_url_template.as_deref(),
+            Some("https://downloads.example.com/{name}/{tag}/{file}")
+        );
+        assert!(spec.macos.unwrap().sign.is_none());
+    }
 }
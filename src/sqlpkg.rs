@@ -8,7 +8,9 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Asset {
-    //path:
+    /// base URL of the files, only set when they aren't on the repo's GitHub releases
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
     pub pattern: Option<String>,
     pub files: HashMap<String, String>,
     pub checksums: HashMap<String, String>,
@@ -42,6 +44,17 @@ pub(crate) fn write_sqlpkg(project: &Project, sqlpkg_dir: &Path) -> Result<Vec<G
         keywords: vec![], // TODO keywords in spec?
         symbols: None,
         assets: Asset {
+            path: project
+                .spec
+                .package
+                .asset_url_template
+                .as_ref()
+                .and_then(|_| {
+                    project
+                        .release_download_url("")
+                        .strip_suffix('/')
+                        .map(str::to_owned)
+                }),
             pattern: None,
             files: HashMap::new(),
             checksums: HashMap::new(),
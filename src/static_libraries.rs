@@ -0,0 +1,207 @@
+use std::io::Result;
+use std::path::Path;
+
+use crate::spec::TargetStatic;
+use crate::{
+    create_targz, create_zip, GeneratedAsset, GeneratedAssetKind, Os, PlatformDirectory,
+    PlatformFile, Project,
+};
+
+/// Linker name of a static library file, ex `libfoo0.a` -> `foo0`, `foo0.lib` -> `foo0`
+fn link_name(file_name: &str) -> &str {
+    let stem = file_name
+        .rsplit_once('.')
+        .map_or(file_name, |(stem, _extension)| stem);
+    if file_name.ends_with(".a") {
+        stem.strip_prefix("lib").unwrap_or(stem)
+    } else {
+        stem
+    }
+}
+
+/// Static libraries under `lib/` and headers under `include/`, the layout
+/// C build systems expect when pointed at an extracted archive.
+fn static_archive_files(
+    project: &Project,
+    platform_dir: &PlatformDirectory,
+    config: &TargetStatic,
+) -> Vec<PlatformFile> {
+    let libs = platform_dir.static_files.iter().map(|file| {
+        PlatformFile::new(
+            format!("lib/{}", file.name),
+            file.data.clone(),
+            file.metadata.clone(),
+        )
+    });
+    let headers = platform_dir.header_files.iter().map(|file| {
+        PlatformFile::new(
+            format!("include/{}", file.name),
+            file.data.clone(),
+            file.metadata.clone(),
+        )
+    });
+    let link_names: Vec<&str> = platform_dir
+        .static_files
+        .iter()
+        .map(|file| link_name(&file.name))
+        .collect();
+    let pkg_config = PlatformFile::new(
+        format!("lib/pkgconfig/{}.pc", project.spec.package.name),
+        templates::pkg_config(project, &link_names, config),
+        None,
+    );
+    let cmake_name = config
+        .cmake_name
+        .as_deref()
+        .unwrap_or(&project.spec.package.name);
+    let static_libs: Vec<(&str, &str)> = platform_dir
+        .static_files
+        .iter()
+        .map(|file| (link_name(&file.name), file.name.as_str()))
+        .collect();
+    let cmake_config = PlatformFile::new(
+        format!("lib/cmake/{cmake_name}/{cmake_name}Config.cmake"),
+        templates::cmake_config(cmake_name, &static_libs, config),
+        None,
+    );
+    let cmake_config_version = PlatformFile::new(
+        format!("lib/cmake/{cmake_name}/{cmake_name}ConfigVersion.cmake"),
+        templates::cmake_config_version(&project.version),
+        None,
+    );
+    libs.chain(headers)
+        .chain([pkg_config, cmake_config, cmake_config_version])
+        .collect()
+}
+
+pub(crate) fn write_static_libraries(
+    project: &Project,
+    static_dir: &Path,
+    config: &TargetStatic,
+) -> Result<Vec<GeneratedAsset>> {
+    let mut assets = vec![];
+    for platform_dir in &project.platform_directories {
+        let _span = platform_dir.span().entered();
+        if platform_dir.static_files.is_empty() {
+            continue;
+        }
+        let files = static_archive_files(project, platform_dir, config);
+        let files = files.iter().collect::<Vec<&PlatformFile>>();
+        let base_name = format!(
+            "{}-{}-devel-{}-{}",
+            project.spec.package.name,
+            project.version,
+            platform_dir.os.to_string(),
+            platform_dir.cpu.to_string()
+        );
+        let (name, data) = match platform_dir.os {
+            Os::Windows => (format!("{base_name}.zip"), create_zip(&files)?),
+            _ => (format!("{base_name}.tar.gz"), create_targz(&files)?),
+        };
+        assets.push(GeneratedAsset::from(
+            GeneratedAssetKind::Static((platform_dir.os.clone(), platform_dir.cpu.clone())),
+            &static_dir.join(name),
+            &data,
+        )?);
+    }
+    Ok(assets)
+}
+
+mod templates {
+    use semver::Version;
+
+    use crate::spec::TargetStatic;
+    use crate::Project;
+
+    pub(crate) fn pkg_config(
+        project: &Project,
+        link_names: &[&str],
+        config: &TargetStatic,
+    ) -> String {
+        let name = &project.spec.package.name;
+        let description = &project.spec.package.description;
+        let homepage = &project.spec.package.homepage;
+        let version = &project.version;
+        let libs = link_names
+            .iter()
+            .map(|link_name| format!("-l{link_name}"))
+            .chain(config.libs.iter().cloned())
+            .collect::<Vec<String>>()
+            .join(" ");
+        let cflags = std::iter::once("-I${includedir}".to_owned())
+            .chain(config.cflags.iter().cloned())
+            .collect::<Vec<String>>()
+            .join(" ");
+        format!(
+            r#"prefix=${{pcfiledir}}/../..
+exec_prefix=${{prefix}}
+libdir=${{exec_prefix}}/lib
+includedir=${{prefix}}/include
+
+Name: {name}
+Description: {description}
+URL: {homepage}
+Version: {version}
+Libs: -L${{libdir}} {libs}
+Cflags: {cflags}
+"#
+        )
+    }
+
+    pub(crate) fn cmake_config(
+        cmake_name: &str,
+        static_libs: &[(&str, &str)],
+        config: &TargetStatic,
+    ) -> String {
+        let link_libraries = config.libs.join(";");
+        let compile_options = config.cflags.join(";");
+        let targets = static_libs
+            .iter()
+            .map(|(link_name, file_name)| {
+                format!(
+                    r#"if(NOT TARGET {cmake_name}::{link_name})
+  add_library({cmake_name}::{link_name} STATIC IMPORTED)
+  set_target_properties({cmake_name}::{link_name} PROPERTIES
+    IMPORTED_LOCATION "${{_IMPORT_PREFIX}}/lib/{file_name}"
+    INTERFACE_INCLUDE_DIRECTORIES "${{_IMPORT_PREFIX}}/include"
+    INTERFACE_LINK_LIBRARIES "{link_libraries}"
+    INTERFACE_COMPILE_OPTIONS "{compile_options}"
+  )
+endif()
+"#
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!(
+            r#"get_filename_component(_IMPORT_PREFIX "${{CMAKE_CURRENT_LIST_DIR}}/../../.." ABSOLUTE)
+
+{targets}
+set(_IMPORT_PREFIX)
+"#
+        )
+    }
+
+    pub(crate) fn cmake_config_version(version: &Version) -> String {
+        // CMake can only compare numeric versions, so prerelease/build metadata is dropped
+        let major = version.major;
+        let package_version = Version::new(version.major, version.minor, version.patch);
+        format!(
+            r#"set(PACKAGE_VERSION "{package_version}")
+
+if(PACKAGE_FIND_VERSION VERSION_GREATER PACKAGE_VERSION)
+  set(PACKAGE_VERSION_COMPATIBLE FALSE)
+else()
+  if(PACKAGE_FIND_VERSION_MAJOR STREQUAL "{major}")
+    set(PACKAGE_VERSION_COMPATIBLE TRUE)
+  else()
+    set(PACKAGE_VERSION_COMPATIBLE FALSE)
+  endif()
+  if(PACKAGE_FIND_VERSION STREQUAL PACKAGE_VERSION)
+    set(PACKAGE_VERSION_EXACT TRUE)
+  endif()
+endif()
+"#
+        )
+    }
+}
@@ -0,0 +1,493 @@
+use crate::binary::{u16_at, u32_at, u64_at};
+
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+const SHT_REL: u32 = 9;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_INFO_LINK: u64 = 0x40;
+
+/// Field offsets of the 32 and 64 bit ELF layouts
+struct ElfLayout {
+    is_64: bool,
+    little_endian: bool,
+}
+
+impl ElfLayout {
+    fn word(&self, data: &[u8], offset: usize) -> Option<u64> {
+        if self.is_64 {
+            u64_at(data, offset, self.little_endian)
+        } else {
+            u32_at(data, offset, self.little_endian).map(u64::from)
+        }
+    }
+
+    fn put_word(&self, data: &mut [u8], offset: usize, value: u64) {
+        if self.is_64 {
+            self.put_bytes(data, offset, &value.to_le_bytes(), &value.to_be_bytes());
+        } else {
+            let value = value as u32;
+            self.put_bytes(data, offset, &value.to_le_bytes(), &value.to_be_bytes());
+        }
+    }
+
+    fn put_u32(&self, data: &mut [u8], offset: usize, value: u32) {
+        self.put_bytes(data, offset, &value.to_le_bytes(), &value.to_be_bytes());
+    }
+
+    fn put_u16(&self, data: &mut [u8], offset: usize, value: u16) {
+        self.put_bytes(data, offset, &value.to_le_bytes(), &value.to_be_bytes());
+    }
+
+    fn put_bytes(&self, data: &mut [u8], offset: usize, le: &[u8], be: &[u8]) {
+        let bytes = if self.little_endian { le } else { be };
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// (e_phoff, e_shoff, e_phentsize) offsets in the file header
+    fn header_offsets(&self) -> (usize, usize, usize) {
+        if self.is_64 {
+            (0x20, 0x28, 0x36)
+        } else {
+            (0x1c, 0x20, 0x2a)
+        }
+    }
+
+    /// (sh_flags, sh_offset, sh_size, sh_link, sh_info, sh_addralign) offsets in a section header
+    fn section_offsets(&self) -> (usize, usize, usize, usize, usize, usize) {
+        if self.is_64 {
+            (0x08, 0x18, 0x20, 0x28, 0x2c, 0x30)
+        } else {
+            (0x08, 0x10, 0x14, 0x18, 0x1c, 0x20)
+        }
+    }
+
+    /// (p_offset, p_filesz) offsets in a program header
+    fn segment_offsets(&self) -> (usize, usize) {
+        if self.is_64 {
+            (0x08, 0x20)
+        } else {
+            (0x04, 0x10)
+        }
+    }
+}
+
+struct Section<'a> {
+    header: &'a [u8],
+    name: &'a [u8],
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+}
+
+impl Section<'_> {
+    fn has_data(&self) -> bool {
+        self.kind != SHT_NOBITS && self.size > 0
+    }
+
+    fn is_debug(&self) -> bool {
+        self.flags & SHF_ALLOC == 0
+            && (self.name.starts_with(b".debug")
+                || self.name.starts_with(b".zdebug")
+                || self.name == b".symtab"
+                || self.name == b".strtab")
+    }
+}
+
+fn sections<'a>(layout: &ElfLayout, data: &'a [u8]) -> Option<(Vec<Section<'a>>, usize)> {
+    let (_, shoff_at, phentsize_at) = layout.header_offsets();
+    let shoff = layout.word(data, shoff_at)? as usize;
+    let shentsize = u16_at(data, phentsize_at + 4, layout.little_endian)? as usize;
+    let shnum = u16_at(data, phentsize_at + 6, layout.little_endian)? as usize;
+    let shstrndx = u16_at(data, phentsize_at + 8, layout.little_endian)? as usize;
+    let (flags_at, offset_at, size_at, link_at, info_at, align_at) = layout.section_offsets();
+
+    let mut sections = vec![];
+    for i in 0..shnum {
+        let header = data.get(shoff + i * shentsize..shoff + (i + 1) * shentsize)?;
+        sections.push(Section {
+            header,
+            name: &[],
+            kind: u32_at(header, 4, layout.little_endian)?,
+            flags: layout.word(header, flags_at)?,
+            offset: layout.word(header, offset_at)?,
+            size: layout.word(header, size_at)?,
+            link: u32_at(header, link_at, layout.little_endian)?,
+            info: u32_at(header, info_at, layout.little_endian)?,
+            align: layout.word(header, align_at)?,
+        });
+    }
+    let names = sections.get(shstrndx)?;
+    let names = data.get(names.offset as usize..(names.offset + names.size) as usize)?;
+    for section in &mut sections {
+        let start = u32_at(section.header, 0, layout.little_endian)? as usize;
+        let name = names.get(start..)?;
+        section.name = &name[..name.iter().position(|b| *b == 0)?];
+    }
+    Some((sections, shstrndx))
+}
+
+/// Removes the DWARF sections and the static symbol table from an ELF loadable, like
+/// `strip --strip-debug --strip-unneeded`. The dynamic symbols SQLite loads the entrypoint
+/// with are kept. None when the file isn't ELF, has nothing to strip, or is laid out in a
+/// way (non-alloc sections before loaded ones) that can't be rewritten safely.
+/// Mach-O and PE loadables keep their debug info in `.dSYM` bundles and `.pdb` files
+/// next to them, so there is nothing to remove from the binary itself.
+pub(crate) fn strip_elf(data: &[u8]) -> Option<Vec<u8>> {
+    if data.get(..4)? != b"\x7fELF" {
+        return None;
+    }
+    let layout = ElfLayout {
+        is_64: *data.get(4)? == 2,
+        little_endian: *data.get(5)? == 1,
+    };
+    let (sections, shstrndx) = sections(&layout, data)?;
+    let mut removed: Vec<bool> = sections
+        .iter()
+        .enumerate()
+        .map(|(i, section)| i != 0 && i != shstrndx && section.is_debug())
+        .collect();
+    // relocations of the removed sections, ex `.rela.debug_info`
+    for (i, section) in sections.iter().enumerate() {
+        if matches!(section.kind, SHT_REL | SHT_RELA)
+            && section.flags & SHF_ALLOC == 0
+            && removed.get(section.info as usize) == Some(&true)
+        {
+            removed[i] = true;
+        }
+    }
+    if !removed.contains(&true) {
+        return None;
+    }
+
+    // everything the loader maps is copied as-is, the non-alloc sections after it are re-laid out
+    let cut = sections
+        .iter()
+        .skip(1)
+        .filter(|section| section.flags & SHF_ALLOC == 0 && section.has_data())
+        .map(|section| section.offset)
+        .min()?;
+    if sections
+        .iter()
+        .filter(|section| section.flags & SHF_ALLOC != 0 && section.has_data())
+        .any(|section| section.offset + section.size > cut)
+    {
+        return None;
+    }
+    let (phoff_at, shoff_at, phentsize_at) = layout.header_offsets();
+    let phoff = layout.word(data, phoff_at)?;
+    let phentsize = u16_at(data, phentsize_at, layout.little_endian)? as u64;
+    let phnum = u16_at(data, phentsize_at + 2, layout.little_endian)? as u64;
+    if phoff + phentsize * phnum > cut {
+        return None;
+    }
+    let (p_offset_at, p_filesz_at) = layout.segment_offsets();
+    for i in 0..phnum {
+        let header = (phoff + i * phentsize) as usize;
+        let end =
+            layout.word(data, header + p_offset_at)? + layout.word(data, header + p_filesz_at)?;
+        if end > cut {
+            return None;
+        }
+    }
+
+    let mut new_index = vec![0u32; sections.len()];
+    let mut next_index = 0;
+    for (i, is_removed) in removed.iter().enumerate() {
+        if !is_removed {
+            new_index[i] = next_index;
+            next_index += 1;
+        }
+    }
+    let remap = |index: u32| match removed.get(index as usize) {
+        Some(false) => new_index[index as usize],
+        _ => 0,
+    };
+
+    let mut out = data.get(..cut as usize)?.to_vec();
+    let mut new_offsets = vec![None; sections.len()];
+    for (i, section) in sections.iter().enumerate().skip(1) {
+        if removed[i] || section.flags & SHF_ALLOC != 0 || !section.has_data() {
+            continue;
+        }
+        let align = section.align.max(1) as usize;
+        out.resize(out.len().next_multiple_of(align), 0);
+        new_offsets[i] = Some(out.len() as u64);
+        out.extend_from_slice(
+            data.get(section.offset as usize..(section.offset + section.size) as usize)?,
+        );
+    }
+    out.resize(out.len().next_multiple_of(8), 0);
+    let shoff = out.len();
+    let (_, offset_at, _, link_at, info_at, _) = layout.section_offsets();
+    for (i, section) in sections.iter().enumerate() {
+        if removed[i] {
+            continue;
+        }
+        let start = out.len();
+        out.extend_from_slice(section.header);
+        let header = &mut out[start..];
+        if let Some(offset) = new_offsets[i] {
+            layout.put_word(header, offset_at, offset);
+        }
+        layout.put_u32(header, link_at, remap(section.link));
+        let links_info = section.flags & SHF_INFO_LINK != 0
+            || (matches!(section.kind, SHT_REL | SHT_RELA) && section.flags & SHF_ALLOC == 0);
+        if links_info {
+            layout.put_u32(header, info_at, remap(section.info));
+        }
+    }
+    layout.put_word(&mut out, shoff_at, shoff as u64);
+    layout.put_u16(&mut out, phentsize_at + 6, next_index as u16);
+    layout.put_u16(&mut out, phentsize_at + 8, new_index[shstrndx] as u16);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process::Command};
+
+    use super::*;
+
+    const SHT_PROGBITS: u32 = 1;
+    const SHT_SYMTAB: u32 = 2;
+    const SHT_STRTAB: u32 = 3;
+    const SHT_DYNSYM: u32 = 11;
+
+    /// (name, sh_type, sh_flags, sh_link, sh_info, data)
+    type FixtureSection = (&'static str, u32, u64, u32, u32, &'static [u8]);
+
+    /// A shared library with one PT_LOAD segment over the headers and the alloc
+    /// sections, then the non-alloc sections, then `.shstrtab` and the section headers
+    fn fixture(layout: &ElfLayout, sections: &[FixtureSection]) -> Vec<u8> {
+        let (ehsize, phentsize, shentsize) = if layout.is_64 {
+            (0x40, 0x38, 0x40)
+        } else {
+            (0x34, 0x20, 0x28)
+        };
+        let mut shstrtab = b"\0.shstrtab\0".to_vec();
+        let mut names = vec![];
+        for (name, ..) in sections {
+            names.push(shstrtab.len() as u32);
+            shstrtab.extend_from_slice(name.as_bytes());
+            shstrtab.push(0);
+        }
+
+        let mut data = vec![0; ehsize + phentsize];
+        let mut offsets = vec![0; sections.len()];
+        // alloc sections first, so they're all inside the segment
+        for alloc in [true, false] {
+            for (i, (_, _, flags, _, _, contents)) in sections.iter().enumerate() {
+                if (flags & SHF_ALLOC != 0) == alloc {
+                    data.resize(data.len().next_multiple_of(8), 0);
+                    offsets[i] = data.len() as u64;
+                    data.extend_from_slice(contents);
+                }
+            }
+            if alloc {
+                let segment_end = data.len() as u64;
+                let (p_offset_at, p_filesz_at) = layout.segment_offsets();
+                layout.put_u32(&mut data, ehsize, 1);
+                layout.put_word(&mut data, ehsize + p_offset_at, 0);
+                layout.put_word(&mut data, ehsize + p_filesz_at, segment_end);
+            }
+        }
+        let shstrtab_offset = data.len() as u64;
+        data.extend_from_slice(&shstrtab);
+        data.resize(data.len().next_multiple_of(8), 0);
+        let shoff = data.len();
+
+        let (flags_at, offset_at, size_at, link_at, info_at, align_at) = layout.section_offsets();
+        let mut header = |name: u32, kind, flags, offset, size, link, info| {
+            let start = data.len();
+            data.resize(start + shentsize, 0);
+            let header = &mut data[start..];
+            layout.put_u32(header, 0, name);
+            layout.put_u32(header, 4, kind);
+            layout.put_word(header, flags_at, flags);
+            layout.put_word(header, offset_at, offset);
+            layout.put_word(header, size_at, size);
+            layout.put_u32(header, link_at, link);
+            layout.put_u32(header, info_at, info);
+            layout.put_word(header, align_at, 1);
+        };
+        header(0, 0, 0, 0, 0, 0, 0);
+        for (i, (_, kind, flags, link, info, contents)) in sections.iter().enumerate() {
+            header(
+                names[i],
+                *kind,
+                *flags,
+                offsets[i],
+                contents.len() as u64,
+                *link,
+                *info,
+            );
+        }
+        header(
+            1,
+            SHT_STRTAB,
+            0,
+            shstrtab_offset,
+            shstrtab.len() as u64,
+            0,
+            0,
+        );
+
+        data[..4].copy_from_slice(b"\x7fELF");
+        data[4] = if layout.is_64 { 2 } else { 1 };
+        data[5] = if layout.little_endian { 1 } else { 2 };
+        data[6] = 1;
+        layout.put_u16(&mut data, 0x10, 3);
+        let (phoff_at, shoff_at, phentsize_at) = layout.header_offsets();
+        layout.put_word(&mut data, phoff_at, ehsize as u64);
+        layout.put_word(&mut data, shoff_at, shoff as u64);
+        layout.put_u16(&mut data, phentsize_at - 2, ehsize as u16);
+        layout.put_u16(&mut data, phentsize_at, phentsize as u16);
+        layout.put_u16(&mut data, phentsize_at + 2, 1);
+        layout.put_u16(&mut data, phentsize_at + 4, shentsize as u16);
+        layout.put_u16(&mut data, phentsize_at + 6, sections.len() as u16 + 2);
+        layout.put_u16(&mut data, phentsize_at + 8, sections.len() as u16 + 1);
+        data
+    }
+
+    /// Sections 1 and 2 are removed, so every kept index after them shifts down
+    const SECTIONS: &[FixtureSection] = &[
+        (".debug_info", SHT_PROGBITS, 0, 0, 0, b"debug info"),
+        (".rela.debug_info", SHT_RELA, 0, 5, 1, &[7; 24]),
+        (
+            ".dynstr",
+            SHT_STRTAB,
+            SHF_ALLOC,
+            0,
+            0,
+            b"\0sqlite3_sample_init\0",
+        ),
+        (".dynsym", SHT_DYNSYM, SHF_ALLOC, 3, 1, &[1; 48]),
+        (".symtab", SHT_SYMTAB, 0, 6, 1, &[2; 48]),
+        (".strtab", SHT_STRTAB, 0, 0, 0, b"\0sample_internal\0"),
+        (".comment", SHT_PROGBITS, 0, 0, 0, b"GCC: (GNU) 13.2.0\0"),
+    ];
+
+    fn round_trip(layout: ElfLayout) {
+        let input = fixture(&layout, SECTIONS);
+        let stripped = strip_elf(&input).unwrap();
+        assert!(stripped.len() < input.len());
+
+        let (before, _) = sections(&layout, &input).unwrap();
+        let (after, shstrndx) = sections(&layout, &stripped).unwrap();
+        let names: Vec<&[u8]> = after.iter().map(|section| section.name).collect();
+        assert_eq!(
+            names,
+            [&b""[..], b".dynstr", b".dynsym", b".comment", b".shstrtab"]
+        );
+        assert_eq!(after[shstrndx].name, b".shstrtab");
+        // .dynsym still links .dynstr, now at index 1
+        assert_eq!(after[2].link, 1);
+        assert_eq!(after[2].info, 1);
+
+        let (_, shoff_at, _) = layout.header_offsets();
+        let shoff = layout.word(&stripped, shoff_at).unwrap();
+        for section in &after {
+            let original = before
+                .iter()
+                .find(|original| original.name == section.name)
+                .unwrap();
+            assert_eq!(section.size, original.size);
+            assert!(section.offset + section.size <= shoff);
+            assert_eq!(
+                stripped[section.offset as usize..][..section.size as usize],
+                input[original.offset as usize..][..original.size as usize],
+                "contents of {}",
+                String::from_utf8_lossy(section.name)
+            );
+            if section.flags & SHF_ALLOC != 0 {
+                assert_eq!(section.offset, original.offset);
+            }
+        }
+        // the loaded part of the file after the ELF header is copied as-is
+        let (phoff_at, ..) = layout.header_offsets();
+        let ehsize = layout.word(&input, phoff_at).unwrap() as usize;
+        let cut = before
+            .iter()
+            .filter(|section| section.flags & SHF_ALLOC == 0 && section.has_data())
+            .map(|section| section.offset)
+            .min()
+            .unwrap() as usize;
+        assert_eq!(stripped[ehsize..cut], input[ehsize..cut]);
+
+        // nothing is left to strip
+        assert!(strip_elf(&stripped).is_none());
+    }
+
+    #[test]
+    fn strip_elf64_little_endian() {
+        round_trip(ElfLayout {
+            is_64: true,
+            little_endian: true,
+        });
+    }
+
+    #[test]
+    fn strip_elf32_big_endian() {
+        round_trip(ElfLayout {
+            is_64: false,
+            little_endian: false,
+        });
+    }
+
+    /// `readelf` output of `args` on `path`, None when readelf isn't installed
+    fn readelf(args: &[&str], path: &std::path::Path) -> Option<String> {
+        let output = Command::new("readelf").args(args).arg(path).output().ok()?;
+        assert!(
+            output.status.success() && output.stderr.is_empty(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
+
+    /// A real `gcc -g` library, checked with readelf. Skipped when either isn't installed.
+    #[test]
+    fn strip_gcc_output() {
+        let dir = env::temp_dir().join(format!("sqlite-dist-strip-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("sample.c"),
+            "static int internal(int x) { return x + 1; }\nint sqlite3_sample_init(void) { return internal(0); }\n",
+        )
+        .unwrap();
+        let Ok(status) = Command::new("gcc")
+            .args(["-g", "-shared", "-fPIC", "-o", "sample0.so", "sample.c"])
+            .current_dir(&dir)
+            .status()
+        else {
+            fs::remove_dir_all(&dir).unwrap();
+            return;
+        };
+        assert!(status.success());
+        let input = fs::read(dir.join("sample0.so")).unwrap();
+        let stripped = strip_elf(&input).unwrap();
+        fs::write(dir.join("stripped.so"), &stripped).unwrap();
+        let original = dir.join("sample0.so");
+        let path = dir.join("stripped.so");
+
+        let Some(sections) = readelf(&["-S", "-W"], &path) else {
+            fs::remove_dir_all(&dir).unwrap();
+            return;
+        };
+        assert!(!sections.contains(".debug_"), "{sections}");
+        assert!(!sections.contains(".symtab"), "{sections}");
+        assert!(sections.contains(".dynsym"), "{sections}");
+        let dyn_syms = readelf(&["--dyn-syms", "-W"], &path).unwrap();
+        assert!(dyn_syms.contains("sqlite3_sample_init"));
+        assert_eq!(dyn_syms, readelf(&["--dyn-syms", "-W"], &original).unwrap());
+        assert_eq!(
+            readelf(&["-l", "-W"], &path).unwrap(),
+            readelf(&["-l", "-W"], &original).unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
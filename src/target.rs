@@ -0,0 +1,526 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::spec::{Spec, SpecError};
+use crate::{
+    amalgamation, archives, cli_bundle, create_output_dir, debug_symbols, docker, gem, gh_releases,
+    homebrew, layout, npm, pip, release_notes, spm, sqlpkg, static_libraries, workers, xcframework,
+    BuildError, GeneratedAsset, Project,
+};
+
+/// What a target gets to generate its assets with
+pub struct TargetContext<'a> {
+    pub project: &'a Project,
+    /// root output directory, targets write into their own subdirectory of it
+    pub output_dir: &'a Path,
+}
+
+impl TargetContext<'_> {
+    /// Creates and returns the directory the assets of `name` go in, `output_dir/name`
+    /// unless the spec's `output.layout` says otherwise
+    pub fn target_dir(&self, name: &str) -> Result<PathBuf, BuildError> {
+        let path = layout::target_dir(self.project, self.output_dir, name);
+        create_output_dir(&path)?;
+        Ok(path)
+    }
+}
+
+/// A kind of package sqlite-dist can generate. The built-in targets implement
+/// this, and downstream users can register their own on a [`TargetRegistry`].
+pub trait Target: Send + Sync {
+    /// Name used by `--only`/`--skip` and the build cache, ex `pip`
+    fn name(&self) -> &str;
+
+    /// Whether the spec asks for this target
+    fn enabled(&self, spec: &Spec) -> bool;
+
+    /// Checks the spec before anything is generated
+    fn validate(&self, _spec: &Spec) -> Result<(), SpecError> {
+        Ok(())
+    }
+
+    /// The part of `[targets]` this target reads, so the build cache only
+    /// regenerates it when that or a top-level section of the spec changes.
+    /// Defaults to the whole spec.
+    fn config(&self, spec: &Spec) -> Value {
+        serde_json::to_value(spec).unwrap_or_default()
+    }
+
+    /// Whether the assets of a previous build can be reused when the
+    /// config and input files didn't change
+    fn cacheable(&self) -> bool {
+        true
+    }
+
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError>;
+}
+
+/// The targets a build runs, in the order their assets are reported
+pub struct TargetRegistry {
+    targets: Vec<Box<dyn Target>>,
+}
+
+impl TargetRegistry {
+    pub fn builtin() -> Self {
+        Self {
+            targets: vec![
+                Box::new(GithubReleasesTarget),
+                Box::new(StaticTarget),
+                Box::new(XcframeworkTarget),
+                Box::new(PipTarget),
+                Box::new(DatasetteTarget),
+                Box::new(SqliteUtilsTarget),
+                Box::new(NpmTarget),
+                Box::new(GemTarget),
+                Box::new(CliTarget),
+                Box::new(ArchivesTarget),
+                Box::new(WorkersTarget),
+                Box::new(DockerTarget),
+                Box::new(DebugSymbolsTarget),
+            ],
+        }
+    }
+
+    pub fn register(&mut self, target: impl Target + 'static) {
+        self.targets.push(Box::new(target));
+    }
+
+    pub fn targets(&self) -> impl Iterator<Item = &dyn Target> {
+        self.targets.iter().map(|target| target.as_ref())
+    }
+
+    /// Names of the registered targets that aren't configured in the spec's `[targets]`
+    pub(crate) fn custom_names(&self) -> Vec<&str> {
+        self.targets()
+            .map(|target| target.name())
+            .filter(|name| !BUILTIN_NAMES.contains(name))
+            .collect()
+    }
+
+    /// Applies `--only`/`--skip` to custom targets, built-in targets are
+    /// filtered on the spec instead.
+    pub(crate) fn select(&mut self, only: Option<&[String]>, skip: &[String]) {
+        self.targets.retain(|target| {
+            let name = target.name();
+            BUILTIN_NAMES.contains(&name)
+                || (only.is_none_or(|only| only.iter().any(|n| n == name))
+                    && !skip.iter().any(|n| n == name))
+        });
+    }
+}
+
+impl Default for TargetRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+const BUILTIN_NAMES: [&str; 13] = [
+    "github_releases",
+    "static",
+    "xcframework",
+    "pip",
+    "datasette",
+    "sqlite_utils",
+    "npm",
+    "gem",
+    "cli",
+    "archives",
+    "workers",
+    "docker",
+    "debug_symbols",
+];
+
+fn to_value<T: serde::Serialize>(config: T) -> Value {
+    serde_json::to_value(config).unwrap_or_default()
+}
+
+/// GitHub release tarballs, plus sqlpkg, spm, homebrew, and the amalgamation which point at them
+struct GithubReleasesTarget;
+
+impl Target for GithubReleasesTarget {
+    fn name(&self) -> &str {
+        "github_releases"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.github_releases.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        let targets = &spec.targets;
+        to_value((
+            &targets.github_releases,
+            &targets.sqlpkg,
+            &targets.spm,
+            &targets.amalgamation,
+            &targets.homebrew,
+        ))
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let project = ctx.project;
+        let targets = &project.spec.targets;
+        let mut generated_assets = vec![];
+        let path = ctx.target_dir("github_releases")?;
+        let gh_release_assets = gh_releases::write_platform_files(project, &path)?;
+        if let Some(changelog) = targets
+            .github_releases
+            .as_ref()
+            .and_then(|gh| gh.changelog.as_ref())
+        {
+            generated_assets.extend(release_notes::write_release_notes(
+                project, changelog, &path,
+            )?);
+        }
+
+        if targets.sqlpkg.is_some() {
+            let sqlpkg_dir = ctx.target_dir("sqlpkg")?;
+            generated_assets.extend(sqlpkg::write_sqlpkg(project, &sqlpkg_dir)?);
+        };
+
+        if targets.spm.is_some() {
+            let path = ctx.target_dir("spm")?;
+            generated_assets.extend(spm::write_spm(&project.spec, &gh_release_assets, &path)?);
+        };
+
+        if let Some(homebrew_config) = &targets.homebrew {
+            let path = ctx.target_dir("homebrew")?;
+            generated_assets.extend(homebrew::write_homebrew(
+                project,
+                homebrew_config,
+                &gh_release_assets,
+                &path,
+            )?);
+        };
+
+        if let Some(amalgamation_config) = &targets.amalgamation {
+            let amalgamation_path = ctx.target_dir("amalgamation")?;
+            generated_assets.extend(amalgamation::write_amalgamation(
+                project,
+                &amalgamation_path,
+                amalgamation_config,
+            )?);
+        };
+
+        generated_assets.extend(gh_release_assets);
+        Ok(generated_assets)
+    }
+}
+
+struct StaticTarget;
+
+impl Target for StaticTarget {
+    fn name(&self) -> &str {
+        "static"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.static_.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        to_value(&spec.targets.static_)
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let Some(static_config) = &ctx.project.spec.targets.static_ else {
+            return Ok(vec![]);
+        };
+        let static_path = ctx.target_dir("static")?;
+        Ok(static_libraries::write_static_libraries(
+            ctx.project,
+            &static_path,
+            static_config,
+        )?)
+    }
+}
+
+struct XcframeworkTarget;
+
+impl Target for XcframeworkTarget {
+    fn name(&self) -> &str {
+        "xcframework"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.xcframework.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        to_value(&spec.targets.xcframework)
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let Some(xcframework_config) = &ctx.project.spec.targets.xcframework else {
+            return Ok(vec![]);
+        };
+        let xcframework_path = ctx.target_dir("xcframework")?;
+        Ok(xcframework::write_xcframework(
+            ctx.project,
+            &xcframework_path,
+            xcframework_config,
+        )?)
+    }
+}
+
+struct PipTarget;
+
+impl Target for PipTarget {
+    fn name(&self) -> &str {
+        "pip"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.pip.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        to_value(&spec.targets.pip)
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let pip_path = ctx.target_dir("pip")?;
+        Ok(pip::write_base_packages(ctx.project, &pip_path)?)
+    }
+}
+
+struct DatasetteTarget;
+
+impl Target for DatasetteTarget {
+    fn name(&self) -> &str {
+        "datasette"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.pip.is_some() && spec.targets.datasette.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        to_value((&spec.targets.pip, &spec.targets.datasette))
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let datasette_path = ctx.target_dir("datasette")?;
+        Ok(vec![pip::write_datasette(ctx.project, &datasette_path)?])
+    }
+}
+
+struct SqliteUtilsTarget;
+
+impl Target for SqliteUtilsTarget {
+    fn name(&self) -> &str {
+        "sqlite_utils"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.pip.is_some() && spec.targets.sqlite_utils.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        to_value((&spec.targets.pip, &spec.targets.sqlite_utils))
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let sqlite_utils_path = ctx.target_dir("sqlite_utils")?;
+        Ok(vec![pip::write_sqlite_utils(
+            ctx.project,
+            &sqlite_utils_path,
+        )?])
+    }
+}
+
+struct NpmTarget;
+
+impl Target for NpmTarget {
+    fn name(&self) -> &str {
+        "npm"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.npm.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        to_value(&spec.targets.npm)
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let npm_output_directory = ctx.target_dir("npm")?;
+        Ok(npm::write_npm_packages(ctx.project, &npm_output_directory)?)
+    }
+}
+
+struct GemTarget;
+
+impl Target for GemTarget {
+    fn name(&self) -> &str {
+        "gem"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.gem.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        to_value(&spec.targets.gem)
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let Some(gem_config) = &ctx.project.spec.targets.gem else {
+            return Ok(vec![]);
+        };
+        let gem_path = ctx.target_dir("gem")?;
+        Ok(gem::write_gems(ctx.project, &gem_path, gem_config)?)
+    }
+}
+
+struct CliTarget;
+
+impl Target for CliTarget {
+    fn name(&self) -> &str {
+        "cli"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.cli.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        to_value(&spec.targets.cli)
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let Some(cli_config) = &ctx.project.spec.targets.cli else {
+            return Ok(vec![]);
+        };
+        let cli_path = ctx.target_dir("cli")?;
+        Ok(cli_bundle::write_cli_bundles(
+            ctx.project,
+            &cli_path,
+            cli_config,
+        )?)
+    }
+}
+
+struct DockerTarget;
+
+impl Target for DockerTarget {
+    fn name(&self) -> &str {
+        "docker"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.docker.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        to_value(&spec.targets.docker)
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let Some(docker_config) = &ctx.project.spec.targets.docker else {
+            return Ok(vec![]);
+        };
+        let docker_path = ctx.target_dir("docker")?;
+        Ok(docker::write_docker_context(
+            ctx.project,
+            &docker_path,
+            docker_config,
+        )?)
+    }
+}
+
+struct ArchivesTarget;
+
+impl Target for ArchivesTarget {
+    fn name(&self) -> &str {
+        "archives"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.archives.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        to_value(&spec.targets.archives)
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let Some(archives_config) = &ctx.project.spec.targets.archives else {
+            return Ok(vec![]);
+        };
+        let archives_path = ctx.target_dir("archives")?;
+        Ok(archives::write_archives(
+            ctx.project,
+            &archives_path,
+            archives_config,
+        )?)
+    }
+}
+
+struct WorkersTarget;
+
+impl Target for WorkersTarget {
+    fn name(&self) -> &str {
+        "workers"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.workers.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        to_value(&spec.targets.workers)
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let Some(workers_config) = &ctx.project.spec.targets.workers else {
+            return Ok(vec![]);
+        };
+        let workers_path = ctx.target_dir("workers")?;
+        Ok(workers::write_workers_package(
+            ctx.project,
+            &workers_path,
+            workers_config,
+        )?)
+    }
+}
+
+struct DebugSymbolsTarget;
+
+impl Target for DebugSymbolsTarget {
+    fn name(&self) -> &str {
+        "debug_symbols"
+    }
+    fn enabled(&self, spec: &Spec) -> bool {
+        spec.targets.debug_symbols.is_some()
+    }
+    fn config(&self, spec: &Spec) -> Value {
+        to_value(&spec.targets.debug_symbols)
+    }
+    fn generate(&self, ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+        let debug_symbols_path = ctx.target_dir("debug-symbols")?;
+        Ok(debug_symbols::write_debug_symbols(
+            ctx.project,
+            &debug_symbols_path,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Target, TargetContext, TargetRegistry, BUILTIN_NAMES};
+    use crate::{spec::Spec, BuildError, GeneratedAsset};
+
+    struct Custom(&'static str);
+
+    impl Target for Custom {
+        fn name(&self) -> &str {
+            self.0
+        }
+        fn enabled(&self, _spec: &Spec) -> bool {
+            true
+        }
+        fn generate(&self, _ctx: &TargetContext) -> Result<Vec<GeneratedAsset>, BuildError> {
+            Ok(vec![])
+        }
+    }
+
+    fn names(registry: &TargetRegistry) -> Vec<&str> {
+        registry.targets().map(|target| target.name()).collect()
+    }
+
+    fn registry() -> TargetRegistry {
+        let mut registry = TargetRegistry::builtin();
+        registry.register(Custom("conda"));
+        registry.register(Custom("vcpkg"));
+        registry
+    }
+
+    #[test]
+    fn select_filters_custom_targets() {
+        let mut only = registry();
+        only.select(Some(&["pip".to_owned(), "vcpkg".to_owned()]), &[]);
+        assert_eq!(only.custom_names(), ["vcpkg"]);
+
+        let mut skip = registry();
+        skip.select(None, &["conda".to_owned(), "pip".to_owned()]);
+        assert_eq!(skip.custom_names(), ["vcpkg"]);
+
+        // built-in targets are filtered on the spec, the registry keeps them all
+        let mut none = registry();
+        none.select(Some(&[]), &[]);
+        assert!(none.custom_names().is_empty());
+        assert_eq!(
+            names(&none).len(),
+            TargetRegistry::builtin().targets().count()
+        );
+        assert!(names(&none).iter().all(|name| BUILTIN_NAMES.contains(name)));
+    }
+}
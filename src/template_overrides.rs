@@ -0,0 +1,91 @@
+use std::{
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use tera::{Context, Tera};
+
+use crate::{Cpu, Os, Project};
+
+#[derive(Serialize)]
+struct TemplatePlatform<'a> {
+    os: &'a Os,
+    cpu: &'a Cpu,
+}
+
+/// User-supplied Tera templates that replace the built-in text files, keyed by
+/// their path relative to the templates directory (ex `pip/__init__.py`).
+#[derive(Clone)]
+pub(crate) struct TemplateOverrides {
+    tera: Tera,
+    directory: PathBuf,
+}
+
+fn template_error(name: &str, err: tera::Error) -> io::Error {
+    let mut message = format!("template {name}: {err}");
+    let mut source = err.source();
+    while let Some(err) = source {
+        message += &format!(": {err}");
+        source = err.source();
+    }
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl TemplateOverrides {
+    pub(crate) fn load(directory: &Path) -> io::Result<Self> {
+        let glob = directory.join("**").join("*");
+        let glob = glob.to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "templates directory must be valid UTF-8",
+            )
+        })?;
+        let tera = Tera::new(glob).map_err(|err| template_error(glob, err))?;
+        Ok(Self {
+            tera,
+            directory: directory.to_path_buf(),
+        })
+    }
+
+    /// Raw contents of every override, sorted by name
+    pub(crate) fn sources(&self) -> io::Result<Vec<(String, Vec<u8>)>> {
+        let mut names: Vec<&str> = self.tera.get_template_names().collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| Ok((name.to_owned(), std::fs::read(self.directory.join(name))?)))
+            .collect()
+    }
+}
+
+impl Project {
+    /// Renders the user's override for `name` if there is one, otherwise returns `default`.
+    /// Overrides receive the spec, version, platform, and the built-in output as `default`.
+    pub(crate) fn render_template(
+        &self,
+        name: &str,
+        platform: Option<(&Os, &Cpu)>,
+        default: String,
+    ) -> io::Result<String> {
+        let Some(overrides) = &self.template_overrides else {
+            return Ok(default);
+        };
+        if !overrides.tera.get_template_names().any(|t| t == name) {
+            return Ok(default);
+        }
+        let mut context = Context::new();
+        context.insert("spec", &self.spec);
+        context.insert("version", &self.version.to_string());
+        context.insert(
+            "platform",
+            &platform.map(|(os, cpu)| TemplatePlatform { os, cpu }),
+        );
+        context.insert("default", &default);
+        overrides
+            .tera
+            .render(name, &context)
+            .map_err(|err| template_error(name, err))
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::io::{self, Result};
+use std::path::Path;
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::spec::TargetUpdateApi;
+use crate::{create_output_dir, GeneratedAsset, GeneratedAssetKind, Project};
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Download {
+    url: String,
+    sha256: String,
+    size: usize,
+}
+
+/// One version as it's served in `latest.json`, and listed in `versions.json`
+#[derive(Serialize, Deserialize, Clone)]
+struct Release {
+    version: Version,
+    tag: String,
+    #[serde(default)]
+    yanked: bool,
+    /// `$OS-$CPU` -> `loadable`/`static` -> download
+    platforms: BTreeMap<String, BTreeMap<String, Download>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Versions {
+    name: String,
+    /// newest version that isn't yanked or a pre-release
+    latest: Option<Version>,
+    /// newest first
+    versions: Vec<Release>,
+}
+
+fn release(project: &Project, assets: &[GeneratedAsset]) -> Release {
+    let mut platforms: BTreeMap<String, BTreeMap<String, Download>> = BTreeMap::new();
+    for asset in assets {
+        let (type_, gh_release) = match &asset.kind {
+            GeneratedAssetKind::GithubReleaseLoadable(gh_release) => ("loadable", gh_release),
+            GeneratedAssetKind::GithubReleaseStatic(gh_release) => ("static", gh_release),
+            _ => continue,
+        };
+        platforms
+            .entry(format!(
+                "{}-{}",
+                gh_release.platform.0.to_string(),
+                gh_release.platform.1.to_string()
+            ))
+            .or_default()
+            .insert(
+                type_.to_owned(),
+                Download {
+                    url: gh_release.url.clone(),
+                    sha256: asset.checksum_sha256.clone(),
+                    size: asset.size,
+                },
+            );
+    }
+    Release {
+        version: project.version.clone(),
+        tag: project.spec.package.git_tag(&project.version),
+        yanked: false,
+        platforms,
+    }
+}
+
+/// `latest.json` for install.sh, which can't parse JSON: a `version $VERSION` line,
+/// then a `$OS-$CPU $TYPE $URL $SHA256` line per download
+fn latest_txt(release: &Release) -> String {
+    let mut lines = vec![format!("version {}", release.version)];
+    for (platform, downloads) in &release.platforms {
+        for (type_, download) in downloads {
+            lines.push(format!(
+                "{platform} {type_} {} {}",
+                download.url, download.sha256
+            ));
+        }
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Writes `latest.json` and `latest.txt`, the downloads of this version unless it's
+/// a pre-release or yanked, and `versions.json`, every version so far. Previous
+/// versions come from the `versions` file of the spec, usually the `versions.json`
+/// of the last release checked out from GitHub Pages. They are uploaded under stable
+/// names, so updaters can poll `<repo>/releases/latest/download/latest.json`, and
+/// install.sh `latest.txt`.
+pub(crate) fn write_update_api(
+    project: &Project,
+    config: &TargetUpdateApi,
+    assets: &[GeneratedAsset],
+    output_dir: &Path,
+) -> Result<Vec<GeneratedAsset>> {
+    let update_api_dir = output_dir.join("update-api");
+    create_output_dir(&update_api_dir)?;
+
+    let previous = config
+        .versions
+        .as_ref()
+        .map(|path| project.spec_directory.join(path))
+        .filter(|path| {
+            let exists = path.exists();
+            if !exists {
+                tracing::warn!("{} doesn't exist, starting a new one", path.display());
+            }
+            exists
+        });
+    let mut versions = match previous {
+        Some(path) => {
+            serde_json::from_slice::<Versions>(&std::fs::read(&path)?).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is not a versions.json: {err}", path.display()),
+                )
+            })?
+        }
+        None => Versions {
+            name: project.spec.package.name.clone(),
+            latest: None,
+            versions: vec![],
+        },
+    };
+    let mut current = release(project, assets);
+    current.yanked = project.spec.yanked.contains(&current.version);
+    for previous in &mut versions.versions {
+        previous.yanked |= project.spec.yanked.contains(&previous.version);
+    }
+    versions
+        .versions
+        .retain(|previous| previous.version != current.version);
+    versions.versions.push(current.clone());
+    versions.versions.sort_by(|a, b| b.version.cmp(&a.version));
+    versions.latest = versions
+        .versions
+        .iter()
+        .find(|release| !release.yanked && release.version.pre.is_empty())
+        .map(|release| release.version.clone());
+
+    let mut assets = vec![];
+    // releases/latest/download must never serve a pre-release or a yanked version
+    if !current.yanked && current.version.pre.is_empty() {
+        assets.push(GeneratedAsset::from(
+            GeneratedAssetKind::UpdateApi,
+            &update_api_dir.join("latest.json"),
+            serde_json::to_string_pretty(&current)?.as_bytes(),
+        )?);
+        assets.push(GeneratedAsset::from(
+            GeneratedAssetKind::UpdateApi,
+            &update_api_dir.join("latest.txt"),
+            latest_txt(&current).as_bytes(),
+        )?);
+    }
+    assets.push(GeneratedAsset::from(
+        GeneratedAssetKind::UpdateApi,
+        &update_api_dir.join("versions.json"),
+        serde_json::to_string_pretty(&versions)?.as_bytes(),
+    )?);
+    Ok(assets)
+}
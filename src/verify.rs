@@ -0,0 +1,112 @@
+use std::{
+    env::consts::{ARCH, OS},
+    path::Path,
+};
+
+use rusqlite::{types::Value, Connection};
+use thiserror::Error;
+
+use crate::{spec::Spec, Cpu, Os, PlatformDirectory};
+
+#[derive(Error, Debug)]
+pub enum VerifyError {
+    #[error("--verify requires a [verify] section with a `sql` statement in the spec")]
+    MissingVerifySql,
+    #[error("no platform directory matches the host platform {0}-{1}")]
+    NoHostPlatform(String, String),
+    #[error("platform directory {0} does not contain any loadable files")]
+    NoLoadableFiles(String),
+    #[error("{context}: {source}")]
+    SqliteError {
+        context: String,
+        source: rusqlite::Error,
+    },
+}
+
+fn host_platform() -> Option<(Os, Cpu)> {
+    let os = match OS {
+        "macos" => Os::Macos,
+        "linux" => Os::Linux,
+        "windows" => Os::Windows,
+        "android" => Os::Android,
+        "freebsd" => Os::Freebsd,
+        "openbsd" => Os::Openbsd,
+        _ => return None,
+    };
+    let cpu = match ARCH {
+        "x86_64" => Cpu::X86_64,
+        "aarch64" => Cpu::Aarch64,
+        "x86" => Cpu::I686,
+        "arm" => Cpu::Armv7a,
+        "riscv64" => Cpu::Riscv64,
+        "loongarch64" => Cpu::Loongarch64,
+        "s390x" => Cpu::S390x,
+        _ => return None,
+    };
+    Some((os, cpu))
+}
+
+fn load_extension(
+    conn: &Connection,
+    loadable_path: &Path,
+    init_function: Option<&str>,
+) -> rusqlite::Result<()> {
+    unsafe {
+        conn.load_extension_enable()?;
+        conn.load_extension(loadable_path, init_function)?;
+        conn.load_extension_disable()?;
+    }
+    Ok(())
+}
+
+/// Loads every loadable of the host platform into an in-memory SQLite database
+/// and runs the spec's verification SQL, erroring if any step fails.
+pub(crate) fn verify_host_loadable(
+    spec: &Spec,
+    platform_directories: &[PlatformDirectory],
+) -> Result<(), VerifyError> {
+    let sql = &spec
+        .verify
+        .as_ref()
+        .ok_or(VerifyError::MissingVerifySql)?
+        .sql;
+    let platform_dir = host_platform()
+        .and_then(|(os, cpu)| {
+            platform_directories
+                .iter()
+                .find(|pd| pd.os == os && pd.cpu == cpu)
+        })
+        .ok_or_else(|| VerifyError::NoHostPlatform(OS.to_owned(), ARCH.to_owned()))?;
+    if platform_dir.loadable_files.is_empty() {
+        return Err(VerifyError::NoLoadableFiles(
+            platform_dir.path.display().to_string(),
+        ));
+    }
+    let conn = Connection::open_in_memory().map_err(|source| VerifyError::SqliteError {
+        context: ":memory:".to_owned(),
+        source,
+    })?;
+    for loadable in &platform_dir.loadable_files {
+        let loadable_path = platform_dir.path.join(&loadable.file.name);
+        load_extension(
+            &conn,
+            &loadable_path,
+            spec.init_function(&loadable.file_stem),
+        )
+        .map_err(|source| VerifyError::SqliteError {
+            context: loadable_path.display().to_string(),
+            source,
+        })?;
+    }
+    let result = conn
+        .query_row(sql, [], |row| row.get::<_, Value>(0))
+        .map_err(|source| VerifyError::SqliteError {
+            context: sql.to_owned(),
+            source,
+        })?;
+    tracing::info!(
+        "Verified {}: {sql} = {result:?}",
+        platform_dir.path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+use semver::Version;
+
+use crate::{gem::gem_version, pip::semver_to_pip_version};
+
+/// One version as each registry spells it, and where they'd disagree on its order
+pub struct VersionReport {
+    pub npm: String,
+    /// None when it has no PEP 440 equivalent
+    pub pip: Option<String>,
+    pub gem: String,
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for VersionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "npm  {}", self.npm)?;
+        writeln!(f, "pip  {}", self.pip.as_deref().unwrap_or("-"))?;
+        writeln!(f, "gem  {}", self.gem)?;
+        for problem in &self.problems {
+            writeln!(f, "warning: {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Converts `version` like the npm, pip, and gem targets do, and reports where
+/// the conversions order it differently than semver does
+pub fn version_report(version: &Version) -> VersionReport {
+    let base = Version::new(version.major, version.minor, version.patch);
+    let pip = semver_to_pip_version(version).ok();
+    let gem = gem_version(version);
+    let mut problems = vec![];
+    if pip.is_none() {
+        problems.push(format!(
+            "{version} has no PEP 440 equivalent, only alpha.N, beta.N, rc.N, and dev.N pre-releases convert"
+        ));
+    }
+    if !version.pre.is_empty() {
+        // RubyGems only treats versions with a letter as pre-releases
+        if !version.pre.chars().any(|c| c.is_ascii_alphabetic()) {
+            problems.push(format!(
+                "gem {gem} is a release newer than {base}, while npm orders {version} before it"
+            ));
+        }
+        if version.pre.split('.').next() == Some("dev") {
+            problems.push(format!(
+                "PEP 440 orders {} before the alphas, betas, and rcs of {base}, while npm and RubyGems order dev after alpha and beta",
+                pip.as_deref().unwrap_or("it")
+            ));
+        }
+    }
+    if !version.build.is_empty() {
+        problems.push(format!(
+            "build metadata +{} is dropped from the gem version, and from the PEP 440 version unless targets.pip.build_metadata is \"local\"",
+            version.build
+        ));
+    }
+    VersionReport {
+        npm: version.to_string(),
+        pip,
+        gem,
+        problems,
+    }
+}
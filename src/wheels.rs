@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::lint::has_placeholder;
+use crate::names::normalize_pypi_name;
+use zip::{result::ZipError, ZipArchive};
+
+#[derive(Error, Debug)]
+pub enum WheelCheckError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] io::Error),
+    #[error("Zipfile error: {0}")]
+    ZipError(#[from] ZipError),
+}
+
+/// `{name}-{version}(-{build})?-{python}-{abi}-{platform}.whl`, split on dashes
+fn filename_parts(path: &Path) -> Option<Vec<String>> {
+    let stem = path.file_name()?.to_str()?.strip_suffix(".whl")?;
+    let parts: Vec<String> = stem.split('-').map(str::to_owned).collect();
+    matches!(parts.len(), 5 | 6).then_some(parts)
+}
+
+/// Records of a CSV file like RECORD, with `"`-quoted fields that may contain
+/// commas, newlines, and `""` for a quote. Blank lines are skipped.
+fn csv_records(contents: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = vec![];
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut chars = contents.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => fields.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                if !fields.is_empty() || !field.is_empty() {
+                    fields.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut fields));
+                }
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".to_owned());
+    }
+    if !fields.is_empty() || !field.is_empty() {
+        fields.push(field);
+        records.push(fields);
+    }
+    Ok(records)
+}
+
+/// `Key: value` headers of METADATA and WHEEL, up to the first blank line
+pub(crate) fn headers(contents: &str) -> Vec<(&str, &str)> {
+    contents
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(": "))
+        .collect()
+}
+
+pub(crate) fn header<'a>(headers: &[(&str, &'a str)], key: &str) -> Option<&'a str> {
+    headers.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+/// The METADATA fields shown on the PyPI project page that still hold a placeholder,
+/// as `(field, value)`. The description body is reported as `description`.
+pub(crate) fn metadata_placeholders(metadata: &str) -> Vec<(&str, &str)> {
+    let mut placeholders: Vec<(&str, &str)> = headers(metadata)
+        .into_iter()
+        .filter(|(key, value)| {
+            ["Summary", "Home-page", "Author", "License", "Project-URL"].contains(key)
+                && has_placeholder(value)
+        })
+        .collect();
+    if let Some((_, description)) = metadata.split_once("\n\n") {
+        if has_placeholder(description) {
+            placeholders.push(("description", description.trim()));
+        }
+    }
+    placeholders
+}
+
+/// The METADATA of a wheel, found through its file name
+pub(crate) fn wheel_metadata(path: &Path) -> Result<Option<String>, WheelCheckError> {
+    let Some(parts) = filename_parts(path) else {
+        return Ok(None);
+    };
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut file = match archive.by_name(&format!("{}-{}.dist-info/METADATA", parts[0], parts[1])) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut metadata = String::new();
+    file.read_to_string(&mut metadata)?;
+    Ok(Some(metadata))
+}
+
+/// Python tag of a wheel, ex `py3`
+pub(crate) fn python_tag(path: &Path) -> Option<String> {
+    let parts = filename_parts(path)?;
+    parts.get(parts.len() - 3).cloned()
+}
+
+/// Checks a wheel the way pip and PyPI would: the file name, that RECORD covers
+/// every file with the right hash and size, and the required METADATA and WHEEL
+/// fields. Returns the problems found, empty for a valid wheel.
+pub fn check_wheel(path: &Path) -> Result<Vec<String>, WheelCheckError> {
+    let mut problems = vec![];
+    let Some(parts) = filename_parts(path) else {
+        return Ok(vec![
+            "file name must be {name}-{version}-{python}-{abi}-{platform}.whl".to_owned(),
+        ]);
+    };
+    let (name, version) = (&parts[0], &parts[1]);
+    let tags = &parts[parts.len() - 3..];
+
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+        files.insert(file.name().to_owned(), data);
+    }
+
+    let dist_info = format!("{name}-{version}.dist-info");
+    let read = |file: &str| {
+        files
+            .get(&format!("{dist_info}/{file}"))
+            .map(|data| String::from_utf8_lossy(data).into_owned())
+    };
+    let (Some(metadata), Some(wheel), Some(record)) =
+        (read("METADATA"), read("WHEEL"), read("RECORD"))
+    else {
+        problems.push(format!(
+            "{dist_info} must contain METADATA, WHEEL, and RECORD"
+        ));
+        return Ok(problems);
+    };
+
+    let metadata = headers(&metadata);
+    for key in ["Metadata-Version", "Name", "Version"] {
+        if header(&metadata, key).is_none() {
+            problems.push(format!("METADATA is missing {key}"));
+        }
+    }
+    if let Some(metadata_name) = header(&metadata, "Name") {
+        if normalize_pypi_name(metadata_name) != normalize_pypi_name(name) {
+            problems.push(format!(
+                "METADATA Name {metadata_name} doesn't match the file name"
+            ));
+        }
+    }
+    if header(&metadata, "Version").is_some_and(|v| v != version) {
+        problems.push("METADATA Version doesn't match the file name".to_owned());
+    }
+
+    let wheel = headers(&wheel);
+    if header(&wheel, "Wheel-Version") != Some("1.0") {
+        problems.push("WHEEL Wheel-Version must be 1.0".to_owned());
+    }
+    if header(&wheel, "Root-Is-Purelib").is_none() {
+        problems.push("WHEEL is missing Root-Is-Purelib".to_owned());
+    }
+    let wheel_tags: Vec<&str> = wheel
+        .iter()
+        .filter(|(key, _)| *key == "Tag")
+        .map(|(_, value)| *value)
+        .collect();
+    for tag in wheel_tags.iter().filter(|tag| tag.contains('.')) {
+        problems.push(format!(
+            "WHEEL Tag: {tag} is a compressed tag set, list each tag on its own line"
+        ));
+    }
+    for python in tags[0].split('.') {
+        for abi in tags[1].split('.') {
+            for platform in tags[2].split('.') {
+                let expected_tag = format!("{python}-{abi}-{platform}");
+                if !wheel_tags.contains(&expected_tag.as_str()) {
+                    problems.push(format!("WHEEL has no Tag: {expected_tag}"));
+                }
+            }
+        }
+    }
+
+    let record_path = format!("{dist_info}/RECORD");
+    let mut recorded = vec![];
+    let records = match csv_records(&record) {
+        Ok(records) => records,
+        Err(problem) => {
+            problems.push(format!("RECORD isn't valid CSV: {problem}"));
+            return Ok(problems);
+        }
+    };
+    for fields in &records {
+        let [file, hash, size] = &fields[..] else {
+            problems.push(format!(
+                "RECORD line '{}' must be path,hash,size",
+                fields.join(",")
+            ));
+            continue;
+        };
+        let file = file.as_str();
+        recorded.push(file.to_owned());
+        if file == record_path {
+            continue;
+        }
+        let Some(data) = files.get(file) else {
+            problems.push(format!("RECORD lists {file}, which isn't in the wheel"));
+            continue;
+        };
+        let expected_hash = format!("sha256={}", URL_SAFE_NO_PAD.encode(Sha256::digest(data)));
+        if *hash != expected_hash {
+            problems.push(format!("RECORD hash of {file} is wrong"));
+        }
+        if *size != data.len().to_string() {
+            problems.push(format!("RECORD size of {file} is wrong"));
+        }
+    }
+    let mut unrecorded: Vec<&String> = files.keys().filter(|f| !recorded.contains(f)).collect();
+    unrecorded.sort();
+    for file in unrecorded {
+        problems.push(format!("{file} is missing from RECORD"));
+    }
+    Ok(problems)
+}
+
+/// The `.whl` files of `path`, or `path` itself when it's a file
+pub fn find_wheels(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut wheels = vec![];
+    for entry in std::fs::read_dir(path)? {
+        let entry_path = entry?.path();
+        if entry_path.is_dir() {
+            wheels.extend(find_wheels(&entry_path)?);
+        } else if entry_path.extension().is_some_and(|ext| ext == "whl") {
+            wheels.push(entry_path);
+        }
+    }
+    wheels.sort();
+    Ok(wheels)
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::io::Result;
+use std::path::Path;
+
+use crate::npm::{ExportTarget, PackageJson, Repository};
+use crate::spec::TargetWorkers;
+use crate::{create_targz, Cpu, GeneratedAsset, GeneratedAssetKind, Os, PlatformFile, Project};
+
+/// An npm package of the wasm build for Cloudflare Workers and other edge runtimes,
+/// `$NAME-workers`. The wasm is imported as a module, which bundlers like wrangler
+/// compile ahead of time, so nothing is fetched or read from disk at runtime.
+pub(crate) fn write_workers_package(
+    project: &Project,
+    workers_dir: &Path,
+    config: &TargetWorkers,
+) -> Result<Vec<GeneratedAsset>> {
+    let Some(wasm_directory) = &project.wasm_directory else {
+        tracing::warn!("the workers target needs a wasm32-emscripten input directory");
+        return Ok(vec![]);
+    };
+    let base_name = project
+        .spec
+        .targets
+        .npm
+        .as_ref()
+        .and_then(|npm| npm.package_name.as_deref())
+        .unwrap_or(&project.spec.package.name);
+    let package_name = config
+        .package_name
+        .clone()
+        .unwrap_or_else(|| format!("{base_name}-workers"));
+    let module = wasm_directory.module().unwrap_or("sqlite3.mjs");
+    let wasm = wasm_directory.wasm().unwrap_or("sqlite3.wasm");
+
+    let package_json = PackageJson {
+        name: package_name.clone(),
+        version: project.version.to_string(),
+        author: project
+            .spec
+            .package
+            .authors
+            .first()
+            .cloned()
+            .unwrap_or_default(),
+        license: project.spec.package.license.clone(),
+        description: project.spec.package.description.clone(),
+        repository: Repository {
+            repo_type: "git".to_owned(),
+            url: project.spec.package.repo.clone(),
+            directory: None,
+        },
+        main: None,
+        module: "./index.mjs".to_owned(),
+        types: Some("./index.d.ts".to_owned()),
+        exports: HashMap::from([(
+            ".".to_owned(),
+            ExportTarget {
+                workerd: Some("./index.mjs".to_owned()),
+                browser: None,
+                require: None,
+                import: "./index.mjs".to_owned(),
+                types: Some("./index.d.ts".to_owned()),
+            },
+        )]),
+        scripts: None,
+        files: None,
+        keywords: Some(vec!["cloudflare-workers".to_owned(), "sqlite".to_owned()]),
+        dependencies: None,
+        optional_dependencies: None,
+        dev_dependencies: None,
+        os: None,
+        cpu: None,
+        engines: None,
+    };
+    let mut files = vec![
+        PlatformFile::new(
+            "package/package.json",
+            serde_json::to_string(&package_json)?,
+            None,
+        ),
+        PlatformFile::new(
+            "package/index.mjs",
+            project.render_template(
+                "workers/index.mjs",
+                None,
+                templates::index_mjs(&package_name, module, wasm),
+            )?,
+            None,
+        ),
+        PlatformFile::new(
+            "package/index.d.ts",
+            project.render_template("workers/index.d.ts", None, templates::index_dts())?,
+            None,
+        ),
+    ];
+    for file in &wasm_directory.files {
+        files.push(PlatformFile::new(
+            format!("package/{}", file.name),
+            file.data.clone(),
+            file.metadata.clone(),
+        ));
+    }
+    let data = create_targz(&files.iter().collect::<Vec<&PlatformFile>>())?;
+    Ok(vec![GeneratedAsset::from(
+        GeneratedAssetKind::Npm(Some((Os::Emscripten, Cpu::Wasm32))),
+        &workers_dir.join(format!(
+            "{}.tar.gz",
+            package_name.replace('@', "").replace('/', "-")
+        )),
+        &data,
+    )?])
+}
+
+mod templates {
+    pub(crate) fn index_mjs(package_name: &str, module: &str, wasm: &str) -> String {
+        let module = serde_json::to_string(&format!("./{module}"))
+            .expect("String values should always serialize as JSON");
+        let wasm = serde_json::to_string(&format!("./{wasm}"))
+            .expect("String values should always serialize as JSON");
+        format!(
+            r#"import factory from {module};
+import wasmModule from {wasm};
+
+/**
+ * Initializes the SQLite build bundled with {package_name}, with the extension
+ * compiled in. The wasm is instantiated from the imported module instead of being
+ * fetched, which Workers don't allow.
+ */
+export async function init(config = {{}}) {{
+  return factory({{
+    ...config,
+    instantiateWasm(imports, receive) {{
+      const instance = new WebAssembly.Instance(wasmModule, imports);
+      receive(instance, wasmModule);
+      return instance.exports;
+    }},
+  }});
+}}
+"#
+        )
+    }
+
+    pub(crate) fn index_dts() -> String {
+        r#"
+export declare function init(config?: object): Promise<any>;
+"#
+        .to_string()
+    }
+}
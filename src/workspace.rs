@@ -0,0 +1,163 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    build, create_output_dir, dry_run, manifest::write_manifest, spec::Spec, BuildError,
+    BuildOptions, BuildOutput, GeneratedAsset, GeneratedAssetKind,
+};
+
+#[derive(Error, Debug)]
+pub enum WorkspaceError {
+    #[error("could not read {0}: {1}")]
+    IOError(PathBuf, io::Error),
+    #[error("{0}")]
+    ParseError(#[from] toml::de::Error),
+    #[error("the workspace has no members")]
+    NoMembers,
+    #[error("{0} is generated by more than one member with different contents, release assets share one namespace")]
+    Collision(String),
+}
+
+/// A workspace spec, ex `sqlite-dist-workspace.toml`, building several extensions at once
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceSpec {
+    pub workspace: Workspace,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workspace {
+    pub members: Vec<WorkspaceMember>,
+}
+
+/// One extension of the workspace, with paths relative to the workspace spec
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceMember {
+    /// ex `sqlite-foo/sqlite-dist.toml`
+    pub spec: String,
+    /// directory of the member's `$OS-$CPU` builds, ex `sqlite-foo/dist`
+    pub input: String,
+}
+
+impl WorkspaceSpec {
+    pub fn from_path(path: &Path) -> Result<Self, WorkspaceError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| WorkspaceError::IOError(path.to_path_buf(), err))?;
+        let spec: WorkspaceSpec = toml::from_str(&contents)?;
+        if spec.workspace.members.is_empty() {
+            return Err(WorkspaceError::NoMembers);
+        }
+        Ok(spec)
+    }
+}
+
+/// Assets every member generates under the same fixed name, ex `sqlpkg.json`,
+/// `Package.swift`, the badges or `latest.json`, whose contents always differ
+fn is_member_scoped(kind: &GeneratedAssetKind) -> bool {
+    matches!(
+        kind,
+        GeneratedAssetKind::Sqlpkg
+            | GeneratedAssetKind::Spm
+            | GeneratedAssetKind::Badges
+            | GeneratedAssetKind::UpdateApi
+    )
+}
+
+/// Files with the same name and contents in more than one member, ex a shared
+/// LICENSE archive, are kept once and hard linked from the other members' directories.
+/// The same name with different contents would overwrite each other in the release.
+/// Member-scoped assets are never deduplicated and are listed as `$NAME/$FILE`.
+fn deduplicate<'a>(
+    output_dir: &Path,
+    assets: &'a [GeneratedAsset],
+) -> Result<Vec<(String, &'a GeneratedAsset)>, BuildError> {
+    let mut unique: HashMap<&str, &GeneratedAsset> = HashMap::new();
+    let mut deduplicated = vec![];
+    for asset in assets.iter().filter(|asset| asset.kind.is_release_asset()) {
+        if is_member_scoped(&asset.kind) {
+            let member = Path::new(&asset.path)
+                .strip_prefix(output_dir)
+                .ok()
+                .and_then(|relative| relative.iter().next())
+                .map(|member| member.to_string_lossy().into_owned());
+            let name = match member {
+                Some(member) => format!("{member}/{}", asset.name),
+                None => asset.name.clone(),
+            };
+            deduplicated.push((name, asset));
+            continue;
+        }
+        let Some(first) = unique.get(asset.name.as_str()) else {
+            unique.insert(&asset.name, asset);
+            deduplicated.push((asset.name.clone(), asset));
+            continue;
+        };
+        if first.checksum_sha256 != asset.checksum_sha256 {
+            return Err(WorkspaceError::Collision(asset.name.clone()).into());
+        }
+        if !dry_run() && first.path != asset.path {
+            fs::remove_file(&asset.path)?;
+            if fs::hard_link(&first.path, &asset.path).is_err() {
+                fs::copy(&first.path, &asset.path)?;
+            }
+        }
+    }
+    Ok(deduplicated)
+}
+
+/// Builds every member of the workspace at `options.spec_path` into its own
+/// `$OUTPUT/$NAME` directory, then writes a `checksums.txt` and manifest covering
+/// all of them to the output directory. The rest of `options` applies to every member.
+pub fn build_workspace(options: &BuildOptions) -> Result<BuildOutput, BuildError> {
+    let workspace_spec = WorkspaceSpec::from_path(&options.spec_path)?;
+    let workspace_directory = options.spec_path.parent().unwrap_or(Path::new("."));
+    create_output_dir(&options.output_dir)?;
+
+    let mut assets = vec![];
+    let mut timings = vec![];
+    for member in &workspace_spec.workspace.members {
+        let spec_path = workspace_directory.join(&member.spec);
+        let name = Spec::from_path(&spec_path)?.package.name;
+        let _span = tracing::info_span!("member", name = %name).entered();
+        let output = build(&BuildOptions {
+            input_dir: workspace_directory.join(&member.input),
+            output_dir: options.output_dir.join(&name),
+            spec_path,
+            version: options.version.clone(),
+            verify: options.verify,
+            dry_run: options.dry_run,
+            no_cache: options.no_cache,
+            only: options.only.clone(),
+            skip: options.skip.clone(),
+            keep_going: options.keep_going,
+            layout: options.layout,
+            profile: options.profile.clone(),
+            dev: options.dev,
+            strict: options.strict,
+            init_checksum_log: options.init_checksum_log,
+        })?;
+        assets.extend(output.assets);
+        timings.extend(output.timings);
+    }
+
+    let checksums_txt = deduplicate(&options.output_dir, &assets)?
+        .iter()
+        .map(|(name, asset)| format!("{name} {}", asset.checksum_sha256))
+        .collect::<Vec<String>>()
+        .join("\n");
+    if dry_run() {
+        return Ok(BuildOutput { assets, timings });
+    }
+    fs::write(options.output_dir.join("checksums.txt"), checksums_txt)?;
+    let manifest = write_manifest(&options.output_dir, None, &assets, &[])?;
+    assets.push(manifest);
+    Ok(BuildOutput { assets, timings })
+}
@@ -0,0 +1,205 @@
+use std::io::{self, Result};
+use std::path::Path;
+
+use crate::macho::create_fat_binary;
+use crate::spec::TargetXcframework;
+use crate::{
+    create_zip, Cpu, GeneratedAsset, GeneratedAssetKind, Os, PlatformDirectory, PlatformFile,
+    Project,
+};
+
+struct XcframeworkLibrary {
+    identifier: String,
+    library_name: String,
+    library: Vec<u8>,
+    headers: Vec<PlatformFile>,
+    architectures: Vec<&'static str>,
+    simulator: bool,
+}
+
+fn apple_architecture(cpu: &Cpu) -> &'static str {
+    match cpu {
+        Cpu::Aarch64 => "arm64",
+        Cpu::X86_64 => "x86_64",
+        _ => unreachable!(
+            "Invalid iOS CPU {:?} provided, should have been filtered out",
+            cpu
+        ),
+    }
+}
+
+/// Device or simulator slices of an xcframework. Simulator builds for several
+/// architectures must be merged into a single fat library.
+fn xcframework_library(
+    platform_dirs: &[&PlatformDirectory],
+    simulator: bool,
+) -> Result<Option<XcframeworkLibrary>> {
+    let Some(first) = platform_dirs.first() else {
+        return Ok(None);
+    };
+    let first_static = first.static_files.first().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} needs a static library for the xcframework target",
+                first.path.display()
+            ),
+        )
+    })?;
+    let mut slices = vec![];
+    for platform_dir in platform_dirs {
+        let static_file = platform_dir.static_files.first().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} needs a static library for the xcframework target",
+                    platform_dir.path.display()
+                ),
+            )
+        })?;
+        slices.push((&platform_dir.cpu, static_file.data.as_slice()));
+    }
+    let library = if slices.len() == 1 {
+        first_static.data.clone()
+    } else {
+        create_fat_binary(&slices)?
+    };
+    let architectures: Vec<&'static str> = platform_dirs
+        .iter()
+        .map(|platform_dir| apple_architecture(&platform_dir.cpu))
+        .collect();
+    let identifier = format!(
+        "ios-{}{}",
+        architectures.join("_"),
+        if simulator { "-simulator" } else { "" }
+    );
+    Ok(Some(XcframeworkLibrary {
+        identifier,
+        library_name: first_static.name.clone(),
+        library,
+        headers: first.header_files.clone(),
+        architectures,
+        simulator,
+    }))
+}
+
+pub(crate) fn write_xcframework(
+    project: &Project,
+    xcframework_dir: &Path,
+    config: &TargetXcframework,
+) -> Result<Vec<GeneratedAsset>> {
+    let ios_dirs = |os: Os| -> Vec<&PlatformDirectory> {
+        project
+            .platform_directories
+            .iter()
+            .filter(|platform_dir| {
+                platform_dir.os == os && matches!(platform_dir.cpu, Cpu::Aarch64 | Cpu::X86_64)
+            })
+            .collect()
+    };
+    let libraries: Vec<XcframeworkLibrary> = [
+        xcframework_library(&ios_dirs(Os::Ios), false)?,
+        xcframework_library(&ios_dirs(Os::IosSimulator), true)?,
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if libraries.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let framework_name = config
+        .framework_name
+        .as_deref()
+        .unwrap_or(&project.spec.package.name);
+    let root = format!("{framework_name}.xcframework");
+    let mut files = vec![PlatformFile::new(
+        format!("{root}/Info.plist"),
+        templates::info_plist(&libraries),
+        None,
+    )];
+    for library in &libraries {
+        files.push(PlatformFile::new(
+            format!("{root}/{}/{}", library.identifier, library.library_name),
+            library.library.clone(),
+            None,
+        ));
+        for header in &library.headers {
+            files.push(PlatformFile::new(
+                format!("{root}/{}/Headers/{}", library.identifier, header.name),
+                header.data.clone(),
+                header.metadata.clone(),
+            ));
+        }
+    }
+    let zip = create_zip(&files.iter().collect::<Vec<&PlatformFile>>())?;
+    Ok(vec![GeneratedAsset::from(
+        GeneratedAssetKind::Xcframework,
+        &xcframework_dir.join(format!(
+            "{}-{}.xcframework.zip",
+            project.spec.package.name, project.version
+        )),
+        &zip,
+    )?])
+}
+
+mod templates {
+    use super::XcframeworkLibrary;
+
+    fn library_dict(library: &XcframeworkLibrary) -> String {
+        let identifier = &library.identifier;
+        let library_name = &library.library_name;
+        let architectures = library
+            .architectures
+            .iter()
+            .map(|arch| format!("\t\t\t\t<string>{arch}</string>"))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let variant = if library.simulator {
+            "\n\t\t\t<key>SupportedPlatformVariant</key>\n\t\t\t<string>simulator</string>"
+        } else {
+            ""
+        };
+        format!(
+            r#"		<dict>
+			<key>HeadersPath</key>
+			<string>Headers</string>
+			<key>LibraryIdentifier</key>
+			<string>{identifier}</string>
+			<key>LibraryPath</key>
+			<string>{library_name}</string>
+			<key>SupportedArchitectures</key>
+			<array>
+{architectures}
+			</array>
+			<key>SupportedPlatform</key>
+			<string>ios</string>{variant}
+		</dict>"#
+        )
+    }
+
+    pub(crate) fn info_plist(libraries: &[XcframeworkLibrary]) -> String {
+        let libraries = libraries
+            .iter()
+            .map(library_dict)
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>AvailableLibraries</key>
+	<array>
+{libraries}
+	</array>
+	<key>CFBundlePackageType</key>
+	<string>XFWK</string>
+	<key>XCFrameworkFormatVersion</key>
+	<string>1.0</string>
+</dict>
+</plist>
+"#
+        )
+    }
+}
@@ -0,0 +1,74 @@
+
+from contextlib import contextmanager
+from os import path
+import sqlite3
+import sys
+
+__version__ = "1.2.3a4"
+__version_info__ = tuple(__version__.split("."))
+
+ENTRYPOINTS = ["sample0"]
+# entrypoint -> init function, for loadables SQLite can't derive it from the file name of
+INIT_FUNCTIONS = {}
+
+def _windows_path(loadable_path: str) -> str:
+  # SQLite's LoadLibrary fails on \\?\ extended-length paths and on some non-ASCII
+  # ones, so drop the prefix and use the 8.3 short name of the directory. The file
+  # name is kept, SQLite derives the entrypoint from it.
+  if loadable_path.startswith("\\\\?\\UNC\\"):
+    loadable_path = "\\\\" + loadable_path[8:]
+  elif loadable_path.startswith("\\\\?\\"):
+    loadable_path = loadable_path[4:]
+  directory, file_name = path.split(loadable_path)
+  if not directory.isascii():
+    import ctypes
+    buffer = ctypes.create_unicode_buffer(32768)
+    if ctypes.windll.kernel32.GetShortPathNameW(directory, buffer, len(buffer)) and buffer.value.isascii():
+      loadable_path = path.join(buffer.value, file_name)
+  return loadable_path
+
+def loadable_path(entrypoint: str = ENTRYPOINTS[0]):
+  """ Returns the full path to the sqlite-sample loadable SQLite extension bundled with this package """
+
+  loadable_path = path.normpath(path.join(path.dirname(path.abspath(__file__)), entrypoint))
+  if sys.platform == "win32":
+    return _windows_path(loadable_path)
+  return loadable_path
+
+def load(conn: sqlite3.Connection)  -> None:
+  """ Load the sqlite-sample SQLite extension into the given database connection. """
+
+  for entrypoint in ENTRYPOINTS:
+    if entrypoint in INIT_FUNCTIONS:
+      # Connection.load_extension() only takes an entrypoint since Python 3.12
+      conn.execute("select load_extension(?, ?)", (loadable_path(entrypoint), INIT_FUNCTIONS[entrypoint]))
+    else:
+      conn.load_extension(loadable_path(entrypoint))
+
+@contextmanager
+def extension(conn: sqlite3.Connection):
+  """ Loads the sqlite-sample SQLite extension into conn, with extension loading only enabled while it loads.
+
+  with sqlite_sample.extension(conn):
+    conn.execute(...)
+  """
+
+  conn.enable_load_extension(True)
+  try:
+    load(conn)
+  finally:
+    conn.enable_load_extension(False)
+  yield conn
+
+def connect(database, **kwargs) -> sqlite3.Connection:
+  """ Like sqlite3.connect(), with the sqlite-sample SQLite extension already loaded. """
+
+  conn = sqlite3.connect(database, **kwargs)
+  try:
+    with extension(conn):
+      pass
+  except Exception:
+    conn.close()
+    raise
+  return conn
+
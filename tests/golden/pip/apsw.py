@@ -0,0 +1,25 @@
+
+import apsw
+import sqlite_base
+
+from . import ENTRYPOINTS, INIT_FUNCTIONS, loadable_path, VERSION_SQL, _check_version
+
+def load(conn: apsw.Connection) -> None:
+  """ Load the sqlite-sample SQLite extension into the given APSW connection. """
+
+  conn.enableloadextension(True)
+  try:
+    for entrypoint in sqlite_base.ENTRYPOINTS:
+      conn.loadextension(sqlite_base.loadable_path(entrypoint), getattr(sqlite_base, "INIT_FUNCTIONS", {}).get(entrypoint))
+    for entrypoint in ENTRYPOINTS:
+      conn.loadextension(loadable_path(entrypoint), INIT_FUNCTIONS.get(entrypoint))
+  finally:
+    conn.enableloadextension(False)
+  _check_version(next(conn.execute(VERSION_SQL))[0])
+
+def connect(filename: str, *args, **kwargs) -> apsw.Connection:
+  """ Like apsw.Connection(), with the sqlite-sample SQLite extension already loaded. """
+
+  conn = apsw.Connection(filename, *args, **kwargs)
+  load(conn)
+  return conn
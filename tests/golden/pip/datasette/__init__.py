@@ -0,0 +1,16 @@
+
+from datasette import hookimpl
+import sqlite_sample
+
+__version__ = "1.2.3a4"
+__version_info__ = tuple(__version__.split("."))
+
+CONFIGURE_SQL = ["select sample_configure()"]
+
+@hookimpl
+def prepare_connection(conn):
+  conn.enable_load_extension(True)
+  sqlite_sample.load(conn)
+  conn.enable_load_extension(False)
+  for sql in CONFIGURE_SQL:
+    conn.execute(sql)
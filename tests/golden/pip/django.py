@@ -0,0 +1,21 @@
+
+""" Loads the sqlite-sample SQLite extension into every SQLite connection Django opens.
+Import it once, ex in your AppConfig.ready():
+
+  import sqlite_sample.django
+"""
+
+from django.db.backends.signals import connection_created
+
+from . import load
+
+def _load_extension(sender, connection, **kwargs):
+  if connection.vendor != "sqlite":
+    return
+  connection.connection.enable_load_extension(True)
+  try:
+    load(connection.connection)
+  finally:
+    connection.connection.enable_load_extension(False)
+
+connection_created.connect(_load_extension, dispatch_uid="sqlite_sample")
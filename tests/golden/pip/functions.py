@@ -0,0 +1,16 @@
+""" SQL functions and virtual tables provided by the sqlite-sample SQLite extension """
+
+from typing import Final
+
+sample_version: Final = "sample_version"
+""" `sample_version()`, scalar function
+
+The version, ex "v1.2.3" """
+
+sample_each: Final = "sample_each"
+""" `sample_each(input)`, table-valued function """
+
+FUNCTIONS: Final = (
+  sample_version,
+  sample_each,
+)
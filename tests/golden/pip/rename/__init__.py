@@ -0,0 +1,7 @@
+
+import warnings
+
+warnings.warn("sqlite-old was renamed to sqlite-sample", DeprecationWarning, stacklevel=2)
+
+from sqlite_sample import *
+from sqlite_sample import __version__, __version_info__
@@ -0,0 +1,33 @@
+
+from sqlite_utils import hookimpl
+import sqlite_sample
+
+__version__ = "1.2.3a4"
+__version_info__ = tuple(__version__.split("."))
+
+@hookimpl
+def prepare_connection(conn):
+  conn.enable_load_extension(True)
+  sqlite_sample.load(conn)
+  conn.enable_load_extension(False)
+
+import json
+import click
+import sqlite_utils
+
+# (name, sql, help)
+COMMANDS = [["sample-version","select sample_version()","Runs `select sample_version()`"]]
+
+def _register_command(cli, name, sql, help):
+  @cli.command(name=name, help=help)
+  @click.argument("path", type=click.Path(dir_okay=False))
+  @click.argument("params", nargs=-1)
+  def command(path, params):
+    db = sqlite_utils.Database(path)
+    for row in db.query(sql, params):
+      click.echo(json.dumps(row))
+
+@hookimpl
+def register_commands(cli):
+  for name, sql, help in COMMANDS:
+    _register_command(cli, name, sql, help)